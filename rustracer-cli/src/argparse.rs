@@ -5,22 +5,89 @@ pub fn parse_args<'a>() -> ArgMatches<'a> {
         .version("0.1")
         .author("Antoine Büsch")
         .about("Toy raytracer in Rust based on PBRTv3")
-        .arg(Arg::with_name("output")
-                 .long("output")
+        .arg(Arg::with_name("outfile")
+                 .long("outfile")
+                 .alias("output")
                  .short("o")
-                 .help("Output file name")
-                 .default_value("image.png"))
-        .arg(Arg::with_name("threads")
-                 .long("threads")
+                 .help("Output file name, overriding the one in the scene file")
+                 .takes_value(true))
+        .arg(Arg::with_name("nthreads")
+                 .long("nthreads")
+                 .alias("threads")
                  .short("t")
                  .help("Number of worker threads")
                  .takes_value(true))
+        .arg(Arg::with_name("quick")
+                 .long("quick")
+                 .help("Render a quick preview: 1/4 of the resolution and 1 sample per pixel"))
+        .arg(Arg::with_name("resolution")
+                 .long("resolution")
+                 .help("Film resolution as WIDTHxHEIGHT, overriding the scene's")
+                 .takes_value(true))
+        .arg(Arg::with_name("resolution-scale")
+                 .long("resolution-scale")
+                 .help("Scale the film resolution by this factor")
+                 .takes_value(true))
+        .arg(Arg::with_name("spp-override")
+                 .long("spp-override")
+                 .help("Number of samples per pixel, regardless of the scene's sampler settings")
+                 .takes_value(true))
+        .arg(Arg::with_name("seed")
+                 .long("seed")
+                 .help("Seed for the random number generators: renders with the same seed are \
+                        identical")
+                 .takes_value(true))
+        .arg(Arg::with_name("passes")
+                 .long("passes")
+                 .help("Number of rendering passes: the image is written after each one")
+                 .takes_value(true))
+        .arg(Arg::with_name("render-time")
+                 .long("render-time")
+                 .help("Keep rendering passes for this many seconds, instead of a fixed number \
+                        of them")
+                 .takes_value(true))
+        .arg(Arg::with_name("only-light")
+                 .long("only-light")
+                 .help("Only render the light with this \"name\" parameter (for debugging)")
+                 .takes_value(true))
+        .arg(Arg::with_name("only-material")
+                 .long("only-material")
+                 .help("Render every material black but the one with this name, or of this \
+                        type if it is not a named material (for debugging)")
+                 .takes_value(true))
+        .arg(Arg::with_name("debug-pixel")
+                 .long("debug-pixel")
+                 .help("Log what happens to the samples of the pixel at X,Y (for debugging)")
+                 .takes_value(true))
+        .arg(Arg::with_name("no-shading-normals")
+                 .long("no-shading-normals")
+                 .help("Shade with the geometric normals instead of the ones interpolated \
+                        from the vertex normals of meshes (for debugging)"))
+        .arg(Arg::with_name("roi")
+                 .long("roi")
+                 .help("Only render the region of the image from pixel X0,Y0 (inclusive) to \
+                        X1,Y1 (exclusive)")
+                 .takes_value(true))
+        .arg(Arg::with_name("base")
+                 .long("base")
+                 .help("Image to take the pixels outside of --roi from, e.g. a previous render")
+                 .requires("roi")
+                 .takes_value(true))
+        .arg(Arg::with_name("write-metadata")
+                 .long("write-metadata")
+                 .help("Write a JSON file describing the render (settings, render time and \
+                        statistics) next to the output image"))
+        .arg(Arg::with_name("quiet")
+                 .long("quiet")
+                 .short("q")
+                 .help("Don't print progress or statistics"))
         .arg(Arg::with_name("verbose")
                  .short("v")
                  .help("log debug information"))
         .arg(Arg::with_name("display")
                  .short("p")
-                 .help("Display image as it is rendered"))
+                 .help("Display image as it is rendered. Drag with the mouse or use the \
+                        arrow keys to orbit around the scene, and scroll or use +/- to zoom"))
         .arg(Arg::with_name("INPUT")
                  .required(true)
                  .index(1)
@@ -1,4 +1,5 @@
 extern crate clap;
+#[macro_use]
 extern crate failure;
 extern crate rustracer_core as rt;
 #[macro_use]
@@ -7,10 +8,13 @@ extern crate flexi_logger;
 
 mod argparse;
 
+use std::time::Duration;
+
 use clap::ArgMatches;
 use failure::Error;
 
-use rt::pbrt;
+use rt::{pbrt, Options, Point2i};
+use rt::bounds::Bounds2i;
 
 fn main() {
     let matches = argparse::parse_args();
@@ -39,7 +43,96 @@ fn main() {
 fn run(matches: &ArgMatches) -> Result<(), Error> {
     rt::init_stats();
     let filename = matches.value_of("INPUT").unwrap();
-    pbrt::parse_scene(filename)?;
+    let mut options = Options::default();
+    if let Some(threads) = matches.value_of("nthreads") {
+        options.num_threads = threads
+            .parse()
+            .map_err(|_| format_err!("Invalid number of threads: {}", threads))?;
+    }
+    options.image_file = matches.value_of("outfile").map(|s| s.to_owned());
+    options.quick_render = matches.is_present("quick");
+    if let Some(resolution) = matches.value_of("resolution") {
+        let resolution = parse_resolution(resolution)
+            .ok_or_else(|| format_err!("Invalid resolution: {}", resolution))?;
+        options.resolution = Some(resolution);
+    }
+    if let Some(scale) = matches.value_of("resolution-scale") {
+        let scale: f32 = scale.parse()
+            .map_err(|_| format_err!("Invalid resolution scale: {}", scale))?;
+        if !(scale.is_finite() && scale > 0.0) {
+            bail!("Invalid resolution scale: {}", scale);
+        }
+        options.resolution_scale = scale;
+    }
+    if let Some(spp) = matches.value_of("spp-override") {
+        let spp: u32 = spp.parse()
+            .map_err(|_| format_err!("Invalid number of samples per pixel: {}", spp))?;
+        options.spp_override = Some(u32::max(1, spp));
+    }
+    if let Some(seed) = matches.value_of("seed") {
+        options.seed = seed.parse()
+            .map_err(|_| format_err!("Invalid seed: {}", seed))?;
+    }
+    if let Some(passes) = matches.value_of("passes") {
+        let passes: u32 = passes.parse()
+            .map_err(|_| format_err!("Invalid number of passes: {}", passes))?;
+        options.passes = Some(u32::max(1, passes));
+    }
+    if let Some(time) = matches.value_of("render-time") {
+        let time: f64 = time.parse()
+            .map_err(|_| format_err!("Invalid render time: {}", time))?;
+        if !(time.is_finite() && time > 0.0) {
+            bail!("Invalid render time: {}", time);
+        }
+        options.render_time = Some(Duration::from_millis((time * 1000.0) as u64));
+    }
+    options.only_light = matches.value_of("only-light").map(|s| s.to_owned());
+    options.only_material = matches.value_of("only-material").map(|s| s.to_owned());
+    if let Some(pixel) = matches.value_of("debug-pixel") {
+        let pixel = parse_pixel(pixel)
+            .ok_or_else(|| format_err!("Invalid pixel coordinates: {}", pixel))?;
+        options.debug_pixel = Some(pixel);
+    }
+    if let Some(roi) = matches.value_of("roi") {
+        let roi = parse_roi(roi).ok_or_else(|| format_err!("Invalid region of interest: {}", roi))?;
+        options.roi = Some(roi);
+    }
+    options.base_image = matches.value_of("base").map(|s| s.to_owned());
+    options.shading_normals = !matches.is_present("no-shading-normals");
+    options.write_metadata = matches.is_present("write-metadata");
+    options.quiet = matches.is_present("quiet");
+    options.display = matches.is_present("display");
+    pbrt::parse_scene(filename, &options)?;
 
     Ok(())
 }
+
+/// Parse a resolution given as "WIDTHxHEIGHT"
+fn parse_resolution(s: &str) -> Option<Point2i> {
+    let mut dims = s.splitn(2, 'x').map(|d| d.trim().parse::<i32>().ok());
+    match (dims.next(), dims.next()) {
+        (Some(Some(x)), Some(Some(y))) if x > 0 && y > 0 => Some(Point2i::new(x, y)),
+        _ => None,
+    }
+}
+
+/// Parse pixel coordinates given as "X,Y"
+fn parse_pixel(s: &str) -> Option<Point2i> {
+    let mut coords = s.splitn(2, ',').map(|c| c.trim().parse::<i32>().ok());
+    match (coords.next(), coords.next()) {
+        (Some(Some(x)), Some(Some(y))) if x >= 0 && y >= 0 => Some(Point2i::new(x, y)),
+        _ => None,
+    }
+}
+
+/// Parse a region of interest given as "X0,Y0,X1,Y1"
+fn parse_roi(s: &str) -> Option<Bounds2i> {
+    let mut coords = s.split(',').map(|c| c.trim().parse::<i32>().ok());
+    match (coords.next(), coords.next(), coords.next(), coords.next(), coords.next()) {
+        (Some(Some(x0)), Some(Some(y0)), Some(Some(x1)), Some(Some(y1)), None)
+            if x0 >= 0 && y0 >= 0 && x0 < x1 && y0 < y1 => {
+            Some(Bounds2i::from_points(&Point2i::new(x0, y0), &Point2i::new(x1, y1)))
+        }
+        _ => None,
+    }
+}
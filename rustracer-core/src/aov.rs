@@ -0,0 +1,194 @@
+//! Arbitrary output variables: extra images written next to the rendered one, with quantities
+//! about the first surface seen through each pixel. They're useful for debugging and as inputs to
+//! denoisers.
+
+use std::path::Path;
+
+use light_arena::Allocator;
+
+use {clamp, Normal3f, Point2f, Vector3f};
+use bsdf::BxDFType;
+use material::TransportMode;
+use ray::Ray;
+use scene::Scene;
+use spectrum::Spectrum;
+
+/// Number of stratified directions per axis used to estimate the albedo of a surface.
+const ALBEDO_STRATA: usize = 4;
+
+/// An output the film can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// The rendered image
+    Beauty,
+    /// Shading normal, as (x, y, z)
+    Normal,
+    /// Hemispherical-directional reflectance of the surface
+    Albedo,
+    /// Distance from the camera
+    Depth,
+    /// Number of samples taken in each pixel
+    SampleCount,
+    /// Heatmap of the average number of rays traced by the paths of each pixel, from blue
+    /// (the fewest) to red (the most). Only the path integrator records them.
+    Bounces,
+}
+
+impl Aov {
+    pub fn from_name(name: &str) -> Option<Aov> {
+        match name {
+            "beauty" => Some(Aov::Beauty),
+            "normal" | "normals" => Some(Aov::Normal),
+            "albedo" => Some(Aov::Albedo),
+            "depth" => Some(Aov::Depth),
+            "samplecount" => Some(Aov::SampleCount),
+            "bounces" => Some(Aov::Bounces),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Aov::Beauty => "beauty",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Depth => "depth",
+            Aov::SampleCount => "samplecount",
+            Aov::Bounces => "bounces",
+        }
+    }
+
+    /// Whether the AOV needs the first hit of each camera ray (see `first_hit()`).
+    pub fn needs_first_hit(&self) -> bool {
+        match *self {
+            Aov::Normal | Aov::Albedo | Aov::Depth => true,
+            Aov::Beauty | Aov::SampleCount | Aov::Bounces => false,
+        }
+    }
+
+    /// Name of the file the AOV is written to, for an image named `image`:
+    /// `<basename>_<aov>.<ext>`.
+    pub fn filename(&self, image: &str) -> String {
+        let path = Path::new(image);
+        let mut name = format!("{}_{}",
+                               path.file_stem().map_or("".into(), |s| s.to_string_lossy()),
+                               self.name());
+        if let Some(ext) = path.extension() {
+            name.push('.');
+            name.push_str(&ext.to_string_lossy());
+        }
+        path.with_file_name(name).to_string_lossy().into_owned()
+    }
+}
+
+/// Colour of `t` in [0, 1] on a blue-green-red heatmap.
+pub fn heatmap(t: f32) -> [f32; 3] {
+    let t = clamp(t, 0.0, 1.0);
+    [clamp(2.0 * t - 1.0, 0.0, 1.0), 1.0 - (2.0 * t - 1.0).abs(), clamp(1.0 - 2.0 * t, 0.0, 1.0)]
+}
+
+/// What a camera ray sees at the first surface it hits.
+#[derive(Debug, Clone, Copy)]
+pub struct FirstHit {
+    pub normal: Normal3f,
+    pub albedo: Spectrum,
+    pub depth: f32,
+}
+
+/// Find the first surface `ray` hits and compute its `FirstHit` quantities.
+pub fn first_hit(scene: &Scene, ray: &Ray, arena: &Allocator) -> Option<FirstHit> {
+    let mut ray = *ray;
+    let mut isect = scene.intersect(&mut ray)?;
+    let depth = (isect.hit.p - ray.o).length();
+    isect.compute_scattering_functions(&ray, TransportMode::RADIANCE, true, arena);
+    let albedo = match isect.bsdf {
+        Some(ref bsdf) => {
+            let n = ALBEDO_STRATA;
+            let samples: Vec<_> = (0..n * n)
+                .map(|i| {
+                         Point2f::new(((i % n) as f32 + 0.5) / n as f32,
+                                      ((i / n) as f32 + 0.5) / n as f32)
+                     })
+                .collect();
+            bsdf.rho(&isect.hit.wo, &samples, BxDFType::all())
+        }
+        None => Spectrum::black(),
+    };
+
+    Some(FirstHit {
+             normal: isect.shading.n,
+             albedo,
+             depth,
+         })
+}
+
+/// Sums of the `FirstHit` quantities of the samples taken in a pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct FirstHitSums {
+    normal: Vector3f,
+    albedo: Spectrum,
+    depth: f32,
+    /// Number of samples that hit something
+    n_hits: u32,
+    /// Number of samples
+    n: u32,
+}
+
+impl Default for FirstHitSums {
+    fn default() -> Self {
+        FirstHitSums {
+            normal: Vector3f::new(0.0, 0.0, 0.0),
+            albedo: Spectrum::black(),
+            depth: 0.0,
+            n_hits: 0,
+            n: 0,
+        }
+    }
+}
+
+impl FirstHitSums {
+    pub fn add(&mut self, hit: Option<&FirstHit>) {
+        self.n += 1;
+        if let Some(hit) = hit {
+            self.normal += Vector3f::from(hit.normal);
+            self.albedo += hit.albedo;
+            self.depth += hit.depth;
+            self.n_hits += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &FirstHitSums) {
+        self.normal += other.normal;
+        self.albedo += other.albedo;
+        self.depth += other.depth;
+        self.n_hits += other.n_hits;
+        self.n += other.n;
+    }
+
+    /// Average normal over the samples, or zero if none hit anything
+    pub fn normal(&self) -> Vector3f {
+        if self.normal.length_squared() == 0.0 {
+            Vector3f::new(0.0, 0.0, 0.0)
+        } else {
+            self.normal.normalize()
+        }
+    }
+
+    /// Average albedo, counting samples that didn't hit anything as black
+    pub fn albedo(&self) -> Spectrum {
+        if self.n == 0 {
+            Spectrum::black()
+        } else {
+            self.albedo / self.n as f32
+        }
+    }
+
+    /// Average depth of the samples that hit something, or infinity if none did
+    pub fn depth(&self) -> f32 {
+        if self.n_hits == 0 {
+            ::std::f32::INFINITY
+        } else {
+            self.depth / self.n_hits as f32
+        }
+    }
+}
@@ -1,42 +1,134 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
 
 use failure::{err_msg, Error};
 use indicatif::HumanDuration;
 
-use {Point3f, Transform, Vector3f};
+use num_cpus;
+
+use {Point2i, Point3f, Transform, Vector3f};
+use bounds::Bounds2i;
 use bvh::BVH;
 use camera::{Camera, PerspectiveCamera};
-use display::NoopDisplayUpdater;
+use display::{self, NoopDisplayUpdater};
 use filter::{BoxFilter, Filter, GaussianFilter, MitchellNetravali, TriangleFilter};
 use film::Film;
 use geometry::Matrix4x4;
-use light::{AreaLight, DiffuseAreaLight, DistantLight, InfiniteAreaLight, Light, PointLight};
-use integrator::{DirectLightingIntegrator, Normal, PathIntegrator, SamplerIntegrator, Whitted};
+use light::{AreaLight, DiffuseAreaLight, DistantLight, InfiniteAreaLight, Light, PointLight,
+            SpotLight};
+use integrator::{AmbientOcclusion, DirectLightingIntegrator, Normal, PathIntegrator,
+                 SamplerIntegrator, Whitted};
 use material::{DisneyMaterial, GlassMaterial, Material, MatteMaterial, Metal, MirrorMaterial,
-               Plastic, SubstrateMaterial, TranslucentMaterial, UberMaterial};
+               PbrMetallicRoughness, Plastic, SubstrateMaterial, TranslucentMaterial,
+               UberMaterial};
+use metadata::RenderMetadata;
 use paramset::{ParamSet, TextureParams};
 use primitive::{GeometricPrimitive, TransformedPrimitive, Primitive};
-use renderer;
+use renderer::{self, RenderStatus};
 use sampler::Sampler;
+use sampler::random::RandomSampler;
 use sampler::zerotwosequence::ZeroTwoSequence;
 use scene::Scene;
-use shapes::{Cylinder, Disk, Shape, Sphere, TriangleMesh};
+use shapes::{Cylinder, Disk, Plane, Rectangle, Shape, Sphere, TriangleMesh};
 use shapes::plymesh;
 use spectrum::Spectrum;
 use stats;
 use texture::{CheckerboardTexture, ConstantTexture, ImageTexture, ScaleTexture, Texture,
-              UVTexture, FbmTexture};
+              UVTexture, FbmTexture, VertexColorTexture};
 
 
 stat_counter!("Scene/Materials created", n_materials_created);
 stat_counter!("Scene/Object instances created", n_object_instances_created);
 stat_counter!("Scene/Object instances used", n_object_instances_used);
+stat_counter!("Scene/Unique primitives in object instances", n_instance_primitives);
+stat_counter!("Scene/Primitives referenced through instances", n_instanced_primitives);
+stat_memory_counter!("Memory/Object instance transforms", instance_transform_bytes);
 pub fn init_stats() {
     n_materials_created::init();
     n_object_instances_created::init();
     n_object_instances_used::init();
+    n_instance_primitives::init();
+    n_instanced_primitives::init();
+    instance_transform_bytes::init();
+}
+
+/// Size of the square image tiles handed out to the rendering threads
+const TILE_SIZE: i32 = 16;
+
+/// Global rendering options, that aren't part of the scene description (usually set from the
+/// command line).
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Number of threads to render with
+    pub num_threads: usize,
+    /// Output file name, overriding the one in the scene description
+    pub image_file: Option<String>,
+    /// Render a quick preview: reduce the resolution and only take one sample per pixel
+    pub quick_render: bool,
+    /// Film resolution, overriding the one in the scene description. `resolution_scale` and
+    /// `quick_render` still apply on top of it.
+    pub resolution: Option<Point2i>,
+    /// Factor applied to the film resolution
+    pub resolution_scale: f32,
+    /// Number of samples per pixel, overriding the sampler's parameters
+    pub spp_override: Option<u32>,
+    /// Don't print progress or statistics
+    pub quiet: bool,
+    /// Seed for the samplers' random number generators. Renders with the same seed are identical.
+    pub seed: u64,
+    /// Number of rendering passes, overriding the integrator's "passes" parameter
+    pub passes: Option<u32>,
+    /// Equal-time rendering: keep rendering passes until this much time has passed, instead of
+    /// a fixed number of them. The first pass always completes, so every pixel gets samples.
+    pub render_time: Option<Duration>,
+    /// Show the image in a window while it renders, with a camera that can be moved around
+    pub display: bool,
+    /// Debugging aid: only render the lights with this name (given by their "name" parameter)
+    pub only_light: Option<String>,
+    /// Debugging aid: render every material but the one with this name black. Named materials
+    /// go by their name, the others by their type.
+    pub only_material: Option<String>,
+    /// Debugging aid: log what happens to the samples of this pixel (rays, intersections, BSDF
+    /// samples and light contributions)
+    pub debug_pixel: Option<Point2i>,
+    /// Use the shading normals of the surfaces (e.g. interpolated from the vertex normals of
+    /// meshes). Turning them off makes the whole shading use the geometric normal instead, to
+    /// tell whether artifacts come from them. Bump maps still perturb the geometric normal.
+    pub shading_normals: bool,
+    /// Write a JSON sidecar file describing the render next to the output image
+    pub write_metadata: bool,
+    /// Only render this region of the image, in pixel coordinates, e.g. to fix a small area of a
+    /// previous render
+    pub roi: Option<Bounds2i>,
+    /// Image to take the pixels outside `roi` from, instead of leaving them black
+    pub base_image: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            num_threads: num_cpus::get(),
+            image_file: None,
+            quick_render: false,
+            resolution: None,
+            resolution_scale: 1.0,
+            spp_override: None,
+            quiet: false,
+            seed: 0,
+            passes: None,
+            render_time: None,
+            display: false,
+            only_light: None,
+            only_material: None,
+            debug_pixel: None,
+            shading_normals: true,
+            write_metadata: false,
+            roi: None,
+            base_image: None,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -166,8 +258,21 @@ pub struct RenderOptions {
     camera_to_world: Transform,
     lights: Vec<Arc<Light>>,
     primitives: Vec<Arc<Primitive>>,
-    instances: HashMap<String, Vec<Arc<Primitive>>>,
-    current_instance: Option<String>,
+    /// Number of shapes of each type created so far, for `Scene::print_summary()`
+    shape_counts: BTreeMap<String, usize>,
+    instances: HashMap<String, InstanceDefinition>,
+    /// Names of the object instances currently being defined. Object definitions can be nested,
+    /// in which case the innermost one is at the end.
+    instance_stack: Vec<String>,
+}
+
+/// Primitives making up a named object instance. The list gets collapsed into a single aggregate
+/// the first time the instance is used, so we keep track of the original number of primitives
+/// separately for the stats.
+#[derive(Default)]
+struct InstanceDefinition {
+    primitives: Vec<Arc<Primitive>>,
+    n_primitives: usize,
 }
 
 impl RenderOptions {
@@ -184,21 +289,34 @@ impl RenderOptions {
         Ok(filter)
     }
 
-    pub fn make_film(&mut self, filter: Box<Filter>) -> Result<Box<Film>, Error> {
+    pub fn make_film(&mut self,
+                     filter: Box<Filter>,
+                     options: &Options)
+                     -> Result<Box<Film>, Error> {
         debug!("Making film");
-        let film = if self.film_name == "image" {
-            Film::create(&mut self.film_params, filter)
+        let mut film = if self.film_name == "image" {
+            Film::create(&mut self.film_params, filter, options)
         } else {
             bail!("Film \"{}\" unknown.", self.film_name);
         };
+        if let Some(ref base_image) = options.base_image {
+            if film.roi.is_none() {
+                warn!("A base image is only used with a region of interest, ignoring {}",
+                      base_image);
+            } else {
+                film.load_base_image(base_image)?;
+            }
+        }
 
         Ok(film)
     }
 
-    pub fn make_sampler(&mut self) -> Result<Box<Sampler>, Error> {
+    pub fn make_sampler(&mut self, options: &Options) -> Result<Box<Sampler>, Error> {
         let sampler = if self.sampler_name == "lowdiscrepancy" ||
                          self.sampler_name == "02sequence" {
-            ZeroTwoSequence::create(&mut self.sampler_params)
+            ZeroTwoSequence::create(&mut self.sampler_params, options)
+        } else if self.sampler_name == "random" {
+            RandomSampler::create(&mut self.sampler_params, options)
         } else {
             bail!("Sampler \"{}\" unknown.", self.sampler_name);
         };
@@ -206,10 +324,10 @@ impl RenderOptions {
         Ok(sampler)
     }
 
-    pub fn make_camera(&mut self) -> Result<Box<Camera>, Error> {
+    pub fn make_camera(&mut self, options: &Options) -> Result<Box<Camera>, Error> {
         debug!("Making camera");
         let filter = self.make_filter()?;
-        let film = self.make_film(filter)?;
+        let film = self.make_film(filter, options)?;
 
         let camera = if self.camera_name == "perspective" {
             PerspectiveCamera::create(&mut self.camera_params, &self.camera_to_world, film)
@@ -232,8 +350,10 @@ impl RenderOptions {
                 DirectLightingIntegrator::create(&mut self.integrator_params)
             } else if self.integrator_name == "path" {
                 PathIntegrator::create(&mut self.integrator_params, camera)
+            } else if self.integrator_name == "ambientocclusion" {
+                AmbientOcclusion::create(&mut self.integrator_params)
             } else if self.integrator_name == "normal" {
-                Box::new(Normal::default())
+                Normal::create(&mut self.integrator_params)
             } else {
                 bail!("Integrator \"{}\" unknown.", self.integrator_name);
             };
@@ -241,14 +361,28 @@ impl RenderOptions {
         Ok(integrator)
     }
 
-    pub fn make_scene(&mut self) -> Result<Arc<Scene>, Error> {
+    pub fn make_scene(&mut self, options: &Options) -> Result<Arc<Scene>, Error> {
         info!("Making scene with {} primitives and {} lights",
               self.primitives.len(),
               self.lights.len());
         let accelerator = make_accelerator(&self.accelerator_name,
                                            &self.primitives,
                                            &mut self.accelerator_params);
-        Ok(Arc::new(Scene::new(accelerator, self.lights.clone())))
+        let mut scene = Scene::new(accelerator, self.lights.clone());
+        scene.shadow_cache = self.accelerator_params.find_one_bool("shadowcache", false);
+        let ray_epsilon = self.accelerator_params
+            .find_one_float("rayepsilon", scene.ray_epsilon);
+        if ray_epsilon >= 0.0 {
+            scene.ray_epsilon = ray_epsilon;
+        } else {
+            warn!("\"rayepsilon\" can't be negative, ignoring it");
+        }
+        scene.shape_counts = self.shape_counts.clone();
+        scene.shading_normals = options.shading_normals;
+        // Not part of pbrt's format: the colour of rays escaping a scene without infinite lights
+        scene.background = self.integrator_params
+            .find_one_spectrum("background", Spectrum::black());
+        Ok(Arc::new(scene))
     }
 }
 
@@ -275,7 +409,7 @@ impl Default for RenderOptions {
             film_params: ParamSet::default(),
             filter_name: "box".to_owned(),
             filter_params: ParamSet::default(),
-            sampler_name: "halton".to_owned(),
+            sampler_name: "02sequence".to_owned(),
             sampler_params: ParamSet::default(),
             accelerator_name: "bvh".to_owned(),
             accelerator_params: ParamSet::default(),
@@ -286,8 +420,9 @@ impl Default for RenderOptions {
             camera_to_world: Transform::default(),
             lights: Vec::new(),
             primitives: Vec::new(),
+            shape_counts: BTreeMap::new(),
             instances: HashMap::new(),
-            current_instance: None,
+            instance_stack: Vec::new(),
         }
     }
 }
@@ -298,7 +433,7 @@ pub struct GraphicsState {
     spectrum_textures: HashMap<String, Arc<Texture<Spectrum>>>,
     material_param: ParamSet,
     material: String,
-    named_material: HashMap<String, Arc<Material>>,
+    named_material: HashMap<String, Option<Arc<Material>>>,
     current_named_material: String,
     area_light_params: ParamSet,
     area_light: String,
@@ -306,7 +441,16 @@ pub struct GraphicsState {
 }
 
 impl GraphicsState {
-    pub fn create_material(&mut self, params: &mut ParamSet) -> Arc<Material> {
+    /// Name of the current material: its name if it is a named material, its type otherwise.
+    fn material_name(&self) -> &str {
+        if self.current_named_material.is_empty() {
+            &self.material
+        } else {
+            &self.current_named_material
+        }
+    }
+
+    pub fn create_material(&mut self, params: &mut ParamSet) -> Option<Arc<Material>> {
         let mut mp = TextureParams::new(params,
                                         &mut self.material_param,
                                         &self.float_textures,
@@ -365,12 +509,20 @@ impl State {
         self.pushed_transforms.push(t);
     }
 
+    /// Pop the last saved graphics state. If there is none, the current one is kept.
     pub fn restore_graphics_state(&mut self) {
-        self.graphics_state = self.pushed_graphics_states.pop().unwrap();
+        match self.pushed_graphics_states.pop() {
+            Some(gs) => self.graphics_state = gs,
+            None => error!("No graphics state to restore. Keeping the current one."),
+        }
     }
 
+    /// Pop the last saved transformation. If there is none, the current one is kept.
     pub fn restore_transform(&mut self) {
-        self.cur_transform = self.pushed_transforms.pop().unwrap();
+        match self.pushed_transforms.pop() {
+            Some(t) => self.cur_transform = t,
+            None => error!("No transformation to restore. Keeping the current one."),
+        }
     }
 }
 
@@ -469,9 +621,17 @@ pub trait Api {
 #[derive(Default)]
 pub struct RealApi {
     state: RefCell<State>,
+    options: Options,
 }
 
 impl RealApi {
+    pub fn new(options: Options) -> RealApi {
+        RealApi {
+            state: RefCell::new(State::default()),
+            options,
+        }
+    }
+
     fn make_light(&self,
                   name: &str,
                   param_set: &mut ParamSet,
@@ -480,6 +640,9 @@ impl RealApi {
         if name == "point" {
             let light = PointLight::create(light_2_world, param_set);
             Ok(light)
+        } else if name == "spot" {
+            let light = SpotLight::create(light_2_world, param_set);
+            Ok(light)
         } else if name == "distant" {
             let light = DistantLight::create(light_2_world, param_set);
             Ok(light)
@@ -491,6 +654,24 @@ impl RealApi {
             Err(err_msg("Unsupported light type"))
         }
     }
+
+    /// Whether the light with the given parameters takes part in the render, i.e. whether it is
+    /// the one selected with `Options::only_light`, if any.
+    fn is_light_enabled(&self, params: &mut ParamSet) -> bool {
+        match self.options.only_light {
+            Some(ref only) => params.find_one_string("name", String::new()) == *only,
+            None => true,
+        }
+    }
+
+    /// Whether the current material should be rendered, rather than replaced by a black one
+    /// because another was selected with `Options::only_material`.
+    fn is_material_enabled(&self, graphics_state: &GraphicsState) -> bool {
+        match self.options.only_material {
+            Some(ref only) => graphics_state.material_name() == only,
+            None => true,
+        }
+    }
 }
 
 impl Api for RealApi {
@@ -786,6 +967,10 @@ impl Api for RealApi {
         Ok(())
     }
 
+    /// Named textures referenced by the parameters are looked up once, when the texture is
+    /// created, so a texture can only refer to textures defined before it. A texture referring to
+    /// its own name therefore gets the previous texture of that name, or the default value if there
+    /// is none: cycles between textures can't be created, and evaluation always terminates.
     fn texture(&self,
                name: String,
                typ: String,
@@ -883,6 +1068,9 @@ impl Api for RealApi {
         debug!("NamedMaterial called with {}", name);
         let mut state = self.state.borrow_mut();
         state.api_state.verify_world()?;
+        if !state.graphics_state.named_material.contains_key(&name) {
+            warn!("NamedMaterial \"{}\" unknown. Using matte material instead.", name);
+        }
         state.graphics_state.current_named_material = name;
         Ok(())
     }
@@ -891,6 +1079,10 @@ impl Api for RealApi {
         debug!("Lightsource called with {}", name);
         let mut state = self.state.borrow_mut();
         state.api_state.verify_world()?;
+        if !self.is_light_enabled(params) {
+            info!("Skipping {} light not selected with --only-light", name);
+            return Ok(());
+        }
         let lt = self.make_light(&name, params, &state.cur_transform)?;
         state.render_options.lights.push(lt);
         Ok(())
@@ -916,19 +1108,41 @@ impl Api for RealApi {
                                  &state.cur_transform.inverse(),
                                  state.graphics_state.reverse_orientation,
                                  params,
-                                 &state.graphics_state);
-        let mat = if !shapes.is_empty() {
-            Some(state.graphics_state.create_material(params))
-        } else {
+                                 &state.graphics_state)
+            .unwrap_or_else(|e| {
+                // Carry on without this shape rather than aborting the whole render
+                error!("Failed to create shape \"{}\": {}", name, e);
+                Vec::new()
+            });
+        let mat = if shapes.is_empty() {
             None
+        } else if self.is_material_enabled(&state.graphics_state) {
+            state.graphics_state.create_material(params)
+        } else {
+            Some(MatteMaterial::black())
+        };
+        let area_light_enabled = {
+            let mut ps = state.graphics_state.area_light_params.clone();
+            state.graphics_state.area_light != "" && self.is_light_enabled(&mut ps)
         };
         for s in shapes {
-            let area = if state.graphics_state.area_light != "" {
+            *state
+                 .render_options
+                 .shape_counts
+                 .entry(s.name().to_owned())
+                 .or_insert(0) += 1;
+            let area = if area_light_enabled {
+                if !s.area().is_finite() {
+                    bail!("Area light \"{}\" can't be attached to shape \"{}\" with infinite area",
+                          state.graphics_state.area_light,
+                          name);
+                }
                 let mut ps = state.graphics_state.area_light_params.clone();
                 let (area_light, light) = make_area_light(&state.graphics_state.area_light,
                                                           &state.cur_transform,
                                                           &mut ps,
-                                                          Arc::clone(&s))?;
+                                                          Arc::clone(&s),
+                                                          &state.graphics_state.spectrum_textures)?;
                 area_lights.push(light);
                 Some(area_light)
             } else {
@@ -941,11 +1155,12 @@ impl Api for RealApi {
                                                               });
             prims.push(prim);
         }
-        if let Some(name) = &state.render_options.current_instance {
-            let mut inst = state.render_options.instances
+        let state = &mut *state;
+        if let Some(name) = state.render_options.instance_stack.last() {
+            let inst = state.render_options.instances
                 .get_mut(name)
                 .ok_or(format_err!("Unable to find instance named {}", name))?;
-            inst.append(&mut prims);
+            inst.primitives.append(&mut prims);
         } else {
             state.render_options.primitives.append(&mut prims);
             state.render_options.lights.append(&mut area_lights);
@@ -977,24 +1192,62 @@ impl Api for RealApi {
             let _ = state.pushed_transforms.pop();
         }
 
-        let camera = state.render_options.make_camera()?;
+        let mut camera = state.render_options.make_camera(&self.options)?;
+        let mut options = self.options.clone();
+        if options.passes.is_none() {
+            let passes = state.render_options.integrator_params.find_one_int("passes", 1);
+            options.passes = Some(i32::max(1, passes) as u32);
+        }
         let mut integrator = state.render_options.make_integrator(&*camera)?;
-        let mut sampler = state.render_options.make_sampler()?;
-        let scene = state.render_options.make_scene()?;
+        let mut sampler = state.render_options.make_sampler(&self.options)?;
+        let scene = state.render_options.make_scene(&self.options)?;
+        scene.print_summary();
+
+        if self.options.display {
+            // Take a single sample per pixel in each pass, so the image refines progressively
+            // and moving the camera gives a quick preview
+            options.passes = Some(options.passes.unwrap_or(1) * sampler.spp() as u32);
+            options.spp_override = Some(1);
+            let mut sampler = state.render_options.make_sampler(&options)?;
+            return display::render_interactive(scene,
+                                               &mut *integrator,
+                                               &mut *camera,
+                                               &options,
+                                               &mut sampler,
+                                               TILE_SIZE);
+        }
 
         // TODO finish
         let start_time = ::std::time::Instant::now();
-        renderer::render(scene,
-                         &mut *integrator,
-                         &*camera,
-                         8,
-                         &mut sampler,
-                         16,
-                         Box::new(NoopDisplayUpdater {}))?;
+        let status = renderer::render(scene,
+                                      &mut *integrator,
+                                      &*camera,
+                                      &options,
+                                      &mut sampler,
+                                      TILE_SIZE,
+                                      Box::new(NoopDisplayUpdater {}),
+                                      None,
+                                      None)?;
         stats::report_stats();
         let duration = start_time.elapsed();
-        println!("Render time: {}", HumanDuration(duration));
-        stats::print_stats();
+        if !self.options.quiet {
+            println!("Render time: {}", HumanDuration(duration));
+            stats::print_stats();
+        }
+        if let (true, RenderStatus::Completed { passes }) = (self.options.write_metadata, status) {
+            let film = camera.get_film();
+            let metadata = RenderMetadata {
+                image: &film.filename,
+                resolution: film.full_resolution,
+                spp: sampler.spp(),
+                passes: passes,
+                integrator: &state.render_options.integrator_name,
+                sampler: sampler.name(),
+                render_time: duration,
+            };
+            let path = metadata.write()?;
+            info!("Wrote render metadata to {}", path.display());
+        }
 
         Ok(())
     }
@@ -1007,11 +1260,11 @@ impl Api for RealApi {
         let mut state = self.state.borrow_mut();
         state.api_state.verify_world()?;
 
-        if state.render_options.current_instance.is_some() {
-            return Err(err_msg("ObjectBegin called inside of instance definition"));
+        if state.render_options.instance_stack.contains(&name) {
+            bail!("ObjectBegin \"{}\" called inside its own definition", name);
         }
-        state.render_options.current_instance = Some(name.to_owned());
-        state.render_options.instances.insert(name, Vec::new());
+        state.render_options.instance_stack.push(name.to_owned());
+        state.render_options.instances.insert(name, InstanceDefinition::default());
 
         Ok(())
     }
@@ -1022,10 +1275,16 @@ impl Api for RealApi {
             let mut state = self.state.borrow_mut();
             state.api_state.verify_world()?;
 
-            if state.render_options.current_instance.is_none() {
-                return Err(err_msg("ObjectEnd called outside of instance definition "));
+            let name = state.render_options
+                .instance_stack
+                .pop()
+                .ok_or(err_msg("ObjectEnd called outside of instance definition "))?;
+            let inst = state.render_options.instances.get_mut(&name).unwrap();
+            inst.n_primitives = inst.primitives.len();
+            if inst.n_primitives == 0 {
+                warn!("Object instance \"{}\" doesn't contain any primitives", name);
             }
-            state.render_options.current_instance = None;
+            n_instance_primitives::add(inst.n_primitives as u64);
         }
         self.attribute_end()?;
         n_object_instances_created::inc();
@@ -1038,32 +1297,47 @@ impl Api for RealApi {
         let mut state = self.state.borrow_mut();
         state.api_state.verify_world()?;
 
-        if state.render_options.current_instance.is_some() {
-            return Err(err_msg("ObjectInstance called inside of instance definition"));
+        // An instance can only be used once it's been fully defined, which also prevents any
+        // reference cycles between nested instances.
+        if state.render_options.instance_stack.contains(&name) {
+            bail!("ObjectInstance \"{}\" used before its ObjectEnd", name);
         }
-        let inst = state
-            .render_options
-            .instances
-            .get_mut(&name)
-            .ok_or(format_err!("Unable to find instance named {}", name))?;
-        if inst.is_empty() {
+        let state = &mut *state;
+        let inst = match state.render_options.instances.get_mut(&name) {
+            Some(inst) => inst,
+            None => bail!("Unable to find instance named \"{}\"", name),
+        };
+        if inst.primitives.is_empty() {
+            warn!("Object instance \"{}\" is empty, ignoring it", name);
             return Ok(());
         }
         n_object_instances_used::inc();
+        n_instanced_primitives::add(inst.n_primitives as u64);
 
-        if inst.len() > 1 {
+        if inst.primitives.len() > 1 {
             // Create aggregate for instance primitives
             let accel = make_accelerator(&state.render_options.accelerator_name,
-                                         &inst,
+                                         &inst.primitives,
                                          &mut state.render_options.accelerator_params);
-            inst.clear();
-            inst.push(accel);
+            inst.primitives.clear();
+            inst.primitives.push(accel);
         }
-        let prim = Arc::new(TransformedPrimitive {
-            primitive: inst.get(0).unwrap().clone(),
+        let prim: Arc<Primitive> = Arc::new(TransformedPrimitive {
+            primitive: Arc::clone(&inst.primitives[0]),
             primitive_to_world: state.cur_transform.clone(),
         });
-        state.render_options.primitives.push(prim);
+        instance_transform_bytes::add(::std::mem::size_of::<TransformedPrimitive>() as u64);
+        // When used inside another object definition, the instance becomes part of that object
+        if let Some(parent) = state.render_options.instance_stack.last() {
+            state.render_options
+                .instances
+                .get_mut(parent)
+                .ok_or(format_err!("Unable to find instance named {}", parent))?
+                .primitives
+                .push(prim);
+        } else {
+            state.render_options.primitives.push(prim);
+        }
 
         Ok(())
     }
@@ -1075,7 +1349,7 @@ fn make_shapes(name: &str,
                reverse_orientation: bool,
                ps: &mut ParamSet,
                graphics_state: &GraphicsState)
-               -> Vec<Arc<Shape>> {
+               -> Result<Vec<Arc<Shape>>, Error> {
     let mut shapes: Vec<Arc<Shape>> = Vec::new();
     if name == "sphere" {
         shapes.push(Sphere::create(object2world, reverse_orientation, ps));
@@ -1083,14 +1357,18 @@ fn make_shapes(name: &str,
         shapes.push(Cylinder::create(object2world, reverse_orientation, ps));
     } else if name == "disk" {
         shapes.push(Disk::create(object2world, reverse_orientation, ps));
+    } else if name == "rectangle" {
+        shapes.push(Rectangle::create(object2world, reverse_orientation, ps));
+    } else if name == "plane" {
+        shapes.push(Plane::create(object2world, reverse_orientation, ps));
     } else if name == "cone" {
-        unimplemented!();
+        bail!("Shape \"cone\" is not supported yet");
     } else if name == "paraboloid" {
-        unimplemented!();
+        bail!("Shape \"paraboloid\" is not supported yet");
     } else if name == "hyperboloid" {
-        unimplemented!();
+        bail!("Shape \"hyperboloid\" is not supported yet");
     } else if name == "curve" {
-        unimplemented!();
+        bail!("Shape \"curve\" is not supported yet");
     } else if name == "trianglemesh" {
         let mut tris = TriangleMesh::create(object2world,
                                             world2object,
@@ -1103,18 +1381,24 @@ fn make_shapes(name: &str,
                                        world2object,
                                        reverse_orientation,
                                        ps,
-                                       &graphics_state.float_textures);
+                                       &graphics_state.float_textures)?;
         shapes.append(&mut tris);
     } else {
         warn!("Unknown shape {}", name);
     }
 
-    shapes
+    Ok(shapes)
 }
 
-fn make_material(name: &str, mp: &mut TextureParams) -> Arc<Material> {
+/// Create the material of the given type. The "none" material has no BSDF at all: rays go
+/// straight through the surface, which is useful for invisible proxy geometry such as the
+/// boundaries of participating media.
+fn make_material(name: &str, mp: &mut TextureParams) -> Option<Arc<Material>> {
+    if name == "none" {
+        return None;
+    }
     n_materials_created::inc();
-    if name == "matte" {
+    let material = if name == "matte" {
         MatteMaterial::create(mp)
     } else if name == "plastic" {
         Plastic::create(mp)
@@ -1132,19 +1416,23 @@ fn make_material(name: &str, mp: &mut TextureParams) -> Arc<Material> {
         UberMaterial::create(mp)
     } else if name == "disney" {
         DisneyMaterial::create(mp)
+    } else if name == "pbrmetallicroughness" {
+        PbrMetallicRoughness::create(mp)
     } else {
         warn!("Unknown material {}. Using matte.", name);
         MatteMaterial::create(mp)
-    }
+    };
+    Some(material)
 }
 
 fn make_area_light(name: &str,
                    light2world: &Transform,
                    params: &mut ParamSet,
-                   shape: Arc<Shape>)
+                   shape: Arc<Shape>,
+                   spectrum_textures: &HashMap<String, Arc<Texture<Spectrum>>>)
                    -> Result<(Arc<AreaLight>, Arc<Light>), Error> {
     if name == "area" || name == "diffuse" {
-        let l = DiffuseAreaLight::create(light2world, params, shape);
+        let l = DiffuseAreaLight::create(light2world, params, shape, spectrum_textures);
         let light: Arc<Light> = l.clone();
         let area_light: Arc<AreaLight> = l.clone();
         Ok((area_light, light))
@@ -1160,7 +1448,7 @@ fn make_float_texture(name: &str,
     let tex: Arc<Texture<f32>> = if name == "constant" {
         Arc::new(ConstantTexture::create_float(transform, tp))
     } else if name == "scale" {
-        Arc::new(ScaleTexture::<f32>::create(tp))
+        Arc::new(ScaleTexture::create_float(tp))
     } else if name == "imagemap" {
         Arc::new(ImageTexture::<f32>::create(transform, tp))
     } else if name == "fbm" {
@@ -1179,7 +1467,7 @@ fn make_spectrum_texture(name: &str,
     let tex: Arc<Texture<Spectrum>> = if name == "constant" {
         Arc::new(ConstantTexture::create_spectrum(transform, tp))
     } else if name == "scale" {
-        Arc::new(ScaleTexture::<Spectrum>::create(tp))
+        ScaleTexture::create_spectrum(tp)
     } else if name == "mix" {
         unimplemented!()
     } else if name == "bilerp" {
@@ -1189,7 +1477,9 @@ fn make_spectrum_texture(name: &str,
     } else if name == "uv" {
         Arc::new(UVTexture::create_spectrum(transform, tp))
     } else if name == "checkerboard" {
-        Arc::new(CheckerboardTexture::create_spectrum(transform, tp))
+        CheckerboardTexture::create_spectrum(transform, tp)
+    } else if name == "vertexcolor" {
+        Arc::new(VertexColorTexture::create_spectrum(tp))
     } else if name == "dots" {
         unimplemented!()
     } else if name == "fbm" {
@@ -1208,3 +1498,520 @@ fn make_spectrum_texture(name: &str,
 
     Ok(tex)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use light_arena::MemoryArena;
+
+    use Point2f;
+    use camera::CameraSample;
+    use material::TransportMode;
+    use ray::Ray;
+
+    fn triangle_params() -> ParamSet {
+        let mut ps = ParamSet::default();
+        ps.init(vec![ParamListEntry::new(ParamType::Int,
+                                         "indices".to_owned(),
+                                         Array::NumArray(vec![0.0, 1.0, 2.0])),
+                     ParamListEntry::new(ParamType::Point3,
+                                         "P".to_owned(),
+                                         Array::NumArray(vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0,
+                                                              0.0, 1.0, 0.0]))]);
+        ps
+    }
+
+    #[test]
+    fn test_instances_share_primitives() {
+        ::init_stats();
+        let api = RealApi::default();
+        api.init().unwrap();
+        api.world_begin().unwrap();
+        api.object_begin("tri".to_owned()).unwrap();
+        api.shape("trianglemesh".to_owned(), &mut triangle_params())
+            .unwrap();
+        api.shape("trianglemesh".to_owned(), &mut triangle_params())
+            .unwrap();
+        api.object_end().unwrap();
+        let one_copy = stats::thread_stats();
+        for i in 0..1000 {
+            api.attribute_begin().unwrap();
+            api.translate(i as f32, 0.0, 0.0).unwrap();
+            api.object_instance("tri".to_owned()).unwrap();
+            api.attribute_end().unwrap();
+        }
+
+        let state = api.state.borrow();
+        assert_eq!(state.render_options.primitives.len(), 1000);
+        // Only counts from this thread, as other tests may be creating meshes and instances
+        let instanced = stats::thread_stats();
+        assert_eq!(instanced.counter("Scene/Unique primitives in object instances"), 2);
+        assert_eq!(instanced.counter("Scene/Primitives referenced through instances"),
+                   2000);
+        // The geometry isn't copied: the instances only add a transform each
+        assert!(one_copy.memory_counter("Memory/Triangle meshes") > 0);
+        assert_eq!(instanced.memory_counter("Memory/Triangle meshes"),
+                   one_copy.memory_counter("Memory/Triangle meshes"));
+        assert_eq!(instanced.memory_counter("Memory/Object instance transforms"),
+                   1000 * ::std::mem::size_of::<TransformedPrimitive>() as u64);
+    }
+
+    #[test]
+    fn test_scene_summary() {
+        ::init_stats();
+        let api = RealApi::default();
+        api.init().unwrap();
+        api.world_begin().unwrap();
+        api.object_begin("tri".to_owned()).unwrap();
+        api.shape("trianglemesh".to_owned(), &mut triangle_params())
+            .unwrap();
+        api.shape("trianglemesh".to_owned(), &mut triangle_params())
+            .unwrap();
+        api.object_end().unwrap();
+        for i in 0..5 {
+            api.attribute_begin().unwrap();
+            api.translate(i as f32, 0.0, 0.0).unwrap();
+            api.object_instance("tri".to_owned()).unwrap();
+            api.attribute_end().unwrap();
+        }
+        api.attribute_begin().unwrap();
+        api.arealightsource("diffuse".to_owned(), &mut ParamSet::default())
+            .unwrap();
+        api.translate(0.0, 0.0, 5.0).unwrap();
+        let mut disk_params = ParamSet::default();
+        disk_params.init(vec![ParamListEntry::new(ParamType::Float,
+                                                  "radius".to_owned(),
+                                                  Array::NumArray(vec![2.0]))]);
+        api.shape("disk".to_owned(), &mut disk_params).unwrap();
+        api.attribute_end().unwrap();
+        api.shape("sphere".to_owned(), &mut ParamSet::default())
+            .unwrap();
+        api.lightsource("point".to_owned(),
+                         &mut point_light_params("key", [0.0, 0.0, -5.0]))
+            .unwrap();
+        let scene = api.state.borrow_mut().render_options.make_scene(&Options::default()).unwrap();
+
+        let summary = scene.summary();
+        // The instanced triangles only count once
+        let shapes: Vec<_> = summary.shapes.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        assert_eq!(shapes, vec![("Disk", 1), ("Sphere", 1), ("Triangle", 2)]);
+        let lights: Vec<_> = summary.lights.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        assert_eq!(lights, vec![("DiffuseAreaLight", 1), ("PointLight", 1)]);
+        assert!((summary.emissive_area - 4.0 * ::std::f32::consts::PI).abs() < 1e-4);
+        assert_eq!(summary.world_bounds.p_min, Point3f::new(-2.0, -2.0, -1.0));
+        assert_eq!(summary.world_bounds.p_max, Point3f::new(5.0, 2.0, 5.0));
+        scene.print_summary();
+    }
+
+    #[test]
+    fn test_area_light_on_plane_is_an_error() {
+        ::init_stats();
+        let api = RealApi::default();
+        api.init().unwrap();
+        api.world_begin().unwrap();
+        api.attribute_begin().unwrap();
+        api.arealightsource("diffuse".to_owned(), &mut ParamSet::default())
+            .unwrap();
+        assert!(api.shape("plane".to_owned(), &mut ParamSet::default())
+                    .is_err());
+        api.attribute_end().unwrap();
+        assert!(api.shape("plane".to_owned(), &mut ParamSet::default())
+                    .is_ok());
+        let state = api.state.borrow();
+        assert!(state.render_options.lights.is_empty());
+        assert_eq!(state.render_options.primitives.len(), 1);
+    }
+
+    #[test]
+    fn test_instance_errors() {
+        ::init_stats();
+        let api = RealApi::default();
+        api.init().unwrap();
+        api.world_begin().unwrap();
+        assert!(api.object_instance("undefined".to_owned()).is_err());
+        api.object_begin("open".to_owned()).unwrap();
+        assert!(api.object_instance("open".to_owned()).is_err());
+        assert!(api.object_begin("open".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_unbalanced_scene_renders_what_it_can() {
+        ::init_stats();
+        let api = RealApi::default();
+        api.init().unwrap();
+        api.world_begin().unwrap();
+        api.attribute_begin().unwrap();
+        api.translate(1.0, 0.0, 0.0).unwrap();
+        // One TransformEnd too many pops the transformation saved by AttributeBegin...
+        api.transform_end().unwrap();
+        api.transform_end().unwrap();
+        // ... so AttributeEnd only has a graphics state to restore
+        api.attribute_end().unwrap();
+        api.attribute_end().unwrap();
+        api.named_material("undefined".to_owned()).unwrap();
+        api.coord_sys_transform("undefined".to_owned()).unwrap();
+        assert!(api.object_instance("undefined".to_owned()).is_err());
+        api.object_begin("empty".to_owned()).unwrap();
+        api.object_end().unwrap();
+        api.object_instance("empty".to_owned()).unwrap();
+        api.shape("trianglemesh".to_owned(), &mut triangle_params())
+            .unwrap();
+
+        let mut state = api.state.borrow_mut();
+        assert_eq!(state.render_options.primitives.len(), 1);
+        state.restore_graphics_state();
+        state.restore_transform();
+    }
+
+    #[test]
+    fn test_sampler_pixel_samples() {
+        ::init_stats();
+        let image = ::std::env::temp_dir().join("rustracer-test-pixel-samples.exr");
+        let options = Options {
+            image_file: Some(image.to_str().unwrap().to_owned()),
+            resolution: Some(Point2i::new(8, 6)),
+            num_threads: 2,
+            quiet: true,
+            ..Options::default()
+        };
+        let api = RealApi::new(options.clone());
+        api.init().unwrap();
+        let mut ps = ParamSet::default();
+        ps.init(vec![ParamListEntry::new(ParamType::Int,
+                                         "pixelsamples".to_owned(),
+                                         Array::NumArray(vec![4.0]))]);
+        // There is no Halton sampler (yet)
+        api.sampler("halton".to_owned(), &mut ps.clone()).unwrap();
+        assert!(api.state
+                    .borrow_mut()
+                    .render_options
+                    .make_sampler(&options)
+                    .is_err());
+        api.sampler("02sequence".to_owned(), &mut ps).unwrap();
+        api.world_begin().unwrap();
+        api.shape("sphere".to_owned(), &mut ParamSet::default())
+            .unwrap();
+
+        let mut state = api.state.borrow_mut();
+        let sampler = state.render_options.make_sampler(&options).unwrap();
+        assert_eq!(sampler.spp(), 4);
+        let quick = Options { quick_render: true, ..options.clone() };
+        let sampler = state.render_options.make_sampler(&quick).unwrap();
+        assert_eq!(sampler.spp(), 1);
+        let overridden = Options { spp_override: Some(64), ..options.clone() };
+        let sampler = state.render_options.make_sampler(&overridden).unwrap();
+        assert_eq!(sampler.spp(), 64);
+
+        // Every pixel actually receives the 4 samples when rendering
+        let camera = state.render_options.make_camera(&options).unwrap();
+        let mut integrator = state.render_options.make_integrator(&*camera).unwrap();
+        let mut sampler = state.render_options.make_sampler(&options).unwrap();
+        let scene = state.render_options.make_scene(&options).unwrap();
+        renderer::render(scene,
+                         &mut *integrator,
+                         &*camera,
+                         &options,
+                         &mut sampler,
+                         TILE_SIZE,
+                         Box::new(NoopDisplayUpdater {}),
+                         None,
+                         None)
+                .unwrap();
+        let _ = ::std::fs::remove_file(&image);
+        let film = camera.get_film();
+        for p in &Bounds2i::from_points(&Point2i::new(0, 0), &Point2i::new(8, 6)) {
+            assert_eq!(film.get_pixel_stats(&p).count(), 4, "pixel {}", p);
+        }
+    }
+
+    #[test]
+    fn test_ray_epsilon_override() {
+        ::init_stats();
+        let scene_ray_epsilon = |params: &mut ParamSet| {
+            let api = RealApi::default();
+            api.init().unwrap();
+            api.accelerator("bvh".to_owned(), params).unwrap();
+            api.world_begin().unwrap();
+            api.shape("sphere".to_owned(), &mut ParamSet::default())
+                .unwrap();
+            let mut state = api.state.borrow_mut();
+            let scene = state.render_options.make_scene(&Options::default()).unwrap();
+            scene.ray_epsilon
+        };
+
+        assert!(scene_ray_epsilon(&mut ParamSet::default()) > 0.0);
+        let mut ps = ParamSet::default();
+        ps.init(vec![ParamListEntry::new(ParamType::Float,
+                                         "rayepsilon".to_owned(),
+                                         Array::NumArray(vec![0.01]))]);
+        assert_eq!(scene_ray_epsilon(&mut ps), 0.01);
+    }
+
+    #[test]
+    fn test_nested_instances() {
+        ::init_stats();
+        let api = RealApi::default();
+        api.init().unwrap();
+        api.world_begin().unwrap();
+        // A "tree" made of 10 instanced "leaves", and a forest of 20 trees
+        api.object_begin("tree".to_owned()).unwrap();
+        api.object_begin("leaf".to_owned()).unwrap();
+        api.shape("trianglemesh".to_owned(), &mut triangle_params())
+            .unwrap();
+        api.object_end().unwrap();
+        for i in 0..10 {
+            api.attribute_begin().unwrap();
+            api.translate(0.0, i as f32, 0.0).unwrap();
+            api.object_instance("leaf".to_owned()).unwrap();
+            api.attribute_end().unwrap();
+        }
+        api.object_end().unwrap();
+        for i in 0..20 {
+            api.attribute_begin().unwrap();
+            api.translate(i as f32, 0.0, 0.0).unwrap();
+            api.object_instance("tree".to_owned()).unwrap();
+            api.attribute_end().unwrap();
+        }
+
+        let state = api.state.borrow();
+        assert!(state.render_options.instance_stack.is_empty());
+        assert_eq!(state.render_options.primitives.len(), 20);
+        let tree = &state.render_options.instances["tree"];
+        assert_eq!(tree.n_primitives, 10);
+        assert_eq!(Arc::strong_count(&tree.primitives[0]), 21);
+        let leaf = &state.render_options.instances["leaf"];
+        assert_eq!(Arc::strong_count(&leaf.primitives[0]), 11);
+        let bounds = state.render_options.primitives[19].world_bounds();
+        assert_eq!(bounds.p_max, Point3f::new(20.0, 10.0, 0.0));
+    }
+
+    fn point_light_params(name: &str, from: [f32; 3]) -> ParamSet {
+        let mut ps = ParamSet::default();
+        ps.init(vec![ParamListEntry::new(ParamType::Point3,
+                                         "from".to_owned(),
+                                         Array::NumArray(from.to_vec())),
+                     ParamListEntry::new(ParamType::Rgb,
+                                         "I".to_owned(),
+                                         Array::NumArray(vec![10.0, 10.0, 10.0])),
+                     ParamListEntry::new(ParamType::String,
+                                         "name".to_owned(),
+                                         Array::StrArray(vec![name.to_owned()]))]);
+        ps
+    }
+
+    /// Radiance seen at the front of a matte unit sphere lit by the given point lights.
+    fn sphere_radiance(options: Options, lights: &[(&str, [f32; 3])]) -> Spectrum {
+        let api = RealApi::new(options);
+        api.init().unwrap();
+        api.world_begin().unwrap();
+        for &(name, from) in lights {
+            api.lightsource("point".to_owned(), &mut point_light_params(name, from))
+                .unwrap();
+        }
+        api.shape("sphere".to_owned(), &mut ParamSet::default())
+            .unwrap();
+        let scene = api.state.borrow_mut().render_options.make_scene(&Options::default()).unwrap();
+
+        let integrator = Whitted::new(5);
+        let mut sampler: Box<Sampler> = Box::new(RandomSampler::new(1));
+        let mut arena = MemoryArena::new(1);
+        let alloc = arena.allocator();
+        let mut ray = Ray::new(Point3f::new(0.0, 0.0, -3.0), Vector3f::new(0.0, 0.0, 1.0));
+        integrator.li(&scene, &mut ray, &mut sampler, &alloc, 0)
+    }
+
+    #[test]
+    fn test_only_light() {
+        ::init_stats();
+        let key = ("key", [0.0, 0.0, -5.0]);
+        let fill = ("fill", [3.0, 0.0, -3.0]);
+        let key_alone = sphere_radiance(Options::default(), &[key]);
+        let both = sphere_radiance(Options::default(), &[key, fill]);
+        assert!(!key_alone.is_black());
+        assert_ne!(both, key_alone);
+
+        let only_key = Options { only_light: Some("key".to_owned()), ..Options::default() };
+        assert_eq!(sphere_radiance(only_key, &[key, fill]), key_alone);
+        let only_fill = Options { only_light: Some("fill".to_owned()), ..Options::default() };
+        assert_eq!(sphere_radiance(only_fill, &[key, fill]),
+                   sphere_radiance(Options::default(), &[fill]));
+    }
+
+    #[test]
+    fn test_only_material() {
+        ::init_stats();
+        let key = ("key", [0.0, 0.0, -5.0]);
+        let only_matte = Options { only_material: Some("matte".to_owned()), ..Options::default() };
+        assert_eq!(sphere_radiance(only_matte, &[key]),
+                   sphere_radiance(Options::default(), &[key]));
+        let only_glass = Options { only_material: Some("glass".to_owned()), ..Options::default() };
+        assert!(sphere_radiance(only_glass, &[key]).is_black());
+    }
+
+    #[test]
+    fn test_none_material_is_passthrough() {
+        ::init_stats();
+        let key = ("key", [0.0, 0.0, -5.0]);
+        let api = RealApi::default();
+        api.init().unwrap();
+        api.world_begin().unwrap();
+        api.lightsource("point".to_owned(), &mut point_light_params(key.0, key.1))
+            .unwrap();
+        api.shape("sphere".to_owned(), &mut ParamSet::default())
+            .unwrap();
+        // An invisible wall in front of the sphere
+        api.attribute_begin().unwrap();
+        api.material("none".to_owned(), &mut ParamSet::default())
+            .unwrap();
+        let mut ps = ParamSet::default();
+        ps.init(vec![ParamListEntry::new(ParamType::Int,
+                                         "indices".to_owned(),
+                                         Array::NumArray(vec![0.0, 1.0, 2.0, 0.0, 2.0, 3.0])),
+                     ParamListEntry::new(ParamType::Point3,
+                                         "P".to_owned(),
+                                         Array::NumArray(vec![-2.0, -2.0, -2.0, 2.0, -2.0, -2.0,
+                                                              2.0, 2.0, -2.0, -2.0, 2.0, -2.0]))]);
+        api.shape("trianglemesh".to_owned(), &mut ps).unwrap();
+        api.attribute_end().unwrap();
+        let scene = api.state.borrow_mut().render_options.make_scene(&Options::default()).unwrap();
+
+        // The camera ray hits the wall first, without a BSDF...
+        let mut ray = Ray::new(Point3f::new(0.0, 0.0, -3.0), Vector3f::new(0.0, 0.0, 1.0));
+        let mut arena = MemoryArena::new(1);
+        let alloc = arena.allocator();
+        let mut isect = scene.intersect(&mut ray).unwrap();
+        assert!((isect.hit.p.z + 2.0).abs() < 1e-4);
+        isect.compute_scattering_functions(&ray, TransportMode::RADIANCE, false, &alloc);
+        assert!(isect.bsdf.is_none());
+        // ... and carries on unrefracted to the sphere behind it
+        let mut continued = isect.spawn_ray(&ray.d);
+        let behind = scene.intersect(&mut continued).unwrap();
+        assert!((behind.hit.p - Point3f::new(0.0, 0.0, -1.0)).length() < 1e-4);
+        // It doesn't cast shadows either
+        let shadow = behind.spawn_ray_to(&Point3f::new(key.1[0], key.1[1], key.1[2]));
+        assert!(!scene.intersect_p(&shadow));
+
+        let integrator = Whitted::new(5);
+        let mut sampler: Box<Sampler> = Box::new(RandomSampler::new(1));
+        let mut ray = Ray::new(Point3f::new(0.0, 0.0, -3.0), Vector3f::new(0.0, 0.0, 1.0));
+        let through_wall = integrator.li(&scene, &mut ray, &mut sampler, &alloc, 0);
+        let without_wall = sphere_radiance(Options::default(), &[key]);
+        assert!(!without_wall.is_black());
+        assert!((through_wall - without_wall).y().abs() < 1e-4,
+                "{:?} vs {:?}",
+                through_wall,
+                without_wall);
+    }
+
+    #[test]
+    fn test_resolution_override() {
+        ::init_stats();
+        let mut render_options = RenderOptions::default();
+        render_options
+            .film_params
+            .init(vec![ParamListEntry::new(ParamType::Int,
+                                           "xresolution".to_owned(),
+                                           Array::NumArray(vec![160.0])),
+                       ParamListEntry::new(ParamType::Int,
+                                           "yresolution".to_owned(),
+                                           Array::NumArray(vec![90.0]))]);
+        let full = render_options.make_camera(&Options::default()).unwrap();
+        let half = Options { resolution: Some(Point2i::new(80, 45)), ..Options::default() };
+        let half = render_options.make_camera(&half).unwrap();
+        assert_eq!(full.get_film().full_resolution, Point2i::new(160, 90));
+        assert_eq!(half.get_film().full_resolution, Point2i::new(80, 45));
+
+        // The same point of the image sees the same direction
+        let direction = |camera: &Camera, x: f32, y: f32| {
+            let sample = CameraSample {
+                p_film: Point2f::new(x, y),
+                p_lens: Point2f::new(0.5, 0.5),
+                time: 0.0,
+            };
+            camera.generate_ray(&sample).0.d.normalize()
+        };
+        for &(x, y) in &[(0.0, 0.0), (0.25, 0.5), (0.9, 0.1), (1.0, 1.0)] {
+            let d_full = direction(&*full, x * 160.0, y * 90.0);
+            let d_half = direction(&*half, x * 80.0, y * 45.0);
+            assert!((d_full - d_half).length() < 1e-5, "{} vs {}", d_full, d_half);
+        }
+    }
+
+    fn scale_texture_params(tex1: &str, tex2: f32) -> ParamSet {
+        let mut ps = ParamSet::default();
+        ps.init(vec![ParamListEntry::new(ParamType::Texture,
+                                         "tex1".to_owned(),
+                                         Array::StrArray(vec![tex1.to_owned()])),
+                     ParamListEntry::new(ParamType::Float,
+                                         "tex2".to_owned(),
+                                         Array::NumArray(vec![tex2]))]);
+        ps
+    }
+
+    #[test]
+    fn test_self_referencing_texture() {
+        ::init_stats();
+        let api = RealApi::default();
+        api.init().unwrap();
+        api.world_begin().unwrap();
+        let disk = Disk::new(0.0, 1.0, 0.0, 360.0, Transform::default(), false);
+        let ray = Ray::new(Point3f::new(0.1, 0.2, 1.0), Vector3f::new(0.0, 0.0, -1.0));
+        let si = disk.intersect(&ray).unwrap().0;
+        let evaluate =
+            |name: &str| api.state.borrow().graphics_state.float_textures[name].evaluate(&si);
+
+        // "loop" doesn't exist yet, so tex1 falls back to its default of 1
+        api.texture("loop".to_owned(),
+                     "float".to_owned(),
+                     "scale".to_owned(),
+                     &mut scale_texture_params("loop", 0.5))
+            .unwrap();
+        assert_eq!(evaluate("loop"), 0.5);
+        // A redefinition refers to the previous definition
+        api.texture("loop".to_owned(),
+                     "float".to_owned(),
+                     "scale".to_owned(),
+                     &mut scale_texture_params("loop", 0.5))
+            .unwrap();
+        assert_eq!(evaluate("loop"), 0.25);
+    }
+
+    #[test]
+    fn test_metadata_sidecar() {
+        ::init_stats();
+        let image = ::std::env::temp_dir().join("rustracer-test-metadata.png");
+        let options = Options {
+            image_file: Some(image.to_str().unwrap().to_owned()),
+            resolution: Some(Point2i::new(8, 6)),
+            spp_override: Some(2),
+            num_threads: 1,
+            quiet: true,
+            write_metadata: true,
+            ..Options::default()
+        };
+        let api = RealApi::new(options);
+        api.init().unwrap();
+        api.world_begin().unwrap();
+        api.shape("sphere".to_owned(), &mut ParamSet::default())
+            .unwrap();
+        api.world_end().unwrap();
+
+        let sidecar = RenderMetadata::sidecar_path(&image);
+        let metadata: ::serde_json::Value =
+            ::serde_json::from_reader(::std::fs::File::open(&sidecar).unwrap()).unwrap();
+        let _ = ::std::fs::remove_file(&image);
+        let _ = ::std::fs::remove_file(&sidecar);
+        assert_eq!(metadata["image"], image.to_str().unwrap());
+        assert_eq!(metadata["resolution"], json!([8, 6]));
+        assert_eq!(metadata["spp"], 2);
+        assert_eq!(metadata["passes"], 1);
+        assert_eq!(metadata["integrator"], "path");
+        assert_eq!(metadata["sampler"], "02sequence");
+        assert!(metadata["render_time"]["seconds"].as_f64().unwrap() >= 0.0);
+        assert_eq!(metadata["version"], env!("CARGO_PKG_VERSION"));
+        assert!(metadata["stats"]["counters"]["Integrator/Camera rays traced"]
+                    .as_u64()
+                    .unwrap() > 0);
+    }
+}
@@ -113,6 +113,29 @@ where
         b
     }
 
+    /// Return true if the two bounding boxes share at least one point (touching counts).
+    pub fn overlaps(bbox1: &Bounds3<T>, bbox2: &Bounds3<T>) -> bool {
+        bbox1.p_max.x >= bbox2.p_min.x && bbox1.p_min.x <= bbox2.p_max.x
+            && bbox1.p_max.y >= bbox2.p_min.y && bbox1.p_min.y <= bbox2.p_max.y
+            && bbox1.p_max.z >= bbox2.p_min.z && bbox1.p_min.z <= bbox2.p_max.z
+    }
+
+    /// Return the bounding box padded by `delta` on every side.
+    pub fn expand(&self, delta: T) -> Bounds3<T> {
+        Bounds3 {
+            p_min: Point3::new(
+                self.p_min.x - delta,
+                self.p_min.y - delta,
+                self.p_min.z - delta,
+            ),
+            p_max: Point3::new(
+                self.p_max.x + delta,
+                self.p_max.y + delta,
+                self.p_max.z + delta,
+            ),
+        }
+    }
+
     pub fn intersect_p(&self, ray: &Ray) -> bool {
         let invdir = Vector3f::new(1.0 / ray.d.x, 1.0 / ray.d.y, 1.0 / ray.d.z);
         let sign = [
@@ -436,7 +459,7 @@ impl From<Bounds2f> for Bounds2i {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Axis {
     X,
     Y,
@@ -556,4 +579,88 @@ mod tests {
         let e = Bounds2i::union(&a, &d);
         assert_eq!(Bounds2i::from_elements(-15, -10, 0, 20), e);
     }
+
+    fn unit_cube() -> Bounds3f {
+        Bounds3f::from_points(&Point3f::new(0.0, 0.0, 0.0), &Point3f::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn test_bounds3_union() {
+        let a = unit_cube();
+        let b = Bounds3f::from_points(&Point3f::new(-1.0, 0.5, 0.5), &Point3f::new(0.5, 3.0, 0.5));
+        let c = Bounds3f::union(&a, &b);
+        assert_eq!(c.p_min, Point3f::new(-1.0, 0.0, 0.0));
+        assert_eq!(c.p_max, Point3f::new(1.0, 3.0, 1.0));
+
+        // The empty bounding box is the identity
+        let d = Bounds3f::union(&a, &Bounds3f::new());
+        assert_eq!(d.p_min, a.p_min);
+        assert_eq!(d.p_max, a.p_max);
+
+        let e = Bounds3f::union_point(&a, &Point3f::new(2.0, -1.0, 0.5));
+        assert_eq!(e.p_min, Point3f::new(0.0, -1.0, 0.0));
+        assert_eq!(e.p_max, Point3f::new(2.0, 1.0, 1.0));
+        let f = Bounds3f::union_point(&Bounds3f::new(), &Point3f::new(2.0, -1.0, 0.5));
+        assert_eq!(f.p_min, f.p_max);
+    }
+
+    #[test]
+    fn test_bounds3_surface_area() {
+        assert_eq!(unit_cube().surface_area(), 6.0);
+        let b = Bounds3f::from_points(&Point3f::new(-1.0, 0.0, 0.0), &Point3f::new(1.0, 3.0, 0.5));
+        assert_eq!(b.surface_area(), 2.0 * (2.0 * 3.0 + 2.0 * 0.5 + 3.0 * 0.5));
+        // Flat boxes still have an area
+        let flat =
+            Bounds3f::from_points(&Point3f::new(0.0, 0.0, 0.0), &Point3f::new(2.0, 3.0, 0.0));
+        assert_eq!(flat.surface_area(), 12.0);
+    }
+
+    #[test]
+    fn test_bounds3_maximum_extent() {
+        // All axes are equally long, so any axis will do
+        let axis = unit_cube().maximum_extent();
+        assert!(axis == Axis::X || axis == Axis::Y || axis == Axis::Z);
+        let b = |x, y, z| {
+            Bounds3f::from_points(&Point3f::new(0.0, 0.0, 0.0), &Point3f::new(x, y, z))
+        };
+        assert_eq!(b(3.0, 1.0, 2.0).maximum_extent(), Axis::X);
+        assert_eq!(b(1.0, 3.0, 2.0).maximum_extent(), Axis::Y);
+        assert_eq!(b(1.0, 2.0, 3.0).maximum_extent(), Axis::Z);
+    }
+
+    #[test]
+    fn test_bounds3_overlaps() {
+        let a = unit_cube();
+        let overlaps = |p1: Point3f, p2: Point3f| {
+            let b = Bounds3f::from_points(&p1, &p2);
+            let res = Bounds3f::overlaps(&a, &b);
+            assert_eq!(res, Bounds3f::overlaps(&b, &a));
+            res
+        };
+        assert!(overlaps(Point3f::new(0.5, 0.5, 0.5), Point3f::new(2.0, 2.0, 2.0)));
+        // Containment and shared faces count as overlapping
+        assert!(overlaps(Point3f::new(0.2, 0.2, 0.2), Point3f::new(0.8, 0.8, 0.8)));
+        assert!(overlaps(Point3f::new(1.0, 0.0, 0.0), Point3f::new(2.0, 1.0, 1.0)));
+        // Overlapping along two axes only isn't enough
+        assert!(!overlaps(Point3f::new(0.5, 0.5, 1.5), Point3f::new(2.0, 2.0, 2.0)));
+        assert!(!overlaps(Point3f::new(-2.0, -2.0, -2.0), Point3f::new(-1.0, -1.0, -1.0)));
+    }
+
+    #[test]
+    fn test_bounds3_expand() {
+        let b = unit_cube().expand(0.5);
+        assert_eq!(b.p_min, Point3f::new(-0.5, -0.5, -0.5));
+        assert_eq!(b.p_max, Point3f::new(1.5, 1.5, 1.5));
+        assert_eq!(b.surface_area(), 24.0);
+    }
+
+    #[test]
+    fn test_bounds3_offset() {
+        let b = Bounds3f::from_points(&Point3f::new(-1.0, 0.0, 2.0), &Point3f::new(1.0, 4.0, 2.0));
+        let o = b.offset(&Point3f::new(0.0, 1.0, 2.0));
+        assert_eq!((o.x, o.y, o.z), (0.5, 0.25, 0.0));
+        assert_eq!(b.lerp(&Point3f::new(0.5, 0.25, 0.0)), Point3f::new(0.0, 1.0, 2.0));
+        let o = b.offset(&b.p_max);
+        assert_eq!((o.x, o.y), (1.0, 1.0));
+    }
 }
@@ -1,10 +1,12 @@
 use std::f32::consts;
 use std::fmt::Debug;
 
-use super::BxDFType;
+use light_arena::Allocator;
+
+use super::{BxDFHolder, BxDFType};
 use {Point2f, Vector3f};
 use geometry::{abs_cos_theta, same_hemisphere};
-use sampling::cosine_sample_hemisphere;
+use sampling::{cosine_sample_hemisphere, uniform_hemisphere_pdf, uniform_sample_hemisphere};
 use spectrum::Spectrum;
 
 pub trait BxDF: Debug {
@@ -23,9 +25,42 @@ pub trait BxDF: Debug {
         let pdf = self.pdf(wo, &wi);
         (self.f(wo, &wi), wi, pdf, BxDFType::empty())
     }
-    // TODO implement rho functions
-    // fn rho(&self, wo: &Vector3f, n_samples: u32) -> (Point2f, Spectrum);
-    // fn rho_hh(&self, n_samples: u32) -> (Point2f, Point2f, Spectrum);
+
+    /// Hemispherical-directional reflectance: the total reflection in direction `wo` due to
+    /// constant illumination over the hemisphere.
+    ///
+    /// The default implementation estimates it with Monte Carlo integration, using one sample
+    /// direction per point of `samples`.
+    fn rho(&self, wo: &Vector3f, samples: &[Point2f]) -> Spectrum {
+        let mut r = Spectrum::black();
+        for u in samples {
+            let (f, wi, pdf, _) = self.sample_f(wo, u);
+            if pdf > 0.0 {
+                r += f * abs_cos_theta(&wi) / pdf;
+            }
+        }
+        r / samples.len() as f32
+    }
+
+    /// Hemispherical-hemispherical reflectance: the fraction of incident light reflected when the
+    /// incident light is the same from all directions.
+    ///
+    /// The default implementation estimates it with Monte Carlo integration: `samples1` are used
+    /// to pick outgoing directions and `samples2` incoming directions, so both slices should have
+    /// the same length.
+    fn rho_hh(&self, samples1: &[Point2f], samples2: &[Point2f]) -> Spectrum {
+        let mut r = Spectrum::black();
+        for (u1, u2) in samples1.iter().zip(samples2.iter()) {
+            let wo = uniform_sample_hemisphere(u1);
+            let pdfo = uniform_hemisphere_pdf();
+            let (f, wi, pdfi, _) = self.sample_f(&wo, u2);
+            if pdfi > 0.0 {
+                r += f * abs_cos_theta(&wi) * abs_cos_theta(&wo) / (pdfo * pdfi);
+            }
+        }
+        r / (consts::PI * samples1.len() as f32)
+    }
+
     fn matches(&self, flags: BxDFType) -> bool {
         self.get_type() & flags == self.get_type()
     }
@@ -42,6 +77,13 @@ pub trait BxDF: Debug {
             0.0
         }
     }
+
+    /// Add glossy approximations of this BxDF to `bxdfs` if it is specular, for path
+    /// regularization, and return true. Return false if the BxDF should be kept as is, which is
+    /// what the default implementation does.
+    fn regularize<'a>(&'a self, _arena: &'a Allocator, _bxdfs: &mut BxDFHolder<'a>) -> bool {
+        false
+    }
 }
 
 #[allow(dead_code)]
@@ -69,15 +111,71 @@ impl BxDF for ScaledBxDF {
         let (spectrum, wi, pdf, bxdftype) = self.bxdf.sample_f(wo, sample);
         (spectrum * self.scale, wi, pdf, bxdftype)
     }
-    // fn rho(&self, wo: &Vector3f, n_samples: u32) -> (Point2f, Spectrum) {
-    //     let (sample, spectrum) = self.bxdf.rho(wo, n_samples);
-    //     (sample, spectrum * self.scale)
-    // }
-    // fn rho_hh(&self, n_samples: u32) -> (Point2f, Point2f, Spectrum) {
-    //     let (sample1, sample2, spectrum) = self.bxdf.rho_hh(n_samples);
-    //     (sample1, sample2, spectrum * self.scale)
-    // }
+    fn rho(&self, wo: &Vector3f, samples: &[Point2f]) -> Spectrum {
+        self.bxdf.rho(wo, samples) * self.scale
+    }
+    fn rho_hh(&self, samples1: &[Point2f], samples2: &[Point2f]) -> Spectrum {
+        self.bxdf.rho_hh(samples1, samples2) * self.scale
+    }
     fn get_type(&self) -> BxDFType {
         self.bxdf.get_type()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bsdf::LambertianReflection;
+
+    /// A Lambertian BxDF that doesn't override `rho`, to test the Monte Carlo estimates.
+    #[derive(Debug)]
+    struct Diffuse {
+        r: Spectrum,
+    }
+
+    impl BxDF for Diffuse {
+        fn f(&self, _wo: &Vector3f, _wi: &Vector3f) -> Spectrum {
+            self.r * consts::FRAC_1_PI
+        }
+
+        fn get_type(&self) -> BxDFType {
+            BxDFType::BSDF_DIFFUSE | BxDFType::BSDF_REFLECTION
+        }
+    }
+
+    fn stratified_samples(n: usize, offset: f32) -> Vec<Point2f> {
+        let mut samples = Vec::with_capacity(n * n);
+        for i in 0..n {
+            for j in 0..n {
+                samples.push(Point2f::new((i as f32 + offset) / n as f32,
+                                          (j as f32 + offset) / n as f32));
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn test_lambertian_rho() {
+        let r = Spectrum::rgb(0.2, 0.5, 0.8);
+        let samples1 = stratified_samples(32, 0.5);
+        let samples2 = stratified_samples(32, 0.25);
+        let lambertian = LambertianReflection::new(r);
+        let diffuse = Diffuse { r };
+
+        for wo in &[Vector3f::new(0.0, 0.0, 1.0),
+                    Vector3f::new(0.6, 0.0, 0.8),
+                    Vector3f::new(0.0, 0.6, -0.8)] {
+            let analytic = lambertian.rho(wo, &samples1);
+            let estimate = diffuse.rho(wo, &samples1);
+            for i in 0..3 {
+                assert_eq!(analytic[i], r[i]);
+                assert!((estimate[i] - r[i]).abs() < 1e-3);
+            }
+        }
+
+        let estimate = diffuse.rho_hh(&samples1, &samples2);
+        for i in 0..3 {
+            assert!((estimate[i] - r[i]).abs() < 1e-2);
+        }
+    }
+}
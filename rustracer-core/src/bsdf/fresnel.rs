@@ -2,14 +2,24 @@ use std::mem;
 use std::fmt::Debug;
 use std::f32;
 
+use light_arena::Allocator;
+
 use {Point2f, Vector3f, ONE_MINUS_EPSILON};
-use bsdf::{BxDF, BxDFType, MicrofacetDistribution};
+use bsdf::{BxDF, BxDFHolder, BxDFType, MicrofacetDistribution, MicrofacetReflection,
+           MicrofacetTransmission, TrowbridgeReitzDistribution};
 use geometry::*;
 use material::TransportMode;
 use sampling::cosine_sample_hemisphere;
 use spectrum::Spectrum;
 use clamp;
 
+/// Roughness (alpha) of the glossy lobes replacing specular ones when a BSDF is regularized
+const REGULARIZED_ALPHA: f32 = 0.3;
+
+fn regularized_distribution<'a>(arena: &'a Allocator) -> &'a MicrofacetDistribution {
+    arena <- TrowbridgeReitzDistribution::new(REGULARIZED_ALPHA, REGULARIZED_ALPHA)
+}
+
 /// Compute the reflection direction
 pub fn reflect(wo: &Vector3f, n: &Vector3f) -> Vector3f {
     -(*wo) + *n * 2.0 * wo.dot(n)
@@ -105,6 +115,10 @@ impl Fresnel {
     pub fn no_op() -> FresnelNoOp {
         FresnelNoOp {}
     }
+
+    pub fn schlick(r0: Spectrum) -> FresnelSchlick {
+        FresnelSchlick { r0 }
+    }
 }
 
 
@@ -144,6 +158,21 @@ impl Fresnel for FresnelNoOp {
     }
 }
 
+/// Schlick's approximation of the Fresnel reflectance, given the reflectance `r0` at normal
+/// incidence: R = R(0) + (1 - R(0)) (1 - cos theta)^5.
+#[derive(Copy, Clone, Debug)]
+pub struct FresnelSchlick {
+    r0: Spectrum,
+}
+
+impl Fresnel for FresnelSchlick {
+    fn evaluate(&self, cos_theta_i: f32) -> Spectrum {
+        let m = clamp(1.0 - cos_theta_i.abs(), 0.0, 1.0);
+        let w = (m * m) * (m * m) * m;
+        self.r0 * (1.0 - w) + Spectrum::white() * w
+    }
+}
+
 /// BRDF for perfect specular reflection
 #[derive(Copy, Clone, Debug)]
 pub struct SpecularReflection<'a> {
@@ -181,6 +210,12 @@ impl<'a> BxDF for SpecularReflection<'a> {
     fn get_type(&self) -> BxDFType {
         BxDFType::BSDF_SPECULAR | BxDFType::BSDF_REFLECTION
     }
+
+    fn regularize<'b>(&'b self, arena: &'b Allocator, bxdfs: &mut BxDFHolder<'b>) -> bool {
+        let distrib = regularized_distribution(arena);
+        bxdfs.add(arena <- MicrofacetReflection::new(self.r, distrib, self.fresnel));
+        true
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -241,6 +276,16 @@ impl BxDF for SpecularTransmission {
     fn get_type(&self) -> BxDFType {
         BxDFType::BSDF_SPECULAR | BxDFType::BSDF_TRANSMISSION
     }
+
+    fn regularize<'a>(&'a self, arena: &'a Allocator, bxdfs: &mut BxDFHolder<'a>) -> bool {
+        let distrib = regularized_distribution(arena);
+        bxdfs.add(arena <- MicrofacetTransmission::new(self.t,
+                                                       distrib,
+                                                       self.eta_a,
+                                                       self.eta_b,
+                                                       self.mode));
+        true
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -323,6 +368,18 @@ impl BxDF for FresnelSpecular {
     fn get_type(&self) -> BxDFType {
         BxDFType::BSDF_SPECULAR | BxDFType::BSDF_REFLECTION | BxDFType::BSDF_TRANSMISSION
     }
+
+    fn regularize<'a>(&'a self, arena: &'a Allocator, bxdfs: &mut BxDFHolder<'a>) -> bool {
+        let distrib = regularized_distribution(arena);
+        let fresnel = arena <- Fresnel::dielectric(self.eta_a, self.eta_b);
+        bxdfs.add(arena <- MicrofacetReflection::new(self.r, distrib, fresnel));
+        bxdfs.add(arena <- MicrofacetTransmission::new(self.t,
+                                                       distrib,
+                                                       self.eta_a,
+                                                       self.eta_b,
+                                                       self.mode));
+        true
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -406,8 +463,11 @@ fn pow5(v: f32) -> f32 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use Vector3f;
-    use bsdf::TrowbridgeReitzDistribution;
+    use light_arena::MemoryArena;
+
+    use {Point2f, Vector3f};
+    use bsdf::{BxDF, BxDFHolder, BxDFType, TrowbridgeReitzDistribution};
+    use material::TransportMode;
     use spectrum::Spectrum;
 
     quickcheck! {
@@ -420,4 +480,108 @@ mod tests {
             pdf >= 0.0
         }
     }
+
+    #[test]
+    fn test_schlick() {
+        let r0 = Spectrum::rgb(0.9, 0.6, 0.04);
+        let schlick = Fresnel::schlick(r0);
+        assert_eq!(schlick.evaluate(1.0), r0);
+        assert_eq!(schlick.evaluate(-1.0), r0);
+        let grazing = schlick.evaluate(1e-3);
+        for i in 0..3 {
+            assert!((grazing[i] - 1.0).abs() < 1e-2, "{}", grazing);
+        }
+        // The reflectance increases monotonically towards grazing angles
+        assert!(schlick.evaluate(0.5).b > r0.b);
+        assert!(schlick.evaluate(0.1).b > schlick.evaluate(0.5).b);
+    }
+
+    #[test]
+    fn test_regularized_specular_reflection_is_glossy() {
+        let mut arena = MemoryArena::new(1);
+        let alloc = arena.allocator();
+        let fresnel = Fresnel::no_op();
+        let specular = SpecularReflection::new(Spectrum::white(), &fresnel);
+        let mut bxdfs = BxDFHolder::new(&alloc);
+        assert!(specular.regularize(&alloc, &mut bxdfs));
+        let bxdfs = bxdfs.into_slice();
+        assert_eq!(bxdfs.len(), 1);
+        let glossy = bxdfs[0];
+        assert_eq!(glossy.get_type(),
+                   BxDFType::BSDF_REFLECTION | BxDFType::BSDF_GLOSSY);
+
+        // The lobe is centered on the mirror direction, but also covers directions close to it
+        let wo = Vector3f::new(0.6, 0.0, 0.8);
+        let mirror = Vector3f::new(-0.6, 0.0, 0.8);
+        let near = Vector3f::new(-0.5, 0.1, 0.86).normalize();
+        assert!(glossy.f(&wo, &mirror).y() > glossy.f(&wo, &near).y());
+        assert!(glossy.f(&wo, &near).y() > 0.0);
+        assert!(glossy.pdf(&wo, &near) > 0.0);
+    }
+
+    #[test]
+    fn test_regularized_fresnel_specular_keeps_both_lobes() {
+        let mut arena = MemoryArena::new(1);
+        let alloc = arena.allocator();
+        let specular = FresnelSpecular::new(Spectrum::white(),
+                                            Spectrum::white(),
+                                            1.0,
+                                            1.5,
+                                            TransportMode::RADIANCE);
+        let mut bxdfs = BxDFHolder::new(&alloc);
+        assert!(specular.regularize(&alloc, &mut bxdfs));
+        let bxdfs = bxdfs.into_slice();
+        assert_eq!(bxdfs.len(), 2);
+        assert!(bxdfs
+                    .iter()
+                    .all(|b| !b.get_type().contains(BxDFType::BSDF_SPECULAR)));
+        assert!(bxdfs[0].get_type().contains(BxDFType::BSDF_REFLECTION));
+        assert!(bxdfs[1].get_type().contains(BxDFType::BSDF_TRANSMISSION));
+
+        // Glossy BxDFs aren't regularized any further
+        let mut more = BxDFHolder::new(&alloc);
+        assert!(!bxdfs[0].regularize(&alloc, &mut more));
+    }
+
+    /// Value of the transmitted sample for the given transport modes, entering then leaving a
+    /// medium of index 1.5.
+    fn transmitted<F>(bxdf: F) -> (f32, f32)
+        where F: Fn(TransportMode) -> Box<BxDF>
+    {
+        let u = Point2f::new(0.5, 0.5);
+        let sample = |wo: Vector3f| {
+            let (f_radiance, wi_radiance, _, _) = bxdf(TransportMode::RADIANCE).sample_f(&wo, &u);
+            let (f_importance, wi_importance, _, _) = bxdf(TransportMode::IMPORTANCE)
+                .sample_f(&wo, &u);
+            // Only the value differs, not the direction
+            assert!((wi_radiance - wi_importance).length() < 1e-6);
+            assert!(wi_radiance.z * wo.z < 0.0);
+            f_radiance.y() / f_importance.y()
+        };
+        (sample(Vector3f::new(0.6, 0.0, 0.8)), sample(Vector3f::new(0.3, 0.0, -0.954)))
+    }
+
+    #[test]
+    fn test_importance_transmission_is_not_scaled() {
+        // Radiance is compressed by (eta_i / eta_t)^2 when refracted into a denser medium and
+        // expanded when leaving it; importance isn't.
+        let expected = (1.0 / (1.5 * 1.5), 1.5 * 1.5);
+        let specular = transmitted(|mode| {
+                                       Box::new(SpecularTransmission::new(Spectrum::white(),
+                                                                          1.0,
+                                                                          1.5,
+                                                                          mode))
+                                   });
+        let fresnel_specular = transmitted(|mode| {
+                                               Box::new(FresnelSpecular::new(Spectrum::white(),
+                                                                             Spectrum::white(),
+                                                                             1.0,
+                                                                             1.5,
+                                                                             mode))
+                                           });
+        for &(entering, leaving) in &[specular, fresnel_specular] {
+            assert!((entering - expected.0).abs() < 1e-5);
+            assert!((leaving - expected.1).abs() < 1e-4);
+        }
+    }
 }
@@ -1,7 +1,7 @@
 use std::f32::consts;
 
 use bsdf::{BxDF, BxDFType};
-use Vector3f;
+use {Point2f, Vector3f};
 use spectrum::Spectrum;
 
 #[derive(Copy, Clone, Debug)]
@@ -20,6 +20,14 @@ impl BxDF for LambertianReflection {
         self.r * consts::FRAC_1_PI
     }
 
+    fn rho(&self, _wo: &Vector3f, _samples: &[Point2f]) -> Spectrum {
+        self.r
+    }
+
+    fn rho_hh(&self, _samples1: &[Point2f], _samples2: &[Point2f]) -> Spectrum {
+        self.r
+    }
+
     fn get_type(&self) -> BxDFType {
         BxDFType::BSDF_DIFFUSE | BxDFType::BSDF_REFLECTION
     }
@@ -41,7 +49,27 @@ impl BxDF for LambertianTransmission {
         self.t * consts::FRAC_1_PI
     }
 
+    fn rho(&self, _wo: &Vector3f, _samples: &[Point2f]) -> Spectrum {
+        self.t
+    }
+
+    fn rho_hh(&self, _samples1: &[Point2f], _samples2: &[Point2f]) -> Spectrum {
+        self.t
+    }
+
     fn get_type(&self) -> BxDFType {
         BxDFType::BSDF_DIFFUSE | BxDFType::BSDF_TRANSMISSION
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bsdf::test_utils::assert_furnace;
+
+    #[test]
+    fn test_white_furnace() {
+        assert_furnace(&LambertianReflection::new(Spectrum::white()), 1.0, 1e-3);
+        assert_furnace(&LambertianReflection::new(Spectrum::grey(0.4)), 0.4, 1e-3);
+    }
+}
@@ -606,3 +606,103 @@ impl MicrofacetDistribution for TrowbridgeReitzDistribution {
         self.sample_visible_area
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bsdf::FresnelNoOp;
+    use bsdf::test_utils::{assert_energy_conserving, assert_furnace, furnace};
+    use sampling::{uniform_hemisphere_pdf, uniform_sample_hemisphere};
+
+    /// Directional albedo of `bxdf` towards `wo`, estimated by sampling the hemisphere uniformly
+    /// with `n * n` stratified samples, i.e. without relying on the BxDF's own sampling.
+    fn uniform_albedo(bxdf: &BxDF, wo: &Vector3f, n: usize) -> f32 {
+        let mut l = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = Point2f::new((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+                let wi = uniform_sample_hemisphere(&u);
+                l += bxdf.f(wo, &wi).y() * abs_cos_theta(&wi) / uniform_hemisphere_pdf();
+            }
+        }
+        l / (n * n) as f32
+    }
+
+    /// Check that `pdf()` agrees with the pdf returned by `sample_f()`, and that importance
+    /// sampling converges to the same albedo as uniform sampling.
+    fn assert_pdf_consistent(bxdf: &BxDF) {
+        for &(x, y, z) in &[(0.0, 0.0, 1.0), (0.6, 0.0, 0.8), (0.0, 0.866_025_4, 0.5)] {
+            let wo = Vector3f::new(x, y, z);
+            let n = 32;
+            for i in 0..n {
+                for j in 0..n {
+                    let u = Point2f::new((i as f32 + 0.5) / n as f32,
+                                         (j as f32 + 0.5) / n as f32);
+                    let (_, wi, pdf, _) = bxdf.sample_f(&wo, &u);
+                    if pdf > 0.0 {
+                        let expected = bxdf.pdf(&wo, &wi);
+                        assert!((pdf - expected).abs() <= 1e-2 * expected,
+                                "{:?}: sample_f() gives pdf {} for {} -> {}, pdf() gives {}",
+                                bxdf,
+                                pdf,
+                                wo,
+                                wi,
+                                expected);
+                    }
+                }
+            }
+
+            let sampled = furnace(bxdf, &wo, 64);
+            let reference = uniform_albedo(bxdf, &wo, 256);
+            assert!((sampled - reference).abs() <= 0.02,
+                    "{:?} in direction {}: got {} with importance sampling, {} without",
+                    bxdf,
+                    wo,
+                    sampled,
+                    reference);
+        }
+    }
+
+    #[test]
+    fn test_white_furnace() {
+        let fresnel = FresnelNoOp {};
+        // Smooth surfaces reflect (almost) all the light: the single scattering model only loses
+        // energy to masking and shadowing.
+        let trowbridge = TrowbridgeReitzDistribution::new(0.05, 0.05);
+        assert_furnace(&MicrofacetReflection::new(Spectrum::white(), &trowbridge, &fresnel),
+                       1.0,
+                       0.02);
+        let beckmann = BeckmannDistribution::new(0.05, 0.05);
+        assert_furnace(&MicrofacetReflection::new(Spectrum::white(), &beckmann, &fresnel),
+                       1.0,
+                       0.02);
+
+        for alpha in &[0.1, 0.3, 0.7] {
+            let trowbridge = TrowbridgeReitzDistribution::new(*alpha, 0.5 * *alpha);
+            assert_energy_conserving(&MicrofacetReflection::new(Spectrum::white(),
+                                                                &trowbridge,
+                                                                &fresnel),
+                                     0.01);
+            let beckmann = BeckmannDistribution::new(*alpha, *alpha);
+            assert_energy_conserving(&MicrofacetReflection::new(Spectrum::white(),
+                                                                &beckmann,
+                                                                &fresnel),
+                                     0.01);
+        }
+    }
+
+    #[test]
+    fn test_reflection_pdf_matches_sampling() {
+        let fresnel = FresnelNoOp {};
+        for alpha in &[0.3, 0.7] {
+            let trowbridge = TrowbridgeReitzDistribution::new(*alpha, 0.5 * *alpha);
+            assert_pdf_consistent(&MicrofacetReflection::new(Spectrum::white(),
+                                                             &trowbridge,
+                                                             &fresnel));
+            let beckmann = BeckmannDistribution::new(*alpha, *alpha);
+            assert_pdf_consistent(&MicrofacetReflection::new(Spectrum::white(),
+                                                             &beckmann,
+                                                             &fresnel));
+        }
+    }
+}
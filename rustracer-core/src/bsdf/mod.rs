@@ -3,6 +3,8 @@ mod fresnel;
 mod lambertian;
 mod oren_nayar;
 mod microfacet;
+#[cfg(test)]
+mod test_utils;
 
 pub use self::bxdf::*;
 pub use self::fresnel::*;
@@ -223,6 +225,25 @@ impl<'a> BSDF<'a> {
         (f, wi_w, pdf, sampled_type)
     }
 
+    /// Hemispherical-directional reflectance of the BxDFs matching the given flags, for the
+    /// outgoing direction `wo_w`. See `BxDF::rho()`.
+    pub fn rho(&self, wo_w: &Vector3f, samples: &[Point2f], flags: BxDFType) -> Spectrum {
+        let wo = self.world_to_local(wo_w);
+        self.bxdfs
+            .iter()
+            .filter(|b| b.matches(flags))
+            .fold(Spectrum::black(), |r, b| r + b.rho(&wo, samples))
+    }
+
+    /// Hemispherical-hemispherical reflectance of the BxDFs matching the given flags. See
+    /// `BxDF::rho_hh()`.
+    pub fn rho_hh(&self, samples1: &[Point2f], samples2: &[Point2f], flags: BxDFType) -> Spectrum {
+        self.bxdfs
+            .iter()
+            .filter(|b| b.matches(flags))
+            .fold(Spectrum::black(), |r, b| r + b.rho_hh(samples1, samples2))
+    }
+
     fn world_to_local(&self, v: &Vector3f) -> Vector3f {
         Vector3f::new(v.dot(&self.ss), v.dot(&self.ts), v.dotn(&self.ns))
     }
@@ -237,6 +258,27 @@ impl<'a> BSDF<'a> {
     pub fn num_components(&self, flags: BxDFType) -> usize {
         self.bxdfs.iter().filter(|b| b.matches(flags)).count()
     }
+
+    /// Return a copy of this BSDF where the specular BxDFs are replaced by glossy approximations
+    /// (see `BxDF::regularize()`). This trades a bit of bias for a lot less variance on paths
+    /// that are hard to sample, like caustics seen through a diffuse bounce.
+    pub fn regularize(&self, arena: &'a Allocator) -> BSDF<'a> {
+        let mut bxdfs = BxDFHolder::new(arena);
+        for &bxdf in self.bxdfs {
+            if !bxdf.regularize(arena, &mut bxdfs) {
+                bxdfs.add(bxdf);
+            }
+        }
+
+        BSDF {
+            eta: self.eta,
+            ns: self.ns,
+            ng: self.ng,
+            ss: self.ss,
+            ts: self.ts,
+            bxdfs: bxdfs.into_slice(),
+        }
+    }
 }
 
 #[test]
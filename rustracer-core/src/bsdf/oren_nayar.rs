@@ -1,10 +1,11 @@
 use std::f32::consts;
 
-use Vector3f;
+use {Point2f, Vector3f};
 use spectrum::Spectrum;
 use bsdf::BxDFType;
-use geometry::{abs_cos_theta, cos_phi, sin_phi, sin_theta};
+use geometry::{abs_cos_theta, cos_phi, same_hemisphere, sin_phi, sin_theta};
 use bsdf::bxdf::BxDF;
+use sampling::cosine_sample_hemisphere;
 
 #[derive(Copy, Clone, Debug)]
 pub struct OrenNayar {
@@ -52,7 +53,80 @@ impl BxDF for OrenNayar {
         self.r * consts::FRAC_1_PI * (self.a + self.b * max_cos * sin_alpha * tan_beta)
     }
 
+    /// Cosine-weighted sampling of the hemisphere on the same side as `wo`.
+    fn sample_f(&self, wo: &Vector3f, u: &Point2f) -> (Spectrum, Vector3f, f32, BxDFType) {
+        let mut wi = cosine_sample_hemisphere(u);
+        if wo.z < 0.0 {
+            wi.z *= -1.0;
+        }
+        (self.f(wo, &wi), wi, self.pdf(wo, &wi), self.get_type())
+    }
+
+    fn pdf(&self, wo: &Vector3f, wi: &Vector3f) -> f32 {
+        if same_hemisphere(wo, wi) {
+            abs_cos_theta(wi) * consts::FRAC_1_PI
+        } else {
+            0.0
+        }
+    }
+
     fn get_type(&self) -> BxDFType {
         BxDFType::BSDF_REFLECTION | BxDFType::BSDF_DIFFUSE
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bsdf::test_utils::{assert_energy_conserving, assert_furnace};
+
+    const N: usize = 64;
+    const N_BINS: usize = 8;
+
+    #[test]
+    fn test_sampling_matches_pdf() {
+        let bxdf = OrenNayar::new(Spectrum::grey(0.5), 20.0);
+        for wo in &[Vector3f::new(0.0, 0.0, 1.0),
+                    Vector3f::new(0.48, 0.6, 0.64),
+                    Vector3f::new(0.6, 0.0, -0.8)] {
+            // Histogram of sampled directions, binned by cos(theta)
+            let mut histogram = [0usize; N_BINS];
+            for i in 0..N {
+                for j in 0..N {
+                    let u = Point2f::new((i as f32 + 0.5) / N as f32, (j as f32 + 0.5) / N as f32);
+                    let (f, wi, pdf, t) = bxdf.sample_f(wo, &u);
+                    assert!(same_hemisphere(wo, &wi));
+                    assert_eq!(t, bxdf.get_type());
+                    assert!((pdf - bxdf.pdf(wo, &wi)).abs() < 1e-6);
+                    assert!((pdf - abs_cos_theta(&wi) * consts::FRAC_1_PI).abs() < 1e-6);
+                    assert!(!f.has_nan());
+                    let bin = ((abs_cos_theta(&wi) * N_BINS as f32) as usize).min(N_BINS - 1);
+                    histogram[bin] += 1;
+                }
+            }
+            // Integrating the pdf over a band of the hemisphere gives cos^2 differences
+            for (bin, count) in histogram.iter().enumerate() {
+                let (c0, c1) = (bin as f32 / N_BINS as f32, (bin + 1) as f32 / N_BINS as f32);
+                let expected = (c1 * c1 - c0 * c0) * (N * N) as f32;
+                assert!((*count as f32 - expected).abs() < 0.05 * (N * N) as f32,
+                        "bin {}: {} samples, expected {}",
+                        bin,
+                        count,
+                        expected);
+            }
+            // Directions in the other hemisphere are never sampled
+            let other = Vector3f::new(0.0, 0.0, -wo.z.signum());
+            assert_eq!(bxdf.pdf(wo, &other), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_white_furnace() {
+        // Without roughness, Oren-Nayar is Lambertian
+        assert_furnace(&OrenNayar::new(Spectrum::white(), 0.0), 1.0, 1e-3);
+        // Rougher surfaces retro-reflect some of the light, but never create any
+        for sigma in &[10.0, 20.0, 45.0, 90.0] {
+            assert_energy_conserving(&OrenNayar::new(Spectrum::white(), *sigma), 1e-3);
+        }
+    }
+}
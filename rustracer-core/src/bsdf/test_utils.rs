@@ -0,0 +1,56 @@
+//! White furnace tests, to check the energy conservation of BxDFs.
+
+use {Point2f, Vector3f};
+use bsdf::BxDF;
+use geometry::abs_cos_theta;
+
+/// Outgoing directions the furnace test is run for, from normal incidence down to 60 degrees.
+const FURNACE_DIRECTIONS: [(f32, f32, f32); 4] = [(0.0, 0.0, 1.0),
+                                                   (0.6, 0.0, 0.8),
+                                                   (-0.36, 0.48, 0.8),
+                                                   (0.0, 0.866_025_4, 0.5)];
+
+/// Radiance reflected towards `wo` by a surface with the given BxDF lit by a uniform environment
+/// of unit radiance, i.e. the BxDF's directional albedo. This is estimated by importance sampling
+/// the BxDF with `n * n` stratified samples.
+pub fn furnace(bxdf: &BxDF, wo: &Vector3f, n: usize) -> f32 {
+    let mut l = 0.0;
+    for i in 0..n {
+        for j in 0..n {
+            let u = Point2f::new((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+            let (f, wi, pdf, _) = bxdf.sample_f(wo, &u);
+            if pdf > 0.0 {
+                l += f.y() * abs_cos_theta(&wi) / pdf;
+            }
+        }
+    }
+    l / (n * n) as f32
+}
+
+/// Run the furnace test for a few outgoing directions and check that the result is within
+/// `tolerance` of `albedo`.
+pub fn assert_furnace(bxdf: &BxDF, albedo: f32, tolerance: f32) {
+    for &(x, y, z) in &FURNACE_DIRECTIONS {
+        let wo = Vector3f::new(x, y, z);
+        let l = furnace(bxdf, &wo, 64);
+        assert!((l - albedo).abs() <= tolerance,
+                "furnace test for {:?} in direction {}: got {}, expected {}",
+                bxdf,
+                wo,
+                l,
+                albedo);
+    }
+}
+
+/// Check that the BxDF reflects at most as much energy as it receives.
+pub fn assert_energy_conserving(bxdf: &BxDF, tolerance: f32) {
+    for &(x, y, z) in &FURNACE_DIRECTIONS {
+        let wo = Vector3f::new(x, y, z);
+        let l = furnace(bxdf, &wo, 64);
+        assert!(l <= 1.0 + tolerance,
+                "{:?} reflects {} in direction {}",
+                bxdf,
+                l,
+                wo);
+    }
+}
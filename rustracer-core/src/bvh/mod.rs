@@ -16,14 +16,18 @@ use ray::Ray;
 use shapes::Shape;
 
 stat_memory_counter!("Memory/BVH tree", tree_bytes);
-stat_ratio!("BVH/Primitives per leaf node", total_primitives_per_leaf);
+stat_int_distribution!("BVH/Primitives per leaf node", primitives_per_leaf);
 stat_counter!("BVH/Interior nodes", interior_nodes);
 stat_counter!("BVH/Leaf nodes", leaf_nodes);
+stat_int_distribution!("BVH/Tree depth", tree_depth);
+stat_int_distribution!("BVH/SAH cost (rounded)", tree_sah_cost);
 pub fn init_stats() {
     tree_bytes::init();
-    total_primitives_per_leaf::init();
+    primitives_per_leaf::init();
     interior_nodes::init();
     leaf_nodes::init();
+    tree_depth::init();
+    tree_sah_cost::init();
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -38,6 +42,9 @@ pub struct BVH {
     max_prims_per_node: usize,
     primitives: Vec<Arc<Primitive>>,
     nodes: Vec<LinearBVHNode>,
+    /// If true, shadow rays go through primitives made of a specular transmitter (e.g. glass),
+    /// which is a cheap way to get light through windows.
+    ignore_specular_shadow: bool,
 }
 
 impl BVH {
@@ -71,7 +78,9 @@ impl BVH {
             SplitMethod::SAH
         };
         let max_prims_per_node = ps.find_one_int("maxnodeprims", 4);
-        BVH::new(max_prims_per_node as usize, prims, split_method)
+        let mut bvh = BVH::new(max_prims_per_node as usize, prims, split_method);
+        bvh.ignore_specular_shadow = ps.find_one_bool("ignorespecularshadow", false);
+        bvh
     }
 
     pub fn new(max_prims_per_node: usize,
@@ -114,18 +123,68 @@ impl BVH {
             max_prims_per_node: min(max_prims_per_node, 255),
             primitives: ordered_prims,
             nodes: nodes,
+            ignore_specular_shadow: false,
         };
         tree_bytes::add((total_nodes * ::std::mem::size_of::<LinearBVHNode>() +
                          ::std::mem::size_of_val(&bvh) +
                          prims.len() * ::std::mem::size_of_val(&prims[0])) as
                         u64);
-        info!("BVH created with {} nodes for {} primitives",
+        let (depth, sah_cost) = (bvh.depth(), bvh.sah_cost());
+        tree_depth::report_value(depth as u64);
+        tree_sah_cost::report_value(sah_cost.round() as u64);
+        info!("BVH created with {} nodes for {} primitives, depth {} and SAH cost {}",
               total_nodes,
-              bvh.primitives.len());
+              bvh.primitives.len(),
+              depth,
+              sah_cost);
 
         bvh
     }
 
+    /// Number of nodes on the longest path from the root to a leaf.
+    pub fn depth(&self) -> usize {
+        if self.nodes.is_empty() {
+            return 0;
+        }
+        let mut max_depth = 0;
+        let mut to_visit = vec![(0, 1)];
+        while let Some((node_idx, depth)) = to_visit.pop() {
+            match self.nodes[node_idx].data {
+                LinearBVHNodeData::Leaf { .. } => max_depth = usize::max(max_depth, depth),
+                LinearBVHNodeData::Interior { second_child_offset, .. } => {
+                    to_visit.push((node_idx + 1, depth + 1));
+                    to_visit.push((second_child_offset, depth + 1));
+                }
+            }
+        }
+        max_depth
+    }
+
+    /// Expected cost of intersecting a ray with the tree according to the surface area
+    /// heuristic, with the same costs as the build: 1 per node traversed and 1 per primitive
+    /// tested. Each node is weighted by the probability that a ray hitting the root hits it,
+    /// which is 1 for all nodes when the tree has no area (e.g. all primitives are points).
+    pub fn sah_cost(&self) -> f32 {
+        let root_area = match self.nodes.first() {
+            Some(root) => root.bounds.surface_area(),
+            None => return 0.0,
+        };
+        self.nodes
+            .iter()
+            .map(|node| {
+                let p_hit = if root_area > 0.0 {
+                    node.bounds.surface_area() / root_area
+                } else {
+                    1.0
+                };
+                match node.data {
+                    LinearBVHNodeData::Leaf { num_prims, .. } => p_hit * num_prims as f32,
+                    LinearBVHNodeData::Interior { .. } => p_hit,
+                }
+            })
+            .sum()
+    }
+
     fn recursive_build(primitives: &[Arc<Primitive>],
                        primitive_info: &mut Vec<BVHPrimitiveInfo>,
                        start: usize,
@@ -414,8 +473,12 @@ impl Primitive for BVH {
     }
 
     fn intersect_p(&self, ray: &Ray) -> bool {
+        self.intersect_p_blocker(ray).0
+    }
+
+    fn intersect_p_blocker(&self, ray: &Ray) -> (bool, Option<usize>) {
         if self.nodes.is_empty() {
-            return false;
+            return (false, None);
         }
 
         let mut to_visit_offset = 0;
@@ -435,9 +498,13 @@ impl Primitive for BVH {
                         num_prims,
                         primitives_offset,
                     } => {
-                        for i in 0..num_prims {
-                            if self.primitives[primitives_offset + i].intersect_p(ray) {
-                                return true;
+                        for i in primitives_offset..primitives_offset + num_prims {
+                            let prim = &self.primitives[i];
+                            if self.ignore_specular_shadow && is_specular_transmitter(prim) {
+                                continue;
+                            }
+                            if prim.intersect_p(ray) {
+                                return (true, Some(i));
                             }
                         }
                         if to_visit_offset == 0 {
@@ -475,7 +542,11 @@ impl Primitive for BVH {
                 current_node_idx = nodes_to_visit[to_visit_offset];
             }
         }
-        false
+        (false, None)
+    }
+
+    fn primitive(&self, idx: usize) -> Option<&Primitive> {
+        self.primitives.get(idx).map(|p| &**p)
     }
 
     fn area_light(&self) -> Option<Arc<AreaLight>> {
@@ -495,6 +566,11 @@ impl Primitive for BVH {
     }
 }
 
+fn is_specular_transmitter(prim: &Arc<Primitive>) -> bool {
+    prim.material()
+        .map_or(false, |m| m.is_specular_transmitter())
+}
+
 struct BVHPrimitiveInfo {
     pub prim_number: usize,
     pub centroid: Point3f,
@@ -537,8 +613,7 @@ impl BVHBuildNode {
 
     fn leaf(first_prim_offset: usize, num_prims: usize, bbox: Bounds3f) -> BVHBuildNode {
         leaf_nodes::inc();
-        total_primitives_per_leaf::add(num_prims as u64);
-        total_primitives_per_leaf::inc_total();
+        primitives_per_leaf::report_value(num_prims as u64);
         BVHBuildNode::Leaf {
             bounds: bbox,
             first_prim_offset: first_prim_offset,
@@ -577,3 +652,108 @@ struct BucketInfo {
     pub count: usize,
     pub bounds: Bounds3f,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use material::MatteMaterial;
+    use test_utils::CountingPrimitive;
+
+    /// A material that pretends to be smooth glass.
+    #[derive(Debug)]
+    struct SpecularTransmitter;
+
+    impl Material for SpecularTransmitter {
+        fn compute_scattering_functions<'a, 'b>(&self,
+                                                _isect: &mut SurfaceInteraction<'a, 'b>,
+                                                _mode: TransportMode,
+                                                _allow_multiple_lobes: bool,
+                                                _arena: &'b Allocator) {
+        }
+
+        fn is_specular_transmitter(&self) -> bool {
+            true
+        }
+    }
+
+    /// Spheres of radius 0.5 centered on the z axis at the given heights.
+    fn spheres(zs: &[f32], material: Arc<Material>) -> Vec<Arc<CountingPrimitive>> {
+        zs.iter()
+            .map(|z| {
+                     Arc::new(CountingPrimitive::sphere(Vector3f::new(0.0, 0.0, *z),
+                                                        0.5,
+                                                        Arc::clone(&material)))
+                 })
+            .collect()
+    }
+
+    fn total_blocked(prims: &[Arc<CountingPrimitive>]) -> usize {
+        prims.iter().map(|p| p.n_blocked()).sum()
+    }
+
+    fn build_bvh(prims: &[Arc<CountingPrimitive>]) -> BVH {
+        let prims: Vec<Arc<Primitive>> = prims
+            .iter()
+            .map(|p| Arc::clone(p) as Arc<Primitive>)
+            .collect();
+        BVH::new(1, &prims, SplitMethod::SAH)
+    }
+
+    #[test]
+    fn test_intersect_p_stops_at_first_blocker() {
+        ::init_stats();
+        let prims = spheres(&[2.0, 4.0, 6.0, 8.0], MatteMaterial::black());
+        let bvh = build_bvh(&prims);
+
+        let ray = Ray::new(Point3f::new(0.0, 0.0, 0.0), Vector3f::new(0.0, 0.0, 1.0));
+        assert!(bvh.intersect_p(&ray));
+        assert_eq!(total_blocked(&prims), 1);
+    }
+
+    #[test]
+    fn test_intersect_p_respects_t_max() {
+        ::init_stats();
+        let prims = spheres(&[2.0, 4.0], MatteMaterial::black());
+        let bvh = build_bvh(&prims);
+
+        // The light is in front of the first sphere
+        let mut ray = Ray::new(Point3f::new(0.0, 0.0, 0.0), Vector3f::new(0.0, 0.0, 1.0));
+        ray.t_max = 1.0;
+        assert!(!bvh.intersect_p(&ray));
+        // The light is between the spheres
+        ray.t_max = 3.0;
+        assert!(bvh.intersect_p(&ray));
+        assert_eq!(prims[0].n_blocked(), 1);
+        assert_eq!(prims[1].n_blocked(), 0);
+    }
+
+    #[test]
+    fn test_ignore_specular_shadow() {
+        ::init_stats();
+        let prims = spheres(&[2.0], Arc::new(SpecularTransmitter));
+        let mut bvh = build_bvh(&prims);
+        let ray = Ray::new(Point3f::new(0.0, 0.0, 0.0), Vector3f::new(0.0, 0.0, 1.0));
+
+        assert!(bvh.intersect_p(&ray));
+        bvh.ignore_specular_shadow = true;
+        assert!(!bvh.intersect_p(&ray));
+    }
+
+    #[test]
+    fn test_quality_of_overlapping_primitives() {
+        ::init_stats();
+        // Identical primitives can't be split, and end up in a single leaf
+        let prims = spheres(&[1.0; 5], MatteMaterial::black());
+        let bvh = build_bvh(&prims);
+        assert_eq!(bvh.depth(), 1);
+        let cost = bvh.sah_cost();
+        assert!(cost.is_finite());
+        assert_eq!(cost, 5.0);
+
+        let prims = spheres(&[0.0, 2.0, 4.0, 6.0], MatteMaterial::black());
+        let bvh = build_bvh(&prims);
+        assert_eq!(bvh.depth(), 3);
+        assert!(bvh.sah_cost() < 4.0);
+    }
+}
@@ -9,8 +9,13 @@ use sampling;
 
 pub trait Camera: Send+ Sync {
     fn get_film(&self) -> &Film;
-    fn generate_ray(&self, sample: &CameraSample) -> Ray;
-    fn generate_ray_differential(&self, sample: &CameraSample) -> Ray;
+    /// Generate the ray for the given sample, along with a weight for how much the radiance
+    /// arriving along it contributes to the image.
+    fn generate_ray(&self, sample: &CameraSample) -> (Ray, f32);
+    fn generate_ray_differential(&self, sample: &CameraSample) -> (Ray, f32);
+    fn camera_to_world(&self) -> &Transform;
+    /// Move the camera. This doesn't clear the film.
+    fn set_camera_to_world(&mut self, camera_to_world: Transform);
 }
 
 /// Projective pinhole camera.
@@ -23,6 +28,8 @@ pub struct PerspectiveCamera {
     focal_distance: f32,
     dx_camera: Vector3f,
     dy_camera: Vector3f,
+    /// Darken the image towards its edges like real lenses do
+    vignette: bool,
 }
 
 impl PerspectiveCamera {
@@ -63,6 +70,7 @@ impl PerspectiveCamera {
             focal_distance: focal_distance,
             dx_camera: dx_camera,
             dy_camera: dy_camera,
+            vignette: false,
         }
     }
 
@@ -106,12 +114,27 @@ impl PerspectiveCamera {
             fov = halffov * 2.0;
         }
 
-        Box::new(PerspectiveCamera::new(cam2world.clone(),
-                                        screen,
-                                        lensradius,
-                                        focaldistance,
-                                        fov,
-                                        film))
+        let mut camera = PerspectiveCamera::new(cam2world.clone(),
+                                                screen,
+                                                lensradius,
+                                                focaldistance,
+                                                fov,
+                                                film);
+        camera.vignette = ps.find_one_bool("vignette", false);
+
+        Box::new(camera)
+    }
+
+    /// Weight of a ray leaving the pinhole in the normalized camera space direction `d`. With
+    /// vignetting, the irradiance on the film falls off with the fourth power of the cosine of
+    /// the angle to the optical axis.
+    fn ray_weight(&self, d: &Vector3f) -> f32 {
+        if self.vignette {
+            let cos2_theta = d.z * d.z;
+            cos2_theta * cos2_theta
+        } else {
+            1.0
+        }
     }
 }
 
@@ -120,11 +143,20 @@ impl Camera for PerspectiveCamera {
         &self.film
     }
 
-    fn generate_ray(&self, sample: &CameraSample) -> Ray {
+    fn camera_to_world(&self) -> &Transform {
+        &self.camera_to_world
+    }
+
+    fn set_camera_to_world(&mut self, camera_to_world: Transform) {
+        self.camera_to_world = camera_to_world;
+    }
+
+    fn generate_ray(&self, sample: &CameraSample) -> (Ray, f32) {
         let p_film = Point3f::new(sample.p_film.x, sample.p_film.y, 0.0);
         let p_camera: Point3f = &self.raster_to_camera * &p_film;
 
         let mut ray = Ray::new(Point3f::zero(), Vector3f::from(p_camera).normalize());
+        let weight = self.ray_weight(&ray.d);
         // modify ray for depth of field
         if self.lens_radius > 0.0 {
             // Sample point on lens
@@ -136,14 +168,15 @@ impl Camera for PerspectiveCamera {
             ray.o = Point3f::new(p_lens.x, p_lens.y, 0.0);
             ray.d = (p_focus - ray.o).normalize();
         }
-        ray.transform(&self.camera_to_world).0
+        (ray.transform(&self.camera_to_world).0, weight)
     }
 
-    fn generate_ray_differential(&self, sample: &CameraSample) -> Ray {
+    fn generate_ray_differential(&self, sample: &CameraSample) -> (Ray, f32) {
         let p_film = Point3f::new(sample.p_film.x, sample.p_film.y, 0.0);
         let p_camera = &self.raster_to_camera * &p_film;
 
         let mut ray = Ray::new(Point3f::zero(), Vector3f::from(p_camera).normalize());
+        let weight = self.ray_weight(&ray.d);
         // modify ray for depth of field
         if self.lens_radius > 0.0 {
             // Sample point on lens
@@ -190,7 +223,7 @@ impl Camera for PerspectiveCamera {
 
         ray.differential = Some(diff);
 
-        ray.transform(&self.camera_to_world).0
+        (ray.transform(&self.camera_to_world).0, weight)
     }
 }
 
@@ -199,3 +232,114 @@ pub struct CameraSample {
     pub p_lens: Point2f,
     pub time: f32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Point2i;
+    use api::{Array, ParamListEntry, ParamType};
+    use filter::BoxFilter;
+    use shapes::{Shape, Sphere};
+
+    /// Render a unit sphere 3 units in front of the camera and return the width and height, in
+    /// pixels, of its silhouette along the middle row and column of the image.
+    fn sphere_extents(xres: i32, yres: i32, params: Vec<ParamListEntry>) -> (usize, usize) {
+        let film = Film::new(Point2i::new(xres, yres),
+                             Bounds2f::from_points(&Point2f::new(0.0, 0.0),
+                                                   &Point2f::new(1.0, 1.0)),
+                             Box::new(BoxFilter::new(0.5, 0.5)),
+                             35.0,
+                             "test.png",
+                             1.0,
+                             f32::INFINITY);
+        let mut ps = ParamSet::default();
+        ps.init(params);
+        let camera = PerspectiveCamera::create(&mut ps, &Transform::default(), Box::new(film));
+        let sphere = Sphere::new(Transform::translate_z(3.0), 1.0, -1.0, 1.0, 360.0, false);
+
+        let hits = |x: i32, y: i32| {
+            let sample = CameraSample {
+                p_film: Point2f::new(x as f32 + 0.5, y as f32 + 0.5),
+                p_lens: Point2f::new(0.5, 0.5),
+                time: 0.0,
+            };
+            sphere.intersect(&camera.generate_ray(&sample).0).is_some()
+        };
+        let width = (0..xres).filter(|&x| hits(x, yres / 2)).count();
+        let height = (0..yres).filter(|&y| hits(xres / 2, y)).count();
+        (width, height)
+    }
+
+    #[test]
+    fn test_wide_image_is_not_stretched() {
+        ::init_stats();
+        let (width, height) = sphere_extents(160, 90, vec![]);
+        assert!(height > 20);
+        assert!((width as i32 - height as i32).abs() <= 1,
+                "sphere is {}x{} pixels",
+                width,
+                height);
+    }
+
+    #[test]
+    fn test_tall_image_is_not_stretched() {
+        ::init_stats();
+        let (width, height) = sphere_extents(90, 160, vec![]);
+        assert!(width > 20);
+        assert!((width as i32 - height as i32).abs() <= 1,
+                "sphere is {}x{} pixels",
+                width,
+                height);
+    }
+
+    #[test]
+    fn test_screenwindow_override() {
+        ::init_stats();
+        // A square screen window on a 16:9 film stretches the image horizontally
+        let sw = ParamListEntry::new(ParamType::Float,
+                                     "screenwindow".to_owned(),
+                                     Array::NumArray(vec![-1.0, 1.0, -1.0, 1.0]));
+        let (width, height) = sphere_extents(160, 90, vec![sw]);
+        let ratio = width as f32 / height as f32;
+        assert!((ratio - 16.0 / 9.0).abs() < 0.1, "sphere is {}x{} pixels", width, height);
+    }
+
+    #[test]
+    fn test_vignette_darkens_corners() {
+        let film = Film::new(Point2i::new(101, 75),
+                             Bounds2f::from_points(&Point2f::new(0.0, 0.0),
+                                                   &Point2f::new(1.0, 1.0)),
+                             Box::new(BoxFilter::new(0.5, 0.5)),
+                             35.0,
+                             "test.png",
+                             1.0,
+                             f32::INFINITY);
+        let vignette = ParamListEntry::new(ParamType::Bool,
+                                           "vignette".to_owned(),
+                                           Array::StrArray(vec!["true".to_owned()]));
+        let mut ps = ParamSet::default();
+        ps.init(vec![vignette]);
+        let camera = PerspectiveCamera::create(&mut ps, &Transform::default(), Box::new(film));
+        let weight = |x: f32, y: f32| {
+            let sample = CameraSample {
+                p_film: Point2f::new(x, y),
+                p_lens: Point2f::new(0.5, 0.5),
+                time: 0.0,
+            };
+            let (ray, weight) = camera.generate_ray(&sample);
+            assert_eq!(camera.generate_ray_differential(&sample).1, weight);
+            // The weight is the cosine of the angle to the optical axis to the fourth
+            let cos_theta = ray.d.normalize().z;
+            assert!((weight - cos_theta.powi(4)).abs() < 1e-5);
+            weight
+        };
+
+        assert!((weight(50.5, 37.5) - 1.0).abs() < 1e-5);
+        let edge = weight(0.0, 37.5);
+        let corner = weight(0.0, 0.0);
+        assert!(edge < 1.0);
+        assert!(corner < edge);
+        assert!((weight(101.0, 75.0) - corner).abs() < 1e-5);
+    }
+}
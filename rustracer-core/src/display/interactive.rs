@@ -0,0 +1,158 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crossbeam;
+use failure::Error;
+
+use super::minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+
+use {clamp, Options};
+use camera::Camera;
+use display::NoopDisplayUpdater;
+use display::orbit::Orbit;
+use film::Film;
+use integrator::SamplerIntegrator;
+use renderer;
+use sampler::Sampler;
+use scene::Scene;
+use spectrum::gamma_correct;
+
+/// How often the window is refreshed while rendering, in milliseconds
+const REFRESH_INTERVAL: u64 = 100;
+/// Radians the camera turns per pixel the mouse is dragged
+const ORBIT_SPEED: f32 = 0.01;
+/// Radians the camera turns per arrow key press
+const KEY_ORBIT_STEP: f32 = 0.1;
+/// Factor applied to the distance to the target per notch of the scroll wheel
+const ZOOM_STEP: f32 = 0.9;
+
+/// Render the scene in a window, restarting from a new viewpoint whenever the camera is moved:
+/// dragging with the left mouse button or the arrow keys orbit around the scene, and the scroll
+/// wheel or +/- zoom in and out. Closing the window or pressing Escape returns.
+pub fn render_interactive(scene: Arc<Scene>,
+                          integrator: &mut SamplerIntegrator,
+                          camera: &mut Camera,
+                          options: &Options,
+                          sampler: &mut Box<Sampler>,
+                          block_size: i32)
+                          -> Result<(), Error> {
+    let resolution = camera.get_film().cropped_pixel_bounds.diagonal();
+    let mut window = Window::new("Rustracer",
+                                 resolution.x as usize,
+                                 resolution.y as usize,
+                                 WindowOptions::default())
+            .map_err(|e| format_err!("Unable to open a window: {:?}", e))?;
+    let (center, _radius) = scene.world_bounding_sphere();
+    let mut orbit = Orbit::new(camera.camera_to_world(), &center);
+    let mut options = options.clone();
+    options.quiet = true;
+
+    loop {
+        camera.get_film().clear();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (status, moved) = {
+            let scene = Arc::clone(&scene);
+            let integrator = &mut *integrator;
+            let camera: &Camera = &*camera;
+            let options = &options;
+            let sampler = &mut *sampler;
+            let render_cancel = Arc::clone(&cancel);
+            crossbeam::scope(|scope| {
+                let render = scope.spawn(move || {
+                    renderer::render(scene,
+                                     integrator,
+                                     camera,
+                                     options,
+                                     sampler,
+                                     block_size,
+                                     Box::new(NoopDisplayUpdater {}),
+                                     None,
+                                     Some(render_cancel))
+                });
+                let moved = show_until_moved(&mut window, camera.get_film(), &mut orbit);
+                cancel.store(true, Ordering::Relaxed);
+                (render.join(), moved)
+            })
+        };
+        status?;
+        if !moved? {
+            return Ok(());
+        }
+        camera.set_camera_to_world(orbit.camera_to_world());
+    }
+}
+
+/// Keep showing the film in the window until the user moves the camera, in which case return
+/// true, or closes the window.
+fn show_until_moved(window: &mut Window, film: &Film, orbit: &mut Orbit) -> Result<bool, Error> {
+    let mut last_mouse = None;
+    loop {
+        let buffer: Vec<u32> = film.get_rgb()
+            .chunks(3)
+            .map(|rgb| {
+                let to_byte = |v: f32| clamp(255.0 * gamma_correct(v) + 0.5, 0.0, 255.0) as u32;
+                to_byte(rgb[0]) << 16 | to_byte(rgb[1]) << 8 | to_byte(rgb[2])
+            })
+            .collect();
+        window
+            .update_with_buffer(&buffer[..])
+            .map_err(|e| format_err!("Could not update window: {:?}", e))?;
+        if !window.is_open() || window.is_key_down(Key::Escape) {
+            return Ok(false);
+        }
+        if handle_input(window, orbit, &mut last_mouse) {
+            return Ok(true);
+        }
+        thread::sleep(Duration::from_millis(REFRESH_INTERVAL));
+    }
+}
+
+/// Move the camera according to the mouse and keyboard. Returns whether it moved.
+fn handle_input(window: &Window, orbit: &mut Orbit, last_mouse: &mut Option<(f32, f32)>) -> bool {
+    let mut moved = false;
+
+    let mouse = window.get_mouse_pos(MouseMode::Discard);
+    if window.get_mouse_down(MouseButton::Left) {
+        if let (Some((x, y)), Some((x0, y0))) = (mouse, *last_mouse) {
+            if x != x0 || y != y0 {
+                orbit.rotate((x0 - x) * ORBIT_SPEED, (y - y0) * ORBIT_SPEED);
+                moved = true;
+            }
+        }
+        *last_mouse = mouse;
+    } else {
+        *last_mouse = None;
+    }
+
+    if let Some((_, scroll)) = window.get_scroll_wheel() {
+        if scroll != 0.0 {
+            orbit.zoom(ZOOM_STEP.powf(scroll));
+            moved = true;
+        }
+    }
+
+    let keys = [(Key::Left, -KEY_ORBIT_STEP, 0.0),
+                (Key::Right, KEY_ORBIT_STEP, 0.0),
+                (Key::Up, 0.0, KEY_ORBIT_STEP),
+                (Key::Down, 0.0, -KEY_ORBIT_STEP)];
+    for &(key, d_theta, d_phi) in &keys {
+        if window.is_key_pressed(key, KeyRepeat::Yes) {
+            orbit.rotate(d_theta, d_phi);
+            moved = true;
+        }
+    }
+    if window.is_key_pressed(Key::Equal, KeyRepeat::Yes) ||
+       window.is_key_pressed(Key::NumPadPlus, KeyRepeat::Yes) {
+        orbit.zoom(ZOOM_STEP);
+        moved = true;
+    }
+    if window.is_key_pressed(Key::Minus, KeyRepeat::Yes) ||
+       window.is_key_pressed(Key::NumPadMinus, KeyRepeat::Yes) {
+        orbit.zoom(1.0 / ZOOM_STEP);
+        moved = true;
+    }
+
+    moved
+}
@@ -1,8 +1,34 @@
 #[cfg(feature = "display")]
 extern crate minifb;
 
+#[cfg(feature = "display")]
+mod interactive;
+#[cfg(feature = "display")]
+mod orbit;
+
+#[cfg(feature = "display")]
+pub use self::interactive::render_interactive;
+#[cfg(feature = "display")]
+pub use self::orbit::Orbit;
+
+#[cfg(not(feature = "display"))]
+use std::sync::Arc;
+
+#[cfg(not(feature = "display"))]
+use failure::Error;
+
 use Point2i;
+#[cfg(not(feature = "display"))]
+use Options;
+#[cfg(not(feature = "display"))]
+use camera::Camera;
 use film::Film;
+#[cfg(not(feature = "display"))]
+use integrator::SamplerIntegrator;
+#[cfg(not(feature = "display"))]
+use sampler::Sampler;
+#[cfg(not(feature = "display"))]
+use scene::Scene;
 
 pub trait DisplayUpdater {
     fn update(&mut self, film: &Film);
@@ -55,6 +81,17 @@ impl MinifbDisplayUpdater {
 // is only moved to some other thread once at the beginning, this should be fine... (I hope!)
 unsafe impl Send for MinifbDisplayUpdater {}
 
+#[cfg(not(feature = "display"))]
+pub fn render_interactive(_scene: Arc<Scene>,
+                          _integrator: &mut SamplerIntegrator,
+                          _camera: &mut Camera,
+                          _options: &Options,
+                          _sampler: &mut Box<Sampler>,
+                          _block_size: i32)
+                          -> Result<(), Error> {
+    bail!("Display support is not compiled in. Please recompile with the \"display\" feature.")
+}
+
 pub struct NoopDisplayUpdater;
 
 impl DisplayUpdater for NoopDisplayUpdater {
@@ -0,0 +1,151 @@
+use std::f32::consts;
+
+use {clamp, coordinate_system, Point3f, Transform, Vector3f};
+
+/// Maximum elevation of the camera above the orbit's equator, which keeps it from going over the
+/// poles where the view would flip.
+const MAX_ELEVATION: f32 = 0.49 * consts::PI;
+const MIN_DISTANCE: f32 = 1e-3;
+
+/// Placement of a camera orbiting around a target point, looking at it. The orbit's axis is the
+/// camera's initial up vector.
+#[derive(Debug, Clone)]
+pub struct Orbit {
+    target: Point3f,
+    up: Vector3f,
+    e1: Vector3f,
+    e2: Vector3f,
+    distance: f32,
+    /// Angle around the up axis
+    theta: f32,
+    /// Elevation above the plane perpendicular to the up axis
+    phi: f32,
+    /// What the camera's transform does on top of looking at the target (e.g. flipping the image)
+    correction: Transform,
+}
+
+impl Orbit {
+    /// Start orbiting from the camera placement `camera_to_world`, around the point of the
+    /// camera's viewing axis closest to `target`.
+    pub fn new(camera_to_world: &Transform, target: &Point3f) -> Orbit {
+        let eye = camera_to_world * &Point3f::new(0.0, 0.0, 0.0);
+        let dir = (camera_to_world * &Vector3f::new(0.0, 0.0, 1.0)).normalize();
+        let up = (camera_to_world * &Vector3f::new(0.0, 1.0, 0.0)).normalize();
+        let mut distance = (*target - eye).dot(&dir);
+        if distance <= MIN_DISTANCE {
+            // The target is behind the camera
+            distance = f32::max((*target - eye).length(), 1.0);
+        }
+        let center = eye + dir * distance;
+        let (e1, e2) = coordinate_system(&up);
+        let offset = -dir;
+        let correction = Transform::look_at(&eye, &center, &up) * camera_to_world.clone();
+
+        Orbit {
+            target: center,
+            up,
+            e1,
+            e2,
+            distance,
+            theta: offset.dot(&e2).atan2(offset.dot(&e1)),
+            phi: clamp(offset.dot(&up), -1.0, 1.0).asin(),
+            correction,
+        }
+    }
+
+    /// Turn around the target by `d_theta` radians around the up axis, and raise the camera by
+    /// `d_phi` radians.
+    pub fn rotate(&mut self, d_theta: f32, d_phi: f32) {
+        self.theta = (self.theta + d_theta) % (2.0 * consts::PI);
+        self.phi = clamp(self.phi + d_phi, -MAX_ELEVATION, MAX_ELEVATION);
+    }
+
+    /// Multiply the distance to the target by `factor`.
+    pub fn zoom(&mut self, factor: f32) {
+        self.distance = f32::max(self.distance * factor, MIN_DISTANCE);
+    }
+
+    pub fn eye(&self) -> Point3f {
+        let around = self.e1 * self.theta.cos() + self.e2 * self.theta.sin();
+        let offset = around * self.phi.cos() + self.up * self.phi.sin();
+        self.target + offset * self.distance
+    }
+
+    pub fn camera_to_world(&self) -> Transform {
+        Transform::look_at(&self.eye(), &self.target, &self.up).inverse() *
+        self.correction.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Point3f, b: &Point3f) {
+        assert!((*a - *b).length() < 1e-4, "{} != {}", a, b);
+    }
+
+    fn start() -> (Transform, Orbit) {
+        let camera_to_world = Transform::look_at(&Point3f::new(1.0, 2.0, -5.0),
+                                                 &Point3f::new(0.0, 0.0, 0.0),
+                                                 &Vector3f::new(0.0, 1.0, 0.0))
+                .inverse();
+        let orbit = Orbit::new(&camera_to_world, &Point3f::new(0.0, 0.0, 0.0));
+        (camera_to_world, orbit)
+    }
+
+    #[test]
+    fn test_orbit_starts_at_camera() {
+        let (camera_to_world, orbit) = start();
+        let c2w = orbit.camera_to_world();
+        for p in &[Point3f::new(0.0, 0.0, 0.0),
+                   Point3f::new(1.0, 0.0, 0.0),
+                   Point3f::new(0.0, 1.0, 0.0),
+                   Point3f::new(0.0, 0.0, 1.0)] {
+            assert_close(&(&c2w * p), &(&camera_to_world * p));
+        }
+
+        // Flips in the camera transform are kept
+        let flipped = &camera_to_world * &Transform::scale(-1.0, 1.0, 1.0);
+        let orbit = Orbit::new(&flipped, &Point3f::new(0.0, 0.0, 0.0));
+        let p = Point3f::new(1.0, 0.0, 0.0);
+        assert_close(&(&orbit.camera_to_world() * &p), &(&flipped * &p));
+    }
+
+    #[test]
+    fn test_rotation_keeps_looking_at_target() {
+        let (_, mut orbit) = start();
+        let distance = (orbit.eye() - Point3f::new(0.0, 0.0, 0.0)).length();
+        orbit.rotate(0.7, 0.3);
+        let c2w = orbit.camera_to_world();
+        let eye = &c2w * &Point3f::new(0.0, 0.0, 0.0);
+        assert!(((eye - Point3f::new(0.0, 0.0, 0.0)).length() - distance).abs() < 1e-4);
+        assert_close(&(&c2w * &Point3f::new(0.0, 0.0, distance)),
+                     &Point3f::new(0.0, 0.0, 0.0));
+
+        // A full turn brings the camera back
+        let before = orbit.eye();
+        orbit.rotate(2.0 * consts::PI, 0.0);
+        assert_close(&orbit.eye(), &before);
+    }
+
+    #[test]
+    fn test_elevation_stops_before_pole() {
+        let (_, mut orbit) = start();
+        orbit.rotate(0.0, 10.0);
+        let dir = (&orbit.camera_to_world() * &Vector3f::new(0.0, 0.0, 1.0)).normalize();
+        assert!(dir.dot(&orbit.up) > -1.0 + 1e-3);
+        assert!(orbit.phi <= MAX_ELEVATION);
+    }
+
+    #[test]
+    fn test_zoom() {
+        let (_, mut orbit) = start();
+        let distance = orbit.distance;
+        orbit.zoom(0.5);
+        let eye = orbit.eye();
+        assert!(((eye - orbit.target).length() - 0.5 * distance).abs() < 1e-4);
+        orbit.zoom(0.0);
+        assert!(orbit.distance > 0.0);
+    }
+}
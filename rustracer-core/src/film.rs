@@ -4,8 +4,10 @@ use std::f32;
 use failure::Error;
 use parking_lot::Mutex;
 
-use {clamp, Point2f, Point2i, Vector2f};
+use {clamp, Options, Point2f, Point2i, Vector2f};
+use aov::{heatmap, Aov, FirstHit, FirstHitSums};
 use bounds::{Bounds2f, Bounds2i};
+use fileutil::has_extension;
 use filter::Filter;
 use imageio;
 use paramset::ParamSet;
@@ -35,14 +37,87 @@ struct Pixel {
     xyz: [f32; 3],
     filter_weight_sum: f32,
     splat_xyz: [AtomicFloat; 3],
-    _pad: f32,
+    stats: SampleStats,
+    first_hits: FirstHitSums,
+    /// Number of rays traced by the paths of the pixel's samples
+    path_rays: SampleStats,
+}
+
+/// Running statistics of the luminance of the samples taken inside a pixel, accumulated with
+/// Welford's algorithm.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SampleStats {
+    n: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl SampleStats {
+    pub fn add(&mut self, x: f32) {
+        let x = f64::from(x);
+        self.n += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.n as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// Combine two sets of statistics (Chan et al.'s parallel variant of Welford's algorithm).
+    pub fn merge(&mut self, other: &SampleStats) {
+        if other.n == 0 {
+            return;
+        }
+        let n = self.n + other.n;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.n as f64 / n as f64;
+        self.m2 += other.m2 + delta * delta * (self.n as f64 * other.n as f64) / n as f64;
+        self.n = n;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.n
+    }
+
+    pub fn mean(&self) -> f32 {
+        self.mean as f32
+    }
+
+    /// Unbiased sample variance
+    pub fn variance(&self) -> f32 {
+        if self.n < 2 {
+            0.0
+        } else {
+            (self.m2 / (self.n - 1) as f64) as f32
+        }
+    }
+
+    /// Standard error of the mean, i.e. how far the pixel's estimate is likely to be from the
+    /// converged value.
+    pub fn std_error(&self) -> f32 {
+        if self.n < 2 {
+            0.0
+        } else {
+            (self.variance() / self.n as f32).sqrt()
+        }
+    }
 }
 
 pub struct Film {
     pub full_resolution: Point2i,
     pub _diagonal: f32,
     pub filename: String,
+    pub variance_filename: Option<String>,
+    /// Write PNG images with 16 bits per channel instead of 8
+    pub sixteen_bit: bool,
+    /// Encode PNG images with `pow(c, 1 / gamma)` instead of the sRGB curve
+    pub gamma: Option<f32>,
+    /// Outputs to write, each to its own file (see `Aov::filename()`). If empty, only the
+    /// beauty image is written, to `filename`.
+    pub aovs: Vec<Aov>,
     pub cropped_pixel_bounds: Bounds2i,
+    /// Only render the pixels in this region (see `Options::roi`)
+    pub roi: Option<Bounds2i>,
+    /// Image the pixels outside `roi` are copied from, in the crop window's scanline order
+    base: Option<Vec<Spectrum>>,
     pixels: Mutex<Vec<Pixel>>,
     filter_table: [f32; FILTER_TABLE_SIZE],
     filter_radius: Vector2f,
@@ -98,19 +173,47 @@ impl Film {
             scale,
             _diagonal: diagonal * 0.001,
             filename: filename.to_owned(),
+            variance_filename: None,
+            sixteen_bit: false,
+            gamma: None,
+            aovs: Vec::new(),
+            roi: None,
+            base: None,
             max_sample_luminance
         }
     }
 
-    pub fn create(ps: &mut ParamSet, filter: Box<Filter>) -> Box<Film> {
+    pub fn create(ps: &mut ParamSet, filter: Box<Filter>, options: &Options) -> Box<Film> {
         let mut filename = ps.find_one_string("filename", "".into());
-        if filename == "" {
+        if let Some(ref image_file) = options.image_file {
+            if filename != "" {
+                warn!("Output filename supplied on command line, \"{}\", is overriding filename \
+                       provided in scene description file, \"{}\".",
+                      image_file,
+                      filename);
+            }
+            filename = image_file.clone();
+        } else if filename == "" {
             filename = "image.png".into();
         } else {
             filename = String::from("rt-") + &filename;
         }
-        let xres = ps.find_one_int("xresolution", 1280);
-        let yres = ps.find_one_int("yresolution", 720);
+        let mut xres = ps.find_one_int("xresolution", 1280);
+        let mut yres = ps.find_one_int("yresolution", 720);
+        if let Some(resolution) = options.resolution {
+            // The camera maps the screen window to whatever resolution the film has, so the
+            // framing stays the same
+            xres = resolution.x;
+            yres = resolution.y;
+        }
+        let mut resolution_scale = options.resolution_scale;
+        if options.quick_render {
+            resolution_scale /= 4.0;
+        }
+        if resolution_scale != 1.0 {
+            xres = i32::max(1, (xres as f32 * resolution_scale).round() as i32);
+            yres = i32::max(1, (yres as f32 * resolution_scale).round() as i32);
+        }
         let mut crop = Bounds2f::from_points(&Point2f::new(0.0, 0.0), &Point2f::new(1.0, 1.0));
         if let Some(cr) = ps.find_float("cropwindow") {
             if cr.len() == 4 {
@@ -125,13 +228,47 @@ impl Film {
         let scale = ps.find_one_float("scale", 1.0);
         let diagonal = ps.find_one_float("diagonal", 35.0);
         let max_sample_luminance = ps.find_one_float("maxsampleluminance", f32::INFINITY);
+        let variance_filename = ps.find_one_string("variancemap", "".into());
+        let sixteen_bit = ps.find_one_bool("sixteenbit", false);
+        if sixteen_bit && !has_extension(&filename, "png") {
+            warn!("\"sixteenbit\" only applies to PNG images, ignoring it for \"{}\"",
+                  filename);
+        }
+        let gamma = ps.find_one_float("gamma", 0.0);
+        if gamma < 0.0 {
+            warn!("\"gamma\" must be positive, using the sRGB curve instead");
+        }
+        let mut aovs = Vec::new();
+        for name in ps.find_string("aovs").unwrap_or_default() {
+            match Aov::from_name(&name) {
+                Some(aov) => aovs.push(aov),
+                None => warn!("Unknown AOV \"{}\", ignoring it", name),
+            }
+        }
         // TODO max_sample_luminance
-        Box::new(Film::new(Point2i::new(xres, yres),
-                           crop,
-                           filter,
-                           diagonal,
-                           &filename,
-                           scale, max_sample_luminance))
+        let mut film = Film::new(Point2i::new(xres, yres),
+                                 crop,
+                                 filter,
+                                 diagonal,
+                                 &filename,
+                                 scale, max_sample_luminance);
+        if variance_filename != "" {
+            film.variance_filename = Some(variance_filename);
+        }
+        film.sixteen_bit = sixteen_bit;
+        if gamma > 0.0 {
+            film.gamma = Some(gamma);
+        }
+        film.aovs = aovs;
+        if let Some(roi) = options.roi {
+            let clipped = Bounds2i::intersect(&roi, &film.cropped_pixel_bounds);
+            if clipped.area() <= 0 {
+                warn!("Region of interest {} is outside of the image, ignoring it", roi);
+            } else {
+                film.roi = Some(clipped);
+            }
+        }
+        Box::new(film)
     }
 
     pub fn get_film_tile(&self, sample_bounds: &Bounds2i) -> FilmTile {
@@ -153,23 +290,33 @@ impl Film {
     }
 
     pub fn merge_film_tile(&self, tile: FilmTile) {
+        // Convert the tile to XYZ before taking the lock, so other threads only wait for the
+        // additions
+        let tile_bounds = tile.get_pixel_bounds();
+        let xyzs: Vec<[f32; 3]> = tile_bounds
+            .into_iter()
+            .map(|pixel| tile.get_pixel(&pixel).contrib_sum.to_xyz())
+            .collect();
         let mut pixels = self.pixels.lock();
-        for pixel in &tile.get_pixel_bounds() {
+        for (pixel, xyz) in tile_bounds.into_iter().zip(xyzs) {
             let tile_pixel = tile.get_pixel(&pixel);
             let pidx = {
                 let width = self.cropped_pixel_bounds.p_max.x - self.cropped_pixel_bounds.p_min.x;
                 ((pixel.y - self.cropped_pixel_bounds.p_min.y) * width +
                  (pixel.x - self.cropped_pixel_bounds.p_min.x)) as usize
             };
-            let xyz = tile_pixel.contrib_sum.to_xyz();
             for i in 0..3 {
                 pixels[pidx].xyz[i] += xyz[i];
             }
             pixels[pidx].filter_weight_sum += tile_pixel.filter_weight_sum;
+            pixels[pidx].stats.merge(&tile_pixel.stats);
+            pixels[pidx].first_hits.merge(&tile_pixel.first_hits);
+            pixels[pidx].path_rays.merge(&tile_pixel.path_rays);
         }
     }
 
-    pub fn write_image(&self) -> Result<(), Error> {
+    /// Return the final RGB values of the pixels in the crop window, in scanline order.
+    pub fn get_rgb(&self) -> Vec<f32> {
         info!("Converting image to RGB and computing final weighted pixel values");
         let splat_scale = 1.0; // TODO
         let pixels = self.pixels.lock();
@@ -177,6 +324,15 @@ impl Film {
         for p in &self.cropped_pixel_bounds {
             // Convert pixel XYZ color to RGB
             let pixel_idx = self.get_pixel_idx(&p);
+            if let (Some(roi), Some(base)) = (self.roi, self.base.as_ref()) {
+                if !roi.inside_exclusive(&p) {
+                    let base_pixel = base[pixel_idx];
+                    rgb.push(base_pixel[0]);
+                    rgb.push(base_pixel[1]);
+                    rgb.push(base_pixel[2]);
+                    continue;
+                }
+            }
             let pixel = &pixels[pixel_idx];
             let mut rgb_pixel = Spectrum::from_xyz(&pixel.xyz);
 
@@ -207,27 +363,183 @@ impl Film {
             rgb.push(rgb_pixel[2]);
         }
 
+        rgb
+    }
+
+    /// Whether the renderer should record the first hit of each camera ray with
+    /// `FilmTile::add_first_hit()`, for the AOVs.
+    pub fn needs_first_hits(&self) -> bool {
+        self.aovs.iter().any(|aov| aov.needs_first_hit())
+    }
+
+    /// Whether the renderer should record the number of rays of each path with
+    /// `FilmTile::add_path_rays()`.
+    pub fn needs_path_rays(&self) -> bool {
+        self.aovs.contains(&Aov::Bounces)
+    }
+
+    /// Write the image, or each of the AOVs if some were requested.
+    pub fn write_image(&self) -> Result<(), Error> {
+        if self.aovs.is_empty() {
+            return self.write_beauty(&self.filename);
+        }
+        for aov in &self.aovs {
+            let filename = aov.filename(&self.filename);
+            if *aov == Aov::Beauty {
+                self.write_beauty(&filename)?;
+            } else {
+                self.write_aov(*aov, &filename)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_beauty(&self, filename: &str) -> Result<(), Error> {
+        let rgb = self.get_rgb();
         // Write RGB image
         info!("Writing image {} with bounds {}",
-              self.filename,
+              filename,
               self.cropped_pixel_bounds);
-        imageio::write_image(&self.filename,
+        if self.sixteen_bit && has_extension(filename, "png") {
+            imageio::write_image_png16(filename,
+                                       &rgb[..],
+                                       &self.cropped_pixel_bounds,
+                                       self.gamma)
+        } else {
+            imageio::write_image(filename,
+                                 &rgb[..],
+                                 &self.cropped_pixel_bounds,
+                                 &self.full_resolution,
+                                 self.gamma)
+        }
+    }
+
+    /// Write an AOV other than the beauty image. Like the variance map, values are written
+    /// as-is (without gamma) and aren't normalized, so prefer EXR output to avoid clamping.
+    fn write_aov(&self, aov: Aov, filename: &str) -> Result<(), Error> {
+        let pixels = self.pixels.lock();
+        let max_path_rays = pixels.iter().map(|p| p.path_rays.mean()).fold(0.0, f32::max);
+        let mut rgb = Vec::with_capacity(3 * self.cropped_pixel_bounds.area() as usize);
+        for p in &self.cropped_pixel_bounds {
+            let pixel = &pixels[self.get_pixel_idx(&p)];
+            let hits = &pixel.first_hits;
+            let value = match aov {
+                Aov::Normal => {
+                    let n = hits.normal();
+                    [n.x, n.y, n.z]
+                }
+                Aov::Albedo => {
+                    let albedo = hits.albedo();
+                    [albedo[0], albedo[1], albedo[2]]
+                }
+                Aov::Depth => [hits.depth(); 3],
+                Aov::SampleCount => [pixel.stats.count() as f32; 3],
+                Aov::Bounces if pixel.path_rays.count() == 0 => [0.0; 3],
+                Aov::Bounces => heatmap(pixel.path_rays.mean() / max_path_rays),
+                Aov::Beauty => unreachable!(),
+            };
+            rgb.extend_from_slice(&value);
+        }
+
+        info!("Writing {} AOV {}", aov.name(), filename);
+        imageio::write_image(filename,
                              &rgb[..],
                              &self.cropped_pixel_bounds,
-                             &self.full_resolution)
+                             &self.full_resolution,
+                             Some(1.0))
     }
 
+    /// Write a greyscale image of the standard error of each pixel's luminance, to see where the
+    /// image hasn't converged yet. Values aren't normalized, so prefer EXR output to avoid
+    /// clamping.
+    ///
+    /// The error of each pixel is computed from the samples it actually received, however many
+    /// the sampler took there, and the number itself is written by the `samplecount` AOV. There is
+    /// no adaptive sampler yet to vary it from pixel to pixel; one would only need to add its
+    /// samples to the film like the other samplers do.
+    pub fn write_variance_map(&self, path: &str) -> Result<(), Error> {
+        let pixels = self.pixels.lock();
+        let mut rgb = Vec::with_capacity(3 * self.cropped_pixel_bounds.area() as usize);
+        let mut total_samples = 0;
+        for p in &self.cropped_pixel_bounds {
+            let stats = &pixels[self.get_pixel_idx(&p)].stats;
+            total_samples += stats.count();
+            let err = stats.std_error() * self.scale;
+            rgb.push(err);
+            rgb.push(err);
+            rgb.push(err);
+        }
+
+        info!("Writing variance map {} ({} samples over {} pixels)",
+              path,
+              total_samples,
+              self.cropped_pixel_bounds.area());
+        imageio::write_image(path,
+                             &rgb[..],
+                             &self.cropped_pixel_bounds,
+                             &self.full_resolution,
+                             None)
+    }
+
+    /// Bounds of the samples needed to render the image, or only its region of interest if
+    /// there is one.
     pub fn get_sample_bounds(&self) -> Bounds2i {
+        let bounds = self.roi.unwrap_or(self.cropped_pixel_bounds);
         let half = Vector2f::new(0.5, 0.5);
         let float_bounds =
-            Bounds2f::from_points(&floor(Point2f::from(self.cropped_pixel_bounds.p_min) + half -
+            Bounds2f::from_points(&floor(Point2f::from(bounds.p_min) + half -
                                          self.filter_radius),
-                                  &ceil(Point2f::from(self.cropped_pixel_bounds.p_max) - half +
+                                  &ceil(Point2f::from(bounds.p_max) - half +
                                         self.filter_radius));
 
         float_bounds.into()
     }
 
+    /// Load the image to copy the pixels outside the region of interest from, e.g. a previous
+    /// render of the whole image. It must have the resolution of the crop window. PNG images are
+    /// assumed to be encoded like the film's output.
+    pub fn load_base_image(&mut self, path: &str) -> Result<(), Error> {
+        let (pixels, resolution) = imageio::read_image(path)?;
+        let expected = self.cropped_pixel_bounds.diagonal();
+        if resolution.x != expected.x || resolution.y != expected.y ||
+           pixels.len() != self.cropped_pixel_bounds.area() as usize {
+            bail!("Base image {} is {}x{} pixels, but the image being rendered is {}x{}",
+                  path,
+                  resolution.x,
+                  resolution.y,
+                  expected.x,
+                  expected.y);
+        }
+        let pixels = if has_extension(path, "png") {
+            pixels
+                .iter()
+                .map(|c| {
+                         Spectrum::rgb(imageio::decode_gamma(c[0], self.gamma),
+                                       imageio::decode_gamma(c[1], self.gamma),
+                                       imageio::decode_gamma(c[2], self.gamma))
+                     })
+                .collect()
+        } else {
+            pixels
+        };
+        self.base = Some(pixels);
+        Ok(())
+    }
+
+    /// Discard all the samples added so far, to start rendering again from scratch.
+    pub fn clear(&self) {
+        let mut pixels = self.pixels.lock();
+        for pixel in pixels.iter_mut() {
+            *pixel = Pixel::default();
+        }
+    }
+
+    /// Statistics of the samples that were added to the pixel `p`.
+    pub fn get_pixel_stats(&self, p: &Point2i) -> SampleStats {
+        let pixels = self.pixels.lock();
+        pixels[self.get_pixel_idx(p)].stats
+    }
+
     fn get_pixel_idx(&self, p: &Point2i) -> usize {
         assert!(self.cropped_pixel_bounds.inside_exclusive(p));
         let width = self.cropped_pixel_bounds.p_max.x - self.cropped_pixel_bounds.p_min.x;
@@ -286,6 +598,13 @@ impl FilmTile {
                                                                   &float_pixel_bounds));
         let (p0, p1) = (bounds.p_min, bounds.p_max);
 
+        // Record the sample's luminance in the pixel it was taken in
+        let p_pixel = Point2i::new(p_film.x.floor() as i32, p_film.y.floor() as i32);
+        if self.pixel_bounds.inside_exclusive(&p_pixel) {
+            let idx = self.get_pixel_index(&p_pixel);
+            self.pixels[idx].stats.add(L.y());
+        }
+
         assert!(p1.x >= p0.x && p1.y >= p0.y,
                 format!("p_film={}, p0={}, p1={}, pixel_bounds={:?}",
                         p_film,
@@ -324,6 +643,25 @@ impl FilmTile {
         }
     }
 
+    /// Record what the camera ray of the sample at `p_film` saw first, or `None` if it didn't
+    /// hit anything, in the pixel it was taken in.
+    pub fn add_first_hit(&mut self, p_film: &Point2f, hit: Option<&FirstHit>) {
+        let p_pixel = Point2i::new(p_film.x.floor() as i32, p_film.y.floor() as i32);
+        if self.pixel_bounds.inside_exclusive(&p_pixel) {
+            let idx = self.get_pixel_index(&p_pixel);
+            self.pixels[idx].first_hits.add(hit);
+        }
+    }
+
+    /// Record the number of rays traced by the path of the sample at `p_film`.
+    pub fn add_path_rays(&mut self, p_film: &Point2f, n: u32) {
+        let p_pixel = Point2i::new(p_film.x.floor() as i32, p_film.y.floor() as i32);
+        if self.pixel_bounds.inside_exclusive(&p_pixel) {
+            let idx = self.get_pixel_index(&p_pixel);
+            self.pixels[idx].path_rays.add(n as f32);
+        }
+    }
+
     pub fn get_pixel<'a>(&'a self, p: &Point2i) -> &'a FilmTilePixel {
         &self.pixels[self.get_pixel_index(p)]
     }
@@ -343,6 +681,9 @@ impl FilmTile {
 pub struct FilmTilePixel {
     contrib_sum: Spectrum,
     filter_weight_sum: f32,
+    stats: SampleStats,
+    first_hits: FirstHitSums,
+    path_rays: SampleStats,
 }
 
 fn ceil(p: Point2f) -> Point2f {
@@ -352,3 +693,312 @@ fn ceil(p: Point2f) -> Point2f {
 fn floor(p: Point2f) -> Point2f {
     Point2f::new(p.x.floor(), p.y.floor())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::{self, File};
+
+    use img;
+    use img::ImageDecoder;
+    use img::png::PNGDecoder;
+
+    use api::{Array, ParamListEntry, ParamType};
+    use filter::GaussianFilter;
+
+    #[test]
+    fn test_sample_stats() {
+        let samples = [0.5, 1.5, 0.0, 4.0, 2.25, 0.75, 3.0, 1.0];
+        let n = samples.len() as f32;
+        let mean = samples.iter().sum::<f32>() / n;
+        let variance = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f32>() / (n - 1.0);
+
+        let mut all = SampleStats::default();
+        for x in &samples {
+            all.add(*x);
+        }
+        assert_eq!(all.count(), 8);
+        assert!((all.mean() - mean).abs() < 1e-6);
+        assert!((all.variance() - variance).abs() < 1e-5);
+        assert!((all.std_error() - (variance / n).sqrt()).abs() < 1e-5);
+
+        // Merging partial statistics gives the same result
+        let (mut a, mut b) = (SampleStats::default(), SampleStats::default());
+        for x in &samples[..3] {
+            a.add(*x);
+        }
+        for x in &samples[3..] {
+            b.add(*x);
+        }
+        a.merge(&b);
+        a.merge(&SampleStats::default());
+        assert_eq!(a.count(), 8);
+        assert!((a.mean() - mean).abs() < 1e-6);
+        assert!((a.variance() - variance).abs() < 1e-5);
+    }
+
+    /// Splat a single sample at `p_film` and compare the weight each pixel received with the
+    /// filter evaluated at the pixel's center.
+    fn check_filter_weights(p_film: Point2f, tolerance: f32) {
+        let make_filter = || GaussianFilter::new(&Vector2f::new(2.0, 1.0), 2.0);
+        let filter = make_filter();
+        let film = Film::new(Point2i::new(32, 32),
+                             Bounds2f::from_points(&Point2f::new(0.0, 0.0),
+                                                   &Point2f::new(1.0, 1.0)),
+                             Box::new(make_filter()),
+                             35.0,
+                             "test.png",
+                             1.0,
+                             f32::INFINITY);
+        let mut tile = film.get_film_tile(&Bounds2i::from_points(&Point2i::new(8, 8),
+                                                                 &Point2i::new(16, 16)));
+        tile.add_sample(&p_film, Spectrum::white());
+
+        let mut n_weighted = 0;
+        for p in &tile.get_pixel_bounds() {
+            let weight = tile.get_pixel(&p).filter_weight_sum;
+            let expected = filter.evaluate(p.x as f32 + 0.5 - p_film.x,
+                                           p.y as f32 + 0.5 - p_film.y);
+            assert!((weight - expected).abs() <= tolerance,
+                    "pixel {}: table weight {}, filter {}",
+                    p,
+                    weight,
+                    expected);
+            if weight > 0.0 {
+                n_weighted += 1;
+            }
+        }
+        // 4 pixels wide, 2 pixels high
+        assert_eq!(n_weighted, 8);
+    }
+
+    #[test]
+    fn test_filter_table_matches_filter() {
+        ::init_stats();
+        // Pixel centers fall in the middle of the table's cells: the weights are exact
+        check_filter_weights(Point2f::new(10.0625, 11.03125), 1e-5);
+        // Elsewhere they're within the filter's variation over a cell
+        check_filter_weights(Point2f::new(11.3, 12.71), 0.1);
+        check_filter_weights(Point2f::new(12.9, 9.05), 0.1);
+    }
+
+    /// Write a small film to a PNG file and return the colour type the file reports.
+    fn written_png_colour_type(sixteen_bit: bool) -> img::ColorType {
+        let filename = env::temp_dir().join(format!("rustracer-test-{}bit.png",
+                                                    if sixteen_bit { 16 } else { 8 }));
+        let mut film = Film::new(Point2i::new(4, 2),
+                                 Bounds2f::from_points(&Point2f::new(0.0, 0.0),
+                                                       &Point2f::new(1.0, 1.0)),
+                                 Box::new(GaussianFilter::new(&Vector2f::new(1.0, 1.0), 2.0)),
+                                 35.0,
+                                 filename.to_str().unwrap(),
+                                 1.0,
+                                 f32::INFINITY);
+        film.sixteen_bit = sixteen_bit;
+        film.write_image().unwrap();
+
+        let mut decoder = PNGDecoder::new(File::open(&filename).unwrap());
+        let colour_type = decoder.colortype().unwrap();
+        let _ = fs::remove_file(&filename);
+        colour_type
+    }
+
+    #[test]
+    fn test_sixteen_bit_png() {
+        ::init_stats();
+        assert_eq!(written_png_colour_type(false), img::RGB(8));
+        assert_eq!(written_png_colour_type(true), img::RGB(16));
+    }
+
+    #[test]
+    fn test_gamma_one_writes_linear_values() {
+        ::init_stats();
+        let filename = env::temp_dir().join("rustracer-test-gamma.png");
+        let mut film = Film::new(Point2i::new(4, 2),
+                                 Bounds2f::from_points(&Point2f::new(0.0, 0.0),
+                                                       &Point2f::new(1.0, 1.0)),
+                                 Box::new(GaussianFilter::new(&Vector2f::new(1.0, 1.0), 2.0)),
+                                 35.0,
+                                 filename.to_str().unwrap(),
+                                 1.0,
+                                 f32::INFINITY);
+        film.gamma = Some(1.0);
+        let sample_bounds = film.get_sample_bounds();
+        let mut tile = film.get_film_tile(&sample_bounds);
+        for p in &sample_bounds {
+            tile.add_sample(&(Point2f::from(p) + Vector2f::new(0.5, 0.5)),
+                            Spectrum::grey(0.25));
+        }
+        film.merge_film_tile(tile);
+        film.write_image().unwrap();
+
+        let image = img::open(&filename).unwrap().to_rgb();
+        let _ = fs::remove_file(&filename);
+        // sRGB would have encoded 0.25 as 137
+        for v in image.into_raw() {
+            assert_eq!(v, 64);
+        }
+    }
+
+    #[test]
+    fn test_only_requested_aovs_are_written() {
+        ::init_stats();
+        let dir = env::temp_dir().join("rustracer-test-aovs");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut ps = ParamSet::default();
+        ps.init(vec![ParamListEntry::new(ParamType::String,
+                                         "aovs".to_owned(),
+                                         Array::StrArray(vec!["beauty".to_owned(),
+                                                              "depth".to_owned()]))]);
+        let options = Options {
+            image_file: Some(dir.join("image.png").to_str().unwrap().to_owned()),
+            resolution: Some(Point2i::new(4, 2)),
+            ..Options::default()
+        };
+        let film = Film::create(&mut ps,
+                                Box::new(GaussianFilter::new(&Vector2f::new(1.0, 1.0), 2.0)),
+                                &options);
+        assert_eq!(film.aovs, vec![Aov::Beauty, Aov::Depth]);
+        assert!(film.needs_first_hits());
+        film.write_image().unwrap();
+
+        let mut written: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        written.sort();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(written, vec!["image_beauty.png", "image_depth.png"]);
+    }
+
+    #[test]
+    fn test_roi_is_composited_over_base() {
+        ::init_stats();
+        let resolution = Point2i::new(8, 6);
+        let full = Bounds2i::from_points(&Point2i::new(0, 0), &resolution);
+        let base_filename = env::temp_dir().join("rustracer-test-roi-base.png");
+        let base_filename = base_filename.to_str().unwrap();
+        // A different grey level in each pixel
+        let base: Vec<f32> = (0..full.area())
+            .flat_map(|i| vec![(5 * i) as f32 / 255.0; 3])
+            .collect();
+        imageio::write_image(base_filename, &base, &full, &resolution, Some(1.0)).unwrap();
+
+        let filename = env::temp_dir().join("rustracer-test-roi.png");
+        let mut film = Film::new(resolution,
+                                 Bounds2f::from_points(&Point2f::new(0.0, 0.0),
+                                                       &Point2f::new(1.0, 1.0)),
+                                 Box::new(GaussianFilter::new(&Vector2f::new(1.0, 1.0), 2.0)),
+                                 35.0,
+                                 filename.to_str().unwrap(),
+                                 1.0,
+                                 f32::INFINITY);
+        film.gamma = Some(1.0);
+        let roi = Bounds2i::from_points(&Point2i::new(2, 2), &Point2i::new(5, 4));
+        film.roi = Some(roi);
+        film.load_base_image(base_filename).unwrap();
+        // Only the region of interest (and the filter's reach around it) gets samples
+        let sample_bounds = film.get_sample_bounds();
+        assert!(sample_bounds.area() < film.cropped_pixel_bounds.area());
+        let mut tile = film.get_film_tile(&sample_bounds);
+        for p in &sample_bounds {
+            tile.add_sample(&(Point2f::from(p) + Vector2f::new(0.5, 0.5)), Spectrum::white());
+        }
+        film.merge_film_tile(tile);
+        film.write_image().unwrap();
+
+        let image = img::open(&filename).unwrap().to_rgb();
+        let _ = fs::remove_file(&filename);
+        let _ = fs::remove_file(base_filename);
+        for (i, p) in full.into_iter().enumerate() {
+            let pixel = image.get_pixel(p.x as u32, p.y as u32).data;
+            let expected = if roi.inside_exclusive(&p) { 255 } else { 5 * i as u8 };
+            assert_eq!(pixel, [expected; 3], "pixel {}", p);
+        }
+    }
+
+    #[test]
+    fn test_roi_outside_image_is_ignored() {
+        ::init_stats();
+        let options = Options {
+            resolution: Some(Point2i::new(8, 6)),
+            roi: Some(Bounds2i::from_points(&Point2i::new(10, 0), &Point2i::new(12, 4))),
+            ..Options::default()
+        };
+        let film = Film::create(&mut ParamSet::default(),
+                                Box::new(GaussianFilter::new(&Vector2f::new(1.0, 1.0), 2.0)),
+                                &options);
+        assert!(film.roi.is_none());
+        assert_eq!(film.get_sample_bounds().area(),
+                   Bounds2i::from_points(&Point2i::new(-1, -1), &Point2i::new(9, 7)).area());
+    }
+
+    #[test]
+    fn test_base_image_must_match_resolution() {
+        ::init_stats();
+        let base_filename = env::temp_dir().join("rustracer-test-small-base.png");
+        let base_filename = base_filename.to_str().unwrap();
+        let resolution = Point2i::new(4, 3);
+        let bounds = Bounds2i::from_points(&Point2i::new(0, 0), &resolution);
+        imageio::write_image(base_filename,
+                             &vec![0.5; 3 * bounds.area() as usize],
+                             &bounds,
+                             &resolution,
+                             None)
+                .unwrap();
+
+        let mut film = Film::new(Point2i::new(8, 6),
+                                 Bounds2f::from_points(&Point2f::new(0.0, 0.0),
+                                                       &Point2f::new(1.0, 1.0)),
+                                 Box::new(GaussianFilter::new(&Vector2f::new(1.0, 1.0), 2.0)),
+                                 35.0,
+                                 "rustracer-test-unused.png",
+                                 1.0,
+                                 f32::INFINITY);
+        film.roi = Some(Bounds2i::from_points(&Point2i::new(2, 2), &Point2i::new(5, 4)));
+        let result = film.load_base_image(base_filename);
+        let _ = fs::remove_file(base_filename);
+        assert!(result.is_err());
+        assert!(film.base.is_none());
+    }
+
+    #[test]
+    fn test_pixel_error_follows_its_sample_count() {
+        ::init_stats();
+        let film = Film::new(Point2i::new(4, 2),
+                             Bounds2f::from_points(&Point2f::new(0.0, 0.0),
+                                                   &Point2f::new(1.0, 1.0)),
+                             Box::new(GaussianFilter::new(&Vector2f::new(1.0, 1.0), 2.0)),
+                             35.0,
+                             "rustracer-test-unused.png",
+                             1.0,
+                             f32::INFINITY);
+        // The same luminances, 4 times in one pixel and 16 times in the other
+        let bounds = film.get_sample_bounds();
+        let mut tile = film.get_film_tile(&bounds);
+        let (few, many) = (Point2i::new(0, 0), Point2i::new(3, 1));
+        for (p, n) in vec![(few, 4), (many, 16)] {
+            for i in 0..n {
+                let l = if i % 2 == 0 { 0.0 } else { 1.0 };
+                tile.add_sample(&(Point2f::from(p) + Vector2f::new(0.5, 0.5)), Spectrum::grey(l));
+            }
+        }
+        film.merge_film_tile(tile);
+
+        let (few, many) = (film.get_pixel_stats(&few), film.get_pixel_stats(&many));
+        assert_eq!(few.count(), 4);
+        assert_eq!(many.count(), 16);
+        assert!(few.std_error() > many.std_error());
+        assert_eq!(film.get_pixel_stats(&Point2i::new(1, 0)).count(), 0);
+    }
+
+    #[test]
+    fn test_single_sample_has_no_error() {
+        let mut stats = SampleStats::default();
+        stats.add(3.0);
+        assert_eq!(stats.variance(), 0.0);
+        assert_eq!(stats.std_error(), 0.0);
+    }
+}
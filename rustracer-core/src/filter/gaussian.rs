@@ -46,3 +46,40 @@ impl Filter for GaussianFilter {
         self.inv_radius
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::{Array, ParamListEntry, ParamType};
+
+    #[test]
+    fn test_truncated_gaussian() {
+        let filter = GaussianFilter::new(&Vector2f::new(2.0, 1.5), 2.0);
+        let g = |d: f32, r: f32| (-2.0 * d * d).exp() - (-2.0 * r * r).exp();
+
+        assert!((filter.evaluate(0.0, 0.0) - g(0.0, 2.0) * g(0.0, 1.5)).abs() < 1e-6);
+        assert!((filter.evaluate(0.7, -0.4) - g(0.7, 2.0) * g(0.4, 1.5)).abs() < 1e-6);
+        // Positive inside the support, zero on its edges and beyond
+        assert!(filter.evaluate(1.99, 1.49) > 0.0);
+        assert_eq!(filter.evaluate(2.0, 0.0), 0.0);
+        assert_eq!(filter.evaluate(0.0, -1.5), 0.0);
+        assert_eq!(filter.evaluate(3.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_create() {
+        let float_param = |name: &str, v: f32| {
+            ParamListEntry::new(ParamType::Float, name.to_owned(), Array::NumArray(vec![v]))
+        };
+        let mut ps = ParamSet::default();
+        ps.init(vec![float_param("xwidth", 1.0),
+                     float_param("ywidth", 3.0),
+                     float_param("alpha", 0.5)]);
+        let filter = GaussianFilter::create(&mut ps);
+
+        assert_eq!(filter.width(), (1.0, 3.0));
+        assert_eq!(filter.evaluate(1.0, 0.0), 0.0);
+        let expected = (1.0 - (-0.5f32).exp()) * (1.0 - (-4.5f32).exp());
+        assert!((filter.evaluate(0.0, 0.0) - expected).abs() < 1e-6);
+    }
+}
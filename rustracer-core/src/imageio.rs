@@ -1,6 +1,6 @@
 use std::path::Path;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 
 use failure::{Error, ResultExt};
 use img;
@@ -10,7 +10,7 @@ use openexr::{FrameBuffer, FrameBufferMut, InputFile, ScanlineOutputFile, Header
 use {Point2i, clamp};
 use bounds::Bounds2i;
 use fileutil::has_extension;
-use spectrum::{Spectrum, gamma_correct};
+use spectrum::{Spectrum, gamma_correct, inverse_gamma_convert_float};
 
 pub fn read_image<P: AsRef<Path>>(path: P) -> Result<(Vec<Spectrum>, Point2i), Error> {
     info!("Loading image {}", path.as_ref().display());
@@ -30,17 +30,39 @@ pub fn read_image<P: AsRef<Path>>(path: P) -> Result<(Vec<Spectrum>, Point2i), E
     }
 }
 
+/// Encode a linear value for an 8 or 16 bit image: with the sRGB curve by default, or with a
+/// plain power curve if a custom `gamma` is given.
+pub fn encode_gamma(v: f32, gamma: Option<f32>) -> f32 {
+    match gamma {
+        Some(g) => f32::max(0.0, v).powf(1.0 / g),
+        None => gamma_correct(v),
+    }
+}
+
+/// Inverse of `encode_gamma()`: convert a value read from an 8 or 16 bit image back to linear.
+pub fn decode_gamma(v: f32, gamma: Option<f32>) -> f32 {
+    match gamma {
+        Some(g) => f32::max(0.0, v).powf(g),
+        None => inverse_gamma_convert_float(v),
+    }
+}
+
+/// Write an image, choosing the format from the file extension. `gamma` is only used by formats
+/// that store encoded values (PNG); floating point formats are always written linear.
 pub fn write_image<P: AsRef<Path>>(name: P,
                                    rgb: &[f32],
                                    output_bounds: &Bounds2i,
-                                   total_resolution: &Point2i)
+                                   total_resolution: &Point2i,
+                                   gamma: Option<f32>)
                                    -> Result<(), Error> {
     let path = name.as_ref();
 
     if has_extension(path, "png") {
-        write_image_png(path, rgb, output_bounds, total_resolution)
+        write_image_png(path, rgb, output_bounds, total_resolution, gamma)
     } else if has_extension(path, "exr") {
         write_image_exr(path, rgb, output_bounds, total_resolution)
+    } else if has_extension(path, "pfm") {
+        write_image_pfm(path, rgb, output_bounds)
     } else {
         Err(format_err!("Unsupported file format"))
     }
@@ -49,12 +71,13 @@ pub fn write_image<P: AsRef<Path>>(name: P,
 fn write_image_png<P: AsRef<Path>>(name: P,
                                    rgb: &[f32],
                                    output_bounds: &Bounds2i,
-                                   _total_resolution: &Point2i)
+                                   _total_resolution: &Point2i,
+                                   gamma: Option<f32>)
                                    -> Result<(), Error> {
     let path = name.as_ref();
     let resolution = output_bounds.diagonal();
     let rgb8: Vec<_> = rgb.iter()
-        .map(|v| clamp(255.0 * gamma_correct(*v) + 0.5, 0.0, 255.0) as u8)
+        .map(|v| clamp(255.0 * encode_gamma(*v, gamma) + 0.5, 0.0, 255.0) as u8)
         .collect();
 
 
@@ -67,13 +90,39 @@ fn write_image_png<P: AsRef<Path>>(name: P,
     Ok(())
 }
 
+/// Write a PNG image with 16 bits per channel, which avoids the banding 8 bit images show in
+/// smooth gradients. Values are encoded as in `encode_gamma`.
+pub fn write_image_png16<P: AsRef<Path>>(name: P,
+                                         rgb: &[f32],
+                                         output_bounds: &Bounds2i,
+                                         gamma: Option<f32>)
+                                         -> Result<(), Error> {
+    let path = name.as_ref();
+    let resolution = output_bounds.diagonal();
+    // PNG stores 16 bit samples in big-endian order
+    let mut rgb16 = Vec::with_capacity(2 * rgb.len());
+    for v in rgb {
+        let v = clamp(65535.0 * encode_gamma(*v, gamma) + 0.5, 0.0, 65535.0) as u16;
+        rgb16.push((v >> 8) as u8);
+        rgb16.push((v & 0xff) as u8);
+    }
+
+    img::save_buffer(path,
+                     &rgb16,
+                     resolution.x as u32,
+                     resolution.y as u32,
+                     img::RGB(16))
+            .context(format!("Failed to save image file {}", path.display()))?;
+    Ok(())
+}
+
 #[cfg(not(feature="exr"))]
 fn write_image_exr<P: AsRef<Path>>(name: P,
                                    rgb: &[f32],
                                    output_bounds: &Bounds2i,
                                    total_resolution: &Point2i)
                                    -> Result<(), Error> {
-    panic!("EXR support is not compiled in. Please recompile with the \"exr\" feature.")
+    bail!("EXR support is not compiled in. Please recompile with the \"exr\" feature.")
 }
 
 #[cfg(feature="exr")]
@@ -107,6 +156,34 @@ fn write_image_exr<P: AsRef<Path>>(name: P,
     Ok(())
 }
 
+/// Write linear floating point RGB values to a PFM file. Unlike EXR, this doesn't need any
+/// optional dependency.
+fn write_image_pfm<P: AsRef<Path>>(name: P,
+                                   rgb: &[f32],
+                                   output_bounds: &Bounds2i)
+                                   -> Result<(), Error> {
+    let path = name.as_ref();
+    let resolution = output_bounds.diagonal();
+    let (width, height) = (resolution.x as usize, resolution.y as usize);
+    let file = File::create(path)
+        .context(format!("Failed to create PFM file {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    // A negative scale means the data is little-endian
+    write!(writer, "PF\n{} {}\n-1\n", width, height)?;
+    // Flip in Y, as P*M has the origin at the lower left.
+    for y in (0..height).rev() {
+        for v in &rgb[3 * y * width..3 * (y + 1) * width] {
+            let bits = v.to_bits();
+            writer.write_all(&[bits as u8,
+                               (bits >> 8) as u8,
+                               (bits >> 16) as u8,
+                               (bits >> 24) as u8])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
 fn read_image_tga_png<P: AsRef<Path>>(path: P) -> Result<(Vec<Spectrum>, Point2i), Error> {
     info!("Loading texture {}", path.as_ref().display());
     let buf = img::open(path)?;
@@ -127,7 +204,8 @@ fn read_image_tga_png<P: AsRef<Path>>(path: P) -> Result<(Vec<Spectrum>, Point2i
 
 fn read_image_hdr<P: AsRef<Path>>(path: P) -> Result<(Vec<Spectrum>, Point2i), Error> {
     info!("Loading HDR image {}", path.as_ref().display());
-    let file = File::open(path.as_ref())?;
+    let file = File::open(path.as_ref())
+        .context(format!("Failed to open HDR file {}", path.as_ref().display()))?;
     let reader = BufReader::new(file);
     let hdr = img::hdr::HDRDecoder::with_strictness(reader, false)?;
 
@@ -142,14 +220,15 @@ fn read_image_hdr<P: AsRef<Path>>(path: P) -> Result<(Vec<Spectrum>, Point2i), E
 
 #[cfg(not(feature="exr"))]
 fn read_image_exr<P: AsRef<Path>>(_path: P) -> Result<(Vec<Spectrum>, Point2i), Error> {
-    panic!("EXR support is not compiled in. Please recompile with the \"exr\" feature.")
+    bail!("EXR support is not compiled in. Please recompile with the \"exr\" feature.")
 }
 
 #[cfg(feature="exr")]
 fn read_image_exr<P: AsRef<Path>>(path: P) -> Result<(Vec<Spectrum>, Point2i), Error> {
     info!("Loading EXR texture {}", path.as_ref().display());
-    let mut file = File::open(path.as_ref())?;
-    let mut exr_file = InputFile::new(&mut file).unwrap();
+    let mut file = File::open(path.as_ref())
+        .context(format!("Failed to open EXR file {}", path.as_ref().display()))?;
+    let mut exr_file = InputFile::new(&mut file)?;
     let (width, height) = {
         let window = exr_file.header().data_window();
         let width = window.max.x - window.min.x + 1;
@@ -167,7 +246,7 @@ fn read_image_exr<P: AsRef<Path>>(path: P) -> Result<(Vec<Spectrum>, Point2i), E
             fb
         };
 
-        exr_file.read_pixels(&mut fb).unwrap();
+        exr_file.read_pixels(&mut fb)?;
     }
 
     let pixels = pixel_data
@@ -196,7 +275,8 @@ fn read_word<R: BufRead>(f: &mut R) -> String {
 
 fn read_image_pfm<P: AsRef<Path>>(path: P) -> Result<(Vec<Spectrum>, Point2i), Error> {
     info!("Loading PFM file {}", path.as_ref().display());
-    let file = File::open(path.as_ref())?;
+    let file = File::open(path.as_ref())
+        .context(format!("Failed to open PFM file {}", path.as_ref().display()))?;
     let mut reader = BufReader::new(file);
     let mut word;
 
@@ -264,3 +344,28 @@ fn read_image_pfm<P: AsRef<Path>>(path: P) -> Result<(Vec<Spectrum>, Point2i), E
 
     Ok((rgb, Point2i::new(width as i32, height as i32)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_pfm_round_trip() {
+        let resolution = Point2i::new(3, 2);
+        let bounds = Bounds2i::from_points(&Point2i::new(0, 0), &resolution);
+        let rgb: Vec<f32> = (0..18).map(|i| i as f32 * 0.25 - 1.0).collect();
+        let filename = env::temp_dir().join("rustracer-test-round-trip.pfm");
+        write_image(&filename, &rgb, &bounds, &resolution, None).unwrap();
+        let (pixels, res) = read_image(&filename).unwrap();
+        let _ = fs::remove_file(&filename);
+
+        assert_eq!(res, resolution);
+        for (i, p) in pixels.iter().enumerate() {
+            for c in 0..3 {
+                assert_eq!(p[c], rgb[3 * i + c]);
+            }
+        }
+    }
+}
@@ -1,26 +1,43 @@
-use std::f32;
+use std::f32::consts;
 
+use light_arena::Allocator;
+
+use {coordinate_system, Vector3f};
 use bounds::Bounds2i;
+use geometry::face_forward;
 use spectrum::Spectrum;
-use light_arena::Allocator;
 use integrator::SamplerIntegrator;
+use paramset::ParamSet;
 use ray::Ray;
 use sampler::Sampler;
-use sampling::uniform_sample_sphere;
+use sampling::{cosine_hemisphere_pdf, cosine_sample_hemisphere, uniform_hemisphere_pdf,
+               uniform_sample_hemisphere};
 use scene::Scene;
 
+/// Ambient occlusion: the cosine-weighted fraction of the hemisphere around the first surface
+/// hit that isn't blocked by other geometry, as grey. An unoccluded surface is white.
 pub struct AmbientOcclusion {
     pixel_bounds: Bounds2i,
     n_samples: usize,
+    /// Sample directions proportionally to the cosine term rather than uniformly over the
+    /// hemisphere. Both give the same result, but cosine sampling has less variance.
+    cos_sample: bool,
 }
 
 impl AmbientOcclusion {
-    pub fn new(n_samples: usize) -> AmbientOcclusion {
+    pub fn new(n_samples: usize, cos_sample: bool) -> AmbientOcclusion {
         AmbientOcclusion {
             n_samples: n_samples,
+            cos_sample: cos_sample,
             pixel_bounds: Bounds2i::new(),
         }
     }
+
+    pub fn create(ps: &mut ParamSet) -> Box<SamplerIntegrator> {
+        let n_samples = ps.find_one_int("nsamples", 64);
+        let cos_sample = ps.find_one_bool("cossample", true);
+        Box::new(Self::new(n_samples.max(1) as usize, cos_sample))
+    }
 }
 
 impl SamplerIntegrator for AmbientOcclusion {
@@ -35,23 +52,107 @@ impl SamplerIntegrator for AmbientOcclusion {
           _arena: &Allocator,
           _depth: u32)
           -> Spectrum {
-        let mut n_clear: usize = 0;
+        let mut l = 0.0;
 
         if let Some(intersection) = scene.intersect(ray) {
-            let n = intersection.hit.n;
+            let n = face_forward(&Vector3f::from(intersection.hit.n), &(-ray.d));
+            let (s, t) = coordinate_system(&n);
             for _ in 0..self.n_samples {
-                let s = sampler.get_2d();
-                let mut w = uniform_sample_sphere(&s);
-                if w.dotn(&n) < 0.0 {
-                    w = -w;
+                let u = sampler.get_2d();
+                let (wi, pdf) = if self.cos_sample {
+                    let wi = cosine_sample_hemisphere(&u);
+                    (wi, cosine_hemisphere_pdf(wi.z))
+                } else {
+                    (uniform_sample_hemisphere(&u), uniform_hemisphere_pdf())
+                };
+                if pdf == 0.0 {
+                    continue;
                 }
-                let ao_ray = intersection.spawn_ray(&w);
+                // Local hemisphere direction to world space
+                let wi = wi.x * s + wi.y * t + wi.z * n;
+                let ao_ray = intersection.spawn_ray(&wi);
                 if !scene.intersect_p(&ao_ray) {
-                    n_clear += 1;
+                    // The integral of cos(theta) over the hemisphere is pi
+                    l += wi.dot(&n) * consts::FRAC_1_PI / pdf;
                 }
             }
         }
 
-        Spectrum::grey((n_clear as f32) / (self.n_samples as f32))
+        Spectrum::grey(l / self.n_samples as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use {Point3f, Transform};
+    use bvh::{BVH, SplitMethod};
+    use light_arena::MemoryArena;
+    use material::MatteMaterial;
+    use primitive::{GeometricPrimitive, Primitive};
+    use sampler::random::RandomSampler;
+    use shapes::{Plane, Shape, Sphere};
+
+    /// Mean and variance of the ambient occlusion estimates at the origin of a ground plane, under
+    /// a unit sphere 2 units above it.
+    fn occlusion_stats(cos_sample: bool) -> (f32, f32) {
+        let ground: Arc<Shape> =
+            Arc::new(Plane::new(Point3f::new(0.0, 0.0, 0.0), Vector3f::new(0.0, 0.0, 1.0), false));
+        let ball: Arc<Shape> = Arc::new(Sphere::new(Transform::translate(&Vector3f::new(0.0,
+                                                                                      0.0,
+                                                                                      2.0)),
+                                                    1.0,
+                                                    -1.0,
+                                                    1.0,
+                                                    360.0,
+                                                    false));
+        let prims: Vec<Arc<Primitive>> = vec![ground, ball]
+            .into_iter()
+            .map(|shape| {
+                     Arc::new(GeometricPrimitive {
+                                  shape,
+                                  area_light: None,
+                                  material: Some(MatteMaterial::black()),
+                              }) as Arc<Primitive>
+                 })
+            .collect();
+        let scene = Scene::new(Arc::new(BVH::new(1, &prims, SplitMethod::SAH)), Vec::new());
+
+        let integrator = AmbientOcclusion::new(4, cos_sample);
+        let mut sampler: Box<Sampler> = Box::new(RandomSampler::new(1));
+        let mut arena = MemoryArena::new(1);
+        let n = 4000;
+        let estimates: Vec<f32> = (0..n)
+            .map(|_| {
+                let mut ray = Ray::new(Point3f::new(-1.0, 0.0, 0.5), Vector3f::new(1.0, 0.0, -0.5));
+                integrator.li(&scene, &mut ray, &mut sampler, &arena.allocator(), 0)[0]
+            })
+            .collect();
+        let mean = estimates.iter().sum::<f32>() / n as f32;
+        let variance = estimates.iter().map(|e| (e - mean) * (e - mean)).sum::<f32>() /
+                       (n - 1) as f32;
+        (mean, variance)
+    }
+
+    #[test]
+    fn test_cosine_sampling_has_less_variance() {
+        ::init_stats();
+        // The sphere covers a cone of half-angle 30 degrees straight above the point, which hides
+        // sin^2(30) = 1/4 of the cosine-weighted hemisphere.
+        let expected = 0.75;
+        let (uniform_mean, uniform_variance) = occlusion_stats(false);
+        let (cosine_mean, cosine_variance) = occlusion_stats(true);
+        assert!((uniform_mean - expected).abs() < 0.02,
+                "uniform sampling: {}",
+                uniform_mean);
+        assert!((cosine_mean - expected).abs() < 0.02,
+                "cosine sampling: {}",
+                cosine_mean);
+        assert!(cosine_variance < 0.8 * uniform_variance,
+                "variance {} with cosine sampling, {} with uniform sampling",
+                cosine_variance,
+                uniform_variance);
     }
 }
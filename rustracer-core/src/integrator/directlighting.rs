@@ -1,8 +1,11 @@
 use std::sync::Arc;
 
+use Point2f;
 use bounds::Bounds2i;
+use interaction::SurfaceInteraction;
 use light_arena::Allocator;
-use integrator::{uniform_sample_all_light, uniform_sample_one_light, SamplerIntegrator};
+use integrator::{choose_light, estimate_direct, uniform_sample_all_light,
+                 uniform_sample_one_light, SamplerIntegrator};
 use material::TransportMode;
 use paramset::ParamSet;
 use ray::Ray;
@@ -29,6 +32,8 @@ pub struct DirectLightingIntegrator {
     max_depth: u8,
     //
     n_light_samples: Vec<usize>,
+    /// Number of lights chosen per pixel sample with the UniformSampleOne strategy
+    n_one_light_samples: usize,
 }
 
 impl DirectLightingIntegrator {
@@ -38,6 +43,7 @@ impl DirectLightingIntegrator {
             max_depth: n,
             light_strategy: strategy,
             n_light_samples: Vec::new(),
+            n_one_light_samples: 1,
         }
     }
 
@@ -56,6 +62,49 @@ impl DirectLightingIntegrator {
         // TODO pixel_bounds
         Box::new(Self::new(max_depth as u8, strategy))
     }
+
+    /// Estimate the direct lighting at `it` from one light chosen at random, using the sample
+    /// arrays requested in `preprocess()` if the sampler provides them.
+    fn sample_one_light(&self,
+                        it: &SurfaceInteraction,
+                        scene: &Scene,
+                        sampler: &mut Box<Sampler>)
+                        -> Spectrum {
+        match self.one_light_samples(sampler) {
+            Some((u_select, u_light, u_scattering)) => {
+                let mut ld = Spectrum::black();
+                for k in 0..u_select.len() {
+                    if let Some((light_num, light_pdf)) = choose_light(scene, None, u_select[k]) {
+                        ld += estimate_direct(it,
+                                              &u_scattering[k],
+                                              &scene.lights[light_num],
+                                              &u_light[k],
+                                              scene,
+                                              sampler) / light_pdf;
+                    }
+                }
+                ld / u_select.len() as f32
+            }
+            None => uniform_sample_one_light(it, scene, sampler, None),
+        }
+    }
+
+    /// Return the next light selection, light and BSDF sample arrays, or `None` if the sampler
+    /// doesn't have them.
+    fn one_light_samples(&self,
+                         sampler: &mut Box<Sampler>)
+                         -> Option<(Vec<f32>, Vec<Point2f>, Vec<Point2f>)> {
+        let n = self.n_one_light_samples;
+        let u_select = sampler.get_1d_array(n).map(|a| a.to_vec());
+        let u_light = sampler.get_2d_array(n).map(|a| a.to_vec());
+        let u_scattering = sampler.get_2d_array(n).map(|a| a.to_vec());
+        match (u_select, u_light, u_scattering) {
+            (Some(u_select), Some(u_light), Some(u_scattering)) => {
+                Some((u_select, u_light, u_scattering))
+            }
+            _ => None,
+        }
+    }
 }
 
 impl SamplerIntegrator for DirectLightingIntegrator {
@@ -79,6 +128,15 @@ impl SamplerIntegrator for DirectLightingIntegrator {
                     sampler.request_2d_array(self.n_light_samples[j]);
                 }
             }
+        } else {
+            // Take the light selection, light and BSDF samples from arrays too, so they are
+            // stratified over the samples of the pixel rather than independent.
+            self.n_one_light_samples = sampler.round_count(1);
+            for _i in 0..self.max_depth {
+                sampler.request_1d_array(self.n_one_light_samples);
+                sampler.request_2d_array(self.n_one_light_samples);
+                sampler.request_2d_array(self.n_one_light_samples);
+            }
         }
     }
 
@@ -93,6 +151,11 @@ impl SamplerIntegrator for DirectLightingIntegrator {
 
         match scene.intersect(ray) {
             Some(mut isect) => {
+                pixel_debug!("Depth {}: ray {} hit {} with normal {}",
+                             depth,
+                             ray,
+                             isect.hit.p,
+                             isect.hit.n);
                 let wo = isect.hit.wo;
 
                 // Compute scattering functions for surface interaction
@@ -113,7 +176,7 @@ impl SamplerIntegrator for DirectLightingIntegrator {
                             uniform_sample_all_light(&isect, scene, sampler, &self.n_light_samples)
                         }
                         LightStrategy::UniformSampleOne => {
-                            uniform_sample_one_light(&isect, scene, sampler, None)
+                            self.sample_one_light(&isect, scene, sampler)
                         }
                     }
                 }
@@ -131,14 +194,149 @@ impl SamplerIntegrator for DirectLightingIntegrator {
                 }
             }
             None => {
-                // If we didn't intersect anything, add the backgound radiance from every light
-                colour = scene
-                    .lights
-                    .iter()
-                    .fold(Spectrum::black(), |c, l| c + l.le(ray));
+                // If we didn't intersect anything, add the background radiance
+                colour = scene.le(ray);
             }
         }
 
         colour
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use light_arena::MemoryArena;
+
+    use {Point2i, Point3f, Transform, Vector3f};
+    use bvh::{BVH, SplitMethod};
+    use light::{Light, PointLight};
+    use material::{Material, MatteMaterial};
+    use primitive::{GeometricPrimitive, Primitive};
+    use sampler::random::RandomSampler;
+    use sampler::zerotwosequence::ZeroTwoSequence;
+    use shapes::Sphere;
+    use stats;
+    use test_utils::create_material;
+
+    #[test]
+    fn test_one_light_samples_are_stratified() {
+        ::init_stats();
+        let sphere = GeometricPrimitive {
+            shape: Arc::new(Sphere::new(Transform::default(), 1.0, -1.0, 1.0, 360.0, false)),
+            area_light: None,
+            material: None,
+        };
+        let prims: Vec<Arc<Primitive>> = vec![Arc::new(sphere)];
+        let lights: Vec<Arc<Light>> =
+            vec![Arc::new(PointLight::new(Point3f::new(0.0, 0.0, 5.0), Spectrum::white()))];
+        let scene = Arc::new(Scene::new(Arc::new(BVH::new(1, &prims, SplitMethod::SAH)), lights));
+
+        let spp = 16;
+        let mut integrator = DirectLightingIntegrator::new(1, LightStrategy::UniformSampleOne);
+        let mut sampler: Box<Sampler> = Box::new(ZeroTwoSequence::new(spp, 0));
+        integrator.preprocess(scene, &mut sampler);
+
+        // Over the samples of a pixel, each 1D stratum gets one light selection sample and each
+        // cell of a 4x4 grid one light sample
+        sampler.start_pixel(&Point2i::new(3, 5));
+        let mut select_strata = vec![0; spp];
+        let mut light_strata = vec![0; spp];
+        loop {
+            let (u_select, u_light, _) = integrator.one_light_samples(&mut sampler).unwrap();
+            assert_eq!(u_select.len(), 1);
+            select_strata[(u_select[0] * spp as f32) as usize] += 1;
+            let p = u_light[0];
+            light_strata[4 * (p.x * 4.0) as usize + (p.y * 4.0) as usize] += 1;
+            if !sampler.start_next_sample() {
+                break;
+            }
+        }
+        assert!(select_strata.iter().all(|&c| c == 1), "{:?}", select_strata);
+        assert!(light_strata.iter().all(|&c| c == 1), "{:?}", light_strata);
+    }
+
+    /// Number of shadow rays traced on this thread when sampling the light of a sphere lit from
+    /// above, `n` times at its top.
+    fn shadow_rays_at_top(material: Arc<Material>, n: usize) -> u64 {
+        let sphere = GeometricPrimitive {
+            shape: Arc::new(Sphere::new(Transform::default(), 1.0, -1.0, 1.0, 360.0, false)),
+            area_light: None,
+            material: Some(material),
+        };
+        let lights: Vec<Arc<Light>> =
+            vec![Arc::new(PointLight::new(Point3f::new(0.0, 0.0, 5.0), Spectrum::white()))];
+        let scene = Scene::new(Arc::new(sphere), lights);
+        let mut sampler: Box<Sampler> = Box::new(RandomSampler::new(1));
+        let mut arena = MemoryArena::new(1);
+        let allocator = arena.allocator();
+        let mut ray = Ray::new(Point3f::new(0.0, 0.0, 3.0), Vector3f::new(0.0, 0.0, -1.0));
+        let mut isect = scene.intersect(&mut ray).unwrap();
+        isect.compute_scattering_functions(&ray, TransportMode::RADIANCE, false, &allocator);
+
+        let shadow_rays = || {
+            stats::thread_stats().counter("Intersections/Shadow ray intersection tests")
+        };
+        let before = shadow_rays();
+        for _ in 0..n {
+            uniform_sample_one_light(&isect, &scene, &mut sampler, None);
+        }
+        shadow_rays() - before
+    }
+
+    #[test]
+    fn test_black_bsdf_skips_shadow_rays() {
+        ::init_stats();
+        assert_eq!(shadow_rays_at_top(MatteMaterial::black(), 100), 0);
+        let grey = create_material(Vec::new(), MatteMaterial::create);
+        assert_eq!(shadow_rays_at_top(grey, 100), 100);
+    }
+
+    #[test]
+    fn test_back_facing_samples_skip_shadow_rays() {
+        ::init_stats();
+        let sphere = GeometricPrimitive {
+            shape: Arc::new(Sphere::new(Transform::default(), 1.0, -1.0, 1.0, 360.0, false)),
+            area_light: None,
+            material: Some(create_material(Vec::new(), MatteMaterial::create)),
+        };
+        let light_pos = Point3f::new(0.0, 0.0, 5.0);
+        let lights: Vec<Arc<Light>> = vec![Arc::new(PointLight::new(light_pos, Spectrum::white()))];
+        let scene = Scene::new(Arc::new(sphere), lights);
+        let mut sampler: Box<Sampler> = Box::new(RandomSampler::new(1));
+        let mut arena = MemoryArena::new(1);
+
+        // Shade points all around the sphere: the light is behind the ones on its lower half,
+        // where the BSDF is black
+        let shadow_rays = || {
+            stats::thread_stats().counter("Intersections/Shadow ray intersection tests")
+        };
+        let before = shadow_rays();
+        let (mut n_samples, mut n_lit) = (0, 0);
+        for i in 0..8 {
+            for j in 0..8 {
+                let cos_theta = 1.0 - 2.0 * (i as f32 + 0.5) / 8.0;
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let phi = 2.0 * ::std::f32::consts::PI * (j as f32 + 0.5) / 8.0;
+                let dir = Vector3f::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                let allocator = arena.allocator();
+                let mut ray = Ray::new(Point3f::new(0.0, 0.0, 0.0) + dir * 3.0, -dir);
+                let mut isect = scene.intersect(&mut ray).unwrap();
+                isect.compute_scattering_functions(&ray,
+                                                   TransportMode::RADIANCE,
+                                                   false,
+                                                   &allocator);
+                uniform_sample_one_light(&isect, &scene, &mut sampler, None);
+                n_samples += 1;
+                if Vector3f::from(isect.hit.n).dot(&(light_pos - isect.hit.p)) > 0.0 {
+                    n_lit += 1;
+                }
+            }
+        }
+
+        // Without the short-circuit, every light sample would need a shadow ray
+        assert_eq!(shadow_rays() - before, n_lit);
+        assert!(n_lit < n_samples, "{} of {} samples are lit", n_lit, n_samples);
+    }
+}
@@ -1,7 +1,9 @@
+use std::cell::Cell;
 use std::sync::Arc;
 use std::cmp;
 
-use {Point2f, Vector3f};
+use {Point2f, Point2i, Vector3f};
+use aov::{self, FirstHit};
 use bounds::Bounds2i;
 use bsdf::{self, BxDFType};
 use spectrum::Spectrum;
@@ -13,6 +15,16 @@ use sampler::Sampler;
 use sampling::{power_heuristic, Distribution1D};
 use scene::Scene;
 
+/// Log a message, but only while rendering the pixel chosen with `Options::debug_pixel`, to trace
+/// what happens there without drowning in the logs of the whole image.
+macro_rules! pixel_debug {
+    ($($arg:tt)*) => {
+        if let Some(p) = ::integrator::debugged_pixel() {
+            info!("[pixel {}] {}", p, format_args!($($arg)*));
+        }
+    }
+}
+
 mod whitted;
 mod directlighting;
 mod path;
@@ -23,12 +35,40 @@ pub use self::whitted::Whitted;
 pub use self::directlighting::{DirectLightingIntegrator, LightStrategy};
 pub use self::path::PathIntegrator;
 pub use self::ao::AmbientOcclusion;
-pub use self::normal::Normal;
+pub use self::normal::{Normal, NormalOutput};
 
 pub fn init_stats() {
     path::init_stats();
 }
 
+thread_local! {
+    static DEBUGGED_PIXEL: Cell<Option<Point2i>> = Cell::new(None);
+    static PATH_RAYS: Cell<Option<u32>> = Cell::new(None);
+}
+
+/// Set the pixel whose samples the current thread is computing if it is being debugged, or
+/// `None` otherwise. `pixel_debug!` only logs while it is set.
+pub fn set_debugged_pixel(p: Option<Point2i>) {
+    DEBUGGED_PIXEL.with(|d| d.set(p));
+}
+
+/// The pixel being debugged by the current thread, if any.
+pub fn debugged_pixel() -> Option<Point2i> {
+    DEBUGGED_PIXEL.with(|d| d.get())
+}
+
+/// Record the number of rays traced by the path the current thread just followed, for the
+/// bounces AOV (see `aov::Aov::Bounces`).
+pub fn record_path_rays(n: u32) {
+    PATH_RAYS.with(|r| r.set(Some(n)));
+}
+
+/// Return and clear the number of rays recorded by `record_path_rays()` on the current thread,
+/// or `None` if the integrator doesn't record it.
+pub fn take_path_rays() -> Option<u32> {
+    PATH_RAYS.with(|r| r.replace(None))
+}
+
 pub trait SamplerIntegrator: Send + Sync {
     fn pixel_bounds(&self) -> &Bounds2i;
 
@@ -42,6 +82,11 @@ pub trait SamplerIntegrator: Send + Sync {
           depth: u32)
           -> Spectrum;
 
+    /// What the camera ray `ray` sees at the first surface it hits, for the film's AOVs.
+    fn first_hit(&self, scene: &Scene, ray: &Ray, arena: &Allocator) -> Option<FirstHit> {
+        aov::first_hit(scene, ray, arena)
+    }
+
     #[allow(non_snake_case)]
     fn specular_reflection(&self,
                            ray: &mut Ray,
@@ -176,27 +221,15 @@ pub fn uniform_sample_one_light<'a, D: Into<Option<&'a Distribution1D>>>(
     sampler: &mut Box<Sampler>,
     distrib: D,
 ) -> Spectrum{
-    let distrib = distrib.into();
-    let n_lights = scene.lights.len();
-    if n_lights == 0 {
+    if scene.lights.is_empty() {
         Spectrum::black()
     } else {
         // Randomly chose a light to sample
         let s = sampler.get_1d();
-        let (light_num, light_pdf) = match distrib {
-            Some(distrib) => distrib.sample_discrete(s),
-            None => (cmp::min(n_lights - 1, (s * n_lights as f32) as usize), 1.0 / n_lights as f32),
+        let (light_num, light_pdf) = match choose_light(scene, distrib, s) {
+            Some(choice) => choice,
+            None => return Spectrum::black(),
         };
-
-        debug!("sampler.get_1d()={}, n_lights={}, light_num={}, light_pdf={}",
-               s,
-               n_lights,
-               light_num,
-               light_pdf);
-
-        if light_pdf == 0.0 {
-            return Spectrum::black();
-        }
         let light = &scene.lights[light_num];
         let u_light = sampler.get_2d();
         let u_scattering = sampler.get_2d();
@@ -204,12 +237,41 @@ pub fn uniform_sample_one_light<'a, D: Into<Option<&'a Distribution1D>>>(
     }
 }
 
+/// Use the sample `s` to pick one of the scene's lights, according to `distrib` if given or
+/// uniformly otherwise. Return the index of the light and the probability of choosing it, or
+/// `None` if the scene has no lights or the chosen light can't be sampled.
+pub fn choose_light<'a, D: Into<Option<&'a Distribution1D>>>(scene: &Scene,
+                                                             distrib: D,
+                                                             s: f32)
+                                                             -> Option<(usize, f32)> {
+    let n_lights = scene.lights.len();
+    if n_lights == 0 {
+        return None;
+    }
+    let (light_num, light_pdf) = match distrib.into() {
+        Some(distrib) => distrib.sample_discrete(s),
+        None => (cmp::min(n_lights - 1, (s * n_lights as f32) as usize), 1.0 / n_lights as f32),
+    };
+
+    debug!("s={}, n_lights={}, light_num={}, light_pdf={}",
+           s,
+           n_lights,
+           light_num,
+           light_pdf);
+
+    if light_pdf == 0.0 {
+        None
+    } else {
+        Some((light_num, light_pdf))
+    }
+}
+
 pub fn estimate_direct(it: &SurfaceInteraction,
                        u_scattering: &Point2f,
                        light: &Arc<Light>,
                        u_light: &Point2f,
                        scene: &Scene,
-                       _sampler: &mut Box<Sampler>)
+                       sampler: &mut Box<Sampler>)
                        -> Spectrum {
     let specular = false;
 
@@ -224,21 +286,26 @@ pub fn estimate_direct(it: &SurfaceInteraction,
         .as_ref()
         .expect("There should be a BSDF set at this point!");
     let (mut li, wi, light_pdf, vis) = light.sample_li(it.into(), u_light);
-    // info!(
-    //     "EstimateDirect u_light: {} -> Li: {}, wi: {}, pdf: {}",
-    //     u_light,
-    //     li,
-    //     wi,
-    //     light_pdf
-    // );
+    pixel_debug!("EstimateDirect u_light: {} -> Li: {}, wi: {}, pdf: {}",
+                 u_light,
+                 li,
+                 wi,
+                 light_pdf);
     if light_pdf > 0.0 && !li.is_black() {
         // Compute BSDF for light sample
         let f = bsdf.f(&it.hit.wo, &wi, bsdf_flags) * wi.dotn(&it.shading.n).abs();
-        let scattering_pdf = bsdf.pdf(&it.hit.wo, &wi, bsdf_flags);
+        // Black BSDF values are common (e.g. light behind the surface): skip the shadow ray
         if !f.is_black() {
-            if !vis.unoccluded(scene) {
+            let scattering_pdf = bsdf.pdf(&it.hit.wo, &wi, bsdf_flags);
+            if scene.medium.is_some() {
+                li = li * vis.tr(scene, sampler);
+            } else if !vis.unoccluded_from(scene, &**light) {
                 li = Spectrum::black();
             }
+            pixel_debug!("Light sample f: {}, scattering pdf: {}, unoccluded Li: {}",
+                         f,
+                         scattering_pdf,
+                         li);
             // Add light's contribution to reflected radiance
             if !li.is_black() {
                 if is_delta_light(light.flags()) {
@@ -272,7 +339,12 @@ pub fn estimate_direct(it: &SurfaceInteraction,
 
             // Find intersection and compute transmittance
             let mut ray = it.spawn_ray(&wi);
-            let li = match scene.intersect(&mut ray) {
+            let (light_isect, tr) = if scene.medium.is_some() {
+                scene.intersect_tr(&mut ray, sampler)
+            } else {
+                (scene.intersect(&mut ray), Spectrum::white())
+            };
+            let li = match light_isect {
                 Some(light_isect) => {
                     // Add light contribution from material sampling
                     if let Some(area_light) = light_isect.primitive.and_then(|p| p.area_light()) {
@@ -292,7 +364,7 @@ pub fn estimate_direct(it: &SurfaceInteraction,
                 None => light.le(&ray),
             };
             if !li.is_black() {
-                ld += f * li * weight / scattering_pdf;
+                ld += f * li * tr * weight / scattering_pdf;
             }
         }
     }
@@ -3,13 +3,58 @@ use light_arena::Allocator;
 use bounds::Bounds2i;
 use spectrum::Spectrum;
 use integrator::SamplerIntegrator;
+use paramset::ParamSet;
 use ray::Ray;
 use sampler::Sampler;
 use scene::Scene;
+use texture::{Texture, UVTexture};
+
+/// Geometric quantity visualised by the `Normal` debug integrator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalOutput {
+    /// Cosine between the camera ray and the surface normal, as grey
+    Normal,
+    /// Texture coordinates, as (u, v, 0)
+    Uv,
+    /// Lengths of dp/du and dp/dv, as (|dpdu|, |dpdv|, 0)
+    UvDerivatives,
+}
 
-#[derive(Default)]
 pub struct Normal {
     pixel_bounds: Bounds2i,
+    output: NormalOutput,
+    uv_texture: UVTexture,
+}
+
+impl Normal {
+    pub fn new(output: NormalOutput) -> Normal {
+        Normal {
+            pixel_bounds: Bounds2i::default(),
+            output,
+            uv_texture: UVTexture::new(),
+        }
+    }
+
+    pub fn create(ps: &mut ParamSet) -> Box<SamplerIntegrator> {
+        let out = ps.find_one_string("output", "normal".into());
+        let output = if out == "normal" {
+            NormalOutput::Normal
+        } else if out == "uv" {
+            NormalOutput::Uv
+        } else if out == "dpduv" {
+            NormalOutput::UvDerivatives
+        } else {
+            warn!("Output \"{}\" for normal unknown. Using \"normal\".", out);
+            NormalOutput::Normal
+        };
+        Box::new(Self::new(output))
+    }
+}
+
+impl Default for Normal {
+    fn default() -> Normal {
+        Normal::new(NormalOutput::Normal)
+    }
 }
 
 impl SamplerIntegrator for Normal {
@@ -25,10 +70,94 @@ impl SamplerIntegrator for Normal {
           _depth: u32)
           -> Spectrum {
         if let Some(intersection) = scene.intersect(ray) {
-            let n = intersection.hit.n;
-            Spectrum::grey(ray.d.dotn(&n).abs())
+            match self.output {
+                NormalOutput::Normal => {
+                    let n = intersection.hit.n;
+                    Spectrum::grey(ray.d.dotn(&n).abs())
+                }
+                NormalOutput::Uv => self.uv_texture.evaluate(&intersection),
+                NormalOutput::UvDerivatives => {
+                    Spectrum::rgb(intersection.dpdu.length(),
+                                  intersection.dpdv.length(),
+                                  0.0)
+                }
+            }
         } else {
             Spectrum::black()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use light_arena::MemoryArena;
+
+    use {Point3f, Transform, Vector3f};
+    use api::{Array, ParamListEntry, ParamType};
+    use bvh::{BVH, SplitMethod};
+    use primitive::{GeometricPrimitive, Primitive};
+    use sampler::random::RandomSampler;
+    use shapes::TriangleMesh;
+
+    /// A 2x1 quad in the z=0 plane, with uvs spanning [0,1]^2.
+    fn uv_quad() -> Scene {
+        let mut params = ParamSet::default();
+        params.init(vec![ParamListEntry::new(ParamType::Int,
+                                             "indices".to_owned(),
+                                             Array::NumArray(vec![0.0, 1.0, 2.0, 0.0, 2.0, 3.0])),
+                         ParamListEntry::new(ParamType::Point3,
+                                             "P".to_owned(),
+                                             Array::NumArray(vec![0.0, 0.0, 0.0, 2.0, 0.0, 0.0,
+                                                                  2.0, 1.0, 0.0, 0.0, 1.0, 0.0])),
+                         ParamListEntry::new(ParamType::Float,
+                                             "uv".to_owned(),
+                                             Array::NumArray(vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0,
+                                                                  0.0, 1.0]))]);
+        let prims: Vec<Arc<Primitive>> = TriangleMesh::create(&Transform::default(),
+                                                              &Transform::default(),
+                                                              false,
+                                                              &mut params,
+                                                              &HashMap::new())
+                .into_iter()
+                .map(|shape| {
+                         Arc::new(GeometricPrimitive {
+                                      shape,
+                                      area_light: None,
+                                      material: None,
+                                  }) as Arc<Primitive>
+                     })
+                .collect();
+        Scene::new(Arc::new(BVH::new(1, &prims, SplitMethod::SAH)), Vec::new())
+    }
+
+    fn shade(integrator: &Normal, scene: &Scene, x: f32, y: f32) -> Spectrum {
+        let mut sampler: Box<Sampler> = Box::new(RandomSampler::new(1));
+        let mut arena = MemoryArena::new(1);
+        let mut ray = Ray::new(Point3f::new(x, y, 1.0), Vector3f::new(0.0, 0.0, -1.0));
+        integrator.li(scene, &mut ray, &mut sampler, &arena.allocator(), 0)
+    }
+
+    #[test]
+    fn test_uv_output_is_a_red_green_gradient() {
+        ::init_stats();
+        let scene = uv_quad();
+        let integrator = Normal::new(NormalOutput::Uv);
+        for &(u, v) in &[(0.1, 0.1), (0.25, 0.8), (0.5, 0.5), (0.9, 0.3)] {
+            let c = shade(&integrator, &scene, 2.0 * u, v);
+            assert!((c[0] - u).abs() < 1e-4 && (c[1] - v).abs() < 1e-4 && c[2] == 0.0,
+                    "{:?} at ({}, {})",
+                    c,
+                    u,
+                    v);
+        }
+
+        // The quad is stretched twice as much along u as along v
+        let integrator = Normal::new(NormalOutput::UvDerivatives);
+        let c = shade(&integrator, &scene, 1.2, 0.4);
+        assert!((c[0] - 2.0).abs() < 1e-4 && (c[1] - 1.0).abs() < 1e-4);
+    }
+}
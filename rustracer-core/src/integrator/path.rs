@@ -5,7 +5,7 @@ use light_arena::Allocator;
 use bounds::Bounds2i;
 use bsdf::BxDFType;
 use camera::Camera;
-use integrator::{uniform_sample_one_light, SamplerIntegrator};
+use integrator::{record_path_rays, uniform_sample_one_light, SamplerIntegrator};
 use lightdistrib::{LightDistribution, UniformLightDistribution, SpatialLightDistribution};
 use material::TransportMode;
 use paramset::ParamSet;
@@ -16,9 +16,11 @@ use spectrum::Spectrum;
 
 stat_percent!("Integrator/Zero-radiance paths", zero_radiance_paths);
 stat_int_distribution!("Integrator/Path length", path_length);
+stat_counter!("Integrator/Regularized BSDFs", regularized_bsdfs);
 pub fn init_stats() {
     zero_radiance_paths::init();
     path_length::init();
+    regularized_bsdfs::init();
 }
 
 pub struct PathIntegrator {
@@ -27,13 +29,16 @@ pub struct PathIntegrator {
     rr_threshold: f32,
     light_sampling_strategy: String,
     light_distribution: Option<Box<LightDistribution>>,
+    /// Whether to make specular BSDFs glossy once the path has had a non-specular bounce
+    regularize: bool,
 }
 
 impl PathIntegrator {
     pub fn new(pixel_bounds: Bounds2i,
                max_ray_depth: i32,
                rr_threshold: f32,
-               light_sampling_strategy: String)
+               light_sampling_strategy: String,
+               regularize: bool)
                -> PathIntegrator {
         PathIntegrator {
             pixel_bounds,
@@ -41,6 +46,7 @@ impl PathIntegrator {
             rr_threshold,
             light_sampling_strategy,
             light_distribution: None,
+            regularize,
         }
     }
 
@@ -50,6 +56,7 @@ impl PathIntegrator {
         let max_depth = params.find_one_int("maxdepth", 5);
         let rr_threshold = params.find_one_float("rrthreshold", 1.0);
         let light_strategy = params.find_one_string("lightsamplestrategy", "spatial".into());
+        let regularize = params.find_one_bool("regularize", false);
         let pb = params.find_int("pixelbounds");
         let mut pixel_bounds = camera.get_film().get_sample_bounds();
         if let Some(pb) = pb {
@@ -66,7 +73,11 @@ impl PathIntegrator {
             }
         }
 
-        Box::new(PathIntegrator::new(pixel_bounds, max_depth, rr_threshold, light_strategy))
+        Box::new(PathIntegrator::new(pixel_bounds,
+                                     max_depth,
+                                     rr_threshold,
+                                     light_strategy,
+                                     regularize))
     }
 }
 
@@ -95,6 +106,7 @@ impl SamplerIntegrator for PathIntegrator {
         let mut l = Spectrum::black();
         let mut beta = Spectrum::white();
         let mut specular_bounce = false;
+        let mut any_non_specular_bounces = false;
         let mut ray = *r;
         let mut bounces = 0;
         // Added after book publication: etaScale tracks the accumulated effect
@@ -120,9 +132,7 @@ impl SamplerIntegrator for PathIntegrator {
                 if let Some(ref isect) = found_intersection {
                     l += beta * isect.le(&(-ray.d));
                 } else {
-                    for light in &scene.infinite_lights {
-                        l += beta * light.le(&ray);
-                    }
+                    l += beta * scene.le(&ray);
                 }
             }
 
@@ -133,6 +143,11 @@ impl SamplerIntegrator for PathIntegrator {
 
             // Compute scattering functions and skip over medium boundaries
             let isect = found_intersection.as_mut().unwrap();
+            pixel_debug!("Bounce {}: ray {} hit {} with normal {}",
+                         bounces,
+                         ray,
+                         isect.hit.p,
+                         isect.hit.n);
             isect.compute_scattering_functions(&ray, TransportMode::RADIANCE, true, arena);
             if isect.bsdf.is_none() {
                 // If there's no bsdf, it means we've hit the interface between two
@@ -141,6 +156,13 @@ impl SamplerIntegrator for PathIntegrator {
                 bounces -= 1;
                 continue;
             }
+            // Once the path has been through a diffuse or glossy bounce, paths hitting specular
+            // surfaces are mostly caustics, which are very hard to sample: blur them a bit.
+            if self.regularize && any_non_specular_bounces {
+                let regularized = isect.bsdf.as_ref().unwrap().regularize(arena);
+                isect.bsdf = Some(Arc::new(regularized));
+                regularized_bsdfs::inc();
+            }
             let bsdf = isect.bsdf.clone().unwrap();
             let distrib = self.light_distribution
                 .as_ref()
@@ -151,24 +173,34 @@ impl SamplerIntegrator for PathIntegrator {
             if bsdf.num_components(BxDFType::all() & !BxDFType::BSDF_SPECULAR) > 0 {
                 zero_radiance_paths::inc_total();
                 let ld = beta * uniform_sample_one_light(isect, scene, sampler, distrib);
+                pixel_debug!("Direct lighting contribution: {}", ld);
                 if ld.is_black() {
                     zero_radiance_paths::inc();
                 }
-                assert!(ld.y() >= 0.0);
+                // NaNs are let through: the renderer discards the whole sample
+                debug_assert!(ld.y() >= 0.0 || ld.y().is_nan(),
+                              "negative direct lighting: {}",
+                              ld);
                 l += ld;
             }
 
             // Sample BSDF to get new path direction
             let wo = -ray.d;
             let (f, wi, pdf, flags) = bsdf.sample_f(&wo, &sampler.get_2d(), BxDFType::all());
+            pixel_debug!("Sampled BSDF f: {}, wi: {}, pdf: {}, flags: {:?}", f, wi, pdf, flags);
             if f.is_black() || pdf <= 0.0 {
                 break;
             }
             debug!("Update beta. beta={}, f={}, pdf={}", beta, f, pdf);
             beta = beta * f * wi.dotn(&isect.shading.n).abs() / pdf;
+            if beta.has_nan() || beta.is_infinite() {
+                // A broken BSDF sample: stop following the path rather than tracing rays that
+                // can only carry garbage.
+                break;
+            }
             assert!(beta.y() >= 0.0);
-            // assert!(!beta.y().is_infinite());
             specular_bounce = flags.contains(BxDFType::BSDF_SPECULAR);
+            any_non_specular_bounces |= !specular_bounce;
             if flags.contains(BxDFType::BSDF_SPECULAR) &&
                flags.contains(BxDFType::BSDF_TRANSMISSION) {
                 let eta = bsdf.eta;
@@ -200,6 +232,134 @@ impl SamplerIntegrator for PathIntegrator {
         }
 
         path_length::report_value(bounces as u64);
+        // The last ray is traced whether the path stops there or not
+        record_path_rays(u32::from(bounces) + 1);
+        pixel_debug!("Path of {} bounces, L: {}", bounces, l);
         l
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use light_arena::MemoryArena;
+
+    use {Point3f, Transform, Vector3f};
+    use bvh::{BVH, SplitMethod};
+    use integrator::take_path_rays;
+    use light::{DiffuseAreaLight, Light};
+    use material::{GlassMaterial, MatteMaterial};
+    use primitive::{GeometricPrimitive, Primitive};
+    use sampler::random::RandomSampler;
+    use shapes::{Disk, Sphere};
+    use test_utils::create_material;
+
+    /// A glass ball focusing a small spherical light onto a matte floor.
+    fn caustic_scene() -> Scene {
+        let matte = create_material(Vec::new(), MatteMaterial::create);
+        let floor = GeometricPrimitive {
+            shape: Arc::new(Disk::new(0.0, 10.0, 0.0, 360.0, Transform::default(), false)),
+            area_light: None,
+            material: Some(Arc::clone(&matte)),
+        };
+        let ball = GeometricPrimitive {
+            shape: Arc::new(Sphere::new(Transform::translate(&Vector3f::new(0.0, 0.0, 2.0)),
+                                        1.0,
+                                        -1.0,
+                                        1.0,
+                                        360.0,
+                                        false)),
+            area_light: None,
+            material: Some(create_material(Vec::new(), GlassMaterial::create)),
+        };
+        let bulb = Arc::new(Sphere::new(Transform::translate(&Vector3f::new(0.0, 0.0, 5.0)),
+                                        0.05,
+                                        -0.05,
+                                        0.05,
+                                        360.0,
+                                        false));
+        let light = Arc::new(DiffuseAreaLight::new(Spectrum::grey(100.0), bulb.clone(), 1, false));
+        let emitter = GeometricPrimitive {
+            shape: bulb,
+            area_light: Some(light.clone()),
+            material: Some(matte),
+        };
+
+        let prims: Vec<Arc<Primitive>> = vec![Arc::new(floor), Arc::new(ball), Arc::new(emitter)];
+        let lights: Vec<Arc<Light>> = vec![light];
+        Scene::new(Arc::new(BVH::new(1, &prims, SplitMethod::SAH)), lights)
+    }
+
+    /// Mean and variance of the luminance of `n` path tracing samples of a point of the floor
+    /// lit by the caustic.
+    fn caustic_estimates(regularize: bool, n: usize) -> (f32, f32) {
+        let scene = Arc::new(caustic_scene());
+        let mut integrator = PathIntegrator::new(Bounds2i::from_elements(0, 0, 1, 1),
+                                                 5,
+                                                 1.0,
+                                                 "uniform".into(),
+                                                 regularize);
+        let mut sampler: Box<Sampler> = Box::new(RandomSampler::new(1));
+        sampler.reseed(7);
+        integrator.preprocess(Arc::clone(&scene), &mut sampler);
+
+        let eye = Point3f::new(3.0, 0.0, 1.0);
+        let target = Point3f::new(0.05, 0.05, 0.0);
+        let mut arena = MemoryArena::new(1);
+        let (mut sum, mut sum_sq) = (0.0, 0.0);
+        for _ in 0..n {
+            let alloc = arena.allocator();
+            let mut ray = Ray::new(eye, (target - eye).normalize());
+            let l = integrator.li(&scene, &mut ray, &mut sampler, &alloc, 0).y();
+            sum += f64::from(l);
+            sum_sq += f64::from(l) * f64::from(l);
+        }
+        let mean = sum / n as f64;
+        (mean as f32, (sum_sq / n as f64 - mean * mean) as f32)
+    }
+
+    #[test]
+    fn test_regularization_reduces_caustic_variance() {
+        ::init_stats();
+        let (mean, variance) = caustic_estimates(false, 10_000);
+        let (regularized_mean, regularized_variance) = caustic_estimates(true, 10_000);
+
+        // Both see the caustic, but it is much less noisy once regularized
+        assert!(mean > 0.0);
+        assert!(regularized_mean > 0.0);
+        assert!(regularized_variance < 0.5 * variance,
+                "variance {} with regularization, {} without",
+                regularized_variance,
+                variance);
+        assert!(regularized_bsdfs::get() > 0);
+    }
+
+    #[test]
+    fn test_path_rays_are_recorded() {
+        ::init_stats();
+        let scene = Arc::new(caustic_scene());
+        let mut integrator = PathIntegrator::new(Bounds2i::from_elements(0, 0, 1, 1),
+                                                 5,
+                                                 1.0,
+                                                 "uniform".into(),
+                                                 false);
+        let mut sampler: Box<Sampler> = Box::new(RandomSampler::new(1));
+        integrator.preprocess(Arc::clone(&scene), &mut sampler);
+        let mut arena = MemoryArena::new(1);
+        let alloc = arena.allocator();
+        let eye = Point3f::new(3.0, 0.0, 1.0);
+
+        // A ray escaping right away is the only ray of its path
+        let _ = take_path_rays();
+        let mut ray = Ray::new(eye, Vector3f::new(0.0, 0.0, 1.0));
+        integrator.li(&scene, &mut ray, &mut sampler, &alloc, 0);
+        assert_eq!(take_path_rays(), Some(1));
+        assert_eq!(take_path_rays(), None);
+
+        // One hitting the floor is followed by at least another one
+        let mut ray = Ray::new(eye, Vector3f::new(-1.0, 0.0, -0.5).normalize());
+        integrator.li(&scene, &mut ray, &mut sampler, &alloc, 0);
+        assert!(take_path_rays().unwrap() >= 2);
+    }
+}
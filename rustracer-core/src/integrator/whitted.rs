@@ -49,6 +49,11 @@ impl SamplerIntegrator for Whitted {
 
         match scene.intersect(ray) {
             Some(mut isect) => {
+                pixel_debug!("Depth {}: ray {} hit {} with normal {}",
+                             depth,
+                             ray,
+                             isect.hit.p,
+                             isect.hit.n);
                 let n = isect.shading.n;
                 let wo = isect.hit.wo;
 
@@ -74,7 +79,7 @@ impl SamplerIntegrator for Whitted {
                     }
 
                     let f = bsdf.f(&wo, &wi, bsdf::BxDFType::all());
-                    if !f.is_black() && visibility_tester.unoccluded(scene) {
+                    if !f.is_black() && visibility_tester.unoccluded_from(scene, &**light) {
                         colour += f * li * wi.dotn(&n).abs() / pdf;
                     }
                 }
@@ -92,10 +97,7 @@ impl SamplerIntegrator for Whitted {
                 }
             }
             None => {
-                colour = scene
-                    .lights
-                    .iter()
-                    .fold(Spectrum::black(), |c, l| c + l.le(ray));
+                colour = scene.le(ray);
             }
         }
 
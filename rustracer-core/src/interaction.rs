@@ -13,6 +13,10 @@ use shapes::Shape;
 use spectrum::Spectrum;
 use transform;
 
+/// Rays spawned towards a point stop slightly short of it, so that they don't hit the surface the
+/// point lies on.
+const SHADOW_EPSILON: f32 = 1e-4;
+
 #[derive(Copy, Clone)]
 pub struct Interaction {
     /// The point where the ray hit the primitive
@@ -61,16 +65,16 @@ impl Interaction {
 
     pub fn spawn_ray_to(&self, p: &Point3f) -> Ray {
         let o = offset_ray_origin(&self.p, &self.p_error, &self.n, &(*p - self.p));
-        let d = *p - self.p;
+        let d = *p - o;
         assert!(d.x != 0.0 || d.y != 0.0 || d.z != 0.0);
-        Ray::segment(o, d, 1.0 - 1e-4)
+        Ray::segment(o, d, 1.0 - SHADOW_EPSILON)
     }
 
     pub fn spawn_ray_to_interaction(&self, it: &Interaction) -> Ray {
         let origin = offset_ray_origin(&self.p, &self.p_error, &self.n, &(it.p - self.p));
         let target = offset_ray_origin(&it.p, &it.p_error, &it.n, &(origin - it.p));
         let d = target - origin;
-        Ray::segment(origin, d, 1.0 - 1e-4)
+        Ray::segment(origin, d, 1.0 - SHADOW_EPSILON)
     }
 }
 
@@ -99,6 +103,8 @@ pub struct SurfaceInteraction<'a, 'b> {
     pub primitive: Option<&'a Primitive>,
     /// Shading information
     pub shading: Shading,
+    /// Colour interpolated from the vertices of the hit mesh, if it has some
+    pub color: Option<Spectrum>,
     /// BSDF of the surface at the intersection point
     pub bsdf: Option<Arc<BSDF<'b>>>,
 }
@@ -141,6 +147,38 @@ impl<'a, 'b> SurfaceInteraction<'a, 'b> {
                 dndu: dndu,
                 dndv: dndv,
             },
+            color: None,
+            bsdf: None,
+        }
+    }
+
+    /// Surface interaction at the point `it` sampled on `shape` (see `Shape::sample()`), whose
+    /// (u, v) coordinates are `uv`. Only the position, normal and (u, v) coordinates are known,
+    /// which is enough to evaluate textures there, albeit without filtering them.
+    pub fn from_sample(it: &Interaction,
+                       uv: Point2f,
+                       shape: &'a Shape)
+                       -> SurfaceInteraction<'a, 'b> {
+        SurfaceInteraction {
+            hit: *it,
+            uv,
+            dpdu: zero(),
+            dpdv: zero(),
+            dndu: zero(),
+            dndv: zero(),
+            dpdx: zero(),
+            dpdy: zero(),
+            dudx: 0.0,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 0.0,
+            shape,
+            primitive: None,
+            shading: Shading {
+                n: it.n,
+                ..Shading::default()
+            },
+            color: None,
             bsdf: None,
         }
     }
@@ -148,7 +186,7 @@ impl<'a, 'b> SurfaceInteraction<'a, 'b> {
     pub fn le(&self, w: &Vector3f) -> Spectrum {
         self.primitive
             .and_then(|p| p.area_light())
-            .map(|light| light.l(self.into(), w))
+            .map(|light| light.l_surface(self, w))
             .unwrap_or_else(Spectrum::black)
     }
 
@@ -162,23 +200,24 @@ impl<'a, 'b> SurfaceInteraction<'a, 'b> {
             uv: self.uv,
             dpdu: t * &self.dpdu,
             dpdv: t * &self.dpdv,
-            dndu: zero(),
-            dndv: zero(),
-            dpdx: zero(),
-            dpdy: zero(),
-            dudx: 0.0,
-            dvdx: 0.0,
-            dudy: 0.0,
-            dvdy: 0.0,
+            dndu: t * &self.dndu,
+            dndv: t * &self.dndv,
+            dpdx: t * &self.dpdx,
+            dpdy: t * &self.dpdy,
+            dudx: self.dudx,
+            dvdx: self.dvdx,
+            dudy: self.dudy,
+            dvdy: self.dvdy,
             shape: self.shape,
             primitive: self.primitive,
             shading: Shading {
                 n: t.transform_normal(&self.shading.n).normalize(),
                 dpdu: t * &self.shading.dpdu,
                 dpdv: t * &self.shading.dpdv,
-                dndu: zero(),
-                dndv: zero(),
+                dndu: t * &self.shading.dndu,
+                dndv: t * &self.shading.dndv,
             },
+            color: self.color,
             bsdf: self.bsdf.clone(),
         };
         si.shading.n = face_forward_n(&si.shading.n, &si.hit.n);
@@ -204,10 +243,19 @@ impl<'a, 'b> SurfaceInteraction<'a, 'b> {
     }
 
     pub fn spawn_ray_to(&self, p: &Point3f) -> Ray {
-        let d = *p - self.hit.p;
-        assert!(d.x != 0.0 || d.y != 0.0 || d.z != 0.0);
-        let o = offset_ray_origin(&self.hit.p, &self.hit.p_error, &self.hit.n, &d);
-        Ray::segment(o, d, 1.0 - 1e-4)
+        self.hit.spawn_ray_to(p)
+    }
+
+    /// Replace the shading geometry (e.g. interpolated normals) with the true geometry of the
+    /// surface, so everything downstream shades with the geometric normal.
+    pub fn use_geometric_shading(&mut self) {
+        self.shading = Shading {
+            n: self.hit.n,
+            dpdu: self.dpdu,
+            dpdv: self.dpdv,
+            dndu: self.dndu,
+            dndv: self.dndv,
+        };
     }
 
     pub fn set_shading_geometry(&mut self,
@@ -22,10 +22,13 @@ extern crate light_arena;
 #[macro_use]
 extern crate log;
 extern crate num;
+extern crate num_cpus;
 #[cfg(feature="exr")]
 extern crate openexr;
 extern crate ply_rs;
 extern crate parking_lot;
+#[macro_use]
+extern crate serde_json;
 extern crate state;
 
 #[cfg(test)]
@@ -44,6 +47,7 @@ use num::{Num, One, Signed};
 #[macro_use]
 mod stats;
 mod api;
+pub mod aov;
 mod blockedarray;
 pub mod bounds;
 mod bsdf;
@@ -56,12 +60,15 @@ mod fileutil;
 pub mod film;
 pub mod filter;
 mod geometry;
+#[macro_use]
 pub mod integrator;
 mod interaction;
 pub mod imageio;
 pub mod light;
 pub mod lightdistrib;
 pub mod material;
+pub mod medium;
+mod metadata;
 pub mod mipmap;
 mod noise;
 mod paramset;
@@ -77,6 +84,8 @@ pub mod shapes;
 pub mod spectrum;
 pub mod texture;
 pub mod transform;
+#[cfg(test)]
+pub(crate) mod test_utils;
 
 pub fn init_stats() {
     // This one needs to be called first
@@ -103,6 +112,7 @@ pub type Point3f = Point3<f32>;
 pub type Point3i = Point3<i32>;
 pub type Normal3f = Normal3<f32>;
 
+pub use api::Options;
 pub use transform::Transform;
 
 pub const MACHINE_EPSILON: f32 = f32::EPSILON * 0.5;
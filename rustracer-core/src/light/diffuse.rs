@@ -1,18 +1,27 @@
+use std::collections::HashMap;
 use std::f32::consts::PI;
 use std::sync::Arc;
 
 use {Point2f, Transform, Vector3f};
-use interaction::Interaction;
+use interaction::{Interaction, SurfaceInteraction};
 use light::{AreaLight, Light, LightFlags, VisibilityTester};
 use paramset::ParamSet;
 use shapes::Shape;
 use spectrum::Spectrum;
+use texture::TextureSpectrum;
 
+/// Number of points per dimension at which a textured emission is averaged to estimate the
+/// light's power.
+const POWER_ESTIMATE_SAMPLES: usize = 16;
 
 #[derive(Debug)]
 pub struct DiffuseAreaLight {
     id: u32,
     l_emit: Spectrum,
+    /// Optional texture scaling `l_emit` across the shape, according to its (u, v) coordinates
+    l_texture: Option<Arc<TextureSpectrum>>,
+    /// Average of the emitted radiance over the shape
+    l_average: Spectrum,
     shape: Arc<Shape>,
     n_samples: u32,
     two_sided: bool,
@@ -25,10 +34,27 @@ impl DiffuseAreaLight {
                n_samples: u32,
                two_sided: bool)
                -> DiffuseAreaLight {
+        Self::with_texture(l_emit, None, shape, n_samples, two_sided)
+    }
+
+    /// Create a light whose emission `l_emit` is scaled by the texture `l_texture`, evaluated at
+    /// the emitting point.
+    pub fn with_texture(l_emit: Spectrum,
+                        l_texture: Option<Arc<TextureSpectrum>>,
+                        shape: Arc<Shape>,
+                        n_samples: u32,
+                        two_sided: bool)
+                        -> DiffuseAreaLight {
         let area = shape.area();
+        let l_average = match l_texture {
+            Some(ref texture) => l_emit * average_texture(&*shape, &**texture),
+            None => l_emit,
+        };
         DiffuseAreaLight {
             id: super::get_next_id(),
             l_emit: l_emit,
+            l_texture: l_texture,
+            l_average: l_average,
             shape: shape,
             n_samples: n_samples,
             two_sided: two_sided,
@@ -38,19 +64,34 @@ impl DiffuseAreaLight {
 
     pub fn create(_light2world: &Transform,
                   ps: &mut ParamSet,
-                  shape: Arc<Shape>)
+                  shape: Arc<Shape>,
+                  spectrum_textures: &HashMap<String, Arc<TextureSpectrum>>)
                   -> Arc<DiffuseAreaLight> {
+        let texture_name = ps.find_texture("L", String::new());
+        let texture = if texture_name.is_empty() {
+            None
+        } else {
+            let texture = spectrum_textures.get(&texture_name).cloned();
+            if texture.is_none() {
+                error!("Couldn't find spectrum texture {} for area light", texture_name);
+            }
+            texture
+        };
         let L = ps.find_one_spectrum("L", Spectrum::white());
         let sc = ps.find_one_spectrum("scale", Spectrum::white());
         let nsamples = ps.find_one_int("nsamples", 1);
         let nsamples = ps.find_one_int("samples", nsamples);
         let two_sided = ps.find_one_bool("twosided", false);
 
-        Arc::new(Self::new(L * sc, shape, nsamples as u32, two_sided))
+        Arc::new(Self::with_texture(L * sc, texture, shape, nsamples as u32, two_sided))
     }
 }
 
 impl Light for DiffuseAreaLight {
+    fn name(&self) -> &'static str {
+        "DiffuseAreaLight"
+    }
+
     fn id(&self) -> u32 {
         self.id
     }
@@ -59,16 +100,24 @@ impl Light for DiffuseAreaLight {
                  si: &Interaction,
                  u: &Point2f)
                  -> (Spectrum, Vector3f, f32, VisibilityTester) {
-        let (p_shape, pdf) = self.shape.sample_si(si, u);
+        let (p_shape, uv, pdf) = self.shape
+            .sample_solid_angle(si, u)
+            .unwrap_or_else(|| self.shape.sample_si(si, u));
+        if pdf == 0.0 {
+            let vis = VisibilityTester::new(*si, p_shape);
+            return (Spectrum::black(), Vector3f::new(0.0, 0.0, 0.0), 0.0, vis);
+        }
         assert!(!p_shape.p.x.is_nan() && !p_shape.p.y.is_nan() && !p_shape.p.z.is_nan());
         let wi = (p_shape.p - si.p).normalize();
         let vis = VisibilityTester::new(*si, p_shape);
 
-        (self.l(&p_shape, &(-wi)), wi, pdf, vis)
+        (self.l(&p_shape, &uv, &(-wi)), wi, pdf, vis)
     }
 
     fn pdf_li(&self, si: &Interaction, wi: &Vector3f) -> f32 {
-        self.shape.pdf_wi(si, wi)
+        self.shape
+            .pdf_solid_angle(si, wi)
+            .unwrap_or_else(|| self.shape.pdf_wi(si, wi))
     }
 
     fn n_samples(&self) -> u32 {
@@ -81,16 +130,321 @@ impl Light for DiffuseAreaLight {
 
     fn power(&self) -> Spectrum {
         let factor = if self.two_sided { 2.0 } else { 1.0 };
-        factor * self.l_emit * PI * self.area
+        factor * self.l_average * PI * self.area
+    }
+
+    fn area(&self) -> f32 {
+        self.area
     }
 }
 
 impl AreaLight for DiffuseAreaLight {
-    fn l(&self, si: &Interaction, w: &Vector3f) -> Spectrum {
-        if self.two_sided || si.n.dot(w) > 0.0 {
-            self.l_emit
-        } else {
-            Spectrum::black()
+    /// Emitted radiance from the point `si` on the light's shape, in direction `w`. Unless the
+    /// light is two-sided, light is only emitted on the side the surface normal points to; shapes
+    /// take care of flipping it for `ReverseOrientation` and handedness-swapping transforms.
+    fn l(&self, si: &Interaction, uv: &Point2f, w: &Vector3f) -> Spectrum {
+        if !(self.two_sided || si.n.dot(w) > 0.0) {
+            return Spectrum::black();
+        }
+        match self.l_texture {
+            Some(ref texture) => {
+                let si = SurfaceInteraction::from_sample(si, *uv, &*self.shape);
+                self.l_emit * texture.evaluate(&si)
+            }
+            None => self.l_emit,
+        }
+    }
+
+    fn l_surface(&self, si: &SurfaceInteraction, w: &Vector3f) -> Spectrum {
+        if !(self.two_sided || si.hit.n.dot(w) > 0.0) {
+            return Spectrum::black();
+        }
+        match self.l_texture {
+            Some(ref texture) => self.l_emit * texture.evaluate(si),
+            None => self.l_emit,
+        }
+    }
+}
+
+/// Average value of `texture` over the surface of `shape`.
+fn average_texture(shape: &Shape, texture: &TextureSpectrum) -> Spectrum {
+    let mut sum = Spectrum::black();
+    for i in 0..POWER_ESTIMATE_SAMPLES {
+        for j in 0..POWER_ESTIMATE_SAMPLES {
+            let u = Point2f::new((i as f32 + 0.5) / POWER_ESTIMATE_SAMPLES as f32,
+                                 (j as f32 + 0.5) / POWER_ESTIMATE_SAMPLES as f32);
+            let (p, uv, _) = shape.sample(&u);
+            sum += texture.evaluate(&SurfaceInteraction::from_sample(&p, uv, shape));
+        }
+    }
+    sum / (POWER_ESTIMATE_SAMPLES * POWER_ESTIMATE_SAMPLES) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Normal3f, Point3f};
+    use ray::Ray;
+    use rng::RNG;
+    use shapes::{Cylinder, Disk, Rectangle, Sphere};
+    use texture::{AAMethod, CheckerboardTexture, ConstantTexture, UVMapping2D};
+
+    // Returns the radiance emitted upwards by a disk light lying in the z=0 plane, both when
+    // hitting it with a ray and when sampling it.
+    fn emitted_upwards(o2w: Transform, reverse_orientation: bool) -> (Spectrum, Spectrum) {
+        let disk: Arc<Shape> = Arc::new(Disk::new(0.0, 1.0, 0.0, 360.0, o2w, reverse_orientation));
+        let light = DiffuseAreaLight::new(Spectrum::white(), Arc::clone(&disk), 1, false);
+        let up = Vector3f::new(0.0, 0.0, 1.0);
+
+        let ray = Ray::new(Point3f::new(0.1, 0.2, 1.0), -up);
+        let (isect, _) = disk.intersect(&ray).unwrap();
+        let (sampled, uv, _) = disk.sample(&Point2f::new(0.3, 0.6));
+
+        (light.l_surface(&isect, &up), light.l(&sampled, &uv, &up))
+    }
+
+    #[test]
+    fn test_reverse_orientation_flips_emission() {
+        ::init_stats();
+        let (hit, sampled) = emitted_upwards(Transform::default(), false);
+        assert_eq!(hit, Spectrum::white());
+        assert_eq!(sampled, Spectrum::white());
+
+        let (hit, sampled) = emitted_upwards(Transform::default(), true);
+        assert!(hit.is_black());
+        assert!(sampled.is_black());
+    }
+
+    #[test]
+    fn test_handedness_swap_is_consistent() {
+        ::init_stats();
+        // A mirroring transform flips the disk's orientation; hits and samples must agree on it.
+        let mirror = Transform::scale(-1.0, 1.0, 1.0);
+        let (hit, sampled) = emitted_upwards(mirror.clone(), false);
+        assert_eq!(hit, sampled);
+        let (hit_reversed, sampled_reversed) = emitted_upwards(mirror, true);
+        assert_eq!(hit_reversed, sampled_reversed);
+        assert!(hit.is_black() != hit_reversed.is_black());
+    }
+
+    // Mean and variance of the estimates of the irradiance at `si` using the given sampling
+    // function.
+    fn irradiance_estimate<F>(si: &Interaction, sample: F) -> (f32, f32)
+        where F: Fn(&Point2f) -> (Spectrum, Vector3f, f32)
+    {
+        let n = 4096;
+        let mut rng = RNG::new();
+        let mut values = Vec::with_capacity(n);
+        for _ in 0..n {
+            let u = Point2f::new(rng.uniform_f32(), rng.uniform_f32());
+            let (l, wi, pdf) = sample(&u);
+            if pdf > 0.0 {
+                values.push(l.y() * wi.dotn(&si.n).abs() / pdf);
+            } else {
+                values.push(0.0);
+            }
+        }
+        let mean = values.iter().sum::<f32>() / n as f32;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / n as f32;
+
+        (mean, variance)
+    }
+
+    fn receiver() -> Interaction {
+        Interaction::new(Point3f::new(0.3, 0.1, 0.2),
+                         Vector3f::new(0.0, 0.0, 0.0),
+                         Vector3f::new(0.0, 0.0, -1.0),
+                         Normal3f::new(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn test_disk_solid_angle_sampling_reduces_variance() {
+        ::init_stats();
+        let disk: Arc<Shape> =
+            Arc::new(Disk::new(0.0, 1.0, 0.0, 360.0, Transform::default(), false));
+        let light = DiffuseAreaLight::new(Spectrum::white(), Arc::clone(&disk), 1, false);
+        let si = receiver();
+
+        let (mean_area, variance_area) = irradiance_estimate(&si, |u| {
+            let (p_shape, uv, pdf) = disk.sample_si(&si, u);
+            let wi = (p_shape.p - si.p).normalize();
+            (light.l(&p_shape, &uv, &(-wi)), wi, pdf)
+        });
+        let (mean_sa, variance_sa) = irradiance_estimate(&si, |u| {
+            let (l, wi, pdf, _) = light.sample_li(&si, u);
+            if pdf > 0.0 {
+                assert!((light.pdf_li(&si, &wi) - pdf).abs() < 1e-3 * pdf);
+            }
+            (l, wi, pdf)
+        });
+
+        assert!((mean_sa - mean_area).abs() < 0.05 * mean_area,
+                "{} vs {}",
+                mean_sa,
+                mean_area);
+        assert!(variance_sa * 4.0 < variance_area,
+                "{} vs {}",
+                variance_sa,
+                variance_area);
+    }
+
+    #[test]
+    fn test_sphere_samples_are_on_the_visible_side() {
+        ::init_stats();
+        let sphere: Arc<Shape> =
+            Arc::new(Sphere::new(Transform::translate(&Vector3f::new(0.0, 0.0, -3.0)),
+                                 1.0,
+                                 -1.0,
+                                 1.0,
+                                 360.0,
+                                 false));
+        let light = DiffuseAreaLight::new(Spectrum::white(), Arc::clone(&sphere), 1, false);
+        let si = receiver();
+
+        let (_, variance_area) = irradiance_estimate(&si, |u| {
+            let (p_shape, uv, pdf) = sphere.sample_si(&si, u);
+            let wi = (p_shape.p - si.p).normalize();
+            (light.l(&p_shape, &uv, &(-wi)), wi, pdf)
+        });
+        let (mean_sa, variance_sa) = irradiance_estimate(&si, |u| {
+            let (l, wi, pdf, vis) = light.sample_li(&si, u);
+            assert!(pdf > 0.0);
+            assert!((light.pdf_li(&si, &wi) - pdf).abs() < 1e-3 * pdf);
+            // The sampled point faces the receiver, and is the first point of the sphere along
+            // the sampled direction
+            assert!(!l.is_black());
+            let (isect, _) = sphere.intersect(&si.spawn_ray(&wi)).unwrap();
+            assert!((isect.hit.p - vis.p1.p).length() < 1e-3,
+                    "sampled {} but the sphere is hit at {}",
+                    vis.p1.p,
+                    isect.hit.p);
+            (l, wi, pdf)
+        });
+
+        // The irradiance due to a unit sphere entirely above the horizon is pi / d^2 times the
+        // cosine of the direction to its center
+        let to_center = Point3f::new(0.0, 0.0, -3.0) - si.p;
+        let expected = PI / to_center.length_squared() * to_center.normalize().dotn(&si.n).abs();
+        assert!((mean_sa - expected).abs() < 0.01 * expected,
+                "{} vs {}",
+                mean_sa,
+                expected);
+        assert!(variance_sa * 4.0 < variance_area,
+                "{} vs {}",
+                variance_sa,
+                variance_area);
+    }
+
+    #[test]
+    fn test_rectangle_solid_angle_sampling() {
+        let rect: Arc<Shape> = Arc::new(Rectangle::new(2.0, 1.0, Transform::default(), false));
+        let light = DiffuseAreaLight::new(Spectrum::white(), Arc::clone(&rect), 1, false);
+        let si = receiver();
+
+        let (mean_area, _) = irradiance_estimate(&si, |u| {
+            let (p_shape, uv, pdf) = rect.sample_si(&si, u);
+            let wi = (p_shape.p - si.p).normalize();
+            (light.l(&p_shape, &uv, &(-wi)), wi, pdf)
+        });
+        let (mean_sa, _) = irradiance_estimate(&si, |u| {
+            let (l, wi, pdf, _) = light.sample_li(&si, u);
+            // Every sample lands on the rectangle
+            assert!(pdf > 0.0);
+            assert!((light.pdf_li(&si, &wi) - pdf).abs() < 1e-3 * pdf);
+            (l, wi, pdf)
+        });
+
+        assert!((mean_sa - mean_area).abs() < 0.05 * mean_area,
+                "{} vs {}",
+                mean_sa,
+                mean_area);
+    }
+
+    #[test]
+    fn test_checker_emission_alternates() {
+        ::init_stats();
+        let disk: Arc<Shape> =
+            Arc::new(Disk::new(0.0, 1.0, 0.0, 360.0, Transform::default(), false));
+        // Four checks around the disk, alternately white and black
+        let checker = CheckerboardTexture::new(Arc::new(ConstantTexture::new(Spectrum::white())),
+                                               Arc::new(ConstantTexture::new(Spectrum::black())),
+                                               Box::new(UVMapping2D::new(4.0, 1.0, 0.0, 0.0)),
+                                               AAMethod::None);
+        let light = DiffuseAreaLight::with_texture(Spectrum::grey(2.0),
+                                                   Some(Arc::new(checker)),
+                                                   Arc::clone(&disk),
+                                                   1,
+                                                   false);
+        let up = Vector3f::new(0.0, 0.0, 1.0);
+
+        for k in 0..4 {
+            let phi = (0.25 + 0.5 * k as f32) * PI;
+            let ray = Ray::new(Point3f::new(0.5 * phi.cos(), 0.5 * phi.sin(), 1.0), -up);
+            let (isect, _) = disk.intersect(&ray).unwrap();
+            let expected = if k % 2 == 0 {
+                Spectrum::grey(2.0)
+            } else {
+                Spectrum::black()
+            };
+            assert_eq!(light.l_surface(&isect, &up), expected, "check {}", k);
+            assert_eq!(light.l(&isect.hit, &isect.uv, &up), expected, "check {}", k);
+        }
+
+        // Sampled points get the emission of the check they fall in
+        let si = Interaction::new(Point3f::new(0.0, 0.0, 2.0),
+                                  Vector3f::new(0.0, 0.0, 0.0),
+                                  Vector3f::new(0.0, 0.0, -1.0),
+                                  Normal3f::new(0.0, 0.0, -1.0));
+        let mut n_lit = 0;
+        for i in 0..64 {
+            let u = Point2f::new((i % 8) as f32 / 8.0 + 0.0625, (i / 8) as f32 / 8.0 + 0.0625);
+            let (l, _, _, vis) = light.sample_li(&si, &u);
+            let p = vis.p1.p;
+            let mut phi = p.y.atan2(p.x);
+            if phi < 0.0 {
+                phi += 2.0 * PI;
+            }
+            if (2.0 * phi / PI).floor() as u32 % 2 == 0 {
+                assert_eq!(l, Spectrum::grey(2.0), "sample at {}", p);
+                n_lit += 1;
+            } else {
+                assert!(l.is_black(), "sample at {}", p);
+            }
+        }
+        assert!(n_lit > 0 && n_lit < 64);
+
+        // Half the disk emits
+        let expected_power = 0.5 * 2.0 * PI * PI;
+        assert!((light.power().y() - expected_power).abs() < 1e-3 * expected_power);
+    }
+
+    #[test]
+    fn test_sampled_uv_matches_intersection() {
+        ::init_stats();
+        let shapes: Vec<Arc<Shape>> =
+            vec![Arc::new(Disk::new(0.5, 1.0, 0.25, 270.0, Transform::default(), false)),
+                 Arc::new(Sphere::new(Transform::translate(&Vector3f::new(1.0, 0.0, 0.0)),
+                                      2.0,
+                                      -2.0,
+                                      2.0,
+                                      360.0,
+                                      false)),
+                 Arc::new(Cylinder::new(Transform::default(), 1.0, -1.0, 1.0, 360.0, false)),
+                 Arc::new(Rectangle::new(2.0, 1.0, Transform::default(), false))];
+        for shape in &shapes {
+            for i in 0..8 {
+                for j in 0..8 {
+                    let u = Point2f::new((i as f32 + 0.5) / 8.0, (j as f32 + 0.5) / 8.0);
+                    let (p, uv, _) = shape.sample(&u);
+                    let n = Vector3f::from(p.n);
+                    let (isect, _) = shape.intersect(&Ray::new(p.p + n, -n)).unwrap();
+                    assert!((isect.uv.x - uv.x).abs() < 1e-3 && (isect.uv.y - uv.y).abs() < 1e-3,
+                            "{}: sampled {}, hit {}",
+                            shape.name(),
+                            uv,
+                            isect.uv);
+                }
+            }
         }
     }
 }
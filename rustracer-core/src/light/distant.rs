@@ -4,10 +4,11 @@ use std::sync::Arc;
 use num::Zero;
 use parking_lot::RwLock;
 
-use {Point2f, Point3f, Transform, Vector3f};
+use {coordinate_system, Point2f, Point3f, Transform, Vector3f};
 use interaction::Interaction;
 use light::{Light, LightFlags, VisibilityTester};
 use paramset::ParamSet;
+use sampling::{uniform_cone_pdf, uniform_sample_cone};
 use scene::Scene;
 use spectrum::Spectrum;
 
@@ -16,16 +17,26 @@ pub struct DistantLight {
     id: u32,
     dir: Vector3f,
     emission_colour: Spectrum,
+    /// Cosine of the angular radius of the light's disk, 1 for a perfectly directional light
+    cos_theta_max: f32,
     w_center: RwLock<Point3f>,
     w_radius: RwLock<f32>,
 }
 
 impl DistantLight {
     pub fn new(dir: Vector3f, ec: Spectrum) -> DistantLight {
+        DistantLight::with_angle(dir, ec, 0.0)
+    }
+
+    /// Create a distant light that covers a disk of angular diameter `angle` degrees in the sky,
+    /// like the sun, so that it casts soft shadows. `ec` is the irradiance it gives a surface
+    /// facing it, whatever the angle.
+    pub fn with_angle(dir: Vector3f, ec: Spectrum, angle: f32) -> DistantLight {
         DistantLight {
             id: super::get_next_id(),
             dir: dir.normalize(),
             emission_colour: ec,
+            cos_theta_max: (0.5 * angle).to_radians().cos(),
             w_center: RwLock::new(Point3f::new(0.0, 0.0, 0.0)),
             w_radius: RwLock::new(0.0),
         }
@@ -36,18 +47,23 @@ impl DistantLight {
         let scale = params.find_one_spectrum("scale", Spectrum::white());
         let from = params.find_one_point3f("from", Point3f::zero());
         let to = params.find_one_point3f("to", Point3f::new(0.0, 0.0, 1.0));
+        let angle = params.find_one_float("angle", 0.0);
         let dir = from - to;
-        Arc::new(DistantLight::new(l2w * &dir, L * scale))
+        Arc::new(DistantLight::with_angle(l2w * &dir, L * scale, angle))
     }
 }
 
 impl Light for DistantLight {
+    fn name(&self) -> &'static str {
+        "DistantLight"
+    }
+
     fn id(&self) -> u32 {
         self.id
     }
 
     fn preprocess(&self, scene: &Scene) {
-        let (w_center, w_radius) = scene.world_bounds().bounding_sphere();
+        let (w_center, w_radius) = scene.world_bounding_sphere();
         let mut wc = self.w_center.write();
         *wc = w_center;
         let mut wr = self.w_radius.write();
@@ -56,14 +72,21 @@ impl Light for DistantLight {
 
     fn sample_li(&self,
                  isect: &Interaction,
-                 _u: &Point2f)
+                 u: &Point2f)
                  -> (Spectrum, Vector3f, f32, VisibilityTester) {
+        let (wi, l_i, pdf) = if self.cos_theta_max < 1.0 {
+            // Pick a direction in the light's disk. The light is still treated as a delta light
+            // by the integrators, so only light sampling reaches it.
+            let (e1, e2) = coordinate_system(&self.dir);
+            let w = uniform_sample_cone(u, self.cos_theta_max);
+            let pdf = uniform_cone_pdf(self.cos_theta_max);
+            (e1 * w.x + e2 * w.y + self.dir * w.z, self.emission_colour * pdf, pdf)
+        } else {
+            (self.dir, self.emission_colour, 1.0)
+        };
         let wr = self.w_radius.read();
-        let p_outside = isect.p + self.dir * (2.0 * *wr);
-        (self.emission_colour,
-         self.dir,
-         1.0,
-         VisibilityTester::new(*isect, Interaction::from_point(&p_outside)))
+        let p_outside = isect.p + wi * (2.0 * *wr);
+        (l_i, wi, pdf, VisibilityTester::new(*isect, Interaction::from_point(&p_outside)))
     }
 
     fn pdf_li(&self, _si: &Interaction, _wi: &Vector3f) -> f32 {
@@ -83,3 +106,53 @@ impl Light for DistantLight {
         self.emission_colour * PI * *wr * *wr
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_directions(light: &DistantLight) -> Vec<(Vector3f, Spectrum, f32)> {
+        let it = Interaction::from_point(&Point3f::new(1.0, 2.0, 3.0));
+        let mut samples = Vec::new();
+        for i in 0..8 {
+            for j in 0..8 {
+                let u = Point2f::new((i as f32 + 0.5) / 8.0, (j as f32 + 0.5) / 8.0);
+                let (l_i, wi, pdf, _) = light.sample_li(&it, &u);
+                samples.push((wi, l_i, pdf));
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn test_zero_angle_is_a_hard_directional_light() {
+        let dir = Vector3f::new(1.0, 1.0, 0.5).normalize();
+        let light = DistantLight::with_angle(dir, Spectrum::white(), 0.0);
+        for (wi, l_i, pdf) in sample_directions(&light) {
+            assert_eq!(wi, dir);
+            assert_eq!(l_i, Spectrum::white());
+            assert_eq!(pdf, 1.0);
+        }
+    }
+
+    #[test]
+    fn test_angle_spreads_directions_within_the_cone() {
+        let dir = Vector3f::new(1.0, 1.0, 0.5).normalize();
+        let angle = 4.0f32;
+        let light = DistantLight::with_angle(dir, Spectrum::white(), angle);
+        let cos_theta_max = (0.5 * angle).to_radians().cos();
+
+        let samples = sample_directions(&light);
+        let mut min_cos = 1.0f32;
+        for &(wi, l_i, pdf) in &samples {
+            assert!((wi.length() - 1.0).abs() < 1e-5);
+            let cos_theta = wi.dot(&dir);
+            assert!(cos_theta >= cos_theta_max - 1e-6);
+            min_cos = min_cos.min(cos_theta);
+            // The irradiance estimate is the same as the hard light's
+            assert!((l_i[0] / pdf - 1.0).abs() < 1e-4);
+        }
+        // Directions reach out to the edge of the disk, not just its center
+        assert!(min_cos < 1.0 - 0.5 * (1.0 - cos_theta_max));
+    }
+}
@@ -6,25 +6,51 @@ use std::sync::Arc;
 use num::Zero;
 use parking_lot::RwLock;
 
-use {Point2f, Point2i, Point3f, Transform, Vector3f};
+use {clamp, Point2f, Point2i, Point3f, Transform, Vector3f};
 use geometry::{spherical_phi, spherical_theta};
 use imageio::read_image;
 use interaction::Interaction;
 use light::{Light, LightFlags, VisibilityTester};
-use mipmap::{MIPMap, WrapMode};
+use light::sky::SkyModel;
+use mipmap::{FilterMode, MIPMap, WrapMode};
 use paramset::ParamSet;
 use ray::Ray;
 use sampling::Distribution2D;
 use scene::Scene;
 use spectrum::Spectrum;
 
+/// Resolution of the sampling distribution used for procedural skies.
+const SKY_DISTRIBUTION_WIDTH: usize = 1024;
+const SKY_DISTRIBUTION_HEIGHT: usize = 512;
+
+/// Where the radiance of an infinite light comes from.
+#[derive(Debug)]
+enum Environment {
+    /// An equirectangular environment map
+    Map(Box<MIPMap<Spectrum>>),
+    /// An analytic sky model, multiplied by a scale factor
+    Sky(Box<SkyModel>, Spectrum),
+}
+
+impl Environment {
+    /// Radiance for the given (u, v) coordinates in the equirectangular parameterization, and the
+    /// corresponding direction `w` in light space.
+    fn lookup(&self, uv: &Point2f, w: &Vector3f) -> Spectrum {
+        match self {
+            Environment::Map(l_map) => l_map.lookup(uv, 0.0),
+            Environment::Sky(sky, scale) => sky.radiance(w) * *scale,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct InfiniteAreaLight {
     id: u32,
     light_to_world: Transform,
     world_to_light: Transform,
     n_samples: u32,
-    l_map: Box<MIPMap<Spectrum>>,
+    environment: Environment,
+    average_radiance: Spectrum,
     world_center: RwLock<Point3f>,
     world_radius: RwLock<f32>,
     distribution: Box<Distribution2D>,
@@ -50,7 +76,11 @@ impl InfiniteAreaLight {
             (Point2i::new(1, 1), vec![power])
         };
         //
-        let l_map = Box::new(MIPMap::new(&resolution, &texels[..], false, 0.0, WrapMode::Repeat));
+        let l_map = Box::new(MIPMap::new(&resolution,
+                                         &texels[..],
+                                         FilterMode::EWA,
+                                         0.0,
+                                         WrapMode::Repeat));
         // initialize sampling PDFs for infinite area light
         // - compute scalar-valued image img from environment map
         let (width, height) = (2 * l_map.width(), 2 * l_map.height());
@@ -66,36 +96,124 @@ impl InfiniteAreaLight {
         }
         // - compute sampling distributions for rows and columns of image
         let distribution = Box::new(Distribution2D::new(&img[..], width, height));
+        let average_radiance = l_map.lookup(&Point2f::new(0.5, 0.5), 0.5);
 
         InfiniteAreaLight {
             id: super::get_next_id(),
             world_to_light: l2w.inverse(),
             light_to_world: l2w,
             n_samples: n_samples,
-            l_map: l_map,
+            environment: Environment::Map(l_map),
+            average_radiance,
             world_center: RwLock::new(Point3f::zero()),
             world_radius: RwLock::new(0.0),
             distribution: distribution,
         }
     }
 
+    /// Create an infinite light whose radiance is given by an analytic sky model rather than an
+    /// environment map.
+    pub fn new_sky(l2w: Transform,
+                   n_samples: u32,
+                   scale: Spectrum,
+                   sky: SkyModel)
+                   -> InfiniteAreaLight {
+        // Tabulate the sky's luminance to build the sampling distribution. The sun is much smaller
+        // than a pixel of the distribution, so we make sure any pixel that overlaps it gets the
+        // sun's luminance, otherwise it'd hardly ever get sampled.
+        let (width, height) = (SKY_DISTRIBUTION_WIDTH, SKY_DISTRIBUTION_HEIGHT);
+        let d_theta = PI / height as f32;
+        let d_phi = 2.0 * PI / width as f32;
+        let sun_y = sky.sun_radiance().y();
+        let mut img = Vec::with_capacity(width * height);
+        let mut sum = Spectrum::black();
+        for v in 0..height {
+            let theta = (v as f32 + 0.5) * d_theta;
+            let (sin_theta, cos_theta) = theta.sin_cos();
+            let pixel_radius = 0.5 * (d_theta * d_theta + d_phi * d_phi * sin_theta * sin_theta)
+                .sqrt();
+            for u in 0..width {
+                let phi = (u as f32 + 0.5) * d_phi;
+                let w = Vector3f::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                let l = sky.sky_radiance(&w);
+                let mut y = l.y();
+                let sun_angle = w.dot(sky.sun_direction()).max(-1.0).min(1.0).acos();
+                if sun_angle < sky.sun_angular_radius() + pixel_radius {
+                    y += sun_y;
+                }
+                img.push(y * sin_theta);
+                sum += l * sin_theta;
+            }
+        }
+        let distribution = Box::new(Distribution2D::new(&img[..], width, height));
+        // Average radiance over the whole sphere of directions
+        let sun_solid_angle = 2.0 * PI * (1.0 - sky.sun_angular_radius().cos());
+        let average_radiance = (sum * (d_theta * d_phi) + sky.sun_radiance() * sun_solid_angle) *
+                               scale / (4.0 * PI);
+
+        InfiniteAreaLight {
+            id: super::get_next_id(),
+            world_to_light: l2w.inverse(),
+            light_to_world: l2w,
+            n_samples: n_samples,
+            environment: Environment::Sky(Box::new(sky), scale),
+            average_radiance,
+            world_center: RwLock::new(Point3f::zero()),
+            world_radius: RwLock::new(0.0),
+            distribution: distribution,
+        }
+    }
+
+    /// Create an infinite light from the scene parameters. `"rotation"` spins the environment by
+    /// the given angle in degrees around its up axis (the poles of the map, i.e. the z axis in
+    /// light space), on top of the current transformation.
     pub fn create(l2w: &Transform, params: &mut ParamSet) -> Arc<Light> {
         let L = params.find_one_spectrum("L", Spectrum::white());
         let scale = params.find_one_spectrum("scale", Spectrum::white());
         let mapname = params.find_one_filename("mapname", "".to_owned());
         let n_samples = params.find_one_int("samples", 1);
+        let sky_model = params.find_one_string("skymodel", "".to_owned());
+        let rotation = params.find_one_float("rotation", 0.0);
+        let l2w = l2w * &Transform::rotate(rotation, Vector3f::new(0.0, 0.0, 1.0));
         // TODO quickrender
-        Arc::new(InfiniteAreaLight::new(l2w.clone(), n_samples as u32, L * scale, mapname))
+        if sky_model == "" {
+            return Arc::new(InfiniteAreaLight::new(l2w,
+                                                   n_samples as u32,
+                                                   L * scale,
+                                                   mapname));
+        }
+
+        if sky_model != "preetham" {
+            warn!("Sky model \"{}\" is not supported. Using \"preetham\" instead.",
+                  sky_model);
+        }
+        if mapname != "" {
+            warn!("Infinite light has both a \"mapname\" and a \"skymodel\". Ignoring the map.");
+        }
+        let turbidity = params.find_one_float("turbidity", 3.0);
+        let turbidity = if turbidity < 1.7 || turbidity > 10.0 {
+            warn!("Sky turbidity {} out of the [1.7, 10] range. Clamping it.", turbidity);
+            clamp(turbidity, 1.7, 10.0)
+        } else {
+            turbidity
+        };
+        let sun_direction = params.find_one_vector3f("sundirection", Vector3f::new(0.0, 1.0, 1.0));
+        let sky = SkyModel::new(&sun_direction, turbidity);
+        Arc::new(InfiniteAreaLight::new_sky(l2w, n_samples as u32, L * scale, sky))
     }
 }
 
 impl Light for InfiniteAreaLight {
+    fn name(&self) -> &'static str {
+        "InfiniteAreaLight"
+    }
+
     fn id(&self) -> u32 {
         self.id
     }
 
     fn preprocess(&self, scene: &Scene) {
-        let (w_center, w_radius) = scene.world_bounds().bounding_sphere();
+        let (w_center, w_radius) = scene.world_bounding_sphere();
         let mut wc = self.world_center.write();
         *wc = w_center;
         let mut wr = self.world_radius.write();
@@ -134,7 +252,8 @@ impl Light for InfiniteAreaLight {
         let world_radius = self.world_radius.read();
         let target = isect.p + wi * (2.0 * *world_radius);
         let vis = VisibilityTester::new(*isect, Interaction::from_point(&target));
-        (self.l_map.lookup(&uv, 0.0), wi, pdf, vis)
+        let w_light = Vector3f::new(sin_theta * cos_phi, sin_theta * sin_phi, cos_theta);
+        (self.environment.lookup(&uv, &w_light), wi, pdf, vis)
     }
 
     fn pdf_li(&self, _si: &Interaction, w: &Vector3f) -> f32 {
@@ -162,7 +281,7 @@ impl Light for InfiniteAreaLight {
 
     fn power(&self) -> Spectrum {
         let world_radius = self.world_radius.read();
-        PI * *world_radius * *world_radius * self.l_map.lookup(&Point2f::new(0.5, 0.5), 0.5)
+        PI * *world_radius * *world_radius * self.average_radiance
     }
 
     fn le(&self, ray: &Ray) -> Spectrum {
@@ -170,6 +289,47 @@ impl Light for InfiniteAreaLight {
         let st = Point2f::new(spherical_phi(&w) * FRAC_1_PI * 0.5,
                               spherical_theta(&w) * FRAC_1_PI);
 
-        self.l_map.lookup(&st, 0.0)
+        self.environment.lookup(&st, &w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::{Array, ParamListEntry, ParamType};
+
+    fn sky(rotation: f32) -> Arc<Light> {
+        let mut params = ParamSet::default();
+        params.init(vec![ParamListEntry::new(ParamType::String,
+                                             "skymodel".to_owned(),
+                                             Array::StrArray(vec!["preetham".to_owned()])),
+                         ParamListEntry::new(ParamType::Float,
+                                             "rotation".to_owned(),
+                                             Array::NumArray(vec![rotation]))]);
+        InfiniteAreaLight::create(&Transform::default(), &mut params)
+    }
+
+    fn radiance(light: &Arc<Light>, x: f32, y: f32, z: f32) -> Spectrum {
+        light.le(&Ray::new(Point3f::zero(), Vector3f::new(x, y, z)))
+    }
+
+    #[test]
+    fn test_rotation_spins_the_environment() {
+        let light = sky(0.0);
+        let rotated = sky(180.0);
+        // The sun is in the +y half of the sky, so the sky is brighter there
+        assert!(radiance(&light, 0.0, 1.0, 0.5).y() > radiance(&light, 0.0, -1.0, 0.5).y());
+        for &(x, y, z) in &[(0.0, 1.0, 0.5), (0.3, -0.8, 0.2), (-1.0, 0.2, 1.0), (0.5, 0.5, -0.5)] {
+            // Rotating by 180 degrees moves each direction to the opposite side of the map
+            let l = radiance(&light, -x, -y, z);
+            let l_rotated = radiance(&rotated, x, y, z);
+            assert!((l.y() - l_rotated.y()).abs() <= 1e-3 * l.y(),
+                    "{:?} vs {:?} for ({}, {}, {})",
+                    l,
+                    l_rotated,
+                    x,
+                    y,
+                    z);
+        }
     }
 }
@@ -4,20 +4,25 @@ use std::fmt::Debug;
 use parking_lot::Mutex;
 
 use {Point2f, Vector3f};
-use interaction::Interaction;
+use interaction::{Interaction, SurfaceInteraction};
 use ray::Ray;
+use sampler::Sampler;
 use scene::Scene;
 use spectrum::Spectrum;
 
 mod point;
+mod spot;
 mod distant;
 mod diffuse;
 mod infinite;
+mod sky;
 
 pub use self::point::PointLight;
+pub use self::spot::{SpotFalloff, SpotLight};
 pub use self::distant::DistantLight;
 pub use self::diffuse::DiffuseAreaLight;
 pub use self::infinite::InfiniteAreaLight;
+pub use self::sky::SkyModel;
 
 bitflags! {
     pub struct LightFlags: u32 {
@@ -49,6 +54,23 @@ impl VisibilityTester {
         let r = self.p0.spawn_ray_to_interaction(&self.p1);
         !scene.intersect_p(&r)
     }
+
+    /// Same as `unoccluded()`, when `p1` is on `light`: the scene can then test the occluder
+    /// that last blocked that light first.
+    pub fn unoccluded_from(&self, scene: &Scene, light: &Light) -> bool {
+        let r = self.p0.spawn_ray_to_interaction(&self.p1);
+        !scene.intersect_p_light(&r, light.id())
+    }
+
+    /// Beam transmittance between the 2 points: black if they are occluded, otherwise the
+    /// attenuation due to the media the segment goes through.
+    pub fn tr(&self, scene: &Scene, sampler: &mut Box<Sampler>) -> Spectrum {
+        let mut r = self.p0.spawn_ray_to_interaction(&self.p1);
+        match scene.intersect_tr(&mut r, sampler) {
+            (Some(_), _) => Spectrum::black(),
+            (None, tr) => tr,
+        }
+    }
 }
 
 pub fn get_next_id() -> u32 {
@@ -60,6 +82,9 @@ pub fn get_next_id() -> u32 {
 }
 
 pub trait Light: Debug + Send + Sync {
+    /// Name of the light's type, e.g. "PointLight", as listed in the scene summary.
+    fn name(&self) -> &'static str;
+
     fn id(&self) -> u32;
     /// Sample the light source
     /// Return a tuple of:
@@ -74,6 +99,8 @@ pub trait Light: Debug + Send + Sync {
 
     fn pdf_li(&self, si: &Interaction, wi: &Vector3f) -> f32;
 
+    /// Set up any data that depends on the scene, like its bounding sphere. Called exactly once
+    /// for each light by `Scene::new()`, before any rendering happens.
     fn preprocess(&self, _scene: &Scene) {}
 
     fn n_samples(&self) -> u32;
@@ -82,11 +109,132 @@ pub trait Light: Debug + Send + Sync {
 
     fn power(&self) -> Spectrum;
 
+    /// Surface area of the emitting shape. Only area lights have one.
+    fn area(&self) -> f32 {
+        0.0
+    }
+
+    /// Radiance carried by a ray that escapes the scene without hitting anything. Only infinite
+    /// lights contribute to it, so the default is black.
     fn le(&self, _ray: &Ray) -> Spectrum {
         Spectrum::black()
     }
 }
 
 pub trait AreaLight: Light {
-    fn l(&self, si: &Interaction, w: &Vector3f) -> Spectrum;
+    /// Emitted radiance in direction `w` from the point `si` of the light's shape, whose (u, v)
+    /// coordinates are `uv` (as returned by `Shape::sample()`).
+    fn l(&self, si: &Interaction, uv: &Point2f, w: &Vector3f) -> Spectrum;
+
+    /// Same as `l()`, for a point found by intersecting the light's shape, whose surface
+    /// parameterization is fully known.
+    fn l_surface(&self, si: &SurfaceInteraction, w: &Vector3f) -> Spectrum {
+        self.l(&si.hit, &si.uv, w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use {Point3f, Transform};
+    use primitive::GeometricPrimitive;
+    use shapes::Sphere;
+
+    /// A point light that records how many times it was preprocessed.
+    #[derive(Debug)]
+    struct CountingLight {
+        light: PointLight,
+        n_preprocess: AtomicUsize,
+    }
+
+    impl Light for CountingLight {
+        fn name(&self) -> &'static str {
+            "CountingLight"
+        }
+
+        fn id(&self) -> u32 {
+            self.light.id()
+        }
+
+        fn preprocess(&self, scene: &Scene) {
+            // The scene passed to preprocess() must be complete
+            assert_eq!(scene.lights.len(), 1);
+            self.n_preprocess.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn sample_li(&self,
+                     isect: &Interaction,
+                     u: &Point2f)
+                     -> (Spectrum, Vector3f, f32, VisibilityTester) {
+            assert_eq!(self.n_preprocess.load(Ordering::SeqCst), 1);
+            self.light.sample_li(isect, u)
+        }
+
+        fn pdf_li(&self, si: &Interaction, wi: &Vector3f) -> f32 {
+            self.light.pdf_li(si, wi)
+        }
+
+        fn n_samples(&self) -> u32 {
+            self.light.n_samples()
+        }
+
+        fn flags(&self) -> LightFlags {
+            self.light.flags()
+        }
+
+        fn power(&self) -> Spectrum {
+            self.light.power()
+        }
+    }
+
+    #[test]
+    fn test_scene_preprocesses_lights_once() {
+        ::init_stats();
+        let light = Arc::new(CountingLight {
+                                 light: PointLight::new(Point3f::new(0.0, 0.0, -5.0),
+                                                        Spectrum::white()),
+                                 n_preprocess: AtomicUsize::new(0),
+                             });
+        let sphere = Sphere::new(Transform::default(), 1.0, -1.0, 1.0, 360.0, false);
+        let prim = GeometricPrimitive {
+            shape: Arc::new(sphere),
+            area_light: None,
+            material: None,
+        };
+        let scene = Scene::new(Arc::new(prim), vec![light.clone() as Arc<Light>]);
+        assert_eq!(light.n_preprocess.load(Ordering::SeqCst), 1);
+
+        let it = Interaction::from_point(&Point3f::new(0.0, 0.0, -2.0));
+        for l in &scene.lights {
+            let (li, _wi, pdf, _vis) = l.sample_li(&it, &Point2f::new(0.5, 0.5));
+            assert!(!li.is_black());
+            assert_eq!(pdf, 1.0);
+        }
+        assert_eq!(light.n_preprocess.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_le_for_miss_rays() {
+        ::init_stats();
+        let colour = Spectrum::rgb(0.2, 0.4, 0.8);
+        // No environment map: the infinite light falls back to a constant colour
+        let infinite = InfiniteAreaLight::new(Transform::new(), 1, colour, "does-not-exist.exr");
+        let point = PointLight::new(Point3f::new(0.0, 0.0, 0.0), Spectrum::white());
+        let distant = DistantLight::new(Vector3f::new(0.0, 0.0, 1.0), Spectrum::white());
+
+        for d in &[Vector3f::new(0.0, 0.0, 1.0),
+                   Vector3f::new(1.0, -1.0, 0.0),
+                   Vector3f::new(-0.3, 0.2, -0.9)] {
+            let ray = Ray::new(Point3f::new(1.0, 2.0, 3.0), *d);
+            let l = infinite.le(&ray);
+            for i in 0..3 {
+                assert!((l[i] - colour[i]).abs() < 1e-5);
+            }
+            assert!(point.le(&ray).is_black());
+            assert!(distant.le(&ray).is_black());
+        }
+    }
 }
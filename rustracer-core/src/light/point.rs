@@ -36,6 +36,10 @@ impl PointLight {
 }
 
 impl Light for PointLight {
+    fn name(&self) -> &'static str {
+        "PointLight"
+    }
+
     fn id(&self) -> u32 {
         self.id
     }
@@ -0,0 +1,163 @@
+use std::f32::consts::PI;
+
+use Vector3f;
+use spectrum::Spectrum;
+
+/// Angular radius of the sun's disk, as seen from the earth (in radians).
+const SUN_ANGULAR_RADIUS: f32 = 0.004_65;
+/// Ratio between the luminance of the sun's disk and the luminance of the sky right next to it.
+/// This roughly matches a clear day.
+const SUN_TO_SKY_LUMINANCE: f32 = 1.0e4;
+
+/// Analytic daylight model from "A Practical Analytic Model for Daylight", by Preetham, Shirley
+/// and Smits (1999).
+///
+/// Directions are expressed in light space, with +z pointing towards the zenith. The returned
+/// radiance is expressed in kcd/m², and is black below the horizon.
+#[derive(Debug)]
+pub struct SkyModel {
+    sun_direction: Vector3f,
+    cos_sun_radius: f32,
+    perez_Y: [f32; 5],
+    perez_x: [f32; 5],
+    perez_y: [f32; 5],
+    /// Values of `Y`, `x` and `y` at the zenith, divided by the value of the Perez function at the
+    /// zenith.
+    zenith_Y: f32,
+    zenith_x: f32,
+    zenith_y: f32,
+    sun_radiance: Spectrum,
+}
+
+impl SkyModel {
+    pub fn new(sun_direction: &Vector3f, turbidity: f32) -> SkyModel {
+        let t = turbidity;
+        let sun_direction = sun_direction.normalize();
+        // The model isn't valid for a sun below the horizon
+        let theta_s = f32::min(sun_direction.z.max(-1.0).min(1.0).acos(), 0.5 * PI - 1e-3);
+
+        let perez_Y = [0.1787 * t - 1.4630,
+                       -0.3554 * t + 0.4275,
+                       -0.0227 * t + 5.3251,
+                       0.1206 * t - 2.5771,
+                       -0.0670 * t + 0.3703];
+        let perez_x = [-0.0193 * t - 0.2592,
+                       -0.0665 * t + 0.0008,
+                       -0.0004 * t + 0.2125,
+                       -0.0641 * t - 0.8989,
+                       -0.0033 * t + 0.0452];
+        let perez_y = [-0.0167 * t - 0.2608,
+                       -0.0950 * t + 0.0092,
+                       -0.0079 * t + 0.2102,
+                       -0.0441 * t - 1.6537,
+                       -0.0109 * t + 0.0529];
+
+        // Zenith luminance and chromaticity
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let Y_z = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (th, th2, th3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let t2 = t * t;
+        let x_z = t2 * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th) +
+                  t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394) +
+                  (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let y_z = t2 * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th) +
+                  t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516) +
+                  (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let mut sky = SkyModel {
+            sun_direction,
+            cos_sun_radius: SUN_ANGULAR_RADIUS.cos(),
+            zenith_Y: Y_z / perez(&perez_Y, 0.0, theta_s),
+            zenith_x: x_z / perez(&perez_x, 0.0, theta_s),
+            zenith_y: y_z / perez(&perez_y, 0.0, theta_s),
+            perez_Y,
+            perez_x,
+            perez_y,
+            sun_radiance: Spectrum::black(),
+        };
+        sky.sun_radiance = sky.sky_radiance(&sky.sun_direction) * SUN_TO_SKY_LUMINANCE;
+
+        sky
+    }
+
+    pub fn sun_direction(&self) -> &Vector3f {
+        &self.sun_direction
+    }
+
+    /// Angular radius of the sun's disk.
+    pub fn sun_angular_radius(&self) -> f32 {
+        SUN_ANGULAR_RADIUS
+    }
+
+    /// Radiance arriving from the (normalized) direction `w`, including the sun's disk.
+    pub fn radiance(&self, w: &Vector3f) -> Spectrum {
+        let l = self.sky_radiance(w);
+        if w.z >= 0.0 && w.dot(&self.sun_direction) >= self.cos_sun_radius {
+            l + self.sun_radiance
+        } else {
+            l
+        }
+    }
+
+    /// Radiance of the sun's disk alone.
+    pub fn sun_radiance(&self) -> Spectrum {
+        self.sun_radiance
+    }
+
+    /// Radiance of the sky alone, without the sun's disk.
+    pub fn sky_radiance(&self, w: &Vector3f) -> Spectrum {
+        if w.z < 0.0 {
+            return Spectrum::black();
+        }
+        let theta = w.z.min(1.0).acos();
+        let gamma = w.dot(&self.sun_direction).max(-1.0).min(1.0).acos();
+        let Y = self.zenith_Y * perez(&self.perez_Y, theta, gamma);
+        let x = self.zenith_x * perez(&self.perez_x, theta, gamma);
+        let y = self.zenith_y * perez(&self.perez_y, theta, gamma);
+        if Y <= 0.0 || y <= 0.0 {
+            return Spectrum::black();
+        }
+        // xyY to XYZ
+        let xyz = [x / y * Y, Y, (1.0 - x - y) / y * Y];
+        let rgb = Spectrum::from_xyz(&xyz);
+
+        Spectrum::rgb(rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0))
+    }
+}
+
+/// Perez et al.'s sky luminance distribution function, for a view direction at an angle `theta`
+/// from the zenith and at an angle `gamma` from the sun.
+fn perez(coeffs: &[f32; 5], theta: f32, gamma: f32) -> f32 {
+    let cos_theta = theta.cos().max(1e-3);
+    let cos_gamma = gamma.cos();
+    (1.0 + coeffs[0] * (coeffs[1] / cos_theta).exp()) *
+    (1.0 + coeffs[2] * (coeffs[3] * gamma).exp() + coeffs[4] * cos_gamma * cos_gamma)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sampling::uniform_sample_sphere;
+    use Point2f;
+
+    #[test]
+    fn test_sun_is_brightest() {
+        for sun in &[Vector3f::new(0.0, 0.0, 1.0),
+                     Vector3f::new(0.3, 0.2, 0.8),
+                     Vector3f::new(1.0, 0.0, 0.1)] {
+            for turbidity in &[2.0, 3.0, 6.0] {
+                let sky = SkyModel::new(sun, *turbidity);
+                let sun_y = sky.radiance(sky.sun_direction()).y();
+                for i in 0..64 {
+                    for j in 0..64 {
+                        let u = Point2f::new((i as f32 + 0.5) / 64.0, (j as f32 + 0.5) / 64.0);
+                        let w = uniform_sample_sphere(&u);
+                        let l = sky.radiance(&w);
+                        assert!(!l.has_nan());
+                        assert!(l.y() <= sun_y);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::sync::Arc;
+use std::f32::consts::PI;
+
+use num::Zero;
+
+use {clamp, Point2f, Point3f, Transform, Vector3f};
+use interaction::Interaction;
+use light::{Light, LightFlags, VisibilityTester};
+use paramset::ParamSet;
+use spectrum::Spectrum;
+
+/// Shape of the transition between the fully lit centre of a spotlight's cone and its edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpotFalloff {
+    /// Smoothstep, with a zero slope at both ends of the transition
+    Smooth,
+    /// Linear in the cosine of the angle to the spotlight's axis
+    Linear,
+    /// Fourth power of the linear ramp, as in pbrt-v3
+    Cos4,
+}
+
+impl SpotFalloff {
+    /// Map `delta`, going from 0 at the edge of the cone to 1 at the start of the falloff, to the
+    /// fraction of the intensity emitted.
+    pub fn apply(&self, delta: f32) -> f32 {
+        let delta = clamp(delta, 0.0, 1.0);
+        match *self {
+            SpotFalloff::Smooth => delta * delta * (3.0 - 2.0 * delta),
+            SpotFalloff::Linear => delta,
+            SpotFalloff::Cos4 => (delta * delta) * (delta * delta),
+        }
+    }
+
+    /// Average of `apply()` over [0, 1].
+    fn average(&self) -> f32 {
+        match *self {
+            SpotFalloff::Smooth | SpotFalloff::Linear => 0.5,
+            SpotFalloff::Cos4 => 0.2,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SpotLight {
+    id: u32,
+    pos: Point3f,
+    /// Axis of the cone of light
+    dir: Vector3f,
+    intensity: Spectrum,
+    cos_total_width: f32,
+    cos_falloff_start: f32,
+    falloff: SpotFalloff,
+}
+
+impl SpotLight {
+    /// Create a spotlight at `pos` pointing towards `dir`, lighting a cone of half-angle
+    /// `total_width` degrees. Its intensity starts falling off `falloff_start` degrees away from
+    /// its axis.
+    pub fn new(pos: Point3f,
+               dir: Vector3f,
+               intensity: Spectrum,
+               total_width: f32,
+               falloff_start: f32,
+               falloff: SpotFalloff)
+               -> SpotLight {
+        SpotLight {
+            id: super::get_next_id(),
+            pos,
+            dir: dir.normalize(),
+            intensity,
+            cos_total_width: total_width.to_radians().cos(),
+            cos_falloff_start: falloff_start.to_radians().cos(),
+            falloff,
+        }
+    }
+
+    pub fn create(l2w: &Transform, params: &mut ParamSet) -> Arc<Light> {
+        let I = params.find_one_spectrum("I", Spectrum::white());
+        let scale = params.find_one_spectrum("scale", Spectrum::white());
+        let cone_angle = params.find_one_float("coneangle", 30.0);
+        let cone_delta = params.find_one_float("conedeltaangle", 5.0);
+        let from = params.find_one_point3f("from", Point3f::zero());
+        let to = params.find_one_point3f("to", Point3f::new(0.0, 0.0, 1.0));
+        let falloff = match params.find_one_string("falloff", "smooth".to_owned()).as_str() {
+            "smooth" => SpotFalloff::Smooth,
+            "linear" => SpotFalloff::Linear,
+            "cos4" => SpotFalloff::Cos4,
+            f => {
+                warn!("Unknown spotlight falloff \"{}\". Using \"smooth\".", f);
+                SpotFalloff::Smooth
+            }
+        };
+
+        Arc::new(SpotLight::new(l2w * &from,
+                                l2w * &(to - from),
+                                I * scale,
+                                cone_angle,
+                                cone_angle - cone_delta,
+                                falloff))
+    }
+
+    /// Fraction of the intensity emitted in direction `w`.
+    fn falloff(&self, w: &Vector3f) -> f32 {
+        let cos_theta = w.normalize().dot(&self.dir);
+        if cos_theta < self.cos_total_width {
+            0.0
+        } else if cos_theta >= self.cos_falloff_start {
+            1.0
+        } else {
+            let delta = (cos_theta - self.cos_total_width) /
+                        (self.cos_falloff_start - self.cos_total_width);
+            self.falloff.apply(delta)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn name(&self) -> &'static str {
+        "SpotLight"
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn sample_li(&self,
+                 isect: &Interaction,
+                 _u: &Point2f)
+                 -> (Spectrum, Vector3f, f32, VisibilityTester) {
+        let wi = (self.pos - isect.p).normalize();
+        let l_i = self.intensity * self.falloff(&(-wi)) / (self.pos - isect.p).length_squared();
+        let vt = VisibilityTester::new(*isect, Interaction::from_point(&self.pos));
+
+        (l_i, wi, 1.0, vt)
+    }
+
+    fn pdf_li(&self, _si: &Interaction, _wi: &Vector3f) -> f32 {
+        0.0
+    }
+
+    fn n_samples(&self) -> u32 {
+        1
+    }
+
+    fn flags(&self) -> LightFlags {
+        LightFlags::DELTA_POSITION
+    }
+
+    fn power(&self) -> Spectrum {
+        let band = self.cos_falloff_start - self.cos_total_width;
+        self.intensity * 2.0 * PI *
+        ((1.0 - self.cos_falloff_start) + band * self.falloff.average())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Intensity emitted by a spotlight pointing down the z axis, at `theta` degrees from it.
+    fn intensity_at(light: &SpotLight, theta: f32) -> f32 {
+        let theta = theta.to_radians();
+        let p = Point3f::new(theta.sin(), 0.0, theta.cos());
+        let (l, _, _, _) = light.sample_li(&Interaction::from_point(&p), &Point2f::new(0.5, 0.5));
+        l.y()
+    }
+
+    fn spot(falloff: SpotFalloff) -> SpotLight {
+        SpotLight::new(Point3f::zero(),
+                       Vector3f::new(0.0, 0.0, 1.0),
+                       Spectrum::white(),
+                       30.0,
+                       20.0,
+                       falloff)
+    }
+
+    #[test]
+    fn test_linear_falloff_is_a_ramp() {
+        let light = spot(SpotFalloff::Linear);
+        assert!((intensity_at(&light, 10.0) - 1.0).abs() < 1e-5);
+        assert_eq!(intensity_at(&light, 35.0), 0.0);
+
+        // Linear in the cosine of the angle across the transition band
+        let (cos_start, cos_end) = (20f32.to_radians().cos(), 30f32.to_radians().cos());
+        for i in 1..10 {
+            let cos_theta = cos_end + (cos_start - cos_end) * i as f32 / 10.0;
+            let i_theta = intensity_at(&light, cos_theta.acos().to_degrees());
+            assert!((i_theta - i as f32 / 10.0).abs() < 1e-3,
+                    "{} at step {}",
+                    i_theta,
+                    i);
+        }
+    }
+
+    #[test]
+    fn test_smooth_falloff_is_flat_at_both_ends() {
+        let h = 1e-3;
+        for falloff in &[SpotFalloff::Smooth, SpotFalloff::Linear] {
+            let start_slope = (falloff.apply(h) - falloff.apply(0.0)) / h;
+            let end_slope = (falloff.apply(1.0) - falloff.apply(1.0 - h)) / h;
+            if *falloff == SpotFalloff::Smooth {
+                assert!(start_slope.abs() < 1e-2 && end_slope.abs() < 1e-2);
+            } else {
+                assert!((start_slope - 1.0).abs() < 1e-2 && (end_slope - 1.0).abs() < 1e-2);
+            }
+        }
+
+        // Halfway through the band, both curves agree
+        let light = spot(SpotFalloff::Smooth);
+        let cos_mid = 0.5 * (20f32.to_radians().cos() + 30f32.to_radians().cos());
+        assert!((intensity_at(&light, cos_mid.acos().to_degrees()) - 0.5).abs() < 1e-3);
+    }
+}
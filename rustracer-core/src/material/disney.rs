@@ -40,7 +40,7 @@ pub struct DisneyMaterial {
 
 impl DisneyMaterial {
     pub fn create(mp: &mut TextureParams) -> Arc<Material> {
-        let color = mp.get_spectrum_texture("color", &Spectrum::from(0.5));
+        let color = mp.get_colour_texture("color", &Spectrum::from(0.5));
         let metallic = mp.get_float_texture("metallic", 0.0);
         let eta = mp.get_float_texture("eta", 1.5);
         let roughness = mp.get_float_texture("roughness", 0.5);
@@ -91,7 +91,7 @@ impl Material for DisneyMaterial {
         let mut bxdfs = BxDFHolder::new(arena);
 
         // Diffuse
-        let c = self.color.evaluate(si).clamp();
+        let c = self.color.evaluate(si).clamp(0.0, f32::INFINITY);
         let metallic_weight = self.metallic.evaluate(si);
         let e = self.eta.evaluate(si);
         let strans = self.spec_trans.evaluate(si);
@@ -156,7 +156,7 @@ impl Material for DisneyMaterial {
                           schlick_r0_from_eta(e) * lerp(spec_tint, Spectrum::white(), c_tint),
                           c);
         let fresnel = arena <- DisneyFresnel::new(cspec0, metallic_weight, e);
-        bxdfs.add(arena <- MicrofacetReflection::new(c, distrib, fresnel));
+        bxdfs.add(arena <- MicrofacetReflection::new(Spectrum::white(), distrib, fresnel));
 
         // Clearcoat
         let cc = self.clearcoat.evaluate(si);
@@ -363,7 +363,7 @@ impl BxDF for DisneyClearCoat {
             return (Spectrum::black(), zero(), 0.0, self.get_type());
         }
 
-        let alpha = 0.25;
+        let alpha = lerp(self.gloss, 0.1, 0.001);
         let alpha2 = alpha * alpha;
         let cos_theta = f32::sqrt(f32::max(0.0,
                                            (1.0 - f32::powf(alpha2, 1.0 - u[0])) / (1.0 - alpha2)));
@@ -400,8 +400,7 @@ impl BxDF for DisneyClearCoat {
         // distribution for wh converted to a mesure with respect to the
         // surface normal.
         let Dr = GTR1(abs_cos_theta(&wh), lerp(self.gloss, 0.1, 0.001));
-        Dr / (4.0 * wo.dot(&wh))
-
+        Dr * abs_cos_theta(&wh) / (4.0 * wo.dot(&wh))
     }
 
     fn get_type(&self) -> BxDFType {
@@ -503,7 +502,7 @@ fn GTR1(cos_theta: f32, alpha: f32) -> f32 {
     let alpha2 = alpha * alpha;
 
     (alpha2 - 1.0) /
-    (f32::consts::PI * f32::log10(alpha2) * (1.0 + (alpha2 - 1.0) * cos_theta * cos_theta))
+    (f32::consts::PI * f32::ln(alpha2) * (1.0 + (alpha2 - 1.0) * cos_theta * cos_theta))
 }
 
 #[inline]
@@ -518,3 +517,113 @@ fn smithG_GGX(cos_theta: f32, alpha: f32) -> f32 {
 fn sqr(x: f32) -> f32 {
     x * x
 }
+
+#[cfg(test)]
+mod tests {
+    use light_arena::MemoryArena;
+
+    use super::*;
+    use test_utils::{create_material, float_param, hit_disk};
+
+    #[test]
+    fn test_gtr1_is_normalized() {
+        // D(wh) * cos(theta_h) integrates to 1 over the hemisphere. With x = cos^2(theta_h), the
+        // integral becomes pi * int_0^1 D(sqrt(x)) dx.
+        for alpha in &[0.1, 0.25, 0.5] {
+            let n = 100_000;
+            let integral = (0..n)
+                .map(|i| {
+                         let x = (i as f32 + 0.5) / n as f32;
+                         f32::consts::PI * GTR1(x.sqrt(), *alpha) / n as f32
+                     })
+                .sum::<f32>();
+            assert!((integral - 1.0).abs() < 1e-3, "alpha={}: {}", alpha, integral);
+        }
+    }
+
+    #[test]
+    fn test_clearcoat_sampling_matches_pdf() {
+        for gloss in &[0.0, 0.5, 1.0] {
+            let clearcoat = DisneyClearCoat::new(1.0, *gloss);
+            let wo = Vector3f::new(0.48, 0.6, 0.64);
+            for i in 0..16 {
+                for j in 0..16 {
+                    let u = Point2f::new((i as f32 + 0.5) / 16.0, (j as f32 + 0.5) / 16.0);
+                    let (f, wi, pdf, _) = clearcoat.sample_f(&wo, &u);
+                    if pdf == 0.0 {
+                        continue;
+                    }
+                    assert!(!f.has_nan());
+                    assert!((pdf - clearcoat.pdf(&wo, &wi)).abs() <= 1e-4 * pdf);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_extra_lobes_are_optional() {
+        ::init_stats();
+        let base = create_material(vec![], DisneyMaterial::create);
+        let full = create_material(vec![float_param("sheen", 0.5),
+                                        float_param("clearcoat", 1.0),
+                                        float_param("spectrans", 0.5)],
+                                   DisneyMaterial::create);
+
+        let mut arena = MemoryArena::new(1);
+        let alloc = arena.allocator();
+        let mut si_base = hit_disk();
+        let mut si_full = si_base.clone();
+        base.compute_scattering_functions(&mut si_base, TransportMode::RADIANCE, true, &alloc);
+        full.compute_scattering_functions(&mut si_full, TransportMode::RADIANCE, true, &alloc);
+        let bsdf_base = si_base.bsdf.unwrap();
+        let bsdf_full = si_full.bsdf.unwrap();
+
+        // Diffuse, retro-reflection and specular
+        assert_eq!(bsdf_base.num_components(BxDFType::all()), 3);
+        assert_eq!(bsdf_base.num_components(BxDFType::BSDF_REFLECTION | BxDFType::BSDF_DIFFUSE),
+                   2);
+        assert_eq!(bsdf_base.num_components(BxDFType::BSDF_REFLECTION | BxDFType::BSDF_GLOSSY),
+                   1);
+        // ... plus sheen, clearcoat and transmission
+        assert_eq!(bsdf_full.num_components(BxDFType::all()), 6);
+        assert_eq!(bsdf_full.num_components(BxDFType::BSDF_TRANSMISSION |
+                                            BxDFType::BSDF_GLOSSY),
+                   1);
+
+        // Without the extra lobes, the response is just the base diffuse + specular one, built by
+        // hand here from the default parameters (grey, dielectric, eta = 1.5, roughness = 0.5).
+        let c = Spectrum::from(0.5);
+        let diffuse = DisneyDiffuse::new(c);
+        let retro = DisneyRetro::new(c, 0.5);
+        let distrib = DisneyMicrofacetDistribution::new(sqr(0.5), sqr(0.5));
+        let fresnel = DisneyFresnel::new(Spectrum::from(schlick_r0_from_eta(1.5)), 0.0, 1.5);
+        let specular = MicrofacetReflection::new(Spectrum::white(), &distrib, &fresnel);
+        let lobes: [&BxDF; 3] = [&diffuse, &retro, &specular];
+        let si_ref = hit_disk();
+        let bsdf_ref = BSDF::new(&si_ref, 1.0, &lobes);
+
+        for &(wo, wi) in &[(Vector3f::new(0.0, 0.0, 1.0), Vector3f::new(0.0, 0.0, 1.0)),
+                           (Vector3f::new(0.0, 0.0, 1.0), Vector3f::new(0.6, 0.0, 0.8)),
+                           (Vector3f::new(0.48, 0.6, 0.64), Vector3f::new(-0.48, -0.6, 0.64)),
+                           (Vector3f::new(0.8, 0.0, 0.6), Vector3f::new(0.0, -0.28, 0.96))] {
+            let f_base = bsdf_base.f(&wo, &wi, BxDFType::all());
+            let f_ref = bsdf_ref.f(&wo, &wi, BxDFType::all());
+            for i in 0..3 {
+                assert!((f_base[i] - f_ref[i]).abs() < 1e-6,
+                        "wo={:?}, wi={:?}: {:?} != {:?}",
+                        wo,
+                        wi,
+                        f_base,
+                        f_ref);
+            }
+            let pdf_base = bsdf_base.pdf(&wo, &wi, BxDFType::all());
+            let pdf_ref = bsdf_ref.pdf(&wo, &wi, BxDFType::all());
+            assert!((pdf_base - pdf_ref).abs() < 1e-6,
+                    "wo={:?}, wi={:?}: {} != {}",
+                    wo,
+                    wi,
+                    pdf_base,
+                    pdf_ref);
+        }
+    }
+}
@@ -20,15 +20,20 @@ pub struct GlassMaterial {
     index: Arc<TextureFloat>,
     bump_map: Option<Arc<TextureFloat>>,
     remap_roughness: bool,
+    /// True if the roughness is zero everywhere
+    is_specular: bool,
 }
 
 impl GlassMaterial {
     pub fn create(mp: &mut TextureParams) -> Arc<Material> {
         info!("Creating Glass material");
-        let Kr = mp.get_spectrum_texture("Kr", &Spectrum::white());
-        let Kt = mp.get_spectrum_texture("Kt", &Spectrum::white());
+        let Kr = mp.get_colour_texture("Kr", &Spectrum::white());
+        let Kt = mp.get_colour_texture("Kt", &Spectrum::white());
         let eta = mp.get_float_texture_or_none("eta")
             .unwrap_or_else(|| mp.get_float_texture("index", 1.5));
+        let is_specular = ["uroughness", "vroughness"]
+            .iter()
+            .all(|n| !mp.has_texture(n) && mp.find_float(n, 0.0) == 0.0);
         let rough_u = mp.get_float_texture("uroughness", 0.0);
         let rough_v = mp.get_float_texture("vroughness", 0.0);
         let bump_map = mp.get_float_texture_or_none("bumpmap");
@@ -42,6 +47,7 @@ impl GlassMaterial {
                      index: eta,
                      bump_map,
                      remap_roughness,
+                     is_specular,
                  })
     }
 }
@@ -98,4 +104,8 @@ impl Material for GlassMaterial {
         let bsdf = BSDF::new(si, eta, bxdfs.into_slice());
         si.bsdf = Some(Arc::new(bsdf));
     }
+
+    fn is_specular_transmitter(&self) -> bool {
+        self.is_specular
+    }
 }
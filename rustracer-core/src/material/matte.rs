@@ -1,3 +1,4 @@
+use std::f32;
 use std::sync::Arc;
 
 use light_arena::Allocator;
@@ -8,7 +9,7 @@ use interaction::SurfaceInteraction;
 use material::{Material, TransportMode};
 use paramset::TextureParams;
 use spectrum::Spectrum;
-use texture::{TextureSpectrum, TextureFloat};
+use texture::{ConstantTexture, TextureSpectrum, TextureFloat};
 
 #[derive(Debug)]
 pub struct MatteMaterial {
@@ -20,7 +21,7 @@ pub struct MatteMaterial {
 impl MatteMaterial {
     pub fn create(mp: &mut TextureParams) -> Arc<Material> {
         info!("Creating Matte material");
-        let kd = mp.get_spectrum_texture("Kd", &Spectrum::grey(0.5));
+        let kd = mp.get_colour_texture("Kd", &Spectrum::grey(0.5));
         let sigma = mp.get_float_texture("sigma", 0.0);
         let bump_map = mp.get_float_texture_or_none("bumpmap");
 
@@ -30,6 +31,16 @@ impl MatteMaterial {
                      bump_map,
                  })
     }
+
+    /// A material that doesn't reflect any light, to hide surfaces while still letting them cast
+    /// shadows.
+    pub fn black() -> Arc<Material> {
+        Arc::new(MatteMaterial {
+                     kd: Arc::new(ConstantTexture::new(Spectrum::black())),
+                     sigma: Arc::new(ConstantTexture::new(0.0)),
+                     bump_map: None,
+                 })
+    }
 }
 
 impl Material for MatteMaterial {
@@ -44,8 +55,9 @@ impl Material for MatteMaterial {
             super::bump(bump_map, si);
         }
 
-        let r = self.kd.evaluate(si).clamp();
-        let sigma = clamp(self.sigma.evaluate(si), 0.0, 1.0);
+        let r = self.kd.evaluate(si).clamp(0.0, f32::INFINITY);
+        // Standard deviation of the microfacets' angle, in degrees
+        let sigma = clamp(self.sigma.evaluate(si), 0.0, 90.0);
         if sigma == 0.0 {
             bxdfs.add(arena <- LambertianReflection::new(r));
         } else {
@@ -56,3 +68,40 @@ impl Material for MatteMaterial {
         si.bsdf = Some(Arc::new(bsdf));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use light_arena::MemoryArena;
+
+    use super::*;
+    use Vector3f;
+    use bsdf::BxDFType;
+    use test_utils::{create_material, float_param, hit_disk};
+
+    /// Ratio between the light reflected back towards a light at 70 degrees from the normal, and
+    /// towards a light along the normal.
+    fn grazing_backscatter(sigma: f32) -> f32 {
+        ::init_stats();
+        let material = create_material(vec![float_param("sigma", sigma)], MatteMaterial::create);
+        let mut arena = MemoryArena::new(1);
+        let alloc = arena.allocator();
+        let mut si = hit_disk();
+        material.compute_scattering_functions(&mut si, TransportMode::RADIANCE, true, &alloc);
+        let bsdf = si.bsdf.unwrap();
+
+        let normal = Vector3f::new(0.0, 0.0, 1.0);
+        let theta = 70f32.to_radians();
+        let grazing = Vector3f::new(theta.sin(), 0.0, theta.cos());
+        bsdf.f(&grazing, &grazing, BxDFType::all()).y() /
+        bsdf.f(&normal, &normal, BxDFType::all()).y()
+    }
+
+    #[test]
+    fn test_sigma_selects_oren_nayar() {
+        // Lambertian surfaces reflect the same radiance in all directions...
+        assert!((grazing_backscatter(0.0) - 1.0).abs() < 1e-4);
+        // ... rough ones reflect more light back towards grazing lights, so they look flatter
+        assert!(grazing_backscatter(20.0) > 1.2);
+        assert!(grazing_backscatter(40.0) > grazing_backscatter(20.0));
+    }
+}
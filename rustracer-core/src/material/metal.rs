@@ -4,7 +4,7 @@ use light_arena::Allocator;
 
 use bsdf::{BxDFHolder, Fresnel, MicrofacetReflection, TrowbridgeReitzDistribution, BSDF};
 use interaction::SurfaceInteraction;
-use material::{self, Material, TransportMode};
+use material::{self, FresnelModel, Material, TransportMode};
 use paramset::TextureParams;
 use spectrum::Spectrum;
 use texture::{TextureSpectrum, TextureFloat};
@@ -18,6 +18,10 @@ pub struct Metal {
     urough: Option<Arc<TextureFloat>>,
     vrough: Option<Arc<TextureFloat>>,
     remap_roughness: bool,
+    fresnel: FresnelModel,
+    /// Reflectance at normal incidence for the Schlick Fresnel. Computed from `eta` and `k` if
+    /// not given.
+    r0: Option<Arc<TextureSpectrum>>,
 }
 
 impl Metal {
@@ -33,6 +37,8 @@ impl Metal {
         let vrough = mp.get_float_texture_or_none("vroughness");
         let bump = mp.get_float_texture_or_none("bumpmap");
         let remap_roughness = mp.find_bool("remaproughness", true);
+        let fresnel = FresnelModel::create(mp, &[FresnelModel::Conductor, FresnelModel::Schlick]);
+        let r0 = mp.get_spectrum_texture_or_none("r0");
 
         Arc::new(Metal {
                      eta: eta,
@@ -42,6 +48,8 @@ impl Metal {
                      urough,
                      vrough,
                      remap_roughness,
+                     fresnel,
+                     r0,
                  })
     }
 }
@@ -68,9 +76,18 @@ impl Material for Metal {
             urough = TrowbridgeReitzDistribution::roughness_to_alpha(urough);
             vrough = TrowbridgeReitzDistribution::roughness_to_alpha(vrough);
         }
-        let fresnel = arena <- Fresnel::conductor(Spectrum::white(),
-                                                  self.eta.evaluate(si),
-                                                  self.k.evaluate(si));
+        let conductor = Fresnel::conductor(Spectrum::white(),
+                                           self.eta.evaluate(si),
+                                           self.k.evaluate(si));
+        let fresnel: &Fresnel = if self.fresnel == FresnelModel::Schlick {
+            let r0 = match self.r0 {
+                Some(ref r0) => r0.evaluate(si),
+                None => conductor.evaluate(1.0),
+            };
+            arena <- Fresnel::schlick(r0)
+        } else {
+            arena <- conductor
+        };
         let distrib = arena <- TrowbridgeReitzDistribution::new(urough, vrough);
         bxdfs.add(arena <- MicrofacetReflection::new(Spectrum::white(), distrib, fresnel));
 
@@ -1,3 +1,4 @@
+use std::f32;
 use std::sync::Arc;
 
 use light_arena::Allocator;
@@ -18,7 +19,7 @@ pub struct MirrorMaterial {
 impl MirrorMaterial {
     pub fn create(mp: &mut TextureParams) -> Arc<Material> {
         info!("Creating Mirror material");
-        let Kr = mp.get_spectrum_texture("Kr", &Spectrum::grey(0.9));
+        let Kr = mp.get_colour_texture("Kr", &Spectrum::grey(0.9));
         let bump_map = mp.get_float_texture_or_none("bumpmap");
 
         Arc::new(MirrorMaterial { kr: Kr, bump_map })
@@ -35,7 +36,7 @@ impl Material for MirrorMaterial {
             super::bump(bump, si);
         }
         let mut bxdfs = BxDFHolder::new(arena);
-        let R = self.kr.evaluate(si).clamp();
+        let R = self.kr.evaluate(si).clamp(0.0, f32::INFINITY);
         if !R.is_black() {
             let fresnel = arena <- Fresnel::no_op();
             bxdfs.add(arena <- SpecularReflection::new(R, fresnel));
@@ -5,11 +5,13 @@ use light_arena::Allocator;
 
 use {Normal3f, Vector2f, Vector3f};
 use interaction::SurfaceInteraction;
+use paramset::TextureParams;
 use texture::Texture;
 
 mod disney;
 mod matte;
 mod metal;
+mod pbr;
 mod plastic;
 mod glass;
 mod mirror;
@@ -20,6 +22,7 @@ mod uber;
 pub use self::disney::DisneyMaterial;
 pub use self::matte::MatteMaterial;
 pub use self::metal::Metal;
+pub use self::pbr::PbrMetallicRoughness;
 pub use self::plastic::Plastic;
 pub use self::glass::GlassMaterial;
 pub use self::mirror::MirrorMaterial;
@@ -34,12 +37,56 @@ pub enum TransportMode {
     IMPORTANCE,
 }
 
+/// Fresnel reflectance of the specular lobe of microfacet materials, selected with their
+/// "fresnel" string parameter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FresnelModel {
+    /// Exact reflectance of a dielectric interface
+    Dielectric,
+    /// Exact reflectance of a conductor
+    Conductor,
+    /// Schlick's approximation, from the reflectance at normal incidence
+    Schlick,
+}
+
+impl FresnelModel {
+    /// Read the "fresnel" parameter. `supported` lists the models the material can use; the
+    /// first one is the default.
+    pub fn create(mp: &mut TextureParams, supported: &[FresnelModel]) -> FresnelModel {
+        let name = mp.find_string("fresnel", "");
+        let model = match name.as_ref() {
+            "" => return supported[0],
+            "dielectric" => Some(FresnelModel::Dielectric),
+            "conductor" => Some(FresnelModel::Conductor),
+            "schlick" => Some(FresnelModel::Schlick),
+            _ => None,
+        };
+        match model {
+            Some(model) if supported.contains(&model) => model,
+            _ => {
+                warn!("Fresnel model \"{}\" unknown or not supported by this material. Using \
+                       {:?}.",
+                      name,
+                      supported[0]);
+                supported[0]
+            }
+        }
+    }
+}
+
 pub trait Material: Debug + Send + Sync {
     fn compute_scattering_functions<'a, 'b>(&self,
                                             isect: &mut SurfaceInteraction<'a, 'b>,
                                             mode: TransportMode,
                                             allow_multiple_lobes: bool,
                                             arena: &'b Allocator);
+
+    /// Return true if the material only scatters light in specular directions and lets light
+    /// through, like smooth glass. Such surfaces can be ignored by shadow rays when the
+    /// accelerator's "ignorespecularshadow" option is set.
+    fn is_specular_transmitter(&self) -> bool {
+        false
+    }
 }
 
 
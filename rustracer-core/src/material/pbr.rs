@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use light_arena::Allocator;
+
+use {clamp, lerp};
+use bsdf::{BxDFHolder, Fresnel, LambertianReflection, MicrofacetReflection,
+           TrowbridgeReitzDistribution, BSDF};
+use spectrum::Spectrum;
+use interaction::SurfaceInteraction;
+use material::{Material, TransportMode};
+use paramset::TextureParams;
+use texture::{TextureSpectrum, TextureFloat};
+
+/// Reflectance at normal incidence of the dielectric end of the metallic range (eta = 1.5).
+const DIELECTRIC_R0: f32 = 0.04;
+
+/// Material following the "metallic-roughness" workflow of glTF and most real-time engines.
+///
+/// `metallic` interpolates between a plastic-like material, with a diffuse lobe of the base color
+/// under a colorless dielectric coating, and a conductor whose specular reflection is tinted by
+/// the base color. An optional `occlusion` texture, such as a baked ambient occlusion map, darkens
+/// the diffuse lobe.
+#[derive(Debug)]
+pub struct PbrMetallicRoughness {
+    base_color: Arc<TextureSpectrum>,
+    metallic: Arc<TextureFloat>,
+    roughness: Arc<TextureFloat>,
+    occlusion: Arc<TextureFloat>,
+    bump_map: Option<Arc<TextureFloat>>,
+    remap_roughness: bool,
+}
+
+impl PbrMetallicRoughness {
+    pub fn create(mp: &mut TextureParams) -> Arc<Material> {
+        info!("Creating PbrMetallicRoughness material");
+        let base_color = mp.get_colour_texture("basecolor", &Spectrum::grey(0.5));
+        let metallic = mp.get_float_texture("metallic", 0.0);
+        let roughness = mp.get_float_texture("roughness", 0.5);
+        let occlusion = mp.get_float_texture("occlusion", 1.0);
+        let bump_map = mp.get_float_texture_or_none("bumpmap");
+        let remap_roughness = mp.find_bool("remaproughness", true);
+
+        Arc::new(PbrMetallicRoughness {
+                     base_color,
+                     metallic,
+                     roughness,
+                     occlusion,
+                     bump_map,
+                     remap_roughness,
+                 })
+    }
+}
+
+impl Material for PbrMetallicRoughness {
+    fn compute_scattering_functions<'a, 'b>(&self,
+                                            si: &mut SurfaceInteraction<'a, 'b>,
+                                            _mode: TransportMode,
+                                            _allow_multiple_lobes: bool,
+                                            arena: &'b Allocator) {
+        if let Some(ref bump) = self.bump_map {
+            super::bump(bump, si);
+        }
+        let base_color = self.base_color.evaluate(si).clamp(0.0, 1.0);
+        let metallic = clamp(self.metallic.evaluate(si), 0.0, 1.0);
+
+        let mut bxdfs = BxDFHolder::new(arena);
+        let occlusion = clamp(self.occlusion.evaluate(si), 0.0, 1.0);
+        // Metals have no diffuse component
+        let kd = base_color * (1.0 - metallic) * occlusion;
+        if !kd.is_black() {
+            bxdfs.add(arena <- LambertianReflection::new(kd));
+        }
+        let r0 = lerp(metallic, Spectrum::grey(DIELECTRIC_R0), base_color);
+        let fresnel = arena <- Fresnel::schlick(r0);
+        let mut roughness = self.roughness.evaluate(si);
+        if self.remap_roughness {
+            roughness = TrowbridgeReitzDistribution::roughness_to_alpha(roughness);
+        }
+        let distrib = arena <- TrowbridgeReitzDistribution::new(roughness, roughness);
+        bxdfs.add(arena <- MicrofacetReflection::new(Spectrum::white(), distrib, fresnel));
+
+        let bsdf: BSDF<'b> = BSDF::new(si, 1.0, bxdfs.into_slice());
+        si.bsdf = Some(Arc::new(bsdf));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use light_arena::MemoryArena;
+
+    use super::*;
+    use Vector3f;
+    use api::ParamListEntry;
+    use bsdf::BxDFType;
+    use test_utils::{create_material, float_param, hit_disk, rgb_param};
+
+    /// Diffuse and glossy reflection of a red material, lit and seen from near the normal.
+    fn red_response(metallic: f32) -> (Spectrum, Spectrum) {
+        red_response_with(vec![float_param("metallic", metallic)])
+    }
+
+    fn red_response_with(mut params: Vec<ParamListEntry>) -> (Spectrum, Spectrum) {
+        ::init_stats();
+        params.push(rgb_param("basecolor", [0.8, 0.1, 0.1]));
+        params.push(float_param("roughness", 0.3));
+        let material = create_material(params, PbrMetallicRoughness::create);
+        let mut arena = MemoryArena::new(1);
+        let alloc = arena.allocator();
+        let mut si = hit_disk();
+        material.compute_scattering_functions(&mut si, TransportMode::RADIANCE, true, &alloc);
+        let bsdf = si.bsdf.unwrap();
+
+        let wo = Vector3f::new(0.1, 0.0, 1.0).normalize();
+        let wi = Vector3f::new(-0.1, 0.0, 1.0).normalize();
+        (bsdf.f(&wo, &wi, BxDFType::BSDF_REFLECTION | BxDFType::BSDF_DIFFUSE),
+         bsdf.f(&wo, &wi, BxDFType::BSDF_REFLECTION | BxDFType::BSDF_GLOSSY))
+    }
+
+    #[test]
+    fn test_metallic_zero_is_plastic_like() {
+        let (diffuse, glossy) = red_response(0.0);
+        // Red diffuse base under a colorless coating
+        assert!(diffuse[0] > 5.0 * diffuse[1]);
+        assert!(glossy[0] > 0.0);
+        assert!((glossy[0] - glossy[1]).abs() < 1e-4 * glossy[0]);
+        assert!((glossy[1] - glossy[2]).abs() < 1e-4 * glossy[0]);
+    }
+
+    #[test]
+    fn test_metallic_one_is_conductor_like() {
+        let (diffuse, glossy) = red_response(1.0);
+        assert!(diffuse.is_black());
+        // Specular reflection tinted by the base color, and much stronger than a dielectric's
+        assert!(glossy[0] > 5.0 * glossy[1]);
+        let (_, dielectric_glossy) = red_response(0.0);
+        assert!(glossy[0] > 10.0 * dielectric_glossy[0]);
+    }
+
+    #[test]
+    fn test_occlusion_scales_diffuse_albedo() {
+        let (diffuse, glossy) = red_response(0.0);
+        let (occluded_diffuse, occluded_glossy) =
+            red_response_with(vec![float_param("occlusion", 0.5)]);
+        for i in 0..3 {
+            assert!((occluded_diffuse[i] - 0.5 * diffuse[i]).abs() < 1e-6);
+        }
+        // The specular coating is left alone
+        assert_eq!(occluded_glossy, glossy);
+    }
+}
@@ -6,7 +6,7 @@ use bsdf::{BxDFHolder, Fresnel, LambertianReflection, MicrofacetReflection,
            TrowbridgeReitzDistribution, BSDF};
 use spectrum::Spectrum;
 use interaction::SurfaceInteraction;
-use material::{Material, TransportMode};
+use material::{FresnelModel, Material, TransportMode};
 use paramset::TextureParams;
 use texture::{TextureSpectrum, TextureFloat};
 
@@ -18,16 +18,23 @@ pub struct Plastic {
     roughness: Arc<TextureFloat>,
     bump_map: Option<Arc<TextureFloat>>,
     remap_roughness: bool,
+    fresnel: FresnelModel,
+    /// Reflectance at normal incidence for the Schlick Fresnel
+    r0: Arc<TextureSpectrum>,
 }
 
 impl Plastic {
     pub fn create(mp: &mut TextureParams) -> Arc<Material> {
         info!("Creating Plastic material");
-        let Kd = mp.get_spectrum_texture("Kd", &Spectrum::grey(0.25));
-        let Ks = mp.get_spectrum_texture("Ks", &Spectrum::grey(0.25));
+        let Kd = mp.get_colour_texture("Kd", &Spectrum::grey(0.25));
+        let Ks = mp.get_colour_texture("Ks", &Spectrum::grey(0.25));
         let roughness = mp.get_float_texture("roughness", 0.1);
         let bump_map = mp.get_float_texture_or_none("bumpmap");
         let remap_roughness = mp.find_bool("remaproughness", true);
+        let fresnel = FresnelModel::create(mp,
+                                           &[FresnelModel::Dielectric, FresnelModel::Schlick]);
+        // Same reflectance at normal incidence as the dielectric Fresnel
+        let r0 = mp.get_spectrum_texture("r0", &Fresnel::dielectric(1.5, 1.0).evaluate(1.0));
 
         Arc::new(Plastic {
                      kd: Kd,
@@ -35,6 +42,8 @@ impl Plastic {
                      roughness,
                      bump_map,
                      remap_roughness,
+                     fresnel,
+                     r0,
                  })
     }
 }
@@ -56,7 +65,11 @@ impl Material for Plastic {
             bxdfs.add(arena <- LambertianReflection::new(kd));
         }
         if !ks.is_black() {
-            let fresnel = arena <- Fresnel::dielectric(1.5, 1.0);
+            let fresnel: &Fresnel = if self.fresnel == FresnelModel::Schlick {
+                arena <- Fresnel::schlick(self.r0.evaluate(si))
+            } else {
+                arena <- Fresnel::dielectric(1.5, 1.0)
+            };
             let mut roughness = self.roughness.evaluate(si);
             if self.remap_roughness {
                 roughness = TrowbridgeReitzDistribution::roughness_to_alpha(roughness);
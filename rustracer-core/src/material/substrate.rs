@@ -1,3 +1,4 @@
+use std::f32;
 use std::sync::Arc;
 
 use light_arena::Allocator;
@@ -21,8 +22,8 @@ pub struct SubstrateMaterial {
 
 impl SubstrateMaterial {
     pub fn create(mp: &mut TextureParams) -> Arc<Material> {
-        let kd = mp.get_spectrum_texture("Kd", &Spectrum::grey(0.5));
-        let ks = mp.get_spectrum_texture("Ks", &Spectrum::grey(0.5));
+        let kd = mp.get_colour_texture("Kd", &Spectrum::grey(0.5));
+        let ks = mp.get_colour_texture("Ks", &Spectrum::grey(0.5));
         let urough = mp.get_float_texture("uroughness", 0.1);
         let vrough = mp.get_float_texture("vroughness", 0.1);
         let bump_map = mp.get_float_texture_or_none("bumpmap");
@@ -50,8 +51,8 @@ impl Material for SubstrateMaterial {
         }
         let mut bxdfs = BxDFHolder::new(arena);
 
-        let d = self.kd.evaluate(si).clamp();
-        let s = self.ks.evaluate(si).clamp();
+        let d = self.kd.evaluate(si).clamp(0.0, f32::INFINITY);
+        let s = self.ks.evaluate(si).clamp(0.0, f32::INFINITY);
         let mut roughu = self.nu.evaluate(si);
         let mut roughv = self.nv.evaluate(si);
 
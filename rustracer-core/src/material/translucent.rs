@@ -1,3 +1,4 @@
+use std::f32;
 use std::sync::Arc;
 
 use light_arena::Allocator;
@@ -23,10 +24,10 @@ pub struct TranslucentMaterial {
 
 impl TranslucentMaterial {
     pub fn create(mp: &mut TextureParams) -> Arc<Material> {
-        let kd = mp.get_spectrum_texture("Kd", &Spectrum::from(0.25));
-        let ks = mp.get_spectrum_texture("Ks", &Spectrum::from(0.25));
-        let reflect = mp.get_spectrum_texture("reflect", &Spectrum::from(0.5));
-        let transmit = mp.get_spectrum_texture("transmit", &Spectrum::from(0.5));
+        let kd = mp.get_colour_texture("Kd", &Spectrum::from(0.25));
+        let ks = mp.get_colour_texture("Ks", &Spectrum::from(0.25));
+        let reflect = mp.get_colour_texture("reflect", &Spectrum::from(0.5));
+        let transmit = mp.get_colour_texture("transmit", &Spectrum::from(0.5));
         let roughness = mp.get_float_texture("roughness", 0.1);
         let bumpmap = mp.get_float_texture_or_none("bumpmap");
         let remap_roughness = mp.find_bool("remaproughness", true);
@@ -56,11 +57,11 @@ impl Material for TranslucentMaterial {
             super::bump(bump_map, si);
         }
 
-        let r = self.reflect.evaluate(si).clamp();
-        let t = self.transmit.evaluate(si).clamp();
+        let r = self.reflect.evaluate(si).clamp(0.0, f32::INFINITY);
+        let t = self.transmit.evaluate(si).clamp(0.0, f32::INFINITY);
 
         if !r.is_black() || !t.is_black() {
-            let kd = self.kd.evaluate(si).clamp();
+            let kd = self.kd.evaluate(si).clamp(0.0, f32::INFINITY);
             if !kd.is_black() {
                 if !r.is_black() {
                     bxdfs.add(arena <- LambertianReflection::new(r * kd));
@@ -69,7 +70,7 @@ impl Material for TranslucentMaterial {
                     bxdfs.add(arena <- LambertianTransmission::new(t * kd));
                 }
             }
-            let ks = self.ks.evaluate(si).clamp();
+            let ks = self.ks.evaluate(si).clamp(0.0, f32::INFINITY);
             if !ks.is_black() && (!r.is_black() || !t.is_black()) {
                 let mut rough = self.roughness.evaluate(si);
                 if self.remap_roughness {
@@ -1,3 +1,4 @@
+use std::f32;
 use std::sync::Arc;
 
 use light_arena::Allocator;
@@ -27,10 +28,10 @@ pub struct UberMaterial {
 
 impl UberMaterial {
     pub fn create(mp: &mut TextureParams) -> Arc<Material> {
-        let kd = mp.get_spectrum_texture("Kd", &Spectrum::from(0.25));
-        let ks = mp.get_spectrum_texture("Ks", &Spectrum::from(0.25));
-        let kr = mp.get_spectrum_texture("Kr", &Spectrum::from(0.0));
-        let kt = mp.get_spectrum_texture("Kt", &Spectrum::from(0.0));
+        let kd = mp.get_colour_texture("Kd", &Spectrum::from(0.25));
+        let ks = mp.get_colour_texture("Ks", &Spectrum::from(0.25));
+        let kr = mp.get_colour_texture("Kr", &Spectrum::from(0.0));
+        let kt = mp.get_colour_texture("Kt", &Spectrum::from(0.0));
         let roughness = mp.get_float_texture("roughness", 0.1);
         let uroughness = mp.get_float_texture_or_none("uroughness");
         let vroughness = mp.get_float_texture_or_none("vroughness");
@@ -69,8 +70,8 @@ impl Material for UberMaterial {
         }
 
         let e = self.eta.evaluate(si);
-        let op = self.opacity.evaluate(si).clamp();
-        let t = (Spectrum::white() - op).clamp();
+        let op = self.opacity.evaluate(si).clamp(0.0, f32::INFINITY);
+        let t = (Spectrum::white() - op).clamp(0.0, f32::INFINITY);
 
         let mut eta = e;
         if !t.is_black() {
@@ -78,12 +79,12 @@ impl Material for UberMaterial {
             bxdfs.add(arena <- SpecularTransmission::new(t, 1.0, 1.0, mode));
         }
 
-        let kd = op * self.kd.evaluate(si).clamp();
+        let kd = op * self.kd.evaluate(si).clamp(0.0, f32::INFINITY);
         if !kd.is_black() {
             bxdfs.add(arena <- LambertianReflection::new(kd));
         }
 
-        let ks = op * self.ks.evaluate(si).clamp();
+        let ks = op * self.ks.evaluate(si).clamp(0.0, f32::INFINITY);
         if !ks.is_black() {
             let fresnel = arena <- Fresnel::dielectric(1.0, e);
             let mut roughu = self.roughnessu
@@ -102,13 +103,13 @@ impl Material for UberMaterial {
             bxdfs.add(arena <- MicrofacetReflection::new(ks, distrib, fresnel));
         }
 
-        let kr = op * self.kr.evaluate(si).clamp();
+        let kr = op * self.kr.evaluate(si).clamp(0.0, f32::INFINITY);
         if !kr.is_black() {
             let fresnel = arena <- Fresnel::dielectric(1.0, e);
             bxdfs.add(arena <-SpecularReflection::new(kr, fresnel));
         }
 
-        let kt = op * self.kt.evaluate(si).clamp();
+        let kt = op * self.kt.evaluate(si).clamp(0.0, f32::INFINITY);
         if !kt.is_black() {
             bxdfs.add(arena <- SpecularTransmission::new(kt, 1.0, e, mode));
         }
@@ -117,3 +118,66 @@ impl Material for UberMaterial {
         si.bsdf = Some(Arc::new(bsdf));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use light_arena::MemoryArena;
+
+    use super::*;
+    use Vector3f;
+    use bsdf::BxDFType;
+    use material::MatteMaterial;
+    use test_utils::{create_material, hit_disk, rgb_param};
+
+    #[test]
+    fn test_kd_only_is_matte() {
+        ::init_stats();
+        let kd = [0.2, 0.4, 0.6];
+        // Like in pbrt, Ks defaults to 0.25 so it needs to be turned off explicitly
+        let uber = create_material(vec![rgb_param("Kd", kd), rgb_param("Ks", [0.0; 3])],
+                                   UberMaterial::create);
+        let matte = create_material(vec![rgb_param("Kd", kd)], MatteMaterial::create);
+
+        let mut arena = MemoryArena::new(1);
+        let alloc = arena.allocator();
+        let mut si_uber = hit_disk();
+        let mut si_matte = si_uber.clone();
+        uber.compute_scattering_functions(&mut si_uber, TransportMode::RADIANCE, true, &alloc);
+        matte.compute_scattering_functions(&mut si_matte, TransportMode::RADIANCE, true, &alloc);
+        let bsdf_uber = si_uber.bsdf.unwrap();
+        let bsdf_matte = si_matte.bsdf.unwrap();
+
+        assert_eq!(bsdf_uber.num_components(BxDFType::all()), 1);
+        assert_eq!(bsdf_uber.num_components(BxDFType::BSDF_DIFFUSE | BxDFType::BSDF_REFLECTION),
+                   1);
+        let wo = Vector3f::new(0.0, 0.0, 1.0);
+        for wi in &[Vector3f::new(0.0, 0.0, 1.0),
+                    Vector3f::new(0.6, 0.0, 0.8),
+                    Vector3f::new(-0.48, 0.6, 0.64)] {
+            let f_uber = bsdf_uber.f(&wo, wi, BxDFType::all());
+            let f_matte = bsdf_matte.f(&wo, wi, BxDFType::all());
+            for i in 0..3 {
+                assert!((f_uber[i] - f_matte[i]).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_opacity_adds_passthrough() {
+        ::init_stats();
+        let uber = create_material(vec![rgb_param("opacity", [0.5; 3])], UberMaterial::create);
+
+        let mut arena = MemoryArena::new(1);
+        let alloc = arena.allocator();
+        let mut si = hit_disk();
+        uber.compute_scattering_functions(&mut si, TransportMode::RADIANCE, true, &alloc);
+        let bsdf = si.bsdf.unwrap();
+
+        // Passthrough transmission, diffuse and glossy lobes
+        assert_eq!(bsdf.num_components(BxDFType::all()), 3);
+        assert_eq!(bsdf.num_components(BxDFType::BSDF_SPECULAR | BxDFType::BSDF_TRANSMISSION),
+                   1);
+        // Passthrough doesn't bend rays
+        assert_eq!(bsdf.eta, 1.0);
+    }
+}
@@ -0,0 +1,72 @@
+use std::f32;
+use std::fmt::Debug;
+
+use ray::Ray;
+use sampler::Sampler;
+use spectrum::Spectrum;
+
+/// A participating medium, like fog or smoke, that absorbs and scatters light travelling
+/// through it.
+pub trait Medium: Debug + Send + Sync {
+    /// Beam transmittance along the ray, from its origin to `ray.t_max`.
+    fn tr(&self, ray: &Ray, sampler: &mut Box<Sampler>) -> Spectrum;
+}
+
+/// A medium with the same absorption and scattering coefficients everywhere.
+#[derive(Debug)]
+pub struct HomogeneousMedium {
+    sigma_a: Spectrum,
+    sigma_s: Spectrum,
+    sigma_t: Spectrum,
+}
+
+impl HomogeneousMedium {
+    pub fn new(sigma_a: Spectrum, sigma_s: Spectrum) -> HomogeneousMedium {
+        HomogeneousMedium {
+            sigma_a,
+            sigma_s,
+            sigma_t: sigma_a + sigma_s,
+        }
+    }
+
+    pub fn sigma_a(&self) -> Spectrum {
+        self.sigma_a
+    }
+
+    pub fn sigma_s(&self) -> Spectrum {
+        self.sigma_s
+    }
+}
+
+impl Medium for HomogeneousMedium {
+    fn tr(&self, ray: &Ray, _sampler: &mut Box<Sampler>) -> Spectrum {
+        // Beer's law. Clamp the distance so an infinite ray gives 0 rather than NaN for
+        // components of sigma_t that are 0.
+        let distance = f32::min(ray.t_max * ray.d.length(), f32::MAX);
+        (self.sigma_t * -distance).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use {Point3f, Vector3f};
+    use sampler::zerotwosequence::ZeroTwoSequence;
+
+    #[test]
+    fn test_homogeneous_transmittance() {
+        let medium = HomogeneousMedium::new(Spectrum::rgb(0.1, 0.5, 0.0), Spectrum::grey(0.1));
+        let mut sampler: Box<Sampler> = Box::new(ZeroTwoSequence::new(1, 1));
+        // The direction isn't normalized: the distance is 2 * 1.5
+        let ray = Ray::segment(Point3f::new(1.0, 2.0, 3.0), Vector3f::new(0.0, 2.0, 0.0), 1.5);
+        let tr = medium.tr(&ray, &mut sampler);
+        assert!((tr[0] - (-0.6f32).exp()).abs() < 1e-6);
+        assert!((tr[1] - (-1.8f32).exp()).abs() < 1e-6);
+        assert!((tr[2] - (-0.3f32).exp()).abs() < 1e-6);
+
+        // Infinite rays are fully attenuated
+        let ray = Ray::new(Point3f::new(0.0, 0.0, 0.0), Vector3f::new(1.0, 0.0, 0.0));
+        assert!(medium.tr(&ray, &mut sampler).is_black());
+    }
+}
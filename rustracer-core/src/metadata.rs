@@ -0,0 +1,59 @@
+//! Sidecar file describing how a render was made, to keep alongside the image when archiving it.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use failure::{Error, ResultExt};
+use indicatif::HumanDuration;
+use serde_json::{self, Value};
+
+use Point2i;
+use stats;
+
+pub struct RenderMetadata<'a> {
+    pub image: &'a str,
+    pub resolution: Point2i,
+    pub spp: usize,
+    pub passes: u32,
+    pub integrator: &'a str,
+    pub sampler: &'a str,
+    pub render_time: Duration,
+}
+
+impl<'a> RenderMetadata<'a> {
+    /// Path of the sidecar file for `image`: the same path, with a `.json` extension.
+    pub fn sidecar_path<P: AsRef<Path>>(image: P) -> PathBuf {
+        image.as_ref().with_extension("json")
+    }
+
+    /// The metadata as JSON, including the statistics gathered by `stats::report_stats()`.
+    pub fn to_json(&self) -> Value {
+        let seconds = self.render_time.as_secs() as f64 +
+                      f64::from(self.render_time.subsec_nanos()) * 1e-9;
+        json!({
+            "image": self.image,
+            "resolution": [self.resolution.x, self.resolution.y],
+            "spp": self.spp,
+            "passes": self.passes,
+            "integrator": self.integrator,
+            "sampler": self.sampler,
+            "render_time": {
+                "seconds": seconds,
+                "human": format!("{}", HumanDuration(self.render_time)),
+            },
+            "version": env!("CARGO_PKG_VERSION"),
+            "stats": stats::stats_json(),
+        })
+    }
+
+    /// Write the metadata next to the image, and return the path of the file.
+    pub fn write(&self) -> Result<PathBuf, Error> {
+        let path = Self::sidecar_path(self.image);
+        let file = File::create(&path)
+            .context(format!("Failed to create metadata file {}", path.display()))?;
+        serde_json::to_writer_pretty(file, &self.to_json())
+            .context(format!("Failed to write metadata file {}", path.display()))?;
+        Ok(path)
+    }
+}
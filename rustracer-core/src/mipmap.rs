@@ -25,6 +25,19 @@ pub enum WrapMode {
     Clamp,
 }
 
+/// How texture lookups are filtered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    /// Value of the texel containing the lookup point, for pixel art or data textures
+    Nearest,
+    /// Bilinear interpolation of the finest level
+    Bilinear,
+    /// Trilinear interpolation between the two levels closest to the filter width
+    Trilinear,
+    /// Elliptically weighted average, which handles anisotropic footprints
+    EWA,
+}
+
 const WEIGHT_LUT_SIZE: usize = 128;
 lazy_static! {
     static ref WEIGHT_LUT: [f32; WEIGHT_LUT_SIZE] = {
@@ -40,7 +53,7 @@ lazy_static! {
 
 #[derive(Debug)]
 pub struct MIPMap<T> {
-    do_trilinear: bool,
+    filter: FilterMode,
     max_anisotropy: f32,
     wrap_mode: WrapMode,
     resolution: Point2i,
@@ -60,7 +73,7 @@ impl<T> MIPMap<T>
 {
     pub fn new(res: &Point2i,
                img: &[T],
-               do_trilinear: bool,
+               filter: FilterMode,
                max_anisotropy: f32,
                wrap_mode: WrapMode)
                -> MIPMap<T> {
@@ -126,7 +139,7 @@ impl<T> MIPMap<T>
         }
 
         let mut mipmap = MIPMap {
-            do_trilinear: do_trilinear,
+            filter,
             max_anisotropy: max_anisotropy,
             wrap_mode: wrap_mode,
             resolution: resolution,
@@ -222,10 +235,15 @@ impl<T> MIPMap<T>
     pub fn lookup_diff(&self, st: &Point2f, dst0: &Vector2f, dst1: &Vector2f) -> T {
         let mut dst0 = *dst0;
         let mut dst1 = *dst1;
-        if self.do_trilinear {
-            let width = f32::max(f32::max(f32::abs(dst0[0]), f32::abs(dst0[1])),
-                                 f32::max(f32::abs(dst1[0]), f32::abs(dst1[1])));
-            return self.lookup(st, 2.0 * width);
+        match self.filter {
+            FilterMode::Nearest => return self.nearest(0, st),
+            FilterMode::Bilinear => return self.triangle(0, st),
+            FilterMode::Trilinear => {
+                let width = f32::max(f32::max(f32::abs(dst0[0]), f32::abs(dst0[1])),
+                                     f32::max(f32::abs(dst1[0]), f32::abs(dst1[1])));
+                return self.lookup(st, 2.0 * width);
+            }
+            FilterMode::EWA => {}
         }
         n_ewa_lookups::inc();
 
@@ -254,6 +272,14 @@ impl<T> MIPMap<T>
              self.EWA(ilod + 1, st, &dst0, &dst1))
     }
 
+    /// Value of the texel of the given level containing `st`, without any filtering.
+    pub fn nearest(&self, level: usize, st: &Point2f) -> T {
+        let level = clamp(level, 0, self.levels() - 1);
+        let s = (st.x * self.pyramid[level].u_size() as f32).floor() as isize;
+        let t = (st.y * self.pyramid[level].v_size() as f32).floor() as isize;
+        *self.texel(level, s, t)
+    }
+
     pub fn triangle(&self, level: usize, st: &Point2f) -> T {
         let level = clamp(level, 0, self.levels() - 1);
         let s = st.x * self.pyramid[level].u_size() as f32 - 0.5;
@@ -392,3 +418,65 @@ fn modulo(a: isize, b: isize) -> usize {
         result as usize
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4x4 texture where each texel's value is its column index.
+    fn columns(wrap_mode: WrapMode) -> MIPMap<f32> {
+        let img: Vec<f32> = (0..16).map(|i| (i % 4) as f32).collect();
+        MIPMap::new(&Point2i::new(4, 4), &img[..], FilterMode::EWA, 8.0, wrap_mode)
+    }
+
+    #[test]
+    fn test_wrap_modes() {
+        ::init_stats();
+        let outside = Point2f::new(1.5, 0.5);
+
+        let clamp = columns(WrapMode::Clamp);
+        assert_eq!(clamp.lookup(&outside, 0.0), 3.0);
+        assert_eq!(clamp.lookup(&Point2f::new(-0.5, 0.5), 0.0), 0.0);
+
+        let black = columns(WrapMode::Black);
+        assert_eq!(black.lookup(&outside, 0.0), 0.0);
+
+        // s = 1.5 is halfway between columns 1 and 2 once wrapped around
+        let repeat = columns(WrapMode::Repeat);
+        assert!((repeat.lookup(&outside, 0.0) - 1.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_nearest_lookup() {
+        ::init_stats();
+        // A 4x2 texture of distinct values
+        let img: Vec<f32> = (0..8).map(|i| i as f32 * 0.1).collect();
+        let nearest = MIPMap::new(&Point2i::new(4, 2),
+                                  &img[..],
+                                  FilterMode::Nearest,
+                                  8.0,
+                                  WrapMode::Repeat);
+        let bilinear = MIPMap::new(&Point2i::new(4, 2),
+                                   &img[..],
+                                   FilterMode::Bilinear,
+                                   8.0,
+                                   WrapMode::Repeat);
+        let d = Vector2f::new(0.0, 0.0);
+        for t in 0..2 {
+            for s in 0..4 {
+                let expected = img[t * 4 + s];
+                // Texel centers give the exact values, and so does anywhere else in the texel
+                let center = Point2f::new((s as f32 + 0.5) / 4.0, (t as f32 + 0.5) / 2.0);
+                assert_eq!(nearest.lookup_diff(&center, &d, &d), expected);
+                let off_center = Point2f::new((s as f32 + 0.8) / 4.0, (t as f32 + 0.3) / 2.0);
+                assert_eq!(nearest.lookup_diff(&off_center, &d, &d), expected);
+                assert!((bilinear.lookup_diff(&center, &d, &d) - expected).abs() < 1e-6);
+            }
+        }
+
+        // Between texels, bilinear filtering blends them while nearest picks one
+        let between = Point2f::new(0.25, 0.25);
+        assert_eq!(nearest.lookup_diff(&between, &d, &d), img[1]);
+        assert!((bilinear.lookup_diff(&between, &d, &d) - 0.05).abs() < 1e-6);
+    }
+}
@@ -92,6 +92,20 @@ impl ParamSet {
                         .collect();
                     self.add_rgb_spectrum(entry.param_name.clone(), spectra);
                 }
+                ParamType::Blackbody => {
+                    // Either a single temperature, or (temperature, scale) pairs as in pbrt-v3
+                    let values = entry.values.as_num_array();
+                    let spectra = if values.len() == 1 {
+                        vec![Spectrum::from_blackbody(values[0])]
+                    } else {
+                        values
+                            .chunks(2)
+                            .filter(|s| s.len() == 2)
+                            .map(|s| Spectrum::from_blackbody(s[0]) * s[1])
+                            .collect()
+                    };
+                    self.add_rgb_spectrum(entry.param_name.clone(), spectra);
+                }
                 ParamType::Point2 => {
                     let points = entry
                         .values
@@ -332,10 +346,58 @@ impl<'a> TextureParams<'a> {
         self.geom_params.find_one_spectrum(n, d)
     }
 
+    /// Convert a colour given as a parameter to linear RGB: colours are linear unless the
+    /// "srgb" parameter is set, as is often the case for scenes exported from other software.
+    pub fn decode_colour(&mut self, val: Spectrum) -> Spectrum {
+        if self.find_bool("srgb", false) {
+            val.inverse_gamma_correct()
+        } else {
+            val
+        }
+    }
+
+    /// Return true if the parameter `n` refers to a named texture rather than a constant value.
+    pub fn has_texture(&mut self, n: &str) -> bool {
+        &self.geom_params.find_texture(n, "".to_owned()) != "" ||
+        &self.material_params.find_texture(n, "".to_owned()) != ""
+    }
+
+    /// Whether the parameter `n` is given as a float value or refers to a float texture, rather
+    /// than being a spectrum.
+    pub fn is_float(&mut self, n: &str) -> bool {
+        let mut name = self.geom_params.find_texture(n, "".to_owned());
+        if &name == "" {
+            name = self.material_params.find_texture(n, "".to_owned());
+        }
+        if &name != "" {
+            return self.float_textures.contains_key(&name) &&
+                   !self.spectrum_textures.contains_key(&name);
+        }
+        self.geom_params.find_float(n).is_some() || self.material_params.find_float(n).is_some()
+    }
+
     pub fn get_spectrum_texture(&mut self,
                                 n: &str,
                                 default: &Spectrum)
                                 -> Arc<Texture<Spectrum>> {
+        self.lookup_spectrum_texture(n, default, false)
+    }
+
+    /// Like `get_spectrum_texture()`, but for colour parameters such as reflectances: a constant
+    /// value given in the parameter list is decoded with `decode_colour()`. Defaults and physical
+    /// quantities like indices of refraction are always linear.
+    pub fn get_colour_texture(&mut self,
+                              n: &str,
+                              default: &Spectrum)
+                              -> Arc<Texture<Spectrum>> {
+        self.lookup_spectrum_texture(n, default, true)
+    }
+
+    fn lookup_spectrum_texture(&mut self,
+                               n: &str,
+                               default: &Spectrum,
+                               colour: bool)
+                               -> Arc<Texture<Spectrum>> {
         let mut name = self.geom_params.find_texture(n, "".to_owned());
         if &name == "" {
             name = self.material_params.find_texture(n, "".to_owned());
@@ -350,11 +412,27 @@ impl<'a> TextureParams<'a> {
             }
         }
         // If texture wasn't found
-        let val = self.material_params.find_one_spectrum(n, *default);
-        let val = self.geom_params.find_one_spectrum(n, val);
+        let material_val = self.material_params.find_spectrum(n);
+        let val = self.geom_params.find_spectrum(n).or(material_val).map(|v| v[0]);
+        let val = match val {
+            Some(val) if colour => self.decode_colour(val),
+            Some(val) => val,
+            None => *default,
+        };
         Arc::new(ConstantTexture::new(val))
     }
 
+    /// Like `get_spectrum_texture()`, but return `None` if the parameter isn't set.
+    pub fn get_spectrum_texture_or_none(&mut self, n: &str) -> Option<Arc<Texture<Spectrum>>> {
+        let is_set = self.has_texture(n) || self.geom_params.find_spectrum(n).is_some() ||
+                     self.material_params.find_spectrum(n).is_some();
+        if is_set {
+            Some(self.get_spectrum_texture(n, &Spectrum::black()))
+        } else {
+            None
+        }
+    }
+
     pub fn get_float_texture(&mut self, n: &str, default: f32) -> Arc<Texture<f32>> {
         let mut name = self.geom_params.find_texture(n, "".to_owned());
         if &name == "" {
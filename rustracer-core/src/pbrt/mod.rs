@@ -8,14 +8,14 @@ use std::io::prelude::*;
 use combine::State;
 use failure::*;
 
-use api::{Api, RealApi};
+use api::{Api, Options, RealApi};
 use fileutil;
 
-pub fn parse_scene<P: AsRef<Path>>(filename: P) -> Result<(), Error> {
+pub fn parse_scene<P: AsRef<Path>>(filename: P, options: &Options) -> Result<(), Error> {
     let filename = filename.as_ref();
     let tokens = tokenize_file(filename)?;
     fileutil::set_search_directory(fileutil::directory_containing(filename));
-    let api = RealApi::default();
+    let api = RealApi::new(options.clone());
     api.init()?;
     parser::parse(&tokens[..], &api)
         .map_err(|e| format_err!("Failed to parse scene file: {:?}", e))?;
@@ -83,5 +83,5 @@ WorldBegin
 WorldEnd
         "##;
 
-    parse_scene(scene).unwrap();
+    parse_scene(scene, &Options::default()).unwrap();
 }
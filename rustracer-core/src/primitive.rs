@@ -16,8 +16,22 @@ pub trait Primitive: Debug + Send + Sync {
 
     fn intersect(&self, ray: &mut Ray) -> Option<SurfaceInteraction>;
 
+    /// Whether the ray is blocked before `ray.t_max`. Surfaces without a material (see the "none"
+    /// material) let light through, so they never block it.
     fn intersect_p(&self, ray: &Ray) -> bool;
 
+    /// Like `intersect_p()`, but also return the index of the primitive blocking the ray if this
+    /// is an aggregate that can tell which one it is (see `primitive()`).
+    fn intersect_p_blocker(&self, ray: &Ray) -> (bool, Option<usize>) {
+        (self.intersect_p(ray), None)
+    }
+
+    /// The primitive with the given index in this aggregate, as returned by
+    /// `intersect_p_blocker()`.
+    fn primitive(&self, _idx: usize) -> Option<&Primitive> {
+        None
+    }
+
     fn area_light(&self) -> Option<Arc<AreaLight>>;
 
     fn material(&self) -> Option<Arc<Material>>;
@@ -51,7 +65,7 @@ impl Primitive for GeometricPrimitive {
     }
 
     fn intersect_p(&self, ray: &Ray) -> bool {
-        self.shape.intersect_p(ray)
+        self.material.is_some() && self.shape.intersect_p(ray)
     }
 
     fn area_light(&self) -> Option<Arc<AreaLight>> {
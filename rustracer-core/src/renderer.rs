@@ -1,38 +1,239 @@
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 use crossbeam;
 use failure::Error;
-use indicatif;
+use indicatif::{self, HumanDuration};
 use parking_lot::Mutex;
 
-use Point2i;
+use {Options, Point2i};
 use bounds::Bounds2i;
 use camera::Camera;
 use display::DisplayUpdater;
-use integrator::SamplerIntegrator;
+use integrator::{set_debugged_pixel, take_path_rays, SamplerIntegrator};
 use light_arena::MemoryArena;
+use rng::mix_bits;
 use sampler::Sampler;
 use scene::Scene;
 use spectrum::Spectrum;
 use stats;
 
 stat_counter!("Integrator/Camera rays traced", n_camera_ray);
+stat_counter!("Integrator/NaN samples", n_nan_samples);
+stat_counter!("Integrator/Infinite samples", n_infinite_samples);
+stat_counter!("Integrator/Negative samples", n_negative_samples);
 pub fn init_stats() {
     n_camera_ray::init();
+    n_nan_samples::init();
+    n_infinite_samples::init();
+    n_negative_samples::init();
 }
 
+/// Receives notifications about the progress of a render, e.g. to drive a progress bar.
+///
+/// Tiles are rendered in parallel, so the methods are called from the worker threads.
+pub trait ProgressObserver: Send + Sync {
+    /// Called once the scene has been preprocessed, right before the first pass starts, with the
+    /// time the render has to stop by if `Options::render_time` is set.
+    fn on_render_start(&self, _deadline: Option<Instant>) {}
+
+    /// Called each time a tile has been rendered and merged into the film, with the number of
+    /// tiles completed so far and the total number of tiles.
+    fn on_tile_complete(&self, completed: usize, total: usize);
+}
+
+/// Estimates the time a render has left from the rate tiles were completed at so far, or from
+/// its deadline if it has one.
+#[derive(Debug, Clone, Copy)]
+pub struct EtaEstimator {
+    start: Instant,
+    deadline: Option<Instant>,
+}
+
+impl EtaEstimator {
+    pub fn new(start: Instant, deadline: Option<Instant>) -> EtaEstimator {
+        EtaEstimator { start, deadline }
+    }
+
+    /// Time left at `now` to render the remaining tiles, assuming they take as long on average as
+    /// the `completed` ones did. There's no estimate until a tile has been completed. With a
+    /// deadline, the number of tiles isn't known in advance and the time left until the deadline
+    /// is returned instead.
+    pub fn eta(&self, now: Instant, completed: usize, total: usize) -> Option<Duration> {
+        if let Some(deadline) = self.deadline {
+            return Some(if deadline > now {
+                            deadline - now
+                        } else {
+                            Duration::from_secs(0)
+                        });
+        }
+        if completed == 0 {
+            return None;
+        }
+        let elapsed = now.duration_since(self.start);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+        let left = elapsed * total.saturating_sub(completed) as f64 / completed as f64;
+        Some(Duration::new(left as u64, (left.fract() * 1e9) as u32))
+    }
+}
+
+/// Default `ProgressObserver`, showing a progress bar with the estimated time left on the
+/// terminal unless `options.quiet` is set.
+pub struct ProgressBarObserver {
+    pb: indicatif::ProgressBar,
+    /// Set when the render starts
+    eta: Mutex<Option<EtaEstimator>>,
+}
+
+impl ProgressBarObserver {
+    pub fn new(options: &Options) -> ProgressBarObserver {
+        let pb = if options.quiet {
+            indicatif::ProgressBar::hidden()
+        } else {
+            indicatif::ProgressBar::new(0)
+        };
+        pb.set_style(indicatif::ProgressStyle::default_bar()
+                         .progress_chars("=>-")
+                         .template("[{elapsed_precise}] [{wide_bar}] {percent}% [{pos}/{len}] {msg}"));
+        ProgressBarObserver {
+            pb,
+            eta: Mutex::new(None),
+        }
+    }
+}
+
+impl ProgressObserver for ProgressBarObserver {
+    fn on_render_start(&self, deadline: Option<Instant>) {
+        *self.eta.lock() = Some(EtaEstimator::new(Instant::now(), deadline));
+    }
+
+    fn on_tile_complete(&self, completed: usize, total: usize) {
+        self.pb.set_length(total as u64);
+        self.pb.set_position(completed as u64);
+        let estimator = *self.eta.lock();
+        if let Some(eta) = estimator.and_then(|e| e.eta(Instant::now(), completed, total)) {
+            self.pb.set_message(&format!("ETA {}", HumanDuration(eta)));
+        }
+        if completed == total {
+            self.pb.finish();
+        }
+    }
+}
+
+/// How a call to `render()` ended.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RenderStatus {
+    /// All the tiles were rendered and the image was written. `passes` is the number of passes
+    /// that were completed; with `Options::render_time`, it doesn't count the last pass if the
+    /// deadline cut it short.
+    Completed { passes: u32 },
+    /// The render was cancelled. The camera's film only contains the tiles that were completed
+    /// and no image was written.
+    Cancelled,
+}
+
+/// Render the scene and write the resulting image. Progress is reported to `progress`, or shown
+/// as a progress bar on the terminal if it is `None`. Setting the `cancel` flag stops the render
+/// once the tiles in progress are done.
+///
+/// With several passes (see `Options::passes`), each pass takes the sampler's number of samples
+/// per pixel and adds them to the film, and the image is written after every pass so it can be
+/// looked at while it keeps refining. With `Options::render_time`, passes go on until that time
+/// has passed instead: the last one stops taking new tiles at the deadline, and its tiles that
+/// were rendered add to the samples of the previous passes. The image is then only written once
+/// at the end, so the time budget isn't spent writing files.
 pub fn render(scene: Arc<Scene>,
               integrator: &mut SamplerIntegrator,
               camera: &Camera,
-              num_threads: usize,
+              options: &Options,
               sampler: &mut Box<Sampler>,
               block_size: i32,
-              mut _display: Box<DisplayUpdater + Send>)
-              -> Result<(), Error> {
+              mut _display: Box<DisplayUpdater + Send>,
+              progress: Option<Box<ProgressObserver>>,
+              cancel: Option<Arc<AtomicBool>>)
+              -> Result<RenderStatus, Error> {
+    let progress = progress.unwrap_or_else(|| Box::new(ProgressBarObserver::new(options)));
+    let cancel = cancel.unwrap_or_else(|| Arc::new(AtomicBool::new(false)));
     integrator.preprocess(Arc::clone(&scene), sampler);
+    let deadline = options.render_time.map(|t| Instant::now() + t);
+    progress.on_render_start(deadline);
+    let n_passes = if deadline.is_some() {
+        u32::max_value()
+    } else {
+        options.passes.unwrap_or(1).max(1)
+    };
+    let mut completed_passes = 0;
+    for pass in 0..n_passes {
+        // Without a fixed number of passes, show the progress of the current one
+        let shown_passes = if deadline.is_some() { pass + 1 } else { n_passes };
+        let all_tiles = render_tiles(&scene,
+                                     integrator,
+                                     camera,
+                                     options,
+                                     sampler,
+                                     block_size,
+                                     (pass, shown_passes),
+                                     &*progress,
+                                     &cancel,
+                                     if pass > 0 { deadline } else { None });
+        if cancel.load(Ordering::Relaxed) {
+            info!("Render cancelled");
+            return Ok(RenderStatus::Cancelled);
+        }
+
+        if all_tiles {
+            completed_passes += 1;
+        }
+        info!("Finished pass {} of {}", pass + 1, n_passes);
+        match deadline {
+            Some(d) => {
+                if Instant::now() >= d {
+                    info!("Render time is up after {} complete passes", completed_passes);
+                    break;
+                }
+            }
+            None => write_film(camera)?,
+        }
+    }
+    if deadline.is_some() {
+        write_film(camera)?;
+    }
+
+    Ok(RenderStatus::Completed { passes: completed_passes })
+}
+
+/// Write the camera's film image, and its variance map if one was requested.
+fn write_film(camera: &Camera) -> Result<(), Error> {
+    let film = camera.get_film();
+    film.write_image()?;
+    if let Some(ref variance_filename) = film.variance_filename {
+        film.write_variance_map(variance_filename)?;
+    }
+    Ok(())
+}
+
+/// Render the image into the camera's film, splitting it in square tiles of `block_size` pixels
+/// rendered in parallel. `pass` is the index of the current pass and the total number of passes;
+/// each pass uses different random numbers. No new tiles are started after `deadline`. Return
+/// true if all the tiles were rendered.
+fn render_tiles(scene: &Arc<Scene>,
+                integrator: &SamplerIntegrator,
+                camera: &Camera,
+                options: &Options,
+                sampler: &Box<Sampler>,
+                block_size: i32,
+                pass: (u32, u32),
+                progress: &ProgressObserver,
+                cancel: &AtomicBool,
+                deadline: Option<Instant>)
+                -> bool {
+    let (pass, n_passes) = pass;
     let sample_bounds = camera.get_film().get_sample_bounds();
     let sample_extent = sample_bounds.diagonal();
     let pixel_bounds = integrator.pixel_bounds();
+    let needs_first_hits = camera.get_film().needs_first_hits();
+    let needs_path_rays = camera.get_film().needs_path_rays();
     info!("Rendering with sample_bounds = {}, pixel_bounds = {}",
           sample_bounds,
           pixel_bounds);
@@ -40,22 +241,16 @@ pub fn render(scene: Arc<Scene>,
                                (sample_extent.y + block_size - 1) / block_size);
 
     let num_blocks = n_tiles.x * n_tiles.y;
+    let num_threads = options.num_threads;
     info!("Rendering scene using {} threads", num_threads);
     let image_bounds = Bounds2i::from_points(&Point2i::new(0, 0),
                                              &Point2i::new(n_tiles.x, n_tiles.y));
     let tiles_iter = Arc::new(Mutex::new(image_bounds.into_iter()));
-    let pb = indicatif::ProgressBar::new(num_blocks as _);
-    pb.set_style(indicatif::ProgressStyle::default_bar()
-                     .progress_chars("=>-")
-                     .template("[{elapsed_precise}] [{wide_bar}] {percent}% [{pos}/{len}] {eta}"));
-    pb.tick();
+    let n_completed = AtomicUsize::new(0);
 
     crossbeam::scope(|scope| {
         // We only want to use references to these in the thread, not move the structs themselves...
-        let scene = &scene;
-        let integrator = &integrator;
-        let camera = &camera;
-        let pb = &pb;
+        let n_completed = &n_completed;
 
         // Spawn worker threads
         for _ in 0..num_threads {
@@ -63,6 +258,10 @@ pub fn render(scene: Arc<Scene>,
             let tiles_iter = Arc::clone(&tiles_iter);
             scope.spawn(move || {
                 loop {
+                    if cancel.load(Ordering::Relaxed) ||
+                       deadline.map_or(false, |d| Instant::now() >= d) {
+                        break;
+                    }
                     let maybe_tile = {
                         let mut iter = tiles_iter.lock();
                         iter.next()
@@ -77,10 +276,6 @@ pub fn render(scene: Arc<Scene>,
                     // Allocate MemoryArena for tile
                     let mut arena = MemoryArena::new(1);
 
-                    // Get sampler instance for tile
-                    let seed = tile.y * n_tiles.x + tile.x;
-                    sampler.reseed(seed as u64);
-
                     // Compute sample bounds for tile
                     let x0 = sample_bounds.p_min.x + tile.x * block_size;
                     let x1 = i32::min(x0 + block_size, sample_bounds.p_max.x);
@@ -92,7 +287,15 @@ pub fn render(scene: Arc<Scene>,
 
                     let mut film_tile = camera.get_film().get_film_tile(&tile_bounds);
                     for p in &tile_bounds {
+                        // Seed the sampler for each pixel, so the image doesn't depend on the
+                        // tile size or on which thread rendered the pixel
+                        sampler.reseed(pixel_seed(options.seed, pass, &p));
                         sampler.start_pixel(&p);
+                        set_debugged_pixel(if options.debug_pixel == Some(p) {
+                                               Some(p)
+                                           } else {
+                                               None
+                                           });
 
                         // Do this check after the start_pixel() call; this keeps
                         // the usage of RNG values from (most) Samplers that use
@@ -105,37 +308,540 @@ pub fn render(scene: Arc<Scene>,
                         loop {
                             let alloc = arena.allocator();
                             let s = sampler.get_camera_sample(&p);
-                            let mut ray = camera.generate_ray_differential(&s);
+                            let (mut ray, ray_weight) = camera.generate_ray_differential(&s);
                             ray.scale_differentials(1.0 / (sampler.spp() as f32).sqrt());
                             n_camera_ray::inc();
-                            let mut sample_colour =
-                                integrator.li(scene, &mut ray, &mut sampler, &alloc, 0);
-                            if sample_colour.has_nan() {
-                                error!("Not-a-number radiance value returned for pixel {}, sample {}. Setting to black.", p, sampler.current_sample_number());
-                                sample_colour = Spectrum::black();
-                            }
-                            if sample_colour.y() < -1e-5 {
-                                error!("Negative luminance value, {}, returned for pixel {}, sample {}. Setting to black.", sample_colour.y(), p, sampler.current_sample_number());
-                                sample_colour = Spectrum::black();
+                            pixel_debug!("Camera sample {}: ray {}, weight {}",
+                                         sampler.current_sample_number(),
+                                         ray,
+                                         ray_weight);
+                            if needs_first_hits {
+                                let hit = if ray_weight > 0.0 {
+                                    integrator.first_hit(scene, &ray, &alloc)
+                                } else {
+                                    None
+                                };
+                                film_tile.add_first_hit(&s.p_film, hit.as_ref());
                             }
-                            if sample_colour.y().is_infinite() {
-                                error!("Infinite luminance value returned for pixel {}, sample {}. Setting to black.", p, sampler.current_sample_number());
-                                sample_colour = Spectrum::black();
+                            // Rays the camera doesn't let through don't need tracing
+                            let sample_colour = if ray_weight > 0.0 {
+                                integrator.li(scene, &mut ray, &mut sampler, &alloc, 0) *
+                                ray_weight
+                            } else {
+                                Spectrum::black()
+                            };
+                            if let Some(n) = take_path_rays() {
+                                if needs_path_rays {
+                                    film_tile.add_path_rays(&s.p_film, n);
+                                }
                             }
+                            let sample_colour =
+                                check_radiance(sample_colour, &p, sampler.current_sample_number());
+                            pixel_debug!("Sample {} radiance: {}",
+                                         sampler.current_sample_number(),
+                                         sample_colour);
                             film_tile.add_sample(&s.p_film, sample_colour);
                             if !sampler.start_next_sample() {
                                 break;
                             }
                         }
                     }
+                    set_debugged_pixel(None);
                     camera.get_film().merge_film_tile(film_tile);
-                    pb.inc(1);
+                    let completed = n_completed.fetch_add(1, Ordering::SeqCst) + 1;
+                    let n_tiles = num_blocks as usize;
+                    progress.on_tile_complete(pass as usize * n_tiles + completed,
+                                              n_passes as usize * n_tiles);
                 }
                 stats::report_stats();
             });
         }
     });
-    pb.finish();
+    n_completed.load(Ordering::SeqCst) == num_blocks as usize
+}
+
+/// Sequence number of the random number generator used to sample pixel `p`.
+fn pixel_seed(seed: u64, pass: u32, p: &Point2i) -> u64 {
+    let pixel = ((p.y as u32 as u64) << 32) | p.x as u32 as u64;
+    mix_bits(pixel ^ mix_bits(seed ^ mix_bits(u64::from(pass))))
+}
+
+/// Replace radiance values that would corrupt the image (NaNs, infinities and negative values)
+/// with black, so a single bad sample doesn't ruin a pixel, let alone the whole render.
+fn check_radiance(l: Spectrum, p: &Point2i, sample_num: usize) -> Spectrum {
+    if l.has_nan() {
+        n_nan_samples::inc();
+        error!("Not-a-number radiance value returned for pixel {}, sample {}. Setting to black.",
+               p,
+               sample_num);
+        Spectrum::black()
+    } else if l.is_infinite() {
+        n_infinite_samples::inc();
+        error!("Infinite radiance value returned for pixel {}, sample {}. Setting to black.",
+               p,
+               sample_num);
+        Spectrum::black()
+    } else if l.y() < -1e-5 {
+        n_negative_samples::inc();
+        error!("Negative luminance value, {}, returned for pixel {}, sample {}. Setting to black.",
+               l.y(),
+               p,
+               sample_num);
+        Spectrum::black()
+    } else {
+        l
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::f32;
+    use std::fs;
+    use std::time::Duration;
+
+    use {Point2f, Point3f, Transform, Vector3f};
+    use bounds::Bounds2f;
+    use camera::{CameraSample, PerspectiveCamera};
+    use display::NoopDisplayUpdater;
+    use film::Film;
+    use light_arena::Allocator;
+
+    use bsdf::{BxDF, BxDFHolder, BxDFType, BSDF};
+    use filter::BoxFilter;
+    use integrator::{debugged_pixel, PathIntegrator};
+    use interaction::SurfaceInteraction;
+    use light::{Light, PointLight};
+    use material::{Material, MatteMaterial, TransportMode};
+    use primitive::GeometricPrimitive;
+    use ray::Ray;
+    use sampler::zerotwosequence::ZeroTwoSequence;
+    use shapes::Sphere;
+    use test_utils::create_material;
+
+    /// A diffuse BxDF that returns NaNs, like a BxDF hitting a numerical corner case would.
+    #[derive(Debug)]
+    struct NaNReflection;
+
+    impl BxDF for NaNReflection {
+        fn f(&self, _wo: &Vector3f, _wi: &Vector3f) -> Spectrum {
+            Spectrum::grey(f32::NAN)
+        }
+
+        fn get_type(&self) -> BxDFType {
+            BxDFType::BSDF_DIFFUSE | BxDFType::BSDF_REFLECTION
+        }
+    }
+
+    #[derive(Debug)]
+    struct NaNMaterial;
+
+    impl Material for NaNMaterial {
+        fn compute_scattering_functions<'a, 'b>(&self,
+                                                si: &mut SurfaceInteraction<'a, 'b>,
+                                                _mode: TransportMode,
+                                                _allow_multiple_lobes: bool,
+                                                arena: &'b Allocator) {
+            let mut bxdfs = BxDFHolder::new(arena);
+            bxdfs.add(arena <- NaNReflection);
+            let bsdf = BSDF::new(si, 1.0, bxdfs.into_slice());
+            si.bsdf = Some(Arc::new(bsdf));
+        }
+    }
+
+    fn matte() -> Arc<Material> {
+        create_material(Vec::new(), MatteMaterial::create)
+    }
+
+    /// Path trace a small image of a sphere lit by a point light, and return its pixels.
+    fn render_sphere(material: Arc<Material>, options: &Options, block_size: i32) -> Vec<f32> {
+        render_sphere_with(material,
+                           options,
+                           block_size,
+                           &ProgressBarObserver::new(options),
+                           &AtomicBool::new(false))
+    }
+
+    fn render_sphere_with(material: Arc<Material>,
+                          options: &Options,
+                          block_size: i32,
+                          progress: &ProgressObserver,
+                          cancel: &AtomicBool)
+                          -> Vec<f32> {
+        let (scene, camera, mut integrator, mut sampler) = sphere_scene(material, 4, "test.exr");
+        integrator.preprocess(Arc::clone(&scene), &mut sampler);
+
+        render_tiles(&scene,
+                     &integrator,
+                     &camera,
+                     options,
+                     &sampler,
+                     block_size,
+                     (0, 1),
+                     progress,
+                     cancel,
+                     None);
+        camera.get_film().get_rgb()
+    }
+
+    /// A sphere lit by a point light, seen by a camera whose film is written to `filename`, and a
+    /// path tracer with a sampler taking `spp` samples per pixel.
+    fn sphere_scene(material: Arc<Material>,
+                    spp: usize,
+                    filename: &str)
+                    -> (Arc<Scene>, PerspectiveCamera, PathIntegrator, Box<Sampler>) {
+        let sphere = Sphere::new(Transform::default(), 1.0, -1.0, 1.0, 360.0, false);
+        let prim = GeometricPrimitive {
+            shape: Arc::new(sphere),
+            area_light: None,
+            material: Some(material),
+        };
+        let light: Arc<Light> = Arc::new(PointLight::new(Point3f::new(2.0, 2.0, -2.0),
+                                                         Spectrum::grey(10.0)));
+        let mut scene = Scene::new(Arc::new(prim), vec![light]);
+        scene.background = Spectrum::grey(0.2);
+        let scene = Arc::new(scene);
+
+        let film = Film::new(Point2i::new(16, 12),
+                             Bounds2f::from_points(&Point2f::new(0.0, 0.0),
+                                                   &Point2f::new(1.0, 1.0)),
+                             Box::new(BoxFilter::new(0.5, 0.5)),
+                             35.0,
+                             filename,
+                             1.0,
+                             f32::INFINITY);
+        let screen = Bounds2f::from_points(&Point2f::new(-4.0 / 3.0, -1.0),
+                                           &Point2f::new(4.0 / 3.0, 1.0));
+        let camera = PerspectiveCamera::new(Transform::translate(&Vector3f::new(0.0, 0.0, -4.0)),
+                                            screen,
+                                            0.0,
+                                            1e6,
+                                            40.0,
+                                            Box::new(film));
+        let integrator =
+            PathIntegrator::new(camera.get_film().get_sample_bounds(),
+                                5,
+                                1.0,
+                                "uniform".into(),
+                                false);
+        let sampler: Box<Sampler> = Box::new(ZeroTwoSequence::new(spp, 4));
+
+        (scene, camera, integrator, sampler)
+    }
+
+    #[test]
+    fn test_renders_are_reproducible() {
+        ::init_stats();
+        let mut options = Options::default();
+        options.quiet = true;
+        options.num_threads = 1;
+        let reference = render_sphere(matte(), &options, 16);
+
+        // Neither the number of threads nor the tile size change the result
+        options.num_threads = 3;
+        assert!(render_sphere(matte(), &options, 16) == reference);
+        assert!(render_sphere(matte(), &options, 5) == reference);
+
+        options.seed = 42;
+        assert!(render_sphere(matte(), &options, 5) != reference);
+    }
+
+    #[test]
+    fn test_passes_accumulate_samples() {
+        ::init_stats();
+        let mut options = Options::default();
+        options.quiet = true;
+        options.passes = Some(4);
+        let filename = env::temp_dir().join("rustracer-test-passes.png");
+        let (scene, camera, mut integrator, mut sampler) =
+            sphere_scene(matte(), 1, filename.to_str().unwrap());
+
+        let status = render(scene,
+                            &mut integrator,
+                            &camera,
+                            &options,
+                            &mut sampler,
+                            8,
+                            Box::new(NoopDisplayUpdater {}),
+                            None,
+                            None)
+                .unwrap();
+        assert_eq!(status, RenderStatus::Completed { passes: 4 });
+        assert!(filename.exists());
+        let _ = fs::remove_file(&filename);
+
+        // Every pass took one sample per pixel
+        let film = camera.get_film();
+        for p in &film.cropped_pixel_bounds {
+            assert_eq!(film.get_pixel_stats(&p).count(), 4);
+        }
+    }
+
+    #[test]
+    fn test_render_time_limits_passes() {
+        ::init_stats();
+        let mut options = Options::default();
+        options.quiet = true;
+        options.render_time = Some(Duration::from_millis(1));
+        let filename = env::temp_dir().join("rustracer-test-render-time.png");
+        let (scene, camera, mut integrator, mut sampler) =
+            sphere_scene(matte(), 1, filename.to_str().unwrap());
+
+        let start = Instant::now();
+        let status = render(scene,
+                            &mut integrator,
+                            &camera,
+                            &options,
+                            &mut sampler,
+                            4,
+                            Box::new(NoopDisplayUpdater {}),
+                            None,
+                            None)
+                .unwrap();
+        let passes = match status {
+            RenderStatus::Completed { passes } => passes,
+            RenderStatus::Cancelled => panic!("the render was cancelled"),
+        };
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(filename.exists());
+        let _ = fs::remove_file(&filename);
+
+        // The first pass is always complete, and the last one may have been cut short
+        assert!(passes >= 1);
+        let film = camera.get_film();
+        for p in &film.cropped_pixel_bounds {
+            let count = film.get_pixel_stats(&p).count();
+            assert!(count >= u64::from(passes) && count <= u64::from(passes) + 1,
+                    "{} samples after {} passes",
+                    count,
+                    passes);
+        }
+    }
+
+    /// Wraps a camera, scaling the weight of the rays it generates.
+    struct WeightedCamera {
+        camera: PerspectiveCamera,
+        weight: f32,
+    }
+
+    impl Camera for WeightedCamera {
+        fn get_film(&self) -> &Film {
+            self.camera.get_film()
+        }
+
+        fn camera_to_world(&self) -> &Transform {
+            self.camera.camera_to_world()
+        }
+
+        fn set_camera_to_world(&mut self, camera_to_world: Transform) {
+            self.camera.set_camera_to_world(camera_to_world);
+        }
+
+        fn generate_ray(&self, sample: &CameraSample) -> (Ray, f32) {
+            let (ray, weight) = self.camera.generate_ray(sample);
+            (ray, weight * self.weight)
+        }
+
+        fn generate_ray_differential(&self, sample: &CameraSample) -> (Ray, f32) {
+            let (ray, weight) = self.camera.generate_ray_differential(sample);
+            (ray, weight * self.weight)
+        }
+    }
+
+    fn render_with_ray_weight(weight: f32) -> Vec<f32> {
+        let mut options = Options::default();
+        options.quiet = true;
+        let (scene, camera, mut integrator, mut sampler) = sphere_scene(matte(), 4, "test.exr");
+        let camera = WeightedCamera { camera, weight };
+        integrator.preprocess(Arc::clone(&scene), &mut sampler);
+        render_tiles(&scene,
+                     &integrator,
+                     &camera,
+                     &options,
+                     &sampler,
+                     8,
+                     (0, 1),
+                     &ProgressBarObserver::new(&options),
+                     &AtomicBool::new(false),
+                     None);
+        camera.get_film().get_rgb()
+    }
+
+    #[test]
+    fn test_ray_weight_scales_samples() {
+        ::init_stats();
+        let reference = render_with_ray_weight(1.0);
+        let half = render_with_ray_weight(0.5);
+        assert!(reference.iter().any(|v| *v > 0.0));
+        for (r, h) in reference.iter().zip(half.iter()) {
+            assert!((h - 0.5 * r).abs() <= 1e-5 * r.abs(), "{} is not half of {}", h, r);
+        }
+        assert!(render_with_ray_weight(0.0).iter().all(|v| *v == 0.0));
+    }
+
+    /// Records the progress notifications it receives.
+    #[derive(Default)]
+    struct RecordingObserver {
+        calls: Mutex<Vec<(usize, usize)>>,
+    }
+
+    impl ProgressObserver for RecordingObserver {
+        fn on_tile_complete(&self, completed: usize, total: usize) {
+            self.calls.lock().push((completed, total));
+        }
+    }
+
+    #[test]
+    fn test_eta_decreases_as_tiles_complete() {
+        let start = Instant::now();
+        let eta = EtaEstimator::new(start, None);
+        assert_eq!(eta.eta(start, 0, 10), None);
+        // Tiles completing every 2 seconds
+        let etas: Vec<Duration> = (1..11)
+            .map(|i| eta.eta(start + Duration::from_secs(2 * i as u64), i, 10).unwrap())
+            .collect();
+        for (i, e) in etas.iter().enumerate() {
+            let expected = 2.0 * (9 - i) as f64;
+            let e = e.as_secs() as f64 + f64::from(e.subsec_nanos()) * 1e-9;
+            assert!((e - expected).abs() < 1e-6, "{} instead of {}", e, expected);
+        }
+        assert!(etas.windows(2).all(|w| w[1] < w[0]));
+        assert_eq!(etas[9], Duration::from_secs(0));
+
+        // A slower tile pushes the estimate back up
+        let slow = eta.eta(start + Duration::from_secs(20), 2, 10).unwrap();
+        assert_eq!(slow, Duration::from_secs(80));
+    }
+
+    #[test]
+    fn test_eta_counts_down_to_deadline() {
+        let start = Instant::now();
+        let eta = EtaEstimator::new(start, Some(start + Duration::from_secs(60)));
+        // However many tiles there are, the render stops at the deadline
+        assert_eq!(eta.eta(start, 0, 10), Some(Duration::from_secs(60)));
+        assert_eq!(eta.eta(start + Duration::from_secs(15), 9, 10),
+                   Some(Duration::from_secs(45)));
+        // Once the deadline has passed, the tiles in progress are the only thing left
+        assert_eq!(eta.eta(start + Duration::from_secs(61), 30, 40),
+                   Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn test_progress_is_reported_for_every_tile() {
+        ::init_stats();
+        let mut options = Options::default();
+        options.quiet = true;
+        options.num_threads = 3;
+        let observer = RecordingObserver::default();
+        // The 16x12 image is split in 4x3 tiles of 5 pixels
+        render_sphere_with(matte(), &options, 5, &observer, &AtomicBool::new(false));
+
+        let mut calls = observer.calls.lock().clone();
+        calls.sort();
+        let expected: Vec<(usize, usize)> = (1..13).map(|i| (i, 12)).collect();
+        assert_eq!(calls, expected);
+    }
+
+    /// Wraps an integrator, recording the pixel being debugged (see `debugged_pixel()`) each time
+    /// it computes the radiance of a camera sample.
+    struct DebuggedPixelRecorder {
+        integrator: PathIntegrator,
+        pixels: Mutex<Vec<Option<Point2i>>>,
+    }
+
+    impl SamplerIntegrator for DebuggedPixelRecorder {
+        fn pixel_bounds(&self) -> &Bounds2i {
+            self.integrator.pixel_bounds()
+        }
+
+        fn preprocess(&mut self, scene: Arc<Scene>, sampler: &mut Box<Sampler>) {
+            self.integrator.preprocess(scene, sampler);
+        }
+
+        fn li(&self,
+              scene: &Scene,
+              ray: &mut Ray,
+              sampler: &mut Box<Sampler>,
+              arena: &Allocator,
+              depth: u32)
+              -> Spectrum {
+            self.pixels.lock().push(debugged_pixel());
+            self.integrator.li(scene, ray, sampler, arena, depth)
+        }
+    }
+
+    #[test]
+    fn test_debug_pixel_only_logs_that_pixel() {
+        ::init_stats();
+        let mut options = Options::default();
+        options.quiet = true;
+        options.num_threads = 3;
+        options.debug_pixel = Some(Point2i::new(7, 5));
+        let (scene, camera, integrator, mut sampler) = sphere_scene(matte(), 4, "test.exr");
+        let mut recorder = DebuggedPixelRecorder {
+            integrator,
+            pixels: Mutex::new(Vec::new()),
+        };
+        recorder.preprocess(Arc::clone(&scene), &mut sampler);
+        render_tiles(&scene,
+                     &recorder,
+                     &camera,
+                     &options,
+                     &sampler,
+                     5,
+                     (0, 1),
+                     &ProgressBarObserver::new(&options),
+                     &AtomicBool::new(false),
+                     None);
+
+        // pixel_debug! only logs for the samples of the debugged pixel
+        let pixels = recorder.pixels.lock();
+        assert_eq!(pixels.len(), 16 * 12 * 4);
+        let debugged: Vec<_> = pixels.iter().filter_map(|p| *p).collect();
+        assert_eq!(debugged, vec![Point2i::new(7, 5); 4]);
+    }
+
+    #[test]
+    fn test_cancelled_render_stops_early() {
+        ::init_stats();
+        let mut options = Options::default();
+        options.quiet = true;
+        options.num_threads = 2;
+        let observer = RecordingObserver::default();
+        let pixels = render_sphere_with(matte(), &options, 5, &observer, &AtomicBool::new(true));
+
+        assert!(observer.calls.lock().is_empty());
+        assert!(pixels.iter().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn test_nan_samples_are_discarded() {
+        ::init_stats();
+        let mut options = Options::default();
+        options.quiet = true;
+        let nan_samples = || {
+            stats::STAT_ACCUMULATOR
+                .get()
+                .lock()
+                .counter("Integrator/NaN samples")
+        };
+        let before = nan_samples();
+        let pixels = render_sphere(Arc::new(NaNMaterial), &options, 16);
+
+        assert!(pixels.iter().all(|v| v.is_finite()));
+        // The render threads report their stats when they're done
+        assert!(nan_samples() > before);
+    }
 
-    camera.get_film().write_image()
+    #[test]
+    fn test_check_radiance() {
+        ::init_stats();
+        let p = Point2i::new(1, 2);
+        let l = Spectrum::rgb(0.1, 0.2, 0.3);
+        assert_eq!(check_radiance(l, &p, 0), l);
+        assert!(check_radiance(Spectrum::rgb(0.1, f32::NAN, 0.3), &p, 0).is_black());
+        assert!(check_radiance(Spectrum::rgb(f32::INFINITY, 0.2, 0.3), &p, 0).is_black());
+        assert!(check_radiance(Spectrum::grey(-1.0), &p, 0).is_black());
+    }
 }
@@ -52,6 +52,17 @@ impl RNG {
     }
 }
 
+/// Hash a 64 bit integer, so that close inputs give unrelated outputs.
+pub fn mix_bits(v: u64) -> u64 {
+    let mut v = v;
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5_d329_728e_a185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81da_def4_bc2d_d44d);
+    v ^= v >> 33;
+    v
+}
+
 impl Default for RNG {
     fn default() -> RNG {
         RNG::new()
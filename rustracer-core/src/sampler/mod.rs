@@ -3,22 +3,45 @@ use camera::CameraSample;
 
 pub mod zerotwosequence;
 pub mod lowdiscrepancy;
+pub mod random;
 
 pub trait Sampler: Send + Sync {
     fn start_pixel(&mut self, p: &Point2i);
     fn get_1d(&mut self) -> f32;
     fn get_2d(&mut self) -> Point2f;
     fn get_camera_sample(&mut self, p_raster: &Point2i) -> CameraSample;
-    fn request_1d_array(&mut self, n: usize);
-    fn request_2d_array(&mut self, n: usize);
-    fn round_count(&self, count: usize) -> usize;
-    fn get_1d_array(&mut self, n: usize) -> Option<&[f32]>;
-    fn get_2d_array(&mut self, n: usize) -> Option<&[Point2f]>;
+    /// Request an array of `n` 1D samples for each pixel sample, to be retrieved with
+    /// `get_1d_array()`. Must be called before rendering starts. Samplers that can't generate
+    /// arrays ignore the request, and callers then fall back to `get_1d()`.
+    fn request_1d_array(&mut self, _n: usize) {}
+    /// 2D equivalent of `request_1d_array()`.
+    fn request_2d_array(&mut self, _n: usize) {}
+    /// Round a requested array size to one the sampler can generate well distributed samples for.
+    fn round_count(&self, count: usize) -> usize {
+        count
+    }
+    /// Return the next requested array of `n` 1D samples for the current pixel sample, or `None`
+    /// if all the requested arrays have been consumed (or none were generated).
+    fn get_1d_array(&mut self, _n: usize) -> Option<&[f32]> {
+        None
+    }
+    /// 2D equivalent of `get_1d_array()`.
+    fn get_2d_array(&mut self, _n: usize) -> Option<&[Point2f]> {
+        None
+    }
     fn start_next_sample(&mut self) -> bool;
     fn reseed(&mut self, seed: u64);
     fn spp(&self) -> usize;
     fn box_clone(&self) -> Box<Sampler>;
+    /// Clone the sampler, with its random numbers generated from the sequence `seed`.
+    fn clone_with_seed(&self, seed: u64) -> Box<Sampler> {
+        let mut sampler = self.box_clone();
+        sampler.reseed(seed);
+        sampler
+    }
     fn current_sample_number(&self) -> usize;
+    /// Name of the sampler, as given in scene files.
+    fn name(&self) -> &'static str;
 }
 
 impl Clone for Box<Sampler> {
@@ -0,0 +1,182 @@
+use {Options, Point2f, Point2i};
+use camera::CameraSample;
+use paramset::ParamSet;
+use rng::{mix_bits, RNG};
+use sampler::Sampler;
+
+/// A sampler returning independent uniform random samples, without any stratification. It
+/// converges more slowly than the other samplers, but doesn't introduce any correlation between
+/// samples or dimensions, which makes it a good reference.
+///
+/// Each pixel sample draws from its own random sequence, derived from the seed and the sample
+/// index. The camera sample of a given pixel sample is thus the same whatever the number of random
+/// values consumed by the integrator for the previous samples, so that renders of the same scene
+/// with different integrators (e.g. beauty and albedo passes) line up sample for sample.
+#[derive(Clone)]
+pub struct RandomSampler {
+    spp: usize,
+    current_pixel_sample_index: usize,
+    seed: u64,
+    rng: RNG,
+}
+
+impl RandomSampler {
+    pub fn new(spp: usize) -> RandomSampler {
+        RandomSampler {
+            spp,
+            current_pixel_sample_index: 0,
+            seed: 0,
+            rng: RNG::new(),
+        }
+    }
+
+    fn start_sample(&mut self) {
+        let index = self.current_pixel_sample_index as u64;
+        self.rng.set_sequence(mix_bits(self.seed ^ mix_bits(index)));
+    }
+
+    pub fn create(ps: &mut ParamSet, options: &Options) -> Box<Sampler> {
+        let mut nsamples = ps.find_one_int("pixelsamples", 4);
+        if let Some(spp) = options.spp_override {
+            nsamples = spp as i32;
+        } else if options.quick_render {
+            nsamples = 1;
+        }
+        Box::new(Self::new(i32::max(1, nsamples) as usize))
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn start_pixel(&mut self, _p: &Point2i) {
+        self.current_pixel_sample_index = 0;
+        self.start_sample();
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.uniform_f32()
+    }
+
+    fn get_2d(&mut self) -> Point2f {
+        let x = self.rng.uniform_f32();
+        let y = self.rng.uniform_f32();
+        Point2f::new(x, y)
+    }
+
+    fn get_camera_sample(&mut self, p_raster: &Point2i) -> CameraSample {
+        let p_film = Point2f::from(*p_raster) + self.get_2d();
+        let time = self.get_1d();
+        let p_lens = self.get_2d();
+
+        CameraSample {
+            p_film: p_film,
+            p_lens: p_lens,
+            time: time,
+        }
+    }
+
+    fn start_next_sample(&mut self) -> bool {
+        self.current_pixel_sample_index += 1;
+        self.start_sample();
+        self.current_pixel_sample_index < self.spp
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng.set_sequence(seed);
+    }
+
+    fn spp(&self) -> usize {
+        self.spp
+    }
+
+    fn box_clone(&self) -> Box<Sampler> {
+        Box::new(self.clone())
+    }
+
+    fn current_sample_number(&self) -> usize {
+        self.current_pixel_sample_index
+    }
+
+    fn name(&self) -> &'static str {
+        "random"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_are_uniform_and_independent() {
+        let n = 100_000;
+        let mut sampler = RandomSampler::new(n);
+        sampler.reseed(7);
+        sampler.start_pixel(&Point2i::new(0, 0));
+        // Centered samples for 3 dimensions: one 1D and one 2D
+        let mut samples = Vec::with_capacity(n);
+        loop {
+            let u = sampler.get_1d();
+            let p = sampler.get_2d();
+            assert!([u, p.x, p.y].iter().all(|v| *v >= 0.0 && *v < 1.0));
+            samples.push([u as f64 - 0.5, p.x as f64 - 0.5, p.y as f64 - 0.5]);
+            if !sampler.start_next_sample() {
+                break;
+            }
+        }
+        assert_eq!(samples.len(), n);
+
+        let mean = |f: &Fn(&[f64; 3]) -> f64| samples.iter().map(f).sum::<f64>() / n as f64;
+        for i in 0..3 {
+            // Uniform in [0, 1): mean 1/2 and variance 1/12
+            assert!(mean(&|s| s[i]).abs() < 0.01);
+            assert!((mean(&|s| s[i] * s[i]) - 1.0 / 12.0).abs() < 0.005);
+            // Uncorrelated with the other dimensions
+            for j in i + 1..3 {
+                let correlation = mean(&|s| s[i] * s[j]) * 12.0;
+                assert!(correlation.abs() < 0.02,
+                        "dimensions {} and {} are correlated: {}",
+                        i,
+                        j,
+                        correlation);
+            }
+        }
+    }
+
+    /// Log of the camera samples of pixel `p`, for an integrator drawing `draws(i)` 2D samples
+    /// after the camera sample of pixel sample `i`.
+    fn camera_sample_log(sampler: &mut RandomSampler,
+                         p: &Point2i,
+                         draws: &Fn(usize) -> usize)
+                         -> Vec<(f32, f32, f32, f32, f32)> {
+        let mut log = Vec::new();
+        sampler.reseed(42);
+        sampler.start_pixel(p);
+        loop {
+            let cs = sampler.get_camera_sample(p);
+            log.push((cs.p_film.x, cs.p_film.y, cs.time, cs.p_lens.x, cs.p_lens.y));
+            let i = sampler.current_sample_number();
+            for _ in 0..draws(i) {
+                let _ = sampler.get_2d();
+            }
+            if !sampler.start_next_sample() {
+                break;
+            }
+        }
+        log
+    }
+
+    #[test]
+    fn test_camera_samples_do_not_depend_on_integrator() {
+        let mut sampler = RandomSampler::new(16);
+        let p = Point2i::new(3, 5);
+        // A path tracer, with paths of varying lengths, and an albedo pass drawing nothing more
+        let beauty = camera_sample_log(&mut sampler, &p, &|i| 3 + 7 * (i % 4));
+        let albedo = camera_sample_log(&mut sampler, &p, &|_| 0);
+        assert_eq!(beauty.len(), 16);
+        assert_eq!(beauty, albedo);
+        // ... while pixel samples still get different camera samples
+        for i in 1..beauty.len() {
+            assert_ne!(beauty[i], beauty[i - 1]);
+        }
+    }
+}
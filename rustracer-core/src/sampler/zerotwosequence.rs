@@ -1,6 +1,6 @@
 use num::Zero;
 
-use {Point2f, Point2i};
+use {Options, Point2f, Point2i};
 use camera::CameraSample;
 use paramset::ParamSet;
 use rng::RNG;
@@ -54,10 +54,14 @@ impl ZeroTwoSequence {
         }
     }
 
-    pub fn create(ps: &mut ParamSet) -> Box<Sampler> {
-        let nsamples = ps.find_one_int("pixelsamples", 16);
+    pub fn create(ps: &mut ParamSet, options: &Options) -> Box<Sampler> {
+        let mut nsamples = ps.find_one_int("pixelsamples", 16);
         let sd = ps.find_one_int("dimensions", 4);
-        // TODO quickrender
+        if let Some(spp) = options.spp_override {
+            nsamples = i32::max(1, spp as i32);
+        } else if options.quick_render {
+            nsamples = 1;
+        }
         Box::new(Self::new(nsamples as usize, sd as usize))
     }
 }
@@ -128,8 +132,8 @@ impl Sampler for ZeroTwoSequence {
         }
         assert_eq!(self.sample_1d_array_sizes[self.array_1d_offset], n);
         assert!(self.current_pixel_sample_index < self.spp);
-        let res = &self.sample_array_1d[self.array_1d_offset][(self.current_pixel_sample_index *
-                    n)..];
+        let start = self.current_pixel_sample_index * n;
+        let res = &self.sample_array_1d[self.array_1d_offset][start..start + n];
         self.array_1d_offset += 1;
         Some(res)
     }
@@ -140,8 +144,8 @@ impl Sampler for ZeroTwoSequence {
         }
         assert_eq!(self.sample_2d_array_sizes[self.array_2d_offset], n);
         assert!(self.current_pixel_sample_index < self.spp);
-        let res = &self.sample_array_2d[self.array_2d_offset][(self.current_pixel_sample_index *
-                    n)..];
+        let start = self.current_pixel_sample_index * n;
+        let res = &self.sample_array_2d[self.array_2d_offset][start..start + n];
         self.array_2d_offset += 1;
         Some(res)
     }
@@ -201,4 +205,112 @@ impl Sampler for ZeroTwoSequence {
     fn current_sample_number(&self) -> usize {
         self.current_pixel_sample_index
     }
+
+    fn name(&self) -> &'static str {
+        "02sequence"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requested_arrays() {
+        let mut sampler = ZeroTwoSequence::new(4, 2);
+        sampler.request_1d_array(2);
+        sampler.request_2d_array(8);
+        sampler.request_2d_array(1);
+
+        sampler.start_pixel(&Point2i::new(3, 7));
+        for _ in 0..sampler.spp() {
+            assert_eq!(sampler.get_1d_array(2).map(|a| a.len()), Some(2));
+            {
+                let a = sampler.get_2d_array(8).unwrap();
+                assert_eq!(a.len(), 8);
+                assert!(a.iter().all(|p| p.x >= 0.0 && p.x < 1.0 && p.y >= 0.0 && p.y < 1.0));
+            }
+            assert_eq!(sampler.get_2d_array(1).map(|a| a.len()), Some(1));
+            // Only the requested arrays are available
+            assert!(sampler.get_1d_array(2).is_none());
+            assert!(sampler.get_2d_array(8).is_none());
+            sampler.start_next_sample();
+        }
+    }
+
+    #[test]
+    fn test_arrays_are_stratified_across_pixel_samples() {
+        // The (0, 2)-sequence puts exactly one of the spp * n samples in each 1D stratum
+        let (spp, n) = (4, 8);
+        let mut sampler = ZeroTwoSequence::new(spp, 0);
+        sampler.request_1d_array(n);
+        sampler.start_pixel(&Point2i::new(0, 0));
+        let mut strata = vec![0; spp * n];
+        for _ in 0..spp {
+            for x in sampler.get_1d_array(n).unwrap() {
+                strata[(x * (spp * n) as f32) as usize] += 1;
+            }
+            sampler.start_next_sample();
+        }
+        assert!(strata.iter().all(|&c| c == 1), "{:?}", strata);
+    }
+
+    #[test]
+    fn test_clones_with_seeds() {
+        let sampler = ZeroTwoSequence::new(4, 2);
+        let samples = |seed| {
+            let mut sampler = sampler.clone_with_seed(seed);
+            sampler.start_pixel(&Point2i::new(5, 2));
+            (0..sampler.spp())
+                .map(|_| {
+                         let p = sampler.get_2d();
+                         sampler.start_next_sample();
+                         p
+                     })
+                .collect::<Vec<_>>()
+        };
+
+        // Same pixel, different seeds
+        assert_ne!(samples(1), samples(2));
+        assert_eq!(samples(1), samples(1));
+    }
+
+    /// Log of the camera samples of pixel `p`, for an integrator drawing `draws(i)` 2D samples
+    /// after the camera sample of pixel sample `i`.
+    fn camera_sample_log(sampler: &mut ZeroTwoSequence,
+                         p: &Point2i,
+                         draws: &Fn(usize) -> usize)
+                         -> Vec<(f32, f32, f32, f32, f32)> {
+        let mut log = Vec::new();
+        sampler.reseed(42);
+        sampler.start_pixel(p);
+        loop {
+            let cs = sampler.get_camera_sample(p);
+            log.push((cs.p_film.x, cs.p_film.y, cs.time, cs.p_lens.x, cs.p_lens.y));
+            let i = sampler.current_sample_number();
+            for _ in 0..draws(i) {
+                let _ = sampler.get_2d();
+            }
+            if !sampler.start_next_sample() {
+                break;
+            }
+        }
+        log
+    }
+
+    #[test]
+    fn test_camera_samples_do_not_depend_on_integrator() {
+        let mut sampler = ZeroTwoSequence::new(16, 4);
+        let p = Point2i::new(3, 5);
+        // A path tracer, with paths of varying lengths going past the sampled dimensions and onto
+        // the random generator, and an albedo pass drawing nothing more
+        let beauty = camera_sample_log(&mut sampler, &p, &|i| 3 + 7 * (i % 4));
+        let albedo = camera_sample_log(&mut sampler, &p, &|_| 0);
+        assert_eq!(beauty.len(), 16);
+        assert_eq!(beauty, albedo);
+        // ... while pixel samples still get different camera samples
+        for i in 1..beauty.len() {
+            assert_ne!(beauty[i], beauty[i - 1]);
+        }
+    }
 }
@@ -4,9 +4,11 @@ use {Point2f, Vector2f, Vector3f};
 
 mod distribution1d;
 mod distribution2d;
+mod spherical_rectangle;
 
 pub use self::distribution1d::Distribution1D;
 pub use self::distribution2d::Distribution2D;
+pub use self::spherical_rectangle::SphericalRectangle;
 
 const FRAC_PI_4: f32 = consts::FRAC_PI_2 / 2.0;
 
@@ -19,12 +21,29 @@ pub fn uniform_sample_sphere(u: &Point2f) -> Vector3f {
     Vector3f::new(r * phi.cos(), r * phi.sin(), z)
 }
 
+pub fn uniform_sample_hemisphere(u: &Point2f) -> Vector3f {
+    let z = u.x;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * consts::PI * u.y;
+
+    Vector3f::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_hemisphere_pdf() -> f32 {
+    0.5 * consts::FRAC_1_PI
+}
+
 pub fn cosine_sample_hemisphere(u: &Point2f) -> Vector3f {
     let d = concentric_sample_disk(u);
     let z = (1.0 - d.x * d.x - d.y * d.y).max(0.0).sqrt();
     Vector3f::new(d.x, d.y, z)
 }
 
+#[inline]
+pub fn cosine_hemisphere_pdf(cos_theta: f32) -> f32 {
+    cos_theta * consts::FRAC_1_PI
+}
+
 pub fn concentric_sample_disk(u: &Point2f) -> Point2f {
     // Map uniform random numbers to `[-1, 1]^2`
     let u_offset = 2.0 * *u - Vector2f::new(1.0, 1.0);
@@ -48,6 +67,16 @@ pub fn uniform_sample_triangle(u: &Point2f) -> Point2f {
     Point2f::new(1.0 - su0, u[1] * su0)
 }
 
+/// Sample a direction uniformly inside the cone of directions around +z whose angle to +z has a
+/// cosine of at least `cos_theta_max`.
+pub fn uniform_sample_cone(u: &Point2f, cos_theta_max: f32) -> Vector3f {
+    let cos_theta = (1.0 - u.x) + u.x * cos_theta_max;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * consts::PI * u.y;
+
+    Vector3f::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+}
+
 pub fn uniform_cone_pdf(cos_theta_max: f32) -> f32 {
     1.0 / (2.0 * consts::PI * (1.0 - cos_theta_max))
 }
@@ -0,0 +1,110 @@
+use std::f32::consts;
+
+use {clamp, Point2f, Point3f, Vector3f};
+
+/// Projection of a rectangle onto the unit sphere around a point, used to sample the rectangle
+/// uniformly with respect to solid angle.
+///
+/// See "An Area-Preserving Parametrization for Spherical Rectangles", by Ureña, Fajardo and
+/// King (2013).
+#[derive(Debug)]
+pub struct SphericalRectangle {
+    o: Point3f,
+    // Local reference frame: x and y are aligned with the rectangle's edges, and z points from
+    // the rectangle towards `o`.
+    x: Vector3f,
+    y: Vector3f,
+    z: Vector3f,
+    z0: f32,
+    x0: f32,
+    x1: f32,
+    y0: f32,
+    y1: f32,
+    b0: f32,
+    b1: f32,
+    k: f32,
+    solid_angle: f32,
+}
+
+impl SphericalRectangle {
+    /// Build the spherical rectangle for the rectangle with corner `s` and (orthogonal) edges
+    /// `ex` and `ey`, as seen from `o`.
+    pub fn new(o: &Point3f, s: &Point3f, ex: &Vector3f, ey: &Vector3f) -> SphericalRectangle {
+        let (ex_len, ey_len) = (ex.length(), ey.length());
+        let x = *ex / ex_len;
+        let y = *ey / ey_len;
+        let mut z = x.cross(&y);
+        let d = *s - *o;
+        let mut z0 = d.dot(&z);
+        if z0 > 0.0 {
+            z = -z;
+            z0 = -z0;
+        }
+        let x0 = d.dot(&x);
+        let y0 = d.dot(&y);
+        let x1 = x0 + ex_len;
+        let y1 = y0 + ey_len;
+
+        // Normals of the planes going through `o` and each edge of the rectangle
+        let v00 = Vector3f::new(x0, y0, z0);
+        let v01 = Vector3f::new(x0, y1, z0);
+        let v10 = Vector3f::new(x1, y0, z0);
+        let v11 = Vector3f::new(x1, y1, z0);
+        let n0 = v00.cross(&v10).normalize();
+        let n1 = v10.cross(&v11).normalize();
+        let n2 = v11.cross(&v01).normalize();
+        let n3 = v01.cross(&v00).normalize();
+        // Internal angles of the spherical rectangle
+        let g0 = clamp(-n0.dot(&n1), -1.0, 1.0).acos();
+        let g1 = clamp(-n1.dot(&n2), -1.0, 1.0).acos();
+        let g2 = clamp(-n2.dot(&n3), -1.0, 1.0).acos();
+        let g3 = clamp(-n3.dot(&n0), -1.0, 1.0).acos();
+        let k = 2.0 * consts::PI - g2 - g3;
+        let solid_angle = g0 + g1 - k;
+
+        SphericalRectangle {
+            o: *o,
+            x,
+            y,
+            z,
+            z0,
+            x0,
+            x1,
+            y0,
+            y1,
+            b0: n0.z,
+            b1: n2.z,
+            k,
+            solid_angle: if solid_angle.is_nan() { 0.0 } else { solid_angle.max(0.0) },
+        }
+    }
+
+    pub fn solid_angle(&self) -> f32 {
+        self.solid_angle
+    }
+
+    /// Return the point on the rectangle corresponding to the sample `u`. Points are distributed
+    /// uniformly with respect to solid angle, so their pdf is `1 / self.solid_angle()`.
+    pub fn sample(&self, u: &Point2f) -> Point3f {
+        // Compute the cosine of the angle to the x axis
+        let au = u.x * self.solid_angle + self.k;
+        let fu = (au.cos() * self.b0 - self.b1) / au.sin();
+        let cu = clamp(fu.signum() / (fu * fu + self.b0 * self.b0).sqrt(), -1.0, 1.0);
+        // Compute xu
+        let xu = -(cu * self.z0) / f32::max(1.0 - cu * cu, 1e-14).sqrt();
+        let xu = clamp(xu, self.x0, self.x1);
+        // Compute yv
+        let d = (xu * xu + self.z0 * self.z0).sqrt();
+        let h0 = self.y0 / (d * d + self.y0 * self.y0).sqrt();
+        let h1 = self.y1 / (d * d + self.y1 * self.y1).sqrt();
+        let hv = h0 + u.y * (h1 - h0);
+        let hv2 = hv * hv;
+        let yv = if hv2 < 1.0 - 1e-6 {
+            hv * d / (1.0 - hv2).sqrt()
+        } else {
+            self.y1
+        };
+
+        self.o + self.x * xu + self.y * yv + self.z * self.z0
+    }
+}
@@ -1,62 +1,624 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
+use Point3f;
 use bounds::Bounds3f;
 use interaction::SurfaceInteraction;
 use light::{Light, LightFlags};
+use medium::Medium;
 use primitive::Primitive;
 use ray::Ray;
+use sampler::Sampler;
+use spectrum::Spectrum;
 
 stat_counter!("Intersections/Regular ray intersection tests",
               n_intersection_tests);
 stat_counter!("Intersections/Shadow ray intersection tests",
               n_shadow_tests);
+stat_percent!("Intersections/Shadow rays blocked by the light's last occluder",
+              n_last_blocker_hits);
 pub fn init_stats() {
     n_intersection_tests::init();
     n_shadow_tests::init();
+    n_last_blocker_hits::init();
+}
+
+/// Default `Scene::ray_epsilon`, relative to the diagonal of the scene's bounds: about the
+/// spacing of floating-point coordinates at the scale of the scene.
+const RAY_EPSILON_SCALE: f32 = 1e-7;
+
+thread_local! {
+    /// For each light id, the index in the aggregate (see `Primitive::primitive()`) of the
+    /// primitive that last blocked a shadow ray towards that light on this thread.
+    static LAST_BLOCKERS: RefCell<HashMap<u32, usize>> = RefCell::new(HashMap::new());
 }
 
 pub struct Scene {
     pub lights: Vec<Arc<Light>>,
     pub infinite_lights: Vec<Arc<Light>>,
+    /// Radiance of rays escaping the scene when there is no infinite light
+    pub background: Spectrum,
+    /// Participating medium filling the whole scene, if any
+    pub medium: Option<Arc<Medium>>,
+    /// Test the primitive that last blocked a shadow ray towards a light first, before going
+    /// through the aggregate (see `intersect_p_light()`). Off by default; enabled with the
+    /// accelerator's "shadowcache" parameter.
+    pub shadow_cache: bool,
+    /// Use the shading geometry of the surfaces that are hit (see `Options::shading_normals`).
+    /// When unset, `intersect()` replaces it with their true geometry.
+    pub shading_normals: bool,
+    /// Length at the start of every ray that is ignored when intersecting it with the scene, on
+    /// top of the offset of spawned rays by the error bounds of their origin, to catch the
+    /// self-intersections those bounds miss. Defaults to a fraction of the scene's size, so it
+    /// works at any scale; overridden with the accelerator's "rayepsilon" parameter.
+    pub ray_epsilon: f32,
+    /// Number of shapes of each type (see `Shape::name()`) the scene was made of. The aggregate
+    /// doesn't keep track of them, so they are filled in by whoever builds the scene.
+    pub shape_counts: BTreeMap<String, usize>,
     aggregate: Arc<Primitive>,
+    world_sphere: (Point3f, f32),
 }
 
 impl Scene {
     pub fn new(aggregate: Arc<Primitive>,
                lights: Vec<Arc<Light>>)
                -> Scene {
-        let mut scene = Scene {
-            lights: Vec::new(),
-            infinite_lights: Vec::new(),
+        let infinite_lights = lights
+            .iter()
+            .filter(|l| l.flags().contains(LightFlags::INFINITE))
+            .cloned()
+            .collect();
+        let world_sphere = aggregate.world_bounds().bounding_sphere();
+        let ray_epsilon = if world_sphere.1.is_finite() {
+            RAY_EPSILON_SCALE * 2.0 * world_sphere.1
+        } else {
+            0.0
+        };
+        let scene = Scene {
+            lights: lights,
+            infinite_lights: infinite_lights,
+            background: Spectrum::black(),
+            medium: None,
+            shadow_cache: false,
+            shading_normals: true,
+            ray_epsilon,
+            shape_counts: BTreeMap::new(),
+            world_sphere,
             aggregate: aggregate,
         };
 
-        let mut infinite_lights = Vec::new();
-
-        for l in &lights {
+        // Let the lights set up their scene-dependent data now that the scene is complete
+        for l in &scene.lights {
             l.preprocess(&scene);
-            if l.flags().contains(LightFlags::INFINITE) {
-                infinite_lights.push(Arc::clone(&l));
-            }
         }
 
-        ::std::mem::replace(&mut scene.lights, lights);
-        ::std::mem::replace(&mut scene.infinite_lights, infinite_lights);
-
         scene
     }
 
     pub fn intersect(&self, ray: &mut Ray) -> Option<SurfaceInteraction> {
         n_intersection_tests::inc();
-        self.aggregate.intersect(ray)
+        let o = ray.o;
+        let dt = self.skip_ray_epsilon(ray);
+        let mut isect = if ray.t_max > 0.0 {
+            self.aggregate.intersect(ray)
+        } else {
+            None
+        };
+        ray.o = o;
+        ray.t_max += dt;
+        if !self.shading_normals {
+            if let Some(ref mut isect) = isect {
+                isect.use_geometric_shading();
+            }
+        }
+        isect
     }
 
     pub fn intersect_p(&self, ray: &Ray) -> bool {
         n_shadow_tests::inc();
-        self.aggregate.intersect_p(ray)
+        let mut ray = *ray;
+        self.skip_ray_epsilon(&mut ray);
+        ray.t_max > 0.0 && self.aggregate.intersect_p(&ray)
+    }
+
+    /// Move the origin of `ray` forward by `ray_epsilon`, shortening it accordingly. Return the
+    /// distance it was moved by, in units of the ray's parameter.
+    fn skip_ray_epsilon(&self, ray: &mut Ray) -> f32 {
+        let dt = self.ray_epsilon / ray.d.length();
+        ray.o = ray.at(dt);
+        ray.t_max -= dt;
+        dt
+    }
+
+    /// Like `intersect_p()`, for a shadow ray towards the light with id `light_id`. Neighbouring
+    /// shading points are often shadowed by the same occluder, so with `shadow_cache` set the
+    /// primitive that last blocked a shadow ray towards that light on this thread is tested
+    /// first.
+    pub fn intersect_p_light(&self, ray: &Ray, light_id: u32) -> bool {
+        if !self.shadow_cache {
+            return self.intersect_p(ray);
+        }
+        n_shadow_tests::inc();
+        let mut ray = *ray;
+        self.skip_ray_epsilon(&mut ray);
+        if ray.t_max <= 0.0 {
+            return false;
+        }
+        let ray = &ray;
+        let blocked_by_last = LAST_BLOCKERS.with(|b| match b.borrow().get(&light_id) {
+            Some(&idx) => {
+                n_last_blocker_hits::inc_total();
+                self.aggregate
+                    .primitive(idx)
+                    .map_or(false, |p| p.intersect_p(ray))
+            }
+            None => false,
+        });
+        if blocked_by_last {
+            n_last_blocker_hits::inc();
+            return true;
+        }
+        match self.aggregate.intersect_p_blocker(ray) {
+            (true, Some(idx)) => {
+                LAST_BLOCKERS.with(|b| b.borrow_mut().insert(light_id, idx));
+                true
+            }
+            (blocked, _) => blocked,
+        }
+    }
+
+    /// Like `intersect()`, but goes through surfaces without a material (which only mark the
+    /// boundaries of media) and also returns the beam transmittance of the media between the
+    /// ray's origin and the surface that was hit, or `ray.t_max` if nothing was hit.
+    pub fn intersect_tr(&self,
+                        ray: &mut Ray,
+                        sampler: &mut Box<Sampler>)
+                        -> (Option<SurfaceInteraction>, Spectrum) {
+        let target = if ray.t_max.is_finite() {
+            Some(ray.at(ray.t_max))
+        } else {
+            None
+        };
+        let mut tr = Spectrum::white();
+        loop {
+            let isect = self.intersect(ray);
+            if let Some(ref medium) = self.medium {
+                tr = tr * medium.tr(ray, sampler);
+            }
+            match isect {
+                None => return (None, tr),
+                Some(isect) => {
+                    if isect.primitive.and_then(|p| p.material()).is_some() {
+                        return (Some(isect), tr);
+                    }
+                    *ray = match target {
+                        Some(ref p) => isect.spawn_ray_to(p),
+                        None => isect.spawn_ray(&ray.d),
+                    };
+                }
+            }
+        }
     }
 
     pub fn world_bounds(&self) -> Bounds3f {
         self.aggregate.world_bounds()
     }
+
+    /// Center and radius of the sphere enclosing the scene's bounds, computed once when the
+    /// scene is created.
+    pub fn world_bounding_sphere(&self) -> (Point3f, f32) {
+        self.world_sphere
+    }
+
+    pub fn summary(&self) -> SceneSummary {
+        let mut lights = BTreeMap::new();
+        for l in &self.lights {
+            *lights.entry(l.name().to_owned()).or_insert(0) += 1;
+        }
+        SceneSummary {
+            shapes: self.shape_counts.clone(),
+            lights,
+            emissive_area: self.lights.iter().map(|l| l.area()).sum(),
+            world_bounds: self.world_bounds(),
+        }
+    }
+
+    /// Log a summary of what the scene is made of, to catch mistakes in its description (like a
+    /// light much bigger than intended) before spending time rendering it.
+    pub fn print_summary(&self) {
+        let summary = self.summary();
+        let describe = |counts: &BTreeMap<String, usize>| if counts.is_empty() {
+            "none".to_owned()
+        } else {
+            counts
+                .iter()
+                .map(|(name, n)| format!("{} {}", n, name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        info!("Scene summary:");
+        info!("  Shapes: {}", describe(&summary.shapes));
+        info!("  Lights: {}", describe(&summary.lights));
+        info!("  Total emissive area: {}", summary.emissive_area);
+        info!("  World bounds: {:?} - {:?}",
+              summary.world_bounds.p_min,
+              summary.world_bounds.p_max);
+    }
+
+    /// Radiance carried by a ray that escaped the scene: the sum of the infinite lights'
+    /// emission, or the background colour if there are none.
+    pub fn le(&self, ray: &Ray) -> Spectrum {
+        if self.infinite_lights.is_empty() {
+            self.background
+        } else {
+            self.infinite_lights
+                .iter()
+                .fold(Spectrum::black(), |c, l| c + l.le(ray))
+        }
+    }
+}
+
+/// What a scene is made of, as reported by `Scene::print_summary()`.
+#[derive(Debug, Clone)]
+pub struct SceneSummary {
+    /// Number of shapes of each type. Shapes in an object instance are counted once, however
+    /// many times the instance is used.
+    pub shapes: BTreeMap<String, usize>,
+    /// Number of lights of each type
+    pub lights: BTreeMap<String, usize>,
+    /// Total surface area of the area lights
+    pub emissive_area: f32,
+    pub world_bounds: Bounds3f,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use light_arena::MemoryArena;
+
+    use {Point2f, Transform, Vector3f};
+    use api::{Array, ParamListEntry, ParamType};
+    use bsdf::BxDFType;
+    use bvh::{BVH, SplitMethod};
+    use integrator::{estimate_direct, SamplerIntegrator, Whitted};
+    use light::{InfiniteAreaLight, PointLight};
+    use material::{MatteMaterial, TransportMode};
+    use medium::HomogeneousMedium;
+    use paramset::ParamSet;
+    use primitive::GeometricPrimitive;
+    use sampler::Sampler;
+    use sampler::zerotwosequence::ZeroTwoSequence;
+    use shapes::{Sphere, TriangleMesh};
+    use stats;
+    use test_utils::{create_material, CountingPrimitive};
+
+    fn sphere_scene(lights: Vec<Arc<Light>>) -> Scene {
+        let sphere = Sphere::new(Transform::default(), 1.0, -1.0, 1.0, 360.0, false);
+        let prim = GeometricPrimitive {
+            shape: Arc::new(sphere),
+            area_light: None,
+            material: None,
+        };
+        Scene::new(Arc::new(prim), lights)
+    }
+
+    #[test]
+    fn test_escaped_rays_return_background() {
+        ::init_stats();
+        let background = Spectrum::rgb(0.1, 0.2, 0.3);
+        let mut scene = sphere_scene(Vec::new());
+        scene.background = background;
+
+        let mut ray = Ray::new(Point3f::new(0.0, 0.0, -5.0), Vector3f::new(1.0, 0.0, 0.0));
+        assert!(scene.intersect(&mut ray).is_none());
+        assert_eq!(scene.le(&ray), background);
+
+        let integrator = Whitted::new(5);
+        let mut sampler: Box<Sampler> = Box::new(ZeroTwoSequence::new(1, 4));
+        let mut arena = MemoryArena::new(1);
+        let l = integrator.li(&scene, &mut ray, &mut sampler, &arena.allocator(), 0);
+        assert_eq!(l, background);
+    }
+
+    #[test]
+    fn test_sphere_scene_counts_sphere_intersections() {
+        ::init_stats();
+        let scene = sphere_scene(Vec::new());
+        let integrator = Whitted::new(5);
+        let mut sampler: Box<Sampler> = Box::new(ZeroTwoSequence::new(1, 4));
+        let mut arena = MemoryArena::new(1);
+        for y in 0..8 {
+            for x in 0..8 {
+                let o = Point3f::new(-2.0 + 0.5 * x as f32, -2.0 + 0.5 * y as f32, -5.0);
+                let mut ray = Ray::new(o, Vector3f::new(0.0, 0.0, 1.0));
+                integrator.li(&scene, &mut ray, &mut sampler, &arena.allocator(), 0);
+            }
+        }
+
+        // Only this thread's counters, as other tests may be intersecting triangles meanwhile
+        let acc = stats::thread_stats();
+        let (sphere_hits, sphere_tests) =
+            acc.percentage("Intersections/Ray-sphere intersection tests");
+        assert!(sphere_tests >= 64);
+        assert!(sphere_hits > 0 && sphere_hits < sphere_tests);
+        assert!(acc.percentage("Intersections/Ray-quadric intersection tests").1 >= sphere_tests);
+        assert_eq!(acc.percentage("Intersections/Ray-triangle intersection tests"),
+                   (0, 0));
+        assert_eq!(acc.percentage("Intersections/Ray-cylinder intersection tests"),
+                   (0, 0));
+    }
+
+    #[test]
+    fn test_infinite_lights_replace_background() {
+        ::init_stats();
+        let sky = Spectrum::rgb(0.5, 0.5, 1.0);
+        let light: Arc<Light> =
+            Arc::new(InfiniteAreaLight::new(Transform::new(), 1, sky, "does-not-exist.exr"));
+        let mut scene = sphere_scene(vec![light]);
+        scene.background = Spectrum::rgb(1.0, 0.0, 0.0);
+
+        let ray = Ray::new(Point3f::new(0.0, 0.0, -5.0), Vector3f::new(1.0, 0.0, 0.0));
+        let l = scene.le(&ray);
+        for i in 0..3 {
+            assert!((l[i] - sky[i]).abs() < 1e-5);
+        }
+    }
+
+    /// Direct lighting at the top of a matte unit sphere, lit by a point light `light_distance`
+    /// above it.
+    fn direct_lighting(light_distance: f32, medium: Option<Arc<Medium>>) -> Spectrum {
+        let material = create_material(Vec::new(), MatteMaterial::create);
+        let sphere = Sphere::new(Transform::default(), 1.0, -1.0, 1.0, 360.0, false);
+        let prim = GeometricPrimitive {
+            shape: Arc::new(sphere),
+            area_light: None,
+            material: Some(material),
+        };
+        let light: Arc<Light> = Arc::new(PointLight::new(Point3f::new(0.0,
+                                                                      0.0,
+                                                                      1.0 + light_distance),
+                                                         Spectrum::white()));
+        let mut scene = Scene::new(Arc::new(prim), vec![Arc::clone(&light)]);
+        scene.medium = medium;
+
+        let mut ray = Ray::new(Point3f::new(0.0, 0.0, 1.2), Vector3f::new(0.0, 0.0, -1.0));
+        let mut isect = scene.intersect(&mut ray).unwrap();
+        let mut arena = MemoryArena::new(1);
+        let alloc = arena.allocator();
+        isect.compute_scattering_functions(&ray, TransportMode::RADIANCE, true, &alloc);
+        let mut sampler: Box<Sampler> = Box::new(ZeroTwoSequence::new(1, 1));
+        estimate_direct(&isect,
+                        &Point2f::new(0.5, 0.5),
+                        &light,
+                        &Point2f::new(0.5, 0.5),
+                        &scene,
+                        &mut sampler)
+    }
+
+    #[test]
+    fn test_media_attenuate_direct_lighting() {
+        ::init_stats();
+        let sigma_a = 0.7;
+        let medium: Arc<Medium> = Arc::new(HomogeneousMedium::new(Spectrum::grey(sigma_a),
+                                                                  Spectrum::black()));
+        for d in &[0.5, 1.0, 2.0, 4.0] {
+            let clear = direct_lighting(*d, None);
+            let foggy = direct_lighting(*d, Some(Arc::clone(&medium)));
+            assert!(!clear.is_black());
+            let expected = (-sigma_a * d).exp();
+            for i in 0..3 {
+                assert!((foggy[i] / clear[i] - expected).abs() < 1e-4,
+                        "distance {}: {} vs {}",
+                        d,
+                        foggy[i] / clear[i],
+                        expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_intersect_tr_without_media() {
+        ::init_stats();
+        let scene = sphere_scene(Vec::new());
+        let mut sampler: Box<Sampler> = Box::new(ZeroTwoSequence::new(1, 1));
+
+        let mut ray = Ray::new(Point3f::new(0.0, 0.0, -5.0), Vector3f::new(1.0, 0.0, 0.0));
+        let (isect, tr) = scene.intersect_tr(&mut ray, &mut sampler);
+        assert!(isect.is_none());
+        assert_eq!(tr, Spectrum::white());
+    }
+
+    #[test]
+    fn test_bounding_sphere_encloses_primitives() {
+        ::init_stats();
+        let spheres: Vec<Arc<Primitive>> =
+            vec![(Vector3f::new(0.0, 0.0, 0.0), 1.0),
+                 (Vector3f::new(10.0, -3.0, 2.0), 0.5),
+                 (Vector3f::new(-4.0, 6.0, -20.0), 3.0)]
+                    .into_iter()
+                    .map(|(t, r)| {
+                        let sphere = Sphere::new(Transform::translate(&t), r, -r, r, 360.0, false);
+                        Arc::new(GeometricPrimitive {
+                                     shape: Arc::new(sphere),
+                                     area_light: None,
+                                     material: None,
+                                 }) as Arc<Primitive>
+                    })
+                    .collect();
+        let bvh = BVH::new(1, &spheres, SplitMethod::SAH);
+        let scene = Scene::new(Arc::new(bvh), Vec::new());
+
+        let (center, radius) = scene.world_bounding_sphere();
+        assert_eq!((center, radius), scene.world_bounds().bounding_sphere());
+        for prim in &spheres {
+            let b = prim.world_bounds();
+            for i in 0..8 {
+                let corner = b.corner(i);
+                assert!((corner - center).length() <= radius * (1.0 + 1e-5),
+                        "{:?} is outside the bounding sphere",
+                        corner);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ray_offsets_scale_with_scene_size() {
+        ::init_stats();
+        // Both the scene's ray epsilon and the error bounds secondary rays are offset by follow
+        // the scale of the scene
+        let offsets = |scale: f32| {
+            let sphere = Sphere::new(Transform::default(), scale, -scale, scale, 360.0, false);
+            let prim = GeometricPrimitive {
+                shape: Arc::new(sphere),
+                area_light: None,
+                material: None,
+            };
+            let scene = Scene::new(Arc::new(prim), Vec::new());
+            let mut ray = Ray::new(Point3f::new(0.3, 0.2, -5.0) * scale,
+                                   Vector3f::new(0.0, 0.0, 1.0));
+            let isect = scene.intersect(&mut ray).unwrap();
+            let reflected = isect.spawn_ray(&Vector3f::new(0.0, 0.0, -1.0));
+            assert!(!scene.intersect_p(&reflected));
+            let shadow = isect.spawn_ray_to(&(Point3f::new(0.3, 0.2, -10.0) * scale));
+            assert!(!scene.intersect_p(&shadow));
+            ((reflected.o - isect.hit.p).length(), scene.ray_epsilon)
+        };
+
+        let (small, small_epsilon) = offsets(1.0);
+        let (large, large_epsilon) = offsets(1000.0);
+        assert!(small > 0.0);
+        assert!(large / small > 500.0 && large / small < 2000.0,
+                "offsets {} and {}",
+                small,
+                large);
+        assert!(small_epsilon > 0.0);
+        assert!((large_epsilon / small_epsilon - 1000.0).abs() < 1.0,
+                "epsilons {} and {}",
+                small_epsilon,
+                large_epsilon);
+    }
+
+    #[test]
+    fn test_ray_epsilon_is_skipped() {
+        ::init_stats();
+        let mut scene = sphere_scene(Vec::new());
+        // A ray starting just outside of the sphere, towards it
+        let ray = Ray::new(Point3f::new(0.0, 0.0, -1.01), Vector3f::new(0.0, 0.0, 2.0));
+        let mut hit_ray = ray;
+        let isect = scene.intersect(&mut hit_ray).unwrap();
+        assert!((isect.hit.p.z + 1.0).abs() < 1e-4);
+        assert!(scene.intersect_p(&ray));
+
+        // With a larger epsilon, the near side of the sphere is too close to the ray's origin
+        scene.ray_epsilon = 0.05;
+        assert!(!scene.intersect_p(&Ray::segment(ray.o, ray.d, 0.5)));
+        let mut hit_ray = ray;
+        let isect = scene.intersect(&mut hit_ray).unwrap();
+        assert!((isect.hit.p.z - 1.0).abs() < 1e-4);
+        // The ray is left untouched, and its parameter still counts from its origin
+        assert_eq!(hit_ray.o, ray.o);
+        assert!((hit_ray.t_max - 1.005).abs() < 1e-4, "t = {}", hit_ray.t_max);
+    }
+
+    #[test]
+    fn test_shading_normals_can_be_disabled() {
+        ::init_stats();
+        // A triangle in the z = 0 plane, with vertex normals tilted towards +x
+        let mut params = ParamSet::default();
+        params.init(vec![ParamListEntry::new(ParamType::Int,
+                                             "indices".to_owned(),
+                                             Array::NumArray(vec![0.0, 1.0, 2.0])),
+                         ParamListEntry::new(ParamType::Point3,
+                                             "P".to_owned(),
+                                             Array::NumArray(vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0,
+                                                                  0.0, 1.0, 0.0])),
+                         ParamListEntry::new(ParamType::Normal,
+                                             "N".to_owned(),
+                                             Array::NumArray(vec![1.0, 0.0, 1.0, 1.0, 0.0, 1.0,
+                                                                  1.0, 0.0, 1.0]))]);
+        let tris = TriangleMesh::create(&Transform::default(),
+                                        &Transform::default(),
+                                        false,
+                                        &mut params,
+                                        &HashMap::new());
+        let prim = GeometricPrimitive {
+            shape: Arc::clone(&tris[0]),
+            area_light: None,
+            material: Some(create_material(Vec::new(), MatteMaterial::create)),
+        };
+        let mut scene = Scene::new(Arc::new(prim), Vec::new());
+        // Above the triangle, but below the plane of its shading normal
+        let wi = Vector3f::new(-0.9, 0.0, 0.1).normalize();
+
+        let mut arena = MemoryArena::new(1);
+        for &shading_normals in &[true, false] {
+            scene.shading_normals = shading_normals;
+            let mut ray = Ray::new(Point3f::new(0.25, 0.25, 1.0), Vector3f::new(0.0, 0.0, -1.0));
+            let mut isect = scene.intersect(&mut ray).unwrap();
+            let alloc = arena.allocator();
+            isect.compute_scattering_functions(&ray, TransportMode::RADIANCE, true, &alloc);
+            // The BSDF only samples directions above its shading normal
+            let pdf = isect
+                .bsdf
+                .as_ref()
+                .unwrap()
+                .pdf(&isect.hit.wo, &wi, BxDFType::all());
+            if shading_normals {
+                assert!(isect.shading.n.dotn(&isect.hit.n) < 0.99);
+                assert_eq!(pdf, 0.0);
+            } else {
+                // Everything, including the cosine terms of the integrators, uses the true normal
+                assert_eq!(isect.shading.n, isect.hit.n);
+                assert_eq!(isect.shading.dpdu, isect.dpdu);
+                assert_eq!(isect.shading.dpdv, isect.dpdv);
+                assert!(pdf > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_last_blocker_is_tested_first() {
+        ::init_stats();
+        let sphere = |x: f32| {
+            Arc::new(CountingPrimitive::sphere(Vector3f::new(x, 0.0, 5.0),
+                                               1.0,
+                                               MatteMaterial::black()))
+        };
+        let left = sphere(0.0);
+        let right = sphere(3.0);
+        let prims: Vec<Arc<Primitive>> = vec![left.clone(), right.clone()];
+        let light_pos = Point3f::new(0.0, 0.0, 10.0);
+        let light: Arc<Light> = Arc::new(PointLight::new(light_pos, Spectrum::white()));
+        let mut scene = Scene::new(Arc::new(BVH::new(1, &prims, SplitMethod::SAH)),
+                                   vec![light.clone()]);
+        scene.shadow_cache = true;
+        let shadow_ray = |x: f32| {
+            let o = Point3f::new(x, 0.0, 0.0);
+            let mut ray = Ray::new(o, light_pos - o);
+            ray.t_max = 1.0 - 1e-4;
+            ray
+        };
+        let test = |x: f32| {
+            let ray = shadow_ray(x);
+            let blocked = scene.intersect_p_light(&ray, light.id());
+            // The cache never changes the answer
+            assert_eq!(blocked, scene.intersect_p(&ray));
+            left.take_shadow_tests();
+            right.take_shadow_tests();
+            blocked
+        };
+
+        // Nothing is cached yet, so the BVH finds the blocker...
+        assert!(test(0.0));
+        // ... which is then tried first, and alone, for a nearby ray
+        assert!(scene.intersect_p_light(&shadow_ray(0.1), light.id()));
+        assert_eq!((left.take_shadow_tests(), right.take_shadow_tests()), (1, 0));
+
+        // When the cached blocker misses, the BVH finds the new one
+        assert!(test(6.0));
+        assert!(scene.intersect_p_light(&shadow_ray(5.9), light.id()));
+        assert_eq!((left.take_shadow_tests(), right.take_shadow_tests()), (0, 1));
+        assert!(!test(-6.0));
+    }
 }
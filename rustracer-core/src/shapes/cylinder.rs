@@ -8,7 +8,12 @@ use efloat::{solve_quadratic, EFloat};
 use interaction::{Interaction, SurfaceInteraction};
 use paramset::ParamSet;
 use ray::Ray;
-use shapes::Shape;
+use shapes::{n_quadric_hits, Shape};
+
+stat_percent!("Intersections/Ray-cylinder intersection tests", n_hits);
+pub fn init_stats() {
+    n_hits::init();
+}
 
 #[derive(Debug)]
 pub struct Cylinder {
@@ -23,29 +28,48 @@ pub struct Cylinder {
 }
 
 impl Cylinder {
+    pub fn new(object_to_world: Transform,
+               radius: f32,
+               z_min: f32,
+               z_max: f32,
+               phi_max: f32,
+               reverse_orientation: bool)
+               -> Cylinder {
+        Cylinder {
+            world_to_object: object_to_world.inverse(),
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
+            object_to_world,
+            radius,
+            z_min: z_min.min(z_max),
+            z_max: z_min.max(z_max),
+            phi_max: clamp(phi_max, 0.0, 360.0).to_radians(),
+            reverse_orientation,
+        }
+    }
+
     pub fn create(object_to_world: &Transform,
                   reverse_orientation: bool,
                   params: &mut ParamSet)
                   -> Arc<Shape> {
         let radius = params.find_one_float("radius", 1.0);
-        let z_min = params.find_one_float("z_min", -1.0);
-        let z_max = params.find_one_float("z_max", 1.0);
-        let phi_max = params.find_one_float("phi_max", 360.0);
-
-        Arc::new(Cylinder {
-                     object_to_world: object_to_world.clone(),
-                     world_to_object: object_to_world.inverse(),
-                     radius,
-                     z_min,
-                     z_max,
-                     phi_max: clamp(phi_max, 0.0, 360.0).to_radians(),
-                     reverse_orientation,
-                     transform_swaps_handedness: object_to_world.swaps_handedness(),
-                 })
+        let z_min = params.find_one_float("zmin", -1.0);
+        let z_max = params.find_one_float("zmax", 1.0);
+        let phi_max = params.find_one_float("phimax", 360.0);
+
+        Arc::new(Cylinder::new(object_to_world.clone(),
+                               radius,
+                               z_min,
+                               z_max,
+                               phi_max,
+                               reverse_orientation))
     }
 }
 
 impl Shape for Cylinder {
+    fn name(&self) -> &'static str {
+        "Cylinder"
+    }
+
     fn object_bounds(&self) -> Bounds3f {
         Bounds3f::from_points(&Point3f::new(-self.radius, -self.radius, self.z_min),
                               &Point3f::new(self.radius, self.radius, self.z_max))
@@ -57,6 +81,9 @@ impl Shape for Cylinder {
 
     #[allow(non_snake_case)]
     fn intersect(&self, r: &Ray) -> Option<(SurfaceInteraction, f32)> {
+        n_hits::inc_total();
+        n_quadric_hits::inc_total();
+
         // Transform ray to object space
         let (ray, o_err, d_err) = r.transform(&self.world_to_object);
 
@@ -126,7 +153,7 @@ impl Shape for Cylinder {
 
             // Find parametric representation of cylinder hit
             let u = phi / self.phi_max;
-            let v = (p_hit.z - self.z_min) / (self.z_max / self.z_min);
+            let v = (p_hit.z - self.z_min) / (self.z_max - self.z_min);
 
             // Compute cylinder dpdu and dpdv
             let dpdu = Vector3f::new(-self.phi_max * p_hit.y, self.phi_max * p_hit.x, 0.0);
@@ -165,6 +192,8 @@ impl Shape for Cylinder {
                                                 dndv,
                                                 self);
 
+            n_hits::inc();
+            n_quadric_hits::inc();
             Some((isect.transform(&self.object_to_world), t_shape_hit.into()))
         } else {
             None
@@ -172,6 +201,9 @@ impl Shape for Cylinder {
     }
 
     fn intersect_p(&self, r: &Ray) -> bool {
+        n_hits::inc_total();
+        n_quadric_hits::inc_total();
+
         // Transform ray to object space
         let (ray, o_err, d_err) = r.transform(&self.world_to_object);
 
@@ -239,6 +271,8 @@ impl Shape for Cylinder {
                 }
             }
 
+            n_hits::inc();
+            n_quadric_hits::inc();
             true
         } else {
             false
@@ -250,12 +284,12 @@ impl Shape for Cylinder {
     }
 
 
-    fn sample(&self, u: &Point2f) -> (Interaction, f32) {
+    fn sample(&self, u: &Point2f) -> (Interaction, Point2f, f32) {
         let z = lerp(u[0], self.z_min, self.z_max);
         let phi = u[1] * self.phi_max;
         let mut p_obj = Point3f::new(self.radius * phi.cos(), self.radius * phi.sin(), z);
         let mut n = (&self.object_to_world * &Normal3f::new(p_obj.x, p_obj.y, 0.0)).normalize();
-        if self.reverse_orientation {
+        if self.reverse_orientation ^ self.transform_swaps_handedness {
             n *= -1.0;
         }
         // Reproject p_obj to cylinder surface and compute p_obj_error
@@ -267,7 +301,7 @@ impl Shape for Cylinder {
             .transform_point_with_error(&p_obj, &p_obj_error);
 
         let it = Interaction::new(p, p_error, zero(), n);
-        (it, 1.0 / self.area())
+        (it, Point2f::new(u[1], u[0]), 1.0 / self.area())
     }
 
     fn reverse_orientation(&self) -> bool {
@@ -278,3 +312,58 @@ impl Shape for Cylinder {
         self.transform_swaps_handedness
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts;
+
+    /// A half cylinder covering y >= 0, between z = -1 and z = 1.
+    fn half_cylinder() -> Cylinder {
+        Cylinder::new(Transform::default(), 1.0, -1.0, 1.0, 180.0, false)
+    }
+
+    fn ray_along_x(y: f32, z: f32) -> Ray {
+        Ray::new(Point3f::new(-5.0, y, z), Vector3f::new(1.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn test_rays_at_clipped_away_half_miss() {
+        ::init_stats();
+        let cylinder = half_cylinder();
+        for &y in &[-0.1, -0.5, -0.9] {
+            let ray = ray_along_x(y, 0.0);
+            assert!(cylinder.intersect(&ray).is_none());
+            assert!(!cylinder.intersect_p(&ray));
+        }
+        // Above z_max
+        let ray = ray_along_x(0.5, 1.5);
+        assert!(cylinder.intersect(&ray).is_none());
+        assert!(!cylinder.intersect_p(&ray));
+    }
+
+    #[test]
+    fn test_rays_at_remaining_half_hit_outside() {
+        ::init_stats();
+        let cylinder = half_cylinder();
+        for &(y, z) in &[(0.1, 0.0), (0.5, 0.9), (0.9, -0.5)] {
+            let ray = ray_along_x(y, z);
+            assert!(cylinder.intersect_p(&ray));
+            let (isect, t_hit) = cylinder.intersect(&ray).unwrap();
+            let x = -(1.0 - y * y).sqrt();
+            assert!((t_hit - (x + 5.0)).abs() < 1e-4);
+            let p = isect.hit.p;
+            assert!((p.x - x).abs() < 1e-4 && (p.y - y).abs() < 1e-4 && (p.z - z).abs() < 1e-4);
+            // The normal points away from the axis, towards the ray origin
+            let n = isect.hit.n;
+            assert!((n.x - x).abs() < 1e-4 && (n.y - y).abs() < 1e-4 && n.z.abs() < 1e-4);
+            // dpdu goes around the axis and dpdv along it, both spanning the clipped surface
+            let phi = f32::atan2(y, x);
+            assert!((isect.uv.x - phi / consts::PI).abs() < 1e-4);
+            assert!((isect.uv.y - (z + 1.0) / 2.0).abs() < 1e-4);
+            assert!((isect.dpdu.x + consts::PI * y).abs() < 1e-4);
+            assert!((isect.dpdu.y - consts::PI * x).abs() < 1e-4);
+            assert!((isect.dpdv.z - 2.0).abs() < 1e-4);
+        }
+    }
+}
@@ -1,13 +1,18 @@
 use std::sync::Arc;
 use std::f32::consts;
 
-use {clamp, Normal3f, Point2f, Point3f, Transform, Vector3f};
+use {clamp, lerp, Normal3f, Point2f, Point3f, Transform, Vector2f, Vector3f};
 use bounds::Bounds3f;
 use interaction::{Interaction, SurfaceInteraction};
 use paramset::ParamSet;
 use ray::Ray;
 use sampling::concentric_sample_disk;
-use shapes::Shape;
+use shapes::{n_quadric_hits, Shape, MIN_SOLID_ANGLE};
+
+stat_percent!("Intersections/Ray-disk intersection tests", n_hits);
+pub fn init_stats() {
+    n_hits::init();
+}
 
 #[derive(Debug)]
 pub struct Disk {
@@ -59,10 +64,179 @@ impl Disk {
                            o2w.clone(),
                            reverse_orientation))
     }
+
+    /// World space normal of the disk, flipped the same way SurfaceInteraction::new() does so
+    /// that sampled points and ray hits agree on which side of the disk is the front.
+    fn normal(&self) -> Normal3f {
+        let n = (&self.object_to_world * &Normal3f::new(0.0, 0.0, 1.0)).normalize();
+        if self.reverse_orientation ^ self.transform_swaps_handedness {
+            -n
+        } else {
+            n
+        }
+    }
+
+    /// Whether the object space point `p`, lying in the disk's plane, is on the disk.
+    fn contains(&self, p: &Point3f) -> bool {
+        let dist2 = p.x * p.x + p.y * p.y;
+        if dist2 > self.radius * self.radius || dist2 < self.inner_radius * self.inner_radius {
+            return false;
+        }
+        let mut phi = p.y.atan2(p.x);
+        if phi < 0.0 {
+            phi += 2.0 * consts::PI;
+        }
+        phi <= self.phi_max
+    }
+
+    /// (u, v) coordinates of the object space point `p` on the disk, as computed by
+    /// `intersect()`.
+    fn uv(&self, p: &Point3f) -> Point2f {
+        let mut phi = p.y.atan2(p.x);
+        if phi < 0.0 {
+            phi += 2.0 * consts::PI;
+        }
+        let r = (p.x * p.x + p.y * p.y).sqrt();
+        Point2f::new(phi / self.phi_max,
+                     1.0 - (r - self.inner_radius) / (self.radius - self.inner_radius))
+    }
+
+    /// Polar parameterization of the disk as seen from `p` (see `DiskView`), or `None` if the
+    /// disk isn't a full disk, or is too small or seen too edge-on for it to be worth it.
+    fn view_from(&self, p: &Point3f) -> Option<DiskView> {
+        if self.inner_radius > 0.0 || self.phi_max < 2.0 * consts::PI {
+            return None;
+        }
+        let centre = &self.object_to_world * &Point3f::new(0.0, 0.0, self.height);
+        let ex = &self.object_to_world * &Vector3f::new(1.0, 0.0, 0.0);
+        let ey = &self.object_to_world * &Vector3f::new(0.0, 1.0, 0.0);
+        // Shearing and non-uniform scaling turn the disk into an ellipse
+        if ex.dot(&ey).abs() > 1e-4 * ex.length() * ey.length() ||
+           (ex.length() - ey.length()).abs() > 1e-4 * ex.length() {
+            return None;
+        }
+        let radius = self.radius * ex.length();
+        let (ux, uy) = (ex.normalize(), ey.normalize());
+        let n = ux.cross(&uy);
+        let pc = *p - centre;
+        let h = pc.dot(&n).abs();
+        let dist2 = pc.length_squared();
+        if h == 0.0 || consts::PI * radius * radius * h / (dist2 * dist2.sqrt()) < MIN_SOLID_ANGLE {
+            return None;
+        }
+        let foot = Point2f::new(pc.dot(&ux), pc.dot(&uy));
+        let foot_dist = (foot.x * foot.x + foot.y * foot.y).sqrt();
+        let (phi_min, phi_range) = if foot_dist < radius {
+            (0.0, 2.0 * consts::PI)
+        } else {
+            // Only the directions within the disk's tangents from the foot reach it
+            let half_angle = (radius / foot_dist).asin();
+            ((-foot.y).atan2(-foot.x) - half_angle, 2.0 * half_angle)
+        };
+
+        Some(DiskView {
+                 centre,
+                 ux,
+                 uy,
+                 radius,
+                 foot,
+                 h,
+                 phi_min,
+                 phi_range,
+             })
+    }
+}
+
+/// A full disk as seen from a point, parameterized by the angle `phi` around the foot of the
+/// perpendicular from the point to the disk's plane, and by the cosine of the angle `theta`
+/// between that perpendicular and the direction to the disk. The solid angle measure is then
+/// simply `dphi dcos(theta)`, so sampling `phi` uniformly and `cos(theta)` uniformly along the
+/// part of the disk in direction `phi` gives a closed-form pdf, and no samples miss the disk.
+struct DiskView {
+    /// World space centre of the disk, and orthonormal axes of its plane
+    centre: Point3f,
+    ux: Vector3f,
+    uy: Vector3f,
+    /// World space radius
+    radius: f32,
+    /// Foot of the perpendicular, in the plane's axes relative to the centre
+    foot: Point2f,
+    /// Distance from the point to the plane
+    h: f32,
+    /// Range of the angles around the foot that the disk covers
+    phi_min: f32,
+    phi_range: f32,
+}
+
+impl DiskView {
+    /// Cosines of theta where the half line from the foot in direction `phi` enters and leaves
+    /// the disk, along with the direction itself.
+    fn cos_theta_range(&self, phi: f32) -> (f32, f32, Vector2f) {
+        let e = Vector2f::new(phi.cos(), phi.sin());
+        let b = self.foot.x * e.x + self.foot.y * e.y;
+        let c = self.foot.x * self.foot.x + self.foot.y * self.foot.y - self.radius * self.radius;
+        let sqrt_discrim = (b * b - c).max(0.0).sqrt();
+        let r_near = (-b - sqrt_discrim).max(0.0);
+        let r_far = (-b + sqrt_discrim).max(0.0);
+
+        (self.cos_theta(r_near), self.cos_theta(r_far), e)
+    }
+
+    fn cos_theta(&self, r: f32) -> f32 {
+        self.h / (self.h * self.h + r * r).sqrt()
+    }
+
+    fn pdf(&self, phi: f32) -> f32 {
+        let (cos_near, cos_far, _) = self.cos_theta_range(phi);
+        if cos_near > cos_far {
+            1.0 / (self.phi_range * (cos_near - cos_far))
+        } else {
+            0.0
+        }
+    }
+
+    /// Sample a world space point on the disk, and its pdf with respect to solid angle.
+    fn sample(&self, u: &Point2f) -> (Point3f, f32) {
+        let phi = self.phi_min + u[0] * self.phi_range;
+        let (cos_near, cos_far, e) = self.cos_theta_range(phi);
+        let cos_theta = lerp(u[1], cos_near, cos_far);
+        let r = self.h * (1.0 - cos_theta * cos_theta).max(0.0).sqrt() / cos_theta;
+        let p = Point2f::new(self.foot.x + r * e.x, self.foot.y + r * e.y);
+
+        (self.centre + self.ux * p.x + self.uy * p.y, self.pdf(phi))
+    }
+
+    /// Pdf with respect to solid angle of sampling direction `wi` from `p`.
+    fn pdf_wi(&self, p: &Point3f, wi: &Vector3f) -> f32 {
+        // Find where the ray hits the disk's plane
+        let n = self.ux.cross(&self.uy);
+        let cos = wi.dot(&n);
+        if cos == 0.0 {
+            return 0.0;
+        }
+        let t = (self.centre - *p).dot(&n) / cos;
+        if t <= 0.0 {
+            return 0.0;
+        }
+        let pc = (*p + *wi * t) - self.centre;
+        let hit = Point2f::new(pc.dot(&self.ux), pc.dot(&self.uy));
+        if hit.x * hit.x + hit.y * hit.y > self.radius * self.radius {
+            return 0.0;
+        }
+
+        self.pdf((hit.y - self.foot.y).atan2(hit.x - self.foot.x))
+    }
 }
 
 impl Shape for Disk {
+    fn name(&self) -> &'static str {
+        "Disk"
+    }
+
     fn intersect(&self, r: &Ray) -> Option<(SurfaceInteraction, f32)> {
+        n_hits::inc_total();
+        n_quadric_hits::inc_total();
+
         // Transform ray to object space
         let (ray, _o_err, _d_err) = r.transform(&self.world_to_object);
         // Compute plane intersection for disk
@@ -93,7 +267,7 @@ impl Shape for Disk {
         let one_minus_v = (r_hit - self.inner_radius) / (self.radius - self.inner_radius);
         let v = 1.0 - one_minus_v;
         let dpdu = Vector3f::new(-self.phi_max * p_hit.y, self.phi_max * p_hit.x, 0.0);
-        let dpdv = Vector3f::new(p_hit.x, p_hit.y, 0.0) * (self.radius - self.inner_radius) / r_hit;
+        let dpdv = Vector3f::new(p_hit.x, p_hit.y, 0.0) * (self.inner_radius - self.radius) / r_hit;
         let dndu = Normal3f::new(0.0, 0.0, 0.0);
         let dndv = Normal3f::new(0.0, 0.0, 0.0);
 
@@ -114,6 +288,8 @@ impl Shape for Disk {
                                             self);
         // Update t_hit for quadric intersection
 
+        n_hits::inc();
+        n_quadric_hits::inc();
         Some((isect.transform(&self.object_to_world), t_shape_hit))
     }
 
@@ -131,21 +307,49 @@ impl Shape for Disk {
         Bounds3f::from_points(&p_min, &p_max)
     }
 
-    fn sample(&self, u: &Point2f) -> (Interaction, f32) {
-        let pd = concentric_sample_disk(u);
-        let p_obj = Point3f::new(pd.x * self.radius, pd.y * self.radius, self.height);
+    fn sample(&self, u: &Point2f) -> (Interaction, Point2f, f32) {
+        let p_obj = if self.inner_radius == 0.0 && self.phi_max >= 2.0 * consts::PI {
+            let pd = concentric_sample_disk(u);
+            Point3f::new(pd.x * self.radius, pd.y * self.radius, self.height)
+        } else {
+            // Sample the annulus sector uniformly by area: the area within radius r grows as r^2
+            let r2_min = self.inner_radius * self.inner_radius;
+            let r = (r2_min + u[0] * (self.radius * self.radius - r2_min)).sqrt();
+            let phi = u[1] * self.phi_max;
+            Point3f::new(r * phi.cos(), r * phi.sin(), self.height)
+        };
         let mut it = Interaction::empty();
-        it.n = (&self.object_to_world * &Normal3f::new(0.0, 0.0, 1.0)).normalize();
-        if self.reverse_orientation {
-            it.n = -it.n;
-        }
+        it.n = self.normal();
         let (p, p_err) = self.object_to_world
             .transform_point_with_error(&p_obj, &Vector3f::new(0.0, 0.0, 0.0));
         it.p = p;
         it.p_error = p_err;
         let pdf = 1.0 / self.area();
 
-        (it, pdf)
+        (it, self.uv(&p_obj), pdf)
+    }
+
+    fn sample_solid_angle(&self,
+                          si: &Interaction,
+                          u: &Point2f)
+                          -> Option<(Interaction, Point2f, f32)> {
+        let view = self.view_from(&si.p)?;
+        let (p_world, pdf) = view.sample(u);
+        let mut p_obj = &self.world_to_object * &p_world;
+        p_obj.z = self.height;
+        let mut it = Interaction::empty();
+        it.n = self.normal();
+        let (p, p_err) = self.object_to_world
+            .transform_point_with_error(&p_obj, &Vector3f::new(0.0, 0.0, 0.0));
+        it.p = p;
+        it.p_error = p_err;
+
+        Some((it, self.uv(&p_obj), pdf))
+    }
+
+    fn pdf_solid_angle(&self, si: &Interaction, wi: &Vector3f) -> Option<f32> {
+        let view = self.view_from(&si.p)?;
+        Some(view.pdf_wi(&si.p, wi))
     }
 
     fn area(&self) -> f32 {
@@ -160,3 +364,101 @@ impl Shape for Disk {
         self.transform_swaps_handedness
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn washer() -> Disk {
+        Disk::new(0.5, 1.0, 0.5, 360.0, Transform::default(), false)
+    }
+
+    fn hits(disk: &Disk, x: f32, y: f32) -> bool {
+        let ray = Ray::new(Point3f::new(x, y, -1.0), Vector3f::new(0.0, 0.0, 1.0));
+        disk.intersect(&ray).is_some()
+    }
+
+    #[test]
+    fn test_ray_through_annulus_hole_misses() {
+        ::init_stats();
+        let disk = washer();
+        assert!(!hits(&disk, 0.0, 0.0));
+        assert!(!hits(&disk, 0.3, -0.2));
+        assert!(hits(&disk, 0.75, 0.0));
+        assert!(hits(&disk, -0.5, 0.5));
+        assert!(!hits(&disk, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_partial_sweep() {
+        ::init_stats();
+        // A quarter disk covering x > 0, y > 0
+        let disk = Disk::new(0.0, 1.0, 0.0, 90.0, Transform::default(), false);
+        assert!(hits(&disk, 0.5, 0.5));
+        assert!(!hits(&disk, -0.5, 0.5));
+        assert!(!hits(&disk, 0.5, -0.5));
+        assert!((disk.area() - consts::PI / 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_intersection_geometry() {
+        ::init_stats();
+        let disk = washer();
+        let ray = Ray::new(Point3f::new(0.0, 0.75, -1.0), Vector3f::new(0.0, 0.0, 1.0));
+        let (isect, t_hit) = disk.intersect(&ray).unwrap();
+        // The hit is on the plane z = height
+        assert!((t_hit - 1.5).abs() < 1e-5);
+        assert!((isect.hit.p.z - 0.5).abs() < 1e-5);
+        // u goes around the disk and v from the outer to the inner radius
+        assert!((isect.uv.x - 0.25).abs() < 1e-5);
+        assert!((isect.uv.y - 0.5).abs() < 1e-5);
+        assert!((isect.dpdu.x + 2.0 * consts::PI * 0.75).abs() < 1e-4);
+        assert!(isect.dpdu.y.abs() < 1e-5);
+        assert!((isect.dpdv.y + 0.5).abs() < 1e-5);
+        assert!(isect.dpdv.x.abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_samples_are_on_the_shape() {
+        let disk = Disk::new(0.0, 1.0, 0.5, 90.0, Transform::default(), false);
+        for i in 0..8 {
+            for j in 0..8 {
+                let u = Point2f::new((i as f32 + 0.5) / 8.0, (j as f32 + 0.5) / 8.0);
+                let (it, _, pdf) = disk.sample(&u);
+                assert!(disk.contains(&it.p), "{} is not on the disk", it.p);
+                assert!((pdf - 1.0 / disk.area()).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_solid_angle_samples_match_their_pdf() {
+        ::init_stats();
+        let disk = Disk::new(0.5, 2.0, 0.0, 360.0, Transform::scale(0.5, 0.5, 0.5), false);
+        // Points above the disk, and off to its side
+        for from in &[Point3f::new(0.2, -0.3, 1.0), Point3f::new(3.0, 1.0, -0.5)] {
+            let si = Interaction::new(*from,
+                                      Vector3f::new(0.0, 0.0, 0.0),
+                                      Vector3f::new(0.0, 0.0, 1.0),
+                                      Normal3f::new(0.0, 0.0, 1.0));
+            for i in 0..8 {
+                for j in 0..8 {
+                    let u = Point2f::new((i as f32 + 0.5) / 8.0, (j as f32 + 0.5) / 8.0);
+                    let (it, _, pdf) = disk.sample_solid_angle(&si, &u).unwrap();
+                    let p_obj = &disk.world_to_object * &it.p;
+                    assert!((p_obj.z - 0.5).abs() < 1e-4);
+                    assert!(p_obj.x * p_obj.x + p_obj.y * p_obj.y <= 4.0 + 1e-3,
+                            "{} is not on the disk",
+                            p_obj);
+                    assert!(pdf > 0.0);
+                    let wi = (it.p - si.p).normalize();
+                    let pdf_wi = disk.pdf_solid_angle(&si, &wi).unwrap();
+                    assert!((pdf_wi - pdf).abs() < 1e-3 * pdf, "{} vs {}", pdf_wi, pdf);
+                }
+            }
+            // Directions that miss the disk have a zero pdf
+            let away = (si.p - &disk.object_to_world * &Point3f::new(0.0, 0.0, 0.5)).normalize();
+            assert_eq!(disk.pdf_solid_angle(&si, &away), Some(0.0));
+        }
+    }
+}
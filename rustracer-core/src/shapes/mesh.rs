@@ -1,6 +1,7 @@
 use std::sync::Arc;
 use std::collections::HashMap;
 use std::fmt;
+use std::mem::size_of;
 
 use num::zero;
 
@@ -11,17 +12,23 @@ use geometry;
 use interaction::{Interaction, SurfaceInteraction};
 use paramset::ParamSet;
 use ray::Ray;
+use spectrum::Spectrum;
 use sampling;
 use shapes::Shape;
 use texture::{Texture, TextureFloat, ConstantTexture};
 
+/// Alpha values below this threshold cut holes in triangles, for both camera and shadow rays.
+const ALPHA_CUTOFF: f32 = 0.5;
+
 stat_percent!("Intersections/Ray-triangle intersection tests", n_hits);
 stat_memory_counter!("Memory/Triangle meshes", tri_mesh_bytes);
 stat_ratio!("Scene/Triangles per triangle mesh", n_tris_per_mesh);
+stat_counter!("Scene/Degenerate triangles skipped", n_degenerate_tris);
 pub fn init_stats() {
     n_hits::init();
     tri_mesh_bytes::init();
     n_tris_per_mesh::init();
+    n_degenerate_tris::init();
 }
 
 pub struct TriangleMesh {
@@ -32,7 +39,11 @@ pub struct TriangleMesh {
     n: Option<Vec<Normal3f>>,
     s: Option<Vec<Vector3f>>,
     uv: Option<Vec<Point2f>>,
+    /// Per-vertex colours, interpolated into `SurfaceInteraction::color`
+    colors: Option<Vec<Spectrum>>,
+    /// Cuts holes in the mesh for camera rays
     alpha_mask: Option<Arc<TextureFloat>>,
+    /// Cuts holes in the mesh for shadow rays
     shadow_alpha_mask: Option<Arc<TextureFloat>>,
 }
 
@@ -43,18 +54,28 @@ impl fmt::Debug for TriangleMesh {
 }
 
 impl TriangleMesh {
+    /// Without a `shadow_alpha_mask`, shadow rays go through the holes cut by `alpha_mask`.
     pub fn new(object_to_world: &Transform,
                vertex_indices: &[usize],
                p: &[Point3f],
                s: Option<&[Vector3f]>,
                n: Option<&[Normal3f]>,
                uv: Option<&[Point2f]>,
+               colors: Option<&[Spectrum]>,
                alpha_mask: Option<Arc<TextureFloat>>,
                shadow_alpha_mask: Option<Arc<TextureFloat>>)
                -> Self {
         n_tris_per_mesh::inc_total();
         n_tris_per_mesh::add(vertex_indices.len() as u64);
         let points: Vec<Point3f> = p.iter().map(|pt| object_to_world * pt).collect();
+        tri_mesh_bytes::add((size_of::<TriangleMesh>() +
+                             vertex_indices.len() * size_of::<usize>() +
+                             p.len() * size_of::<Point3f>() +
+                             n.map_or(0, |n| n.len() * size_of::<Normal3f>()) +
+                             s.map_or(0, |s| s.len() * size_of::<Vector3f>()) +
+                             uv.map_or(0, |uv| uv.len() * size_of::<Point2f>()) +
+                             colors.map_or(0, |c| c.len() * size_of::<Spectrum>())) as
+                            u64);
         TriangleMesh {
             object_to_world: object_to_world.clone(),
             world_to_object: object_to_world.inverse(),
@@ -63,8 +84,9 @@ impl TriangleMesh {
             n: n.map(Vec::from),
             s: s.map(Vec::from),
             uv: uv.map(Vec::from),
+            colors: colors.map(Vec::from),
+            shadow_alpha_mask: shadow_alpha_mask.or_else(|| alpha_mask.clone()),
             alpha_mask,
-            shadow_alpha_mask
         }
     }
 
@@ -94,15 +116,23 @@ impl TriangleMesh {
                                  .map(|s| Point2f::new(s[0], s[1]))
                                  .collect()
                          })
-            });
-        // if !uvs.is_empty() {
-        //     if uvs.len() < P.len() {
-        //         error!("Not enough of \"uv\"s for triangle mesh. Expected {}, found {}. Discarding", P.len(), uvs.len());
-        //         uvs.clear();
-        //     } else if uvs.len() > P.len() {
-        //         warn!("More \"uv\"s provided than will be used for triangle mesh. ({} expected, {} found)", P.len(), uvs.len());
-        //     }
-        // }
+            })
+            .and_then(|uv| if uv.len() < P.len() {
+                          // Triangles will fall back to default (u, v) coordinates instead
+                          error!("Not enough \"uv\"s for triangle mesh. Expected {}, found {}. \
+                                  Discarding",
+                                 P.len(),
+                                 uv.len());
+                          None
+                      } else {
+                          if uv.len() > P.len() {
+                              warn!("More \"uv\"s provided than will be used for triangle mesh. \
+                                     ({} expected, {} found)",
+                                    P.len(),
+                                    uv.len());
+                          }
+                          Some(uv)
+                      });
         if vi.is_empty() {
             error!("Vertex indices \"indices\" not provided with triangle mesh shape");
             return Vec::new();
@@ -129,6 +159,15 @@ impl TriangleMesh {
                           Some(n)
                       });
 
+        let Cs = params
+            .find_spectrum("Cs")
+            .and_then(|c| if c.len() != P.len() {
+                          error!("Number of \"Cs\"s for mesh triangle must match \"P\"s");
+                          None
+                      } else {
+                          Some(c)
+                      });
+
         // TODO implement rest of the validation / sanity checking
         let mut alpha_mask = None;
         let alpha_tex_name = params.find_texture("alpha", String::from(""));
@@ -138,7 +177,7 @@ impl TriangleMesh {
             } else {
                 error!("");
             }
-        } else if params.find_one_float("alpha", 1.0) == 0.0 {
+        } else if params.find_one_float("alpha", 1.0) < ALPHA_CUTOFF {
             alpha_mask = Some(Arc::new(ConstantTexture::new(0.0)));
         }
 
@@ -148,10 +187,12 @@ impl TriangleMesh {
             if let Some(tex) = float_textures.get(&shadow_alpha_tex_name) {
                 shadow_alpha_mask = Some(tex.clone());
             } else {
-                error!("");
+                error!("Couldn't find float texture {} for \"shadowalpha\"",
+                       shadow_alpha_tex_name);
             }
-        } else if params.find_one_float("shadowalpha", 1.0) == 0.0 {
-            shadow_alpha_mask = Some(Arc::new(ConstantTexture::new(0.0)));
+        } else if let Some(shadow_alpha) = params.find_float("shadowalpha") {
+            // Given explicitly, it overrides "alpha" even when opaque
+            shadow_alpha_mask = Some(Arc::new(ConstantTexture::new(shadow_alpha[0])));
         }
 
         let res: Vec<Arc<Shape>> =
@@ -162,6 +203,7 @@ impl TriangleMesh {
                                  S.as_ref().map(|s| &s[..]),
                                  N.as_ref().map(|n| &n[..]),
                                  uvs.as_ref().map(|uv| &uv[..]),
+                                 Cs.as_ref().map(|c| &c[..]),
                                  alpha_mask,
                                  shadow_alpha_mask);
 
@@ -186,7 +228,7 @@ impl Triangle {
             reverse_orientation: reverse_orientation,
             swaps_handedness: swaps_handedness,
         };
-        tri_mesh_bytes::add(::std::mem::size_of_val(&tri) as u64);
+        tri_mesh_bytes::add(size_of::<Triangle>() as u64);
 
         tri
     }
@@ -196,6 +238,9 @@ impl Triangle {
         self.mesh.vertex_indices[self.v_start_index + index]
     }
 
+    /// (u, v) coordinates of the triangle's vertices. Without per-vertex coordinates in the mesh,
+    /// every triangle gets the same default ones, so that textures and bump maps still vary
+    /// across its surface.
     fn get_uvs(&self) -> [Point2f; 3] {
         if let Some(ref uv) = self.mesh.uv {
             [uv[self.v(0)], uv[self.v(1)], uv[self.v(2)]]
@@ -208,6 +253,10 @@ impl Triangle {
 }
 
 impl Shape for Triangle {
+    fn name(&self) -> &'static str {
+        "Triangle"
+    }
+
     fn intersect(&self, ray: &Ray) -> Option<(SurfaceInteraction, f32)> {
         n_hits::inc_total();
 
@@ -326,8 +375,8 @@ impl Shape for Triangle {
         let degenerate_uv = determinant.abs() < 1e-8;
         if !degenerate_uv {
             let inv_det = 1.0 / determinant;
-            dpdu = (duv12[1] * dp02 - duv02[1] * dp12) / inv_det;
-            dpdv = (-duv12[0] * dp02 + duv02[0] * dp12) / inv_det;
+            dpdu = (duv12[1] * dp02 - duv02[1] * dp12) * inv_det;
+            dpdv = (-duv12[0] * dp02 + duv02[0] * dp12) * inv_det;
         }
         if degenerate_uv || dpdu.cross(&dpdv).length_squared() == 0.0 {
             // handle zero determinant for triangle partial derivative matric
@@ -359,7 +408,7 @@ impl Shape for Triangle {
                     zero(),
                     self
                 );
-            if alpha_mask.evaluate(&isect_local) == 0.0 {
+            if alpha_mask.evaluate(&isect_local) < ALPHA_CUTOFF {
                 return None;
             }
         }
@@ -405,6 +454,9 @@ impl Shape for Triangle {
         isect.shading.n = ns;
         isect.shading.dpdu = ss;
         isect.shading.dpdv = ts;
+        if let Some(ref c) = self.mesh.colors {
+            isect.color = Some(c[self.v(0)] * b0 + c[self.v(1)] * b1 + c[self.v(2)] * b2);
+        }
 
         // Ensure correct orientation of the geometric normal
         if self.mesh.n.is_some() {
@@ -523,8 +575,8 @@ impl Shape for Triangle {
             return false;
         }
 
-        // Test shadow ray intersection against alpha texture, if present
-        if self.mesh.alpha_mask.is_some() || self.mesh.shadow_alpha_mask.is_some() {
+        // Test shadow ray intersection against shadow alpha texture, if present
+        if let Some(ref shadow_alpha_mask) = self.mesh.shadow_alpha_mask {
             // Compute triangle partial derivatives
             let mut dpdu = Vector3f::new(0.0, 0.0, 0.0);
             let mut dpdv = Vector3f::new(0.0, 0.0, 0.0);
@@ -538,8 +590,8 @@ impl Shape for Triangle {
             let degenerate_uv = determinant.abs() < 1e-8;
             if !degenerate_uv {
                 let inv_det = 1.0 / determinant;
-                dpdu = (duv12[1] * dp02 - duv02[1] * dp12) / inv_det;
-                dpdv = (-duv12[0] * dp02 + duv02[0] * dp12) / inv_det;
+                dpdu = (duv12[1] * dp02 - duv02[1] * dp12) * inv_det;
+                dpdv = (-duv12[0] * dp02 + duv02[0] * dp12) * inv_det;
             }
             if degenerate_uv || dpdu.cross(&dpdv).length_squared() == 0.0 {
                 // handle zero determinant for triangle partial derivative matric
@@ -561,15 +613,8 @@ impl Shape for Triangle {
                     zero(),
                     self
                 );
-            if let Some(ref alpha_mask) = self.mesh.alpha_mask {
-                if alpha_mask.evaluate(&isect_local) == 0.0 {
-                    return false;
-                }
-            }
-            if let Some(ref shadow_alpha_mask) = self.mesh.shadow_alpha_mask {
-                if shadow_alpha_mask.evaluate(&isect_local) == 0.0 {
-                    return false;
-                }
+            if shadow_alpha_mask.evaluate(&isect_local) < ALPHA_CUTOFF {
+                return false;
             }
         }
 
@@ -600,7 +645,7 @@ impl Shape for Triangle {
         Bounds3f::union_point(&Bounds3f::from_points(&p0, &p1), &p2)
     }
 
-    fn sample(&self, u: &Point2f) -> (Interaction, f32) {
+    fn sample(&self, u: &Point2f) -> (Interaction, Point2f, f32) {
         let b = sampling::uniform_sample_triangle(u);
         let p0 = &self.mesh.p[self.v(0)];
         let p1 = &self.mesh.p[self.v(1)];
@@ -622,8 +667,10 @@ impl Shape for Triangle {
         let p_abs_sum = (b[0] * *p0).abs() + (b[1] * *p1).abs() + ((1.0 - b[0] - b[1]) * *p2).abs();
         let p_error = gamma(6) * p_abs_sum;
         let it = Interaction::new(p, Vector3f::from(p_error), zero(), normal);
+        let uv = self.get_uvs();
+        let uv_sample = uv[0] * b[0] + uv[1] * b[1] + uv[2] * (1.0 - b[0] - b[1]);
 
-        (it, 1.0 / self.area())
+        (it, uv_sample, 1.0 / self.area())
     }
 
     fn reverse_orientation(&self) -> bool {
@@ -642,10 +689,26 @@ pub fn create_triangle_mesh(object_to_world: &Transform,
                             s: Option<&[Vector3f]>,
                             n: Option<&[Normal3f]>,
                             uv: Option<&[Point2f]>,
+                            colors: Option<&[Spectrum]>,
                             alpha_mask: Option<Arc<TextureFloat>>,
                             shadow_alpha_mask: Option<Arc<TextureFloat>>)
                             -> Vec<Arc<Shape>> {
-    let mesh = Arc::new(TriangleMesh::new(object_to_world, vertex_indices, p, s, n, uv, alpha_mask, shadow_alpha_mask));
+    let n_input_triangles = vertex_indices.len() / 3;
+    // Skip zero-area triangles: they can never be hit and would only produce NaN normals
+    let vertex_indices: Vec<usize> = vertex_indices
+        .chunks(3)
+        .filter(|v| v.len() == 3 && !is_degenerate(&p[v[0]], &p[v[1]], &p[v[2]]))
+        .flat_map(|v| v.iter().cloned())
+        .collect();
+    let n_degenerate = n_input_triangles - vertex_indices.len() / 3;
+    if n_degenerate > 0 {
+        warn!("Skipped {} degenerate triangles out of {} in triangle mesh",
+              n_degenerate,
+              n_input_triangles);
+        n_degenerate_tris::add(n_degenerate as u64);
+    }
+    let vertex_indices = &vertex_indices[..];
+    let mesh = Arc::new(TriangleMesh::new(object_to_world, vertex_indices, p, s, n, uv, colors, alpha_mask, shadow_alpha_mask));
 
     let n_triangles = vertex_indices.len() / 3;
     let mut tris: Vec<Arc<Shape>> = Vec::with_capacity(n_triangles);
@@ -656,3 +719,266 @@ pub fn create_triangle_mesh(object_to_world: &Transform,
 
     tris
 }
+
+/// Returns true if the triangle has (almost) no area, i.e. if its vertices are collinear or some of
+/// them coincide.
+fn is_degenerate(p0: &Point3f, p1: &Point3f, p2: &Point3f) -> bool {
+    let e1 = *p1 - *p0;
+    let e2 = *p2 - *p0;
+    // |e1 x e2| = |e1| |e2| sin(theta), so this is a test on the angle between the 2 edges that
+    // doesn't depend on the size of the triangle.
+    e1.cross(&e2).length_squared() <= 1e-12 * e1.length_squared() * e2.length_squared()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use api::{Array, ParamListEntry, ParamType};
+    use test_utils::float_param;
+
+    #[test]
+    fn test_mesh_memory_is_reported() {
+        ::init_stats();
+        let p = [Point3f::new(0.0, 0.0, 0.0),
+                 Point3f::new(1.0, 0.0, 0.0),
+                 Point3f::new(1.0, 1.0, 0.0),
+                 Point3f::new(0.0, 1.0, 0.0)];
+        let uv = [Point2f::new(0.0, 0.0),
+                  Point2f::new(1.0, 0.0),
+                  Point2f::new(1.0, 1.0),
+                  Point2f::new(0.0, 1.0)];
+        let indices = [0, 1, 2, 0, 2, 3];
+        let before = tri_mesh_bytes::get();
+        let tris = create_triangle_mesh(&Transform::default(),
+                                        false,
+                                        &indices,
+                                        &p,
+                                        None,
+                                        None,
+                                        Some(&uv),
+                                        None,
+                                        None,
+                                        None);
+        assert_eq!(tris.len(), 2);
+
+        let expected = size_of::<TriangleMesh>() + 6 * size_of::<usize>() +
+                       4 * size_of::<Point3f>() + 4 * size_of::<Point2f>() +
+                       2 * size_of::<Triangle>();
+        assert_eq!(tri_mesh_bytes::get() - before, expected as u64);
+    }
+
+    /// An alpha texture with a 2x2 checkerboard pattern in (u, v) space: opaque squares have an
+    /// alpha of 1, the others are only partially transparent.
+    #[derive(Debug)]
+    struct CheckerAlpha;
+
+    impl Texture<f32> for CheckerAlpha {
+        fn evaluate(&self, si: &SurfaceInteraction) -> f32 {
+            let checker = (2.0 * si.uv.x).floor() as i32 + (2.0 * si.uv.y).floor() as i32;
+            if checker % 2 == 0 { 1.0 } else { 0.3 }
+        }
+    }
+
+    #[test]
+    fn test_alpha_cutout() {
+        ::init_stats();
+        let p = [Point3f::new(0.0, 0.0, 0.0),
+                 Point3f::new(1.0, 0.0, 0.0),
+                 Point3f::new(1.0, 1.0, 0.0),
+                 Point3f::new(0.0, 1.0, 0.0)];
+        let uv = [Point2f::new(0.0, 0.0),
+                  Point2f::new(1.0, 0.0),
+                  Point2f::new(1.0, 1.0),
+                  Point2f::new(0.0, 1.0)];
+        let indices = [0, 1, 2, 0, 2, 3];
+        let tris = create_triangle_mesh(&Transform::default(),
+                                        false,
+                                        &indices,
+                                        &p,
+                                        None,
+                                        None,
+                                        Some(&uv),
+                                        None,
+                                        Some(Arc::new(CheckerAlpha)),
+                                        None);
+
+        for &(x, y, opaque) in &[(0.2, 0.3, true),
+                                 (0.7, 0.8, true),
+                                 (0.7, 0.2, false),
+                                 (0.2, 0.7, false)] {
+            let ray = Ray::new(Point3f::new(x, y, 1.0), Vector3f::new(0.0, 0.0, -1.0));
+            let hit = tris.iter().any(|tri| tri.intersect(&ray).is_some());
+            let blocked = tris.iter().any(|tri| tri.intersect_p(&ray));
+            assert_eq!(hit, opaque, "camera ray at ({}, {})", x, y);
+            assert_eq!(blocked, opaque, "shadow ray at ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn test_degenerate_triangles_are_skipped() {
+        ::init_stats();
+        let p = [Point3f::new(0.0, 0.0, 0.0),
+                 Point3f::new(1.0, 0.0, 0.0),
+                 Point3f::new(0.0, 1.0, 0.0),
+                 Point3f::new(2.0, 0.0, 0.0)];
+        // Second triangle has collinear vertices, third one has 2 identical vertices
+        let indices = [0, 1, 2, 0, 1, 3, 2, 2, 1];
+        let tris = create_triangle_mesh(&Transform::default(),
+                                        false,
+                                        &indices,
+                                        &p,
+                                        None,
+                                        None,
+                                        None,
+                                        None,
+                                        None,
+                                        None);
+        assert_eq!(tris.len(), 1);
+        assert_eq!(tris[0].area(), 0.5);
+    }
+
+    #[test]
+    fn test_rays_do_not_leak_through_shared_edges() {
+        ::init_stats();
+        // A non-planar quad, split along its diagonal from vertex 0 to vertex 2
+        let p = [Point3f::new(0.0, 0.0, 0.0),
+                 Point3f::new(1.0, 0.1, 0.3),
+                 Point3f::new(1.1, 1.0, 0.2),
+                 Point3f::new(0.1, 0.9, -0.1)];
+        let indices = [0, 1, 2, 0, 2, 3];
+        let tris = create_triangle_mesh(&Transform::default(),
+                                        false,
+                                        &indices,
+                                        &p,
+                                        None,
+                                        None,
+                                        None,
+                                        None,
+                                        None,
+                                        None);
+        assert_eq!(tris.len(), 2);
+
+        let origins = [Point3f::new(0.3, 0.4, 5.0),
+                       Point3f::new(-2.0, 3.0, 4.0),
+                       Point3f::new(5.0, -1.0, 2.0),
+                       Point3f::new(0.5, 0.5, -3.0),
+                       Point3f::new(-10.0, -7.0, 0.5)];
+        for o in &origins {
+            for i in 0..1001 {
+                // Aim at a point on the shared edge
+                let target = p[0] + (p[2] - p[0]) * (i as f32 / 1000.0);
+                let ray = Ray::new(*o, target - *o);
+                let hits: Vec<f32> = tris.iter()
+                    .filter_map(|tri| {
+                        let hit = tri.intersect(&ray).map(|(_, t)| t);
+                        assert_eq!(hit.is_some(), tri.intersect_p(&ray));
+                        hit
+                    })
+                    .collect();
+                // The edge may belong to both triangles, but never to neither, and the closest
+                // hit is the same whichever triangle reports it.
+                assert!(!hits.is_empty(), "ray {} slipped between the triangles", ray);
+                if hits.len() == 2 {
+                    assert!((hits[0] - hits[1]).abs() <= 1e-5 * hits[0]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mirrored_mesh_keeps_its_orientation() {
+        ::init_stats();
+        let p = [Point3f::new(0.0, 0.0, 0.0),
+                 Point3f::new(1.0, 0.0, 0.0),
+                 Point3f::new(0.0, 1.0, 0.0)];
+        // Mirroring the mesh in x reverses its winding order in world space, but the normal
+        // should still be the transformed object space normal, i.e. +z.
+        for o2w in &[Transform::default(), Transform::scale(-1.0, 1.0, 1.0)] {
+            let tris = create_triangle_mesh(o2w, false, &[0, 1, 2], &p, None, None, None, None, None, None);
+            let target = o2w * &Point3f::new(0.25, 0.25, 0.0);
+            let ray = Ray::new(target + Vector3f::new(0.0, 0.0, 5.0), Vector3f::new(0.0, 0.0, -1.0));
+            let (si, _) = tris[0].intersect(&ray).unwrap();
+            assert!(si.hit.n.z > 0.99, "n = {}", si.hit.n);
+            assert!(si.shading.n.z > 0.99, "n = {}", si.shading.n);
+        }
+    }
+
+    #[test]
+    fn test_meshes_without_uvs_are_textured() {
+        ::init_stats();
+        let mut params = ParamSet::default();
+        params.init(vec![ParamListEntry::new(ParamType::Int,
+                                             "indices".to_owned(),
+                                             Array::NumArray(vec![0.0, 1.0, 2.0, 0.0, 2.0, 3.0])),
+                         ParamListEntry::new(ParamType::Point3,
+                                             "P".to_owned(),
+                                             Array::NumArray(vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0,
+                                                                  1.0, 1.0, 0.0, 0.0, 1.0, 0.0])),
+                         // Too few to be used
+                         ParamListEntry::new(ParamType::Float,
+                                             "uv".to_owned(),
+                                             Array::NumArray(vec![0.0, 0.0, 1.0, 0.0]))]);
+        let tris = TriangleMesh::create(&Transform::default(),
+                                        &Transform::default(),
+                                        false,
+                                        &mut params,
+                                        &HashMap::new());
+        assert_eq!(tris.len(), 2);
+
+        let mut alphas = Vec::new();
+        for &(x, y) in &[(0.9, 0.1), (0.9, 0.8), (0.1, 0.9), (0.6, 0.9)] {
+            let ray = Ray::new(Point3f::new(x, y, 1.0), Vector3f::new(0.0, 0.0, -1.0));
+            let (si, _) = tris.iter()
+                .filter_map(|tri| tri.intersect(&ray))
+                .next()
+                .unwrap();
+            assert!(si.dpdu.length_squared() > 0.0 && si.dpdv.length_squared() > 0.0);
+            alphas.push(CheckerAlpha.evaluate(&si));
+        }
+        // Not a single (u, v) for the whole mesh: the texture varies across each triangle
+        assert_ne!(alphas[0], alphas[1]);
+        assert_ne!(alphas[2], alphas[3]);
+    }
+
+    fn quad_with_alphas(alphas: Vec<ParamListEntry>) -> Vec<Arc<Shape>> {
+        let mut params = ParamSet::default();
+        let mut entries =
+            vec![ParamListEntry::new(ParamType::Int,
+                                     "indices".to_owned(),
+                                     Array::NumArray(vec![0.0, 1.0, 2.0, 0.0, 2.0, 3.0])),
+                 ParamListEntry::new(ParamType::Point3,
+                                     "P".to_owned(),
+                                     Array::NumArray(vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0,
+                                                          0.0, 0.0, 1.0, 0.0]))];
+        entries.extend(alphas);
+        params.init(entries);
+        TriangleMesh::create(&Transform::default(),
+                             &Transform::default(),
+                             false,
+                             &mut params,
+                             &HashMap::new())
+    }
+
+    /// Whether the quad is seen by camera rays and casts shadows
+    fn visible_and_shadowing(tris: &[Arc<Shape>]) -> (bool, bool) {
+        let ray = Ray::new(Point3f::new(0.7, 0.2, 1.0), Vector3f::new(0.0, 0.0, -1.0));
+        (tris.iter().any(|tri| tri.intersect(&ray).is_some()),
+         tris.iter().any(|tri| tri.intersect_p(&ray)))
+    }
+
+    #[test]
+    fn test_shadow_alpha() {
+        ::init_stats();
+        let no_shadow = quad_with_alphas(vec![float_param("shadowalpha", 0.0)]);
+        assert_eq!(visible_and_shadowing(&no_shadow), (true, false));
+
+        let shadow_only = quad_with_alphas(vec![float_param("alpha", 0.0),
+                                                float_param("shadowalpha", 1.0)]);
+        assert_eq!(visible_and_shadowing(&shadow_only), (false, true));
+
+        // Shadows follow the alpha when no shadow alpha is given
+        let invisible = quad_with_alphas(vec![float_param("alpha", 0.0)]);
+        assert_eq!(visible_and_shadowing(&invisible), (false, false));
+    }
+}
@@ -8,19 +8,35 @@ use interaction::{Interaction, SurfaceInteraction};
 mod cylinder;
 mod disk;
 mod mesh;
+mod plane;
 pub mod plymesh;
+mod rectangle;
 mod sphere;
 
 pub use self::cylinder::Cylinder;
 pub use self::disk::Disk;
 pub use self::mesh::{Triangle, TriangleMesh};
+pub use self::plane::Plane;
+pub use self::rectangle::Rectangle;
 pub use self::sphere::Sphere;
 
+/// Below this solid angle, shapes don't bother with solid angle sampling: area sampling works
+/// just as well and is more robust numerically.
+const MIN_SOLID_ANGLE: f32 = 1e-3;
+
+stat_percent!("Intersections/Ray-quadric intersection tests", n_quadric_hits);
 pub fn init_stats() {
+    n_quadric_hits::init();
+    cylinder::init_stats();
+    disk::init_stats();
     mesh::init_stats();
+    sphere::init_stats();
 }
 
 pub trait Shape: Debug + Send + Sync {
+    /// Name of the shape's type, e.g. "Sphere", as listed in the scene summary.
+    fn name(&self) -> &'static str;
+
     fn intersect(&self, ray: &Ray) -> Option<(SurfaceInteraction, f32)>;
 
     fn intersect_p(&self, ray: &Ray) -> bool {
@@ -33,10 +49,12 @@ pub trait Shape: Debug + Send + Sync {
 
     fn world_bounds(&self) -> Bounds3f;
 
-    fn sample(&self, u: &Point2f) -> (Interaction, f32);
+    /// Sample a point on the shape uniformly by area. Return it along with its (u, v) coordinates,
+    /// as `intersect()` would report them, and its pdf with respect to area.
+    fn sample(&self, u: &Point2f) -> (Interaction, Point2f, f32);
 
-    fn sample_si(&self, si: &Interaction, u: &Point2f) -> (Interaction, f32) {
-        let (intr, mut pdf) = self.sample(u);
+    fn sample_si(&self, si: &Interaction, u: &Point2f) -> (Interaction, Point2f, f32) {
+        let (intr, uv, mut pdf) = self.sample(u);
         let mut wi = intr.p - si.p;
         if wi.length_squared() == 0.0 {
             pdf = 0.0;
@@ -48,7 +66,7 @@ pub trait Shape: Debug + Send + Sync {
             }
         }
 
-        (intr, pdf)
+        (intr, uv, pdf)
     }
 
     fn pdf(&self, _si: &Interaction) -> f32 {
@@ -66,6 +84,25 @@ pub trait Shape: Debug + Send + Sync {
         }
     }
 
+    /// Sample a point on the shape uniformly with respect to the solid angle it subtends as seen
+    /// from `si`. Return `None` if the shape has no closed-form way to do so from this point, in
+    /// which case callers should fall back to `sample_si()`.
+    ///
+    /// The returned pdf is with respect to solid angle, and can be 0 for samples that should be
+    /// discarded.
+    fn sample_solid_angle(&self,
+                          _si: &Interaction,
+                          _u: &Point2f)
+                          -> Option<(Interaction, Point2f, f32)> {
+        None
+    }
+
+    /// Pdf (with respect to solid angle) of sampling direction `wi` from `si` with
+    /// `sample_solid_angle()`, or `None` if that isn't available from this point.
+    fn pdf_solid_angle(&self, _si: &Interaction, _wi: &Vector3f) -> Option<f32> {
+        None
+    }
+
     fn reverse_orientation(&self) -> bool;
 
     fn transform_swaps_handedness(&self) -> bool;
@@ -0,0 +1,171 @@
+use std::f32;
+use std::sync::Arc;
+
+use {coordinate_system, gamma, Normal3f, Point2f, Point3f, Transform, Vector3f};
+use bounds::Bounds3f;
+use interaction::{Interaction, SurfaceInteraction};
+use paramset::ParamSet;
+use ray::Ray;
+use shapes::Shape;
+
+/// Half the size of the box the plane's bounds are clamped to. Accelerators can't deal with
+/// infinite bounds, so the plane behaves as a square this big around its point.
+const PLANE_EXTENT: f32 = 1e4;
+
+/// An infinite plane going through a point, facing the direction of its normal. Handy as a
+/// ground for quick test scenes. It has no finite area, so it can't be used as an area light.
+#[derive(Debug)]
+pub struct Plane {
+    /// World space point on the plane
+    p: Point3f,
+    /// World space unit normal
+    n: Vector3f,
+    /// Unit tangents along which the u and v coordinates are measured, with dpdu x dpdv = n
+    dpdu: Vector3f,
+    dpdv: Vector3f,
+    reverse_orientation: bool,
+}
+
+impl Plane {
+    pub fn new(p: Point3f, n: Vector3f, reverse_orientation: bool) -> Plane {
+        assert!(n.length_squared() > 0.0);
+        let n = n.normalize();
+        let (dpdu, dpdv) = coordinate_system(&n);
+        Plane {
+            p,
+            n,
+            dpdu,
+            dpdv,
+            reverse_orientation,
+        }
+    }
+
+    pub fn create(o2w: &Transform, reverse_orientation: bool, params: &mut ParamSet) -> Arc<Shape> {
+        let p = params.find_one_point3f("point", Point3f::new(0.0, 0.0, 0.0));
+        let n = params.find_one_normal3f("normal", Normal3f::new(0.0, 0.0, 1.0));
+        if n.length_squared() == 0.0 {
+            error!("Degenerate normal for plane. Using (0, 0, 1).");
+        }
+        let n = if n.length_squared() == 0.0 {
+            Normal3f::new(0.0, 0.0, 1.0)
+        } else {
+            n
+        };
+
+        Arc::new(Plane::new(o2w * &p, Vector3f::from(o2w * &n), reverse_orientation))
+    }
+}
+
+impl Shape for Plane {
+    fn name(&self) -> &'static str {
+        "Plane"
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(SurfaceInteraction, f32)> {
+        let d_n = ray.d.dot(&self.n);
+        if d_n == 0.0 {
+            // Reject intersection for rays parallel to the plane
+            return None;
+        }
+        let t_shape_hit = (self.p - ray.o).dot(&self.n) / d_n;
+        if t_shape_hit <= 0.0 || t_shape_hit > ray.t_max {
+            return None;
+        }
+        // Project the hit point back onto the plane to refine it
+        let mut p_hit = ray.at(t_shape_hit);
+        p_hit = p_hit - (p_hit - self.p).dot(&self.n) * self.n;
+        let p_err = gamma(5) * Vector3f::from(p_hit).abs();
+
+        // Find parametric representation of the hit
+        let offset = p_hit - self.p;
+        let uv = Point2f::new(offset.dot(&self.dpdu), offset.dot(&self.dpdv));
+        let dndu = Normal3f::new(0.0, 0.0, 0.0);
+        let dndv = Normal3f::new(0.0, 0.0, 0.0);
+        let isect = SurfaceInteraction::new(p_hit,
+                                            p_err,
+                                            uv,
+                                            -ray.d,
+                                            self.dpdu,
+                                            self.dpdv,
+                                            dndu,
+                                            dndv,
+                                            self);
+
+        Some((isect, t_shape_hit))
+    }
+
+    fn object_bounds(&self) -> Bounds3f {
+        self.world_bounds()
+    }
+
+    fn world_bounds(&self) -> Bounds3f {
+        // Only clamp the directions along the plane, so axis-aligned planes get flat bounds
+        let extent = Vector3f::new(PLANE_EXTENT, PLANE_EXTENT, PLANE_EXTENT);
+        let flat = |c: f32| if c.abs() == 1.0 { 0.0 } else { 1.0 };
+        let extent = Vector3f::new(extent.x * flat(self.n.x),
+                                   extent.y * flat(self.n.y),
+                                   extent.z * flat(self.n.z));
+        Bounds3f::from_points(&(self.p - extent), &(self.p + extent))
+    }
+
+    fn sample(&self, _u: &Point2f) -> (Interaction, Point2f, f32) {
+        let mut it = Interaction::empty();
+        it.p = self.p;
+        it.n = if self.reverse_orientation {
+            -Normal3f::from(self.n)
+        } else {
+            Normal3f::from(self.n)
+        };
+        // There's no uniform distribution over an infinite area
+        (it, Point2f::new(0.0, 0.0), 0.0)
+    }
+
+    fn area(&self) -> f32 {
+        f32::INFINITY
+    }
+
+    fn reverse_orientation(&self) -> bool {
+        self.reverse_orientation
+    }
+
+    fn transform_swaps_handedness(&self) -> bool {
+        // The plane is stored in world space, with the normal given by the scene
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downward_ray_hits_ground() {
+        ::init_stats();
+        let ground = Plane::new(Point3f::new(0.0, 0.0, -1.0), Vector3f::new(0.0, 0.0, 1.0), false);
+        let ray = Ray::new(Point3f::new(3.0, -2.0, 4.0), Vector3f::new(0.0, 0.0, -1.0));
+        let (isect, t_hit) = ground.intersect(&ray).unwrap();
+        assert_eq!(t_hit, 5.0);
+        assert_eq!(isect.hit.p, Point3f::new(3.0, -2.0, -1.0));
+        assert_eq!(isect.hit.n, Normal3f::new(0.0, 0.0, 1.0));
+
+        // Far away and at a slant too
+        let ray = Ray::new(Point3f::new(0.0, 0.0, 1.0), Vector3f::new(0.6, 0.0, -0.8));
+        let (isect, t_hit) = ground.intersect(&ray).unwrap();
+        assert!((t_hit - 2.5).abs() < 1e-5);
+        assert!((isect.hit.p - Point3f::new(1.5, 0.0, -1.0)).length() < 1e-5);
+
+        // Rays going up or parallel to the plane miss it
+        let up = Ray::new(Point3f::new(0.0, 0.0, 1.0), Vector3f::new(0.0, 0.0, 1.0));
+        assert!(ground.intersect(&up).is_none());
+        let parallel = Ray::new(Point3f::new(0.0, 0.0, 1.0), Vector3f::new(1.0, 0.0, 0.0));
+        assert!(ground.intersect(&parallel).is_none());
+    }
+
+    #[test]
+    fn test_bounds_are_finite() {
+        let ground = Plane::new(Point3f::new(0.0, 0.0, -1.0), Vector3f::new(0.0, 0.0, 1.0), false);
+        let bounds = ground.world_bounds();
+        assert_eq!(bounds.p_min, Point3f::new(-PLANE_EXTENT, -PLANE_EXTENT, -1.0));
+        assert_eq!(bounds.p_max, Point3f::new(PLANE_EXTENT, PLANE_EXTENT, -1.0));
+    }
+}
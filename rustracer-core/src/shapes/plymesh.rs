@@ -3,6 +3,7 @@ use std::fs::File;
 use std::io::BufReader;
 use std::collections::HashMap;
 
+use failure::{Error, ResultExt};
 use ply_rs::ply;
 use ply_rs::parser;
 
@@ -18,9 +19,13 @@ pub fn create(o2w: &Transform,
               reverse_orientation: bool,
               params: &mut ParamSet,
               float_textures: &HashMap<String, Arc<Texture<f32>>>)
-              -> Vec<Arc<Shape>> {
+              -> Result<Vec<Arc<Shape>>, Error> {
     let filename = params.find_one_filename("filename", "".into());
-    let f = File::open(&filename).unwrap();
+    if filename == "" {
+        bail!("No \"filename\" parameter provided for PLY mesh");
+    }
+    let f = File::open(&filename)
+        .context(format!("Failed to open PLY file \"{}\"", filename))?;
     let mut f = BufReader::new(f);
 
     // create a parser
@@ -28,7 +33,9 @@ pub fn create(o2w: &Transform,
     let face_parser = parser::Parser::<Face>::new();
 
     // use the parser: read the entire file
-    let header = vertex_parser.read_header(&mut f).unwrap();
+    let header = vertex_parser
+        .read_header(&mut f)
+        .context(format!("Failed to read header of PLY file \"{}\"", filename))?;
     let mut vertex_count = 0;
     let mut face_count = 0;
     let mut has_normals = false;
@@ -38,9 +45,8 @@ pub fn create(o2w: &Transform,
             vertex_count = elem.count;
             if !elem.properties.contains_key("x") || !elem.properties.contains_key("y") ||
                !elem.properties.contains_key("z") {
-                error!("PLY file \"{}\": Vertex coordinate property not found",
-                       filename);
-                return Vec::new();
+                bail!("PLY file \"{}\": Vertex coordinate property not found",
+                      filename);
             }
             if elem.properties.contains_key("nx") && elem.properties.contains_key("ny") &&
                elem.properties.contains_key("nz") {
@@ -60,9 +66,8 @@ pub fn create(o2w: &Transform,
     }
 
     if vertex_count == 0 || face_count == 0 {
-        error!("PLY file \"{}\" is invalid! No face/vertex elements found!",
-               filename);
-        return Vec::new();
+        bail!("PLY file \"{}\" is invalid! No face/vertex elements found!",
+              filename);
     } else {
         info!("Loading PLY file with {} vertices and {} faces",
               vertex_count,
@@ -76,15 +81,15 @@ pub fn create(o2w: &Transform,
             "vertex" => {
                 vertices = vertex_parser
                     .read_payload_for_element(&mut f, elem, &header)
-                    .unwrap();
+                    .context(format!("Failed to read vertices of PLY file \"{}\"", filename))?;
                 // TODO normals + texture
             }
             "face" => {
                 faces = face_parser
                     .read_payload_for_element(&mut f, elem, &header)
-                    .unwrap();
+                    .context(format!("Failed to read faces of PLY file \"{}\"", filename))?;
             }
-            _ => panic!("Unexpected element \"{}\"", elem.name),
+            _ => bail!("PLY file \"{}\": Unexpected element \"{}\"", filename, elem.name),
         }
     }
 
@@ -147,15 +152,16 @@ pub fn create(o2w: &Transform,
         shadow_alpha_mask = Some(Arc::new(ConstantTexture::new(0.0)));
     }
 
-    create_triangle_mesh(o2w,
-                         reverse_orientation,
-                         &vertex_indices,
-                         &p,
-                         None,
-                         if has_normals { Some(&n) } else { None },
-                         if has_texture { Some(&uv) } else { None },
-                         alpha_mask,
-                         shadow_alpha_mask)
+    Ok(create_triangle_mesh(o2w,
+                            reverse_orientation,
+                            &vertex_indices,
+                            &p,
+                            None,
+                            if has_normals { Some(&n) } else { None },
+                            if has_texture { Some(&uv) } else { None },
+                            None,
+                            alpha_mask,
+                            shadow_alpha_mask))
 }
 
 struct Vertex {
@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use {Normal3f, Point2f, Point3f, Transform, Vector3f};
+use bounds::Bounds3f;
+use interaction::{Interaction, SurfaceInteraction};
+use paramset::ParamSet;
+use ray::Ray;
+use sampling::SphericalRectangle;
+use shapes::{Shape, MIN_SOLID_ANGLE};
+
+/// A rectangle in the z=0 plane, centered on the origin, facing +z.
+#[derive(Debug)]
+pub struct Rectangle {
+    x_width: f32,
+    y_width: f32,
+    object_to_world: Transform,
+    world_to_object: Transform,
+    reverse_orientation: bool,
+    transform_swaps_handedness: bool,
+}
+
+impl Rectangle {
+    pub fn new(x_width: f32,
+               y_width: f32,
+               object_to_world: Transform,
+               reverse_orientation: bool)
+               -> Rectangle {
+        assert!(x_width > 0.0 && y_width > 0.0);
+        let transform_swaps_handedness = object_to_world.swaps_handedness();
+        Rectangle {
+            x_width,
+            y_width,
+            world_to_object: object_to_world.inverse(),
+            object_to_world,
+            reverse_orientation,
+            transform_swaps_handedness,
+        }
+    }
+
+    pub fn create(o2w: &Transform,
+                  reverse_orientation: bool,
+                  params: &mut ParamSet)
+                  -> Arc<Shape> {
+        let x_width = params.find_one_float("xwidth", 1.0);
+        let y_width = params.find_one_float("ywidth", 1.0);
+
+        Arc::new(Rectangle::new(x_width, y_width, o2w.clone(), reverse_orientation))
+    }
+
+    /// World space normal of the rectangle, flipped the same way SurfaceInteraction::new() does.
+    fn normal(&self) -> Normal3f {
+        let n = (&self.object_to_world * &Normal3f::new(0.0, 0.0, 1.0)).normalize();
+        if self.reverse_orientation ^ self.transform_swaps_handedness {
+            -n
+        } else {
+            n
+        }
+    }
+
+    /// Corner and edges of the rectangle, in world space.
+    fn world_edges(&self) -> (Point3f, Vector3f, Vector3f) {
+        let corner = &self.object_to_world *
+                     &Point3f::new(-0.5 * self.x_width, -0.5 * self.y_width, 0.0);
+        let ex = &self.object_to_world * &Vector3f::new(self.x_width, 0.0, 0.0);
+        let ey = &self.object_to_world * &Vector3f::new(0.0, self.y_width, 0.0);
+        (corner, ex, ey)
+    }
+
+    fn spherical_rectangle(&self, si: &Interaction) -> Option<SphericalRectangle> {
+        let (corner, ex, ey) = self.world_edges();
+        // Shearing transforms turn the rectangle into a parallelogram
+        if ex.dot(&ey).abs() > 1e-4 * ex.length() * ey.length() {
+            return None;
+        }
+        let rect = SphericalRectangle::new(&si.p, &corner, &ex, &ey);
+        if rect.solid_angle() < MIN_SOLID_ANGLE {
+            return None;
+        }
+        Some(rect)
+    }
+}
+
+impl Shape for Rectangle {
+    fn name(&self) -> &'static str {
+        "Rectangle"
+    }
+
+    fn intersect(&self, r: &Ray) -> Option<(SurfaceInteraction, f32)> {
+        // Transform ray to object space
+        let (ray, _o_err, _d_err) = r.transform(&self.world_to_object);
+        if ray.d.z == 0.0 {
+            // Reject intersection for rays parallel to the rectangle
+            return None;
+        }
+        let t_shape_hit = -ray.o.z / ray.d.z;
+        if t_shape_hit <= 0.0 || t_shape_hit > ray.t_max {
+            return None;
+        }
+        let mut p_hit = ray.at(t_shape_hit);
+        if p_hit.x.abs() > 0.5 * self.x_width || p_hit.y.abs() > 0.5 * self.y_width {
+            return None;
+        }
+        p_hit.z = 0.0;
+
+        // Find parametric representation of the hit
+        let u = p_hit.x / self.x_width + 0.5;
+        let v = p_hit.y / self.y_width + 0.5;
+        let dpdu = Vector3f::new(self.x_width, 0.0, 0.0);
+        let dpdv = Vector3f::new(0.0, self.y_width, 0.0);
+        let dndu = Normal3f::new(0.0, 0.0, 0.0);
+        let dndv = Normal3f::new(0.0, 0.0, 0.0);
+        let p_err = Vector3f::new(0.0, 0.0, 0.0);
+        let isect = SurfaceInteraction::new(p_hit,
+                                            p_err,
+                                            Point2f::new(u, v),
+                                            -ray.d,
+                                            dpdu,
+                                            dpdv,
+                                            dndu,
+                                            dndv,
+                                            self);
+
+        Some((isect.transform(&self.object_to_world), t_shape_hit))
+    }
+
+    fn object_bounds(&self) -> Bounds3f {
+        Bounds3f::from_points(&Point3f::new(-0.5 * self.x_width, -0.5 * self.y_width, 0.0),
+                              &Point3f::new(0.5 * self.x_width, 0.5 * self.y_width, 0.0))
+    }
+
+    fn world_bounds(&self) -> Bounds3f {
+        let (corner, ex, ey) = self.world_edges();
+        let bounds = Bounds3f::from_points(&corner, &(corner + ex));
+        let bounds = Bounds3f::union_point(&bounds, &(corner + ey));
+        Bounds3f::union_point(&bounds, &(corner + ex + ey))
+    }
+
+    fn sample(&self, u: &Point2f) -> (Interaction, Point2f, f32) {
+        let p_obj = Point3f::new((u.x - 0.5) * self.x_width, (u.y - 0.5) * self.y_width, 0.0);
+        let mut it = Interaction::empty();
+        it.n = self.normal();
+        let (p, p_err) = self.object_to_world
+            .transform_point_with_error(&p_obj, &Vector3f::new(0.0, 0.0, 0.0));
+        it.p = p;
+        it.p_error = p_err;
+        let pdf = 1.0 / self.area();
+
+        (it, *u, pdf)
+    }
+
+    fn sample_solid_angle(&self,
+                          si: &Interaction,
+                          u: &Point2f)
+                          -> Option<(Interaction, Point2f, f32)> {
+        let rect = self.spherical_rectangle(si)?;
+        let mut p_obj = &self.world_to_object * &rect.sample(u);
+        p_obj.z = 0.0;
+        let mut it = Interaction::empty();
+        it.n = self.normal();
+        let (p, p_err) = self.object_to_world
+            .transform_point_with_error(&p_obj, &Vector3f::new(0.0, 0.0, 0.0));
+        it.p = p;
+        it.p_error = p_err;
+        let uv = Point2f::new(p_obj.x / self.x_width + 0.5, p_obj.y / self.y_width + 0.5);
+
+        Some((it, uv, 1.0 / rect.solid_angle()))
+    }
+
+    fn pdf_solid_angle(&self, si: &Interaction, wi: &Vector3f) -> Option<f32> {
+        let rect = self.spherical_rectangle(si)?;
+        if self.intersect(&si.spawn_ray(wi)).is_some() {
+            Some(1.0 / rect.solid_angle())
+        } else {
+            Some(0.0)
+        }
+    }
+
+    fn area(&self) -> f32 {
+        let (_, ex, ey) = self.world_edges();
+        ex.cross(&ey).length()
+    }
+
+    fn reverse_orientation(&self) -> bool {
+        self.reverse_orientation
+    }
+
+    fn transform_swaps_handedness(&self) -> bool {
+        self.transform_swaps_handedness
+    }
+}
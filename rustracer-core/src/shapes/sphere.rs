@@ -9,8 +9,29 @@ use geometry::{distance, distance_squared, offset_ray_origin, spherical_directio
 use interaction::{Interaction, SurfaceInteraction};
 use paramset::ParamSet;
 use ray::Ray;
-use sampling::{uniform_sample_sphere, uniform_cone_pdf};
-use shapes::Shape;
+use sampling::uniform_sample_sphere;
+use shapes::{n_quadric_hits, Shape};
+
+/// Below this squared sine of their half-angle, cones are sampled using sines rather than cosines,
+/// which are too close to 1 to be precise enough.
+const SMALL_CONE_SIN2: f32 = 0.000_685_23;
+
+/// Pdf of sampling directions uniformly in a cone, given the squared sine and the cosine of its
+/// half-angle.
+fn cone_pdf(sin_theta_max_2: f32, cos_theta_max: f32) -> f32 {
+    let one_minus_cos = if sin_theta_max_2 < SMALL_CONE_SIN2 {
+        // 1 - cos(x) ~= sin(x)^2 / 2 for small x
+        sin_theta_max_2 / 2.0
+    } else {
+        1.0 - cos_theta_max
+    };
+    1.0 / (2.0 * consts::PI * one_minus_cos)
+}
+
+stat_percent!("Intersections/Ray-sphere intersection tests", n_hits);
+pub fn init_stats() {
+    n_hits::init();
+}
 
 #[derive(Debug)]
 pub struct Sphere {
@@ -50,6 +71,37 @@ impl Sphere {
     }
 
 
+    /// Cone of directions the sphere subtends as seen from `si`: the sphere's center, and the
+    /// squared sine and the cosine of the cone's half-angle. There is no such cone from inside the
+    /// sphere, and partial spheres don't fill it, so this returns `None` in those cases.
+    fn subtended_cone(&self, si: &Interaction) -> Option<(Point3f, f32, f32)> {
+        if self.z_min > -self.radius || self.z_max < self.radius ||
+           self.phi_max < 2.0 * consts::PI * (1.0 - 1e-5) {
+            return None;
+        }
+        let p_center = &self.object_to_world * &Point3f::new(0.0, 0.0, 0.0);
+        let p_origin = offset_ray_origin(&si.p, &si.p_error, &si.n, &(p_center - si.p));
+        if distance_squared(&p_origin, &p_center) <= self.radius * self.radius {
+            return None;
+        }
+
+        let sin_theta_max_2 = self.radius * self.radius / distance_squared(&si.p, &p_center);
+        let cos_theta_max = f32::sqrt(f32::max(0.0, 1.0 - sin_theta_max_2));
+        Some((p_center, sin_theta_max_2, cos_theta_max))
+    }
+
+    /// (u, v) coordinates of the object space point `p` on the sphere, as computed by
+    /// `intersect()`.
+    fn uv(&self, p: &Point3f) -> Point2f {
+        let mut phi = f32::atan2(p.x, p.y);
+        if phi < 0.0 {
+            phi += 2.0 * consts::PI;
+        }
+        let theta = clamp(p.z / self.radius, -1.0, 1.0).acos();
+        Point2f::new(phi / self.phi_max,
+                     (theta - self.theta_min) / (self.theta_max - self.theta_min))
+    }
+
     pub fn create(o2w: &Transform,
                   reverse_orientation: bool,
                   params: &mut ParamSet)
@@ -65,7 +117,14 @@ impl Sphere {
 }
 
 impl Shape for Sphere {
+    fn name(&self) -> &'static str {
+        "Sphere"
+    }
+
     fn intersect(&self, ray: &Ray) -> Option<(SurfaceInteraction, f32)> {
+        n_hits::inc_total();
+        n_quadric_hits::inc_total();
+
         // Transform ray into object space
         let (r, o_err, d_err) = ray.transform(&self.world_to_object);
 
@@ -186,6 +245,8 @@ impl Shape for Sphere {
                                                 dndu,
                                                 dndv,
                                                 self);
+            n_hits::inc();
+            n_quadric_hits::inc();
             Some((isect.transform(&self.object_to_world), t_shape_hit.into()))
         })
     }
@@ -212,12 +273,15 @@ impl Shape for Sphere {
         bounds
     }
 
-    fn sample(&self, u: &Point2f) -> (Interaction, f32) {
+    fn sample(&self, u: &Point2f) -> (Interaction, Point2f, f32) {
         let mut p_obj = Point3f::new(0.0, 0.0, 0.0) + self.radius * uniform_sample_sphere(u);
         let mut it = Interaction::empty();
         it.n = self.object_to_world
             .transform_normal(&Normal3f::new(p_obj.x, p_obj.y, p_obj.z))
             .normalize();
+        if self.reverse_orientation ^ self.transform_swaps_handedness {
+            it.n *= -1.0;
+        }
         p_obj = p_obj * self.radius / distance(&p_obj, &Point3f::new(0.0, 0.0, 0.0));
         let p_obj_error = gamma(5) * Vector3f::from(p_obj).abs();
         let (p, p_err) = self.object_to_world
@@ -225,49 +289,34 @@ impl Shape for Sphere {
         it.p = p;
         it.p_error = p_err;
         let pdf = 1.0 / self.area();
-        (it, pdf)
+        (it, self.uv(&p_obj), pdf)
     }
 
-    fn sample_si(&self, si: &Interaction, u: &Point2f) -> (Interaction, f32) {
-        let p_center = &self.object_to_world * &Point3f::new(0.0, 0.0, 0.0);
-
-        // Sample uniformly on sphere if `pt` is inside it
-        let p_origin = offset_ray_origin(&si.p, &si.p_error, &si.n, &(p_center - si.p));
-        if distance_squared(&p_origin, &p_center) <= self.radius * self.radius {
-            let (intr, mut pdf) = self.sample(u);
-            let mut wi = intr.p - si.p;
-            if wi.length_squared() == 0.0 {
-                pdf = 0.0;
-            } else {
-                // Convert from area measure returned by sample() call above to solid angle measure.
-                wi = wi.normalize();
-                pdf *= distance_squared(&si.p, &intr.p) / intr.n.dot(&(-wi)).abs();
-            }
-            if pdf.is_infinite() {
-                pdf = 0.0;
-            }
-
-            return (intr, pdf);
-        }
+    fn sample_solid_angle(&self,
+                          si: &Interaction,
+                          u: &Point2f)
+                          -> Option<(Interaction, Point2f, f32)> {
+        let (p_center, sin_theta_max_2, cos_theta_max) = self.subtended_cone(si)?;
 
         // Compute coordinate system for sphere sampling
         let wc = (p_center - si.p).normalize();
         let (wc_x, wc_y) = coordinate_system(&wc);
 
-        // Sample sphere uniformly inside subtended cone
-
         // Compute `theta` and `phi` values for sample in cone
-        let sin_theta_max_2 = self.radius * self.radius / distance_squared(&si.p, &p_center);
-        let cos_theta_max = f32::sqrt(f32::max(0.0, 1.0 - sin_theta_max_2));
-        let cos_theta = (1.0 - u[0]) + u[0] * cos_theta_max;
-        let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - cos_theta * cos_theta));
+        let (sin_theta_2, cos_theta) = if sin_theta_max_2 < SMALL_CONE_SIN2 {
+            // Compute from sin^2 directly: 1 - cos^2 loses all precision for tiny cones
+            let sin_theta_2 = sin_theta_max_2 * u[0];
+            (sin_theta_2, f32::sqrt(1.0 - sin_theta_2))
+        } else {
+            let cos_theta = (1.0 - u[0]) + u[0] * cos_theta_max;
+            (f32::max(0.0, 1.0 - cos_theta * cos_theta), cos_theta)
+        };
         let phi = u[1] * 2.0 * consts::PI;
 
         // Compute angle `alpha` from center of sphere to sampled point on surface
         let dc = distance(&si.p, &p_center);
         let ds = dc * cos_theta -
-                 f32::sqrt(f32::max(0.0,
-                                    self.radius * self.radius - dc * dc * sin_theta * sin_theta));
+                 f32::sqrt(f32::max(0.0, self.radius * self.radius - dc * dc * sin_theta_2));
         let cos_alpha = (dc * dc + self.radius * self.radius - ds * ds) / (2.0 * dc * self.radius);
         let sin_alpha = f32::sqrt(f32::max(0.0, 1.0 - cos_alpha * cos_alpha));
 
@@ -281,29 +330,20 @@ impl Shape for Sphere {
         it.p = p_world;
         it.p_error = gamma(5) * Vector3f::from(p_world).abs();
         it.n = Normal3f::from(n_world);
-        if self.reverse_orientation {
+        if self.reverse_orientation ^ self.transform_swaps_handedness {
             it.n *= -1.0;
         }
 
-        // Uniform cone PDF.
-        let pdf = 1.0 / (2.0 * consts::PI * (1.0 - cos_theta_max));
+        let uv = self.uv(&(&self.world_to_object * &p_world));
 
-        (it, pdf)
+        Some((it, uv, cone_pdf(sin_theta_max_2, cos_theta_max)))
     }
 
-    fn pdf_wi(&self, si: &Interaction, wi: &Vector3f) -> f32 {
-        let p_center = &self.object_to_world * &Point3f::new(0.0, 0.0, 0.0);
-        // Return uniform PDF if point is inside the sphere
-        let p_origin = offset_ray_origin(&si.p, &si.p_error, &si.n, &(p_center - si.p));
-        if distance_squared(&p_origin, &p_center) <= self.radius * self.radius {
-            return Shape::pdf_wi(self, si, wi);
-        }
-
-        // Compute general sphere PDF
-        let sin_theta_max_2 = self.radius * self.radius / distance_squared(&si.p, &p_center);
-        let cos_theta_max = f32::sqrt(f32::max(0.0, 1.0 - sin_theta_max_2));
-
-        uniform_cone_pdf(cos_theta_max)
+    fn pdf_solid_angle(&self, si: &Interaction, _wi: &Vector3f) -> Option<f32> {
+        self.subtended_cone(si)
+            .map(|(_, sin_theta_max_2, cos_theta_max)| {
+                     cone_pdf(sin_theta_max_2, cos_theta_max)
+                 })
     }
 
     fn area(&self) -> f32 {
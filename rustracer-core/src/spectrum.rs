@@ -52,16 +52,9 @@ impl Spectrum {
     /// Convert this linear RGB spectrum to non-linear sRGB and return the result as an array of
     /// bytes.
     pub fn to_srgb(&self) -> [u8; 3] {
-        let a = 0.055f32;
-        let b = 1f32 / 2.4;
         let mut srgb = [0; 3];
         for i in 0..3 {
-            let v = if self[i] <= 0.0031308 {
-                12.92 * self[i]
-            } else {
-                (1.0 + a) * f32::powf(self[i], b) - a
-            };
-            srgb[i] = clamp(v * 255.0 + 0.5, 0.0, 255.0) as u8;
+            srgb[i] = clamp(gamma_correct(self[i]) * 255.0 + 0.5, 0.0, 255.0) as u8;
         }
         srgb
     }
@@ -92,6 +85,8 @@ impl Spectrum {
         Spectrum::rgb(r, g, b)
     }
 
+    /// Convert a linear RGB spectrum to XYZ. The Y component is the luminance returned by
+    /// `y()`.
     pub fn to_xyz(&self) -> [f32; 3] {
         let mut xyz = [0.0, 0.0, 0.0];
 
@@ -122,6 +117,27 @@ impl Spectrum {
         Self::from_xyz(&xyz)
     }
 
+    /// Colour of the light emitted by a blackbody at `temperature` Kelvin, following Planck's
+    /// law, normalized to a luminance of 1 so that only its hue depends on the temperature.
+    pub fn from_blackbody(temperature: f32) -> Spectrum {
+        if temperature <= 0.0 {
+            return Spectrum::black();
+        }
+        let mut xyz = [0.0; 3];
+        for i in 0..cie::N_CIE_SAMPLES {
+            let le = blackbody(cie::CIE_LAMBDA[i], temperature);
+            xyz[0] += le * cie::CIE_X[i];
+            xyz[1] += le * cie::CIE_Y[i];
+            xyz[2] += le * cie::CIE_Z[i];
+        }
+        let scale = 1.0 / xyz[1];
+        xyz[0] *= scale;
+        xyz[1] *= scale;
+        xyz[2] *= scale;
+
+        Self::from_xyz(&xyz)
+    }
+
     /// Return true if the colour is black i.e (0, 0 ,0).
     pub fn is_black(&self) -> bool {
         self.r == 0.0 && self.g == 0.0 && self.b == 0.0
@@ -152,10 +168,27 @@ impl Spectrum {
         self.r.max(self.g).max(self.b)
     }
 
-    pub fn clamp(&self) -> Spectrum {
-        Spectrum::rgb(clamp(self.r, 0.0, f32::INFINITY),
-                      clamp(self.g, 0.0, f32::INFINITY),
-                      clamp(self.b, 0.0, f32::INFINITY))
+    /// Largest of the components (same as `max_component_value()`).
+    pub fn max_component(&self) -> f32 {
+        self.max_component_value()
+    }
+
+    /// Linearly interpolate each component between `a` (for `t = 0`) and `b` (for `t = 1`).
+    pub fn lerp(t: f32, a: Spectrum, b: Spectrum) -> Spectrum {
+        lerp(t, a, b)
+    }
+
+    /// Clamp each component to `[min, max]`. Use `clamp(0.0, f32::INFINITY)` to remove negative
+    /// components.
+    pub fn clamp(&self, min: f32, max: f32) -> Spectrum {
+        Spectrum::rgb(clamp(self.r, min, max),
+                      clamp(self.g, min, max),
+                      clamp(self.b, min, max))
+    }
+
+    /// Return a spectrum where each component is the exponential of the original component.
+    pub fn exp(&self) -> Spectrum {
+        Spectrum::rgb(self.r.exp(), self.g.exp(), self.b.exp())
     }
 }
 
@@ -350,6 +383,7 @@ pub fn inverse_gamma_convert_float(v: f32) -> f32 {
     }
 }
 
+/// Apply the sRGB transfer function to a linear value.
 pub fn gamma_correct(v: f32) -> f32 {
     if v <= 0.0031308 {
         12.92 * v
@@ -357,3 +391,130 @@ pub fn gamma_correct(v: f32) -> f32 {
         1.055 * f32::powf(v, 1.0 / 2.4) - 0.055
     }
 }
+
+/// Spectral radiance emitted at wavelength `lambda` (in nm) by a blackbody at temperature `t`
+/// (in Kelvin), as given by Planck's law.
+fn blackbody(lambda: f32, t: f32) -> f32 {
+    const C: f64 = 299_792_458.0;
+    const H: f64 = 6.626_069_57e-34;
+    const KB: f64 = 1.380_648_8e-23;
+    // Compute in double precision, as the exponential overflows single precision floats at low
+    // temperatures
+    let l = f64::from(lambda) * 1e-9;
+    let le = (2.0 * H * C * C) / (l.powi(5) * (((H * C) / (l * KB * f64::from(t))).exp() - 1.0));
+    le as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts;
+
+    #[test]
+    fn test_to_srgb() {
+        // Reference values for the sRGB transfer function
+        assert_eq!(Spectrum::grey(0.0).to_srgb(), [0, 0, 0]);
+        assert_eq!(Spectrum::grey(1.0).to_srgb(), [255, 255, 255]);
+        assert_eq!(Spectrum::grey(0.5).to_srgb(), [188, 188, 188]);
+        assert_eq!(Spectrum::grey(0.214).to_srgb(), [127, 127, 127]);
+        assert_eq!(Spectrum::grey(0.001).to_srgb(), [3, 3, 3]);
+        assert_eq!(Spectrum::rgb(0.0031308, 0.18, 0.8).to_srgb(), [10, 118, 231]);
+        // Out of range values get clamped
+        assert_eq!(Spectrum::rgb(-1.0, 2.0, 1000.0).to_srgb(), [0, 255, 255]);
+    }
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for v in 0..256 {
+            let rgb = [v as u8, v as u8, v as u8];
+            assert_eq!(Spectrum::from_srgb(&rgb).to_srgb(), rgb);
+        }
+    }
+
+    #[test]
+    fn test_luminance() {
+        let (r, g, b) = (Spectrum::red().y(), Spectrum::green().y(), Spectrum::blue().y());
+        assert!(g > r && r > b);
+        assert!((Spectrum::white().y() - 1.0).abs() < 1e-5);
+
+        let s = Spectrum::rgb(0.2, 0.5, 0.9);
+        assert!((s.to_xyz()[1] - s.y()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_xyz_round_trip() {
+        // sRGB white is the D65 white point
+        let xyz = Spectrum::white().to_xyz();
+        assert!((xyz[0] - 0.950456).abs() < 1e-4);
+        assert!((xyz[2] - 1.088754).abs() < 1e-4);
+
+        let s = Spectrum::rgb(0.2, 0.5, 0.9);
+        let back = Spectrum::from_xyz(&s.to_xyz());
+        assert!((back.r - s.r).abs() < 1e-4);
+        assert!((back.g - s.g).abs() < 1e-4);
+        assert!((back.b - s.b).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_blackbody() {
+        // Close to D65, the white point of sRGB
+        let neutral = Spectrum::from_blackbody(6500.0);
+        assert!((neutral.y() - 1.0).abs() < 1e-3);
+        assert!(neutral.max_component_value() / neutral.r.min(neutral.g).min(neutral.b) < 1.1,
+                "{:?}",
+                neutral);
+
+        // Candle light is orange
+        let warm = Spectrum::from_blackbody(2000.0);
+        assert!((warm.y() - 1.0).abs() < 1e-3);
+        assert!(warm.r > 2.0 * warm.g && warm.g > 2.0 * warm.b, "{:?}", warm);
+
+        // And a blue sky is blue
+        let cold = Spectrum::from_blackbody(15000.0);
+        assert!(cold.b > cold.g && cold.g > cold.r, "{:?}", cold);
+    }
+
+    #[test]
+    fn test_clamp() {
+        let s = Spectrum::rgb(-0.5, 0.25, 3.0);
+        assert_eq!(s.clamp(0.0, f32::INFINITY), Spectrum::rgb(0.0, 0.25, 3.0));
+        assert_eq!(s.clamp(0.0, 1.0), Spectrum::rgb(0.0, 0.25, 1.0));
+        assert_eq!(s.clamp(0.5, 0.5), Spectrum::grey(0.5));
+    }
+
+    #[test]
+    fn test_exp() {
+        let s = Spectrum::rgb(0.0, 1.0, -2.0).exp();
+        assert_eq!(s.r, 1.0);
+        assert!((s.g - consts::E).abs() < 1e-6);
+        assert!((s.b - (-2.0f32).exp()).abs() < 1e-6);
+        // Transmittance through a medium: exp(-sigma_t * d)
+        let tr = (Spectrum::rgb(0.0, 0.5, 1.0) * -2.0).exp();
+        assert!(tr.r == 1.0 && tr.g > tr.b && tr.b > 0.0);
+    }
+
+    #[test]
+    fn test_lerp() {
+        let a = Spectrum::rgb(0.0, 1.0, 2.0);
+        let b = Spectrum::rgb(1.0, 1.0, 0.0);
+        assert_eq!(Spectrum::lerp(0.0, a, b), a);
+        assert_eq!(Spectrum::lerp(1.0, a, b), b);
+        assert_eq!(Spectrum::lerp(0.25, a, b), Spectrum::rgb(0.25, 1.0, 1.5));
+        // Same as the generic `lerp()`
+        assert_eq!(Spectrum::lerp(0.75, a, b), lerp(0.75, a, b));
+    }
+
+    #[test]
+    fn test_max_component_value() {
+        assert_eq!(Spectrum::rgb(0.1, 0.7, 0.3).max_component_value(), 0.7);
+        assert_eq!(Spectrum::rgb(-1.0, -2.0, -0.5).max_component_value(), -0.5);
+        assert_eq!(Spectrum::black().max_component_value(), 0.0);
+    }
+
+    #[test]
+    fn test_max_component() {
+        assert_eq!(Spectrum::rgb(0.1, 0.7, 0.3).max_component(), 0.7);
+        assert_eq!(Spectrum::rgb(2.0, -1.0, 0.5).max_component(), 2.0);
+        assert_eq!(Spectrum::rgb(-1.0, -2.0, -0.5).max_component(), -0.5);
+    }
+}
@@ -22,6 +22,19 @@ macro_rules! stat_counter(
                 v.set(v.get() + 1);
             }
 
+            #[allow(dead_code)]
+            #[inline(always)]
+            pub fn add(a: u64) {
+                let v = VALUE.get();
+                v.set(v.get() + a);
+            }
+
+            /// Value of the counter for the current thread
+            #[allow(dead_code)]
+            pub fn get() -> u64 {
+                VALUE.get().get()
+            }
+
             pub fn report(acc: &mut StatAccumulator) {
                 acc.report_counter($d, VALUE.get().get());
             }
@@ -53,6 +66,12 @@ macro_rules! stat_memory_counter(
                 v.set(v.get() + a);
             }
 
+            /// Value of the counter for the current thread
+            #[allow(dead_code)]
+            pub fn get() -> u64 {
+                VALUE.get().get()
+            }
+
             pub fn report(acc: &mut StatAccumulator) {
                 acc.report_memory_counter($d, VALUE.get().get());
             }
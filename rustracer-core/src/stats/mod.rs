@@ -1,6 +1,7 @@
 use std::collections::{HashMap, BTreeMap};
 
 use parking_lot::Mutex;
+use serde_json::Value;
 use state::Storage;
 
 #[macro_use]
@@ -24,11 +25,21 @@ impl StatAccumulator {
         *counter += value;
     }
 
+    /// Accumulated value of the given counter, or 0 if it was never reported.
+    pub fn counter(&self, name: &str) -> u64 {
+        self.counters.get(name).cloned().unwrap_or(0)
+    }
+
     pub fn report_memory_counter(&mut self, name: &str, value: u64) {
         let counter = self.memory_counters.entry(name.to_owned()).or_insert(0);
         *counter += value;
     }
 
+    /// Accumulated value of the given memory counter in bytes, or 0 if it was never reported.
+    pub fn memory_counter(&self, name: &str) -> u64 {
+        self.memory_counters.get(name).cloned().unwrap_or(0)
+    }
+
     pub fn report_int_distribution(&mut self,
                                    name: &str,
                                    sum: u64,
@@ -69,6 +80,12 @@ impl StatAccumulator {
         (*frac).1 += denom;
     }
 
+    /// Accumulated numerator and denominator of the given percentage, or (0, 0) if it was never
+    /// reported.
+    pub fn percentage(&self, name: &str) -> (u64, u64) {
+        self.percentages.get(name).cloned().unwrap_or((0, 0))
+    }
+
     pub fn report_ratio(&mut self, name: &str, num: u64, denom: u64) {
         let frac = self.ratios.entry(name.to_owned()).or_insert((0, 0));
         (*frac).0 += num;
@@ -178,6 +195,36 @@ impl StatAccumulator {
         }
     }
 
+    /// All the statistics as a JSON object, with one member per kind of statistic, each mapping
+    /// the statistics' descriptions to their values.
+    pub fn to_json(&self) -> Value {
+        let distributions: BTreeMap<_, _> = self.int_distribution_sums
+            .iter()
+            .map(|(desc, sum)| {
+                (desc.clone(),
+                 json!({
+                    "sum": sum,
+                    "count": self.int_distribution_counts[desc],
+                    "min": self.int_distribution_mins[desc],
+                    "max": self.int_distribution_maxs[desc],
+                }))
+            })
+            .collect();
+        let sorted = |m: &HashMap<String, u64>| m.iter().collect::<BTreeMap<_, _>>();
+        let fractions = |m: &HashMap<String, (u64, u64)>| {
+            m.iter()
+                .map(|(desc, &(num, denom))| (desc.clone(), json!([num, denom])))
+                .collect::<BTreeMap<_, _>>()
+        };
+        json!({
+            "counters": sorted(&self.counters),
+            "memory_counters": sorted(&self.memory_counters),
+            "int_distributions": distributions,
+            "percentages": fractions(&self.percentages),
+            "ratios": fractions(&self.ratios),
+        })
+    }
+
     fn get_category_and_title<'a>(&self, s: &'a str) -> (&'a str, &'a str) {
         let v: Vec<&'a str> = s.split('/').collect();
         if v.len() > 1 { (v[0], v[1]) } else { ("", s) }
@@ -201,6 +248,21 @@ pub fn report_stats() {
     }
 }
 
+/// Statistics of the current thread only, leaving the global accumulator untouched.
+pub fn thread_stats() -> StatAccumulator {
+    let vec = STAT_REPORTERS.get().lock();
+    let mut acc = StatAccumulator::default();
+    for f in &(*vec) {
+        f(&mut acc);
+    }
+    acc
+}
+
+/// The statistics gathered by `report_stats()`, as JSON.
+pub fn stats_json() -> Value {
+    STAT_ACCUMULATOR.get().lock().to_json()
+}
+
 pub fn print_stats() {
     let acc = STAT_ACCUMULATOR.get().lock();
     (*acc).print_stats();
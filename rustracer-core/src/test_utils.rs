@@ -0,0 +1,142 @@
+//! Helpers to create materials, textures and primitives and evaluate them in tests.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use light_arena::Allocator;
+
+use {Point3f, Transform, Vector3f};
+use api::{Array, ParamListEntry, ParamType};
+use bounds::Bounds3f;
+use interaction::SurfaceInteraction;
+use light::AreaLight;
+use material::{Material, TransportMode};
+use paramset::{ParamSet, TextureParams};
+use primitive::{GeometricPrimitive, Primitive};
+use ray::Ray;
+use shapes::{Disk, Shape, Sphere};
+use spectrum::Spectrum;
+use texture::Texture;
+
+pub fn rgb_param(name: &str, rgb: [f32; 3]) -> ParamListEntry {
+    ParamListEntry::new(ParamType::Rgb, name.to_owned(), Array::NumArray(rgb.to_vec()))
+}
+
+pub fn float_param(name: &str, v: f32) -> ParamListEntry {
+    ParamListEntry::new(ParamType::Float, name.to_owned(), Array::NumArray(vec![v]))
+}
+
+/// Call `f` with `TextureParams` made of the given material parameters and named textures.
+pub fn with_textures<F, R>(params: Vec<ParamListEntry>,
+                           float_textures: &HashMap<String, Arc<Texture<f32>>>,
+                           spectrum_textures: &HashMap<String, Arc<Texture<Spectrum>>>,
+                           f: F)
+                           -> R
+    where F: FnOnce(&mut TextureParams) -> R
+{
+    let mut geom_params = ParamSet::default();
+    let mut material_params = ParamSet::default();
+    material_params.init(params);
+    let mut tp = TextureParams::new(&mut geom_params,
+                                    &mut material_params,
+                                    float_textures,
+                                    spectrum_textures);
+    f(&mut tp)
+}
+
+/// Same as `with_textures()`, without any named textures.
+pub fn with_texture_params<F, R>(params: Vec<ParamListEntry>, f: F) -> R
+    where F: FnOnce(&mut TextureParams) -> R
+{
+    with_textures(params, &HashMap::new(), &HashMap::new(), f)
+}
+
+/// Create a material with the given material parameters and no named textures.
+pub fn create_material<F>(params: Vec<ParamListEntry>, create: F) -> Arc<Material>
+    where F: FnOnce(&mut TextureParams) -> Arc<Material>
+{
+    with_texture_params(params, create)
+}
+
+/// An intersection with a disk facing +z, hit from above.
+pub fn hit_disk<'a, 'b>() -> SurfaceInteraction<'a, 'b> {
+    lazy_static! {
+        static ref DISK: Disk = Disk::new(0.0, 1.0, 0.0, 360.0, Transform::default(), false);
+    }
+    let ray = Ray::new(Point3f::new(0.1, 0.2, 1.0), Vector3f::new(0.0, 0.0, -1.0));
+    DISK.intersect(&ray).unwrap().0
+}
+
+/// A primitive that counts the shadow rays it is tested against, and how many it blocked.
+#[derive(Debug)]
+pub struct CountingPrimitive {
+    prim: GeometricPrimitive,
+    n_shadow_tests: AtomicUsize,
+    n_blocked: AtomicUsize,
+}
+
+impl CountingPrimitive {
+    pub fn sphere(center: Vector3f, radius: f32, material: Arc<Material>) -> CountingPrimitive {
+        let sphere = Sphere::new(Transform::translate(&center),
+                                 radius,
+                                 -radius,
+                                 radius,
+                                 360.0,
+                                 false);
+        CountingPrimitive {
+            prim: GeometricPrimitive {
+                shape: Arc::new(sphere),
+                area_light: None,
+                material: Some(material),
+            },
+            n_shadow_tests: AtomicUsize::new(0),
+            n_blocked: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn n_blocked(&self) -> usize {
+        self.n_blocked.load(Ordering::SeqCst)
+    }
+
+    /// Return the number of shadow rays tested since the last call.
+    pub fn take_shadow_tests(&self) -> usize {
+        self.n_shadow_tests.swap(0, Ordering::SeqCst)
+    }
+}
+
+impl Primitive for CountingPrimitive {
+    fn world_bounds(&self) -> Bounds3f {
+        self.prim.world_bounds()
+    }
+
+    fn intersect(&self, ray: &mut Ray) -> Option<SurfaceInteraction> {
+        self.prim.intersect(ray)
+    }
+
+    fn intersect_p(&self, ray: &Ray) -> bool {
+        self.n_shadow_tests.fetch_add(1, Ordering::SeqCst);
+        let hit = self.prim.intersect_p(ray);
+        if hit {
+            self.n_blocked.fetch_add(1, Ordering::SeqCst);
+        }
+        hit
+    }
+
+    fn area_light(&self) -> Option<Arc<AreaLight>> {
+        self.prim.area_light()
+    }
+
+    fn material(&self) -> Option<Arc<Material>> {
+        self.prim.material()
+    }
+
+    fn compute_scattering_functions<'a, 'b>(&self,
+                                            isect: &mut SurfaceInteraction<'a, 'b>,
+                                            mode: TransportMode,
+                                            allow_multiple_lobes: bool,
+                                            arena: &'b Allocator) {
+        self.prim
+            .compute_scattering_functions(isect, mode, allow_multiple_lobes, arena)
+    }
+}
@@ -6,7 +6,7 @@ use Transform;
 use interaction::SurfaceInteraction;
 use paramset::TextureParams;
 use spectrum::Spectrum;
-use texture::{Texture, TextureMapping2D, UVMapping2D};
+use texture::{IdentityMapping3D, Texture, TextureMapping2D, TextureMapping3D, UVMapping2D};
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum AAMethod {
@@ -38,9 +38,11 @@ impl<T> CheckerboardTexture<T> {
 }
 
 impl CheckerboardTexture<Spectrum> {
-    pub fn create_spectrum(_tex2world: &Transform,
+    /// Create a checkerboard following the surface's (u, v) coordinates, or a solid one with
+    /// `"integer dimension" [3]`.
+    pub fn create_spectrum(tex2world: &Transform,
                            tp: &mut TextureParams)
-                           -> CheckerboardTexture<Spectrum> {
+                           -> Arc<Texture<Spectrum>> {
         let dim = tp.find_int("dimension", 2);
         if dim != 2 && dim != 3 {
             panic!("{} dimensional checkerboard texture not supported", dim);
@@ -78,9 +80,11 @@ impl CheckerboardTexture<Spectrum> {
                       aa);
                 AAMethod::ClosedForm
             };
-            CheckerboardTexture::new(tex1, tex2, Box::new(map), aa_method)
+            Arc::new(CheckerboardTexture::new(tex1, tex2, Box::new(map), aa_method))
         } else {
-            unimplemented!()
+            // Initialize 3D texture mapping `map` from `tp`
+            let map = IdentityMapping3D::new(tex2world.clone());
+            Arc::new(Checkerboard3DTexture::new(tex1, tex2, Box::new(map)))
         }
     }
 }
@@ -94,7 +98,7 @@ impl<T> Texture<T> for CheckerboardTexture<T>
         let (st, dstdx, dstdy) = self.mapping.map(si);
         match self.aa_method {
             AAMethod::None => {
-                if (st.x.floor() as u32 + st.y.floor() as u32) % 2 == 0 {
+                if (st.x.floor() as i32 + st.y.floor() as i32) % 2 == 0 {
                     self.tex1.evaluate(si)
                 } else {
                     self.tex2.evaluate(si)
@@ -125,8 +129,15 @@ impl<T> Texture<T> for CheckerboardTexture<T>
                      2.0 * f32::max(x / 2.0 - f32::floor(x / 2.0) - 0.5, 0.0)) as
                     i32
                 }
-                let sint = (bump_int(s1) - bump_int(s0)) as f32 / (2.0 * ds);
-                let tint = (bump_int(t1) - bump_int(t0)) as f32 / (2.0 * dt);
+                // The fraction of the footprint covered by odd checks along each axis. A footprint
+                // with no width along one of them (e.g. edge-on) only sees the check it's in.
+                let odd_fraction = |x0: f32, x1: f32, x: f32, dx: f32| if dx > 0.0 {
+                    (bump_int(x1) - bump_int(x0)) as f32 / (2.0 * dx)
+                } else {
+                    (f32::floor(x) as i32 & 1) as f32
+                };
+                let sint = odd_fraction(s0, s1, st[0], ds);
+                let tint = odd_fraction(t0, t1, st[1], dt);
                 let mut area2 = sint + tint - 2.0 * sint * tint;
                 if ds > 1.0 || dt > 1.0 {
                     area2 = 0.5
@@ -136,3 +147,122 @@ impl<T> Texture<T> for CheckerboardTexture<T>
         }
     }
 }
+
+/// A solid checkerboard of unit cubes in texture space, which is consistent across seams in the
+/// (u, v) parameterization, as if the object was carved out of a block.
+#[derive(Debug)]
+pub struct Checkerboard3DTexture<T> {
+    tex1: Arc<Texture<T>>,
+    tex2: Arc<Texture<T>>,
+    mapping: Box<TextureMapping3D>,
+}
+
+impl<T> Checkerboard3DTexture<T> {
+    pub fn new(tex1: Arc<Texture<T>>,
+               tex2: Arc<Texture<T>>,
+               mapping: Box<TextureMapping3D>)
+               -> Checkerboard3DTexture<T> {
+        Checkerboard3DTexture {
+            tex1,
+            tex2,
+            mapping,
+        }
+    }
+}
+
+impl<T> Texture<T> for Checkerboard3DTexture<T>
+    where T: Debug
+{
+    fn evaluate(&self, si: &SurfaceInteraction) -> T {
+        let (p, _dpdx, _dpdy) = self.mapping.map(si);
+        if (p.x.floor() as i32 + p.y.floor() as i32 + p.z.floor() as i32) % 2 == 0 {
+            self.tex1.evaluate(si)
+        } else {
+            self.tex2.evaluate(si)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use {Point2f, Point3f, Vector3f};
+    use api::{Array, ParamListEntry, ParamType};
+    use ray::{Ray, RayDifferential};
+    use shapes::{Plane, Shape, Sphere};
+    use test_utils::with_texture_params;
+
+    fn checkerboard(params: Vec<ParamListEntry>) -> Arc<Texture<Spectrum>> {
+        with_texture_params(params,
+                            |tp| CheckerboardTexture::create_spectrum(&Transform::default(), tp))
+    }
+
+    #[test]
+    fn test_solid_checkerboard_ignores_uv() {
+        ::init_stats();
+        let dimension = ParamListEntry::new(ParamType::Int,
+                                            "dimension".to_owned(),
+                                            Array::NumArray(vec![3.0]));
+        let tex = checkerboard(vec![dimension]);
+
+        let sphere = Sphere::new(Transform::default(), 1.5, -1.5, 1.5, 360.0, false);
+        let ray = Ray::new(Point3f::new(0.5, 0.5, -3.0), Vector3f::new(0.0, 0.0, 1.0));
+        let (mut si, _) = sphere.intersect(&ray).unwrap();
+        let colour = tex.evaluate(&si);
+        // Same point, as if it was on the other side of a seam
+        for uv in &[Point2f::new(0.0, 0.0), Point2f::new(0.5, 0.5), Point2f::new(0.99, 0.25)] {
+            si.uv = *uv;
+            assert_eq!(tex.evaluate(&si), colour);
+        }
+
+        // The checks alternate along each axis
+        let mut other_check = si.clone();
+        other_check.hit.p = si.hit.p + Vector3f::new(1.0, 0.0, 0.0);
+        assert_ne!(tex.evaluate(&other_check), colour);
+        other_check.hit.p = si.hit.p + Vector3f::new(1.0, 0.0, 1.0);
+        assert_eq!(tex.evaluate(&other_check), colour);
+    }
+
+    /// Evaluate `tex` where a ray from `o` along `d` hits the ground, with differentials
+    /// for rays a thousandth of a radian apart, as a camera would generate.
+    fn evaluate_on_ground(tex: &Texture<Spectrum>, o: Point3f, d: Vector3f) -> Spectrum {
+        let ground = Plane::new(Point3f::new(0.0, 0.0, 0.0), Vector3f::new(0.0, 0.0, 1.0), false);
+        let d = d.normalize();
+        let mut ray = Ray::new(o, d);
+        let (dx, dy) = ::coordinate_system(&d);
+        ray.differential = Some(RayDifferential {
+                                    rx_origin: o,
+                                    ry_origin: o,
+                                    rx_direction: d + 1e-3 * dx,
+                                    ry_direction: d + 1e-3 * dy,
+                                });
+        let (mut si, _) = ground.intersect(&ray).unwrap();
+        si.compute_differential(&ray);
+        tex.evaluate(&si)
+    }
+
+    #[test]
+    fn test_closed_form_filters_distant_checks() {
+        ::init_stats();
+        let closed_form = checkerboard(Vec::new());
+        let aamode = ParamListEntry::new(ParamType::String,
+                                         "aamode".to_owned(),
+                                         Array::StrArray(vec!["none".to_owned()]));
+        let point_sampled = checkerboard(vec![aamode]);
+
+        // Seen from above, a single check covers the footprint
+        let o = Point3f::new(0.3, 0.3, 1.0);
+        let d = Vector3f::new(0.0, 0.0, -1.0);
+        let colour = evaluate_on_ground(&*closed_form, o, d);
+        assert!(colour == Spectrum::white() || colour == Spectrum::black());
+        assert_eq!(colour, evaluate_on_ground(&*point_sampled, o, d));
+
+        // At a grazing angle the footprint spans many checks, which get blended
+        let d = Vector3f::new(1.0, 0.3, -0.01);
+        let colour = evaluate_on_ground(&*closed_form, o, d);
+        assert!(colour.r > 0.0 && colour.r < 1.0, "{}", colour);
+        let colour = evaluate_on_ground(&*point_sampled, o, d);
+        assert!(colour == Spectrum::white() || colour == Spectrum::black());
+    }
+}
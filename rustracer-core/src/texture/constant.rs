@@ -27,7 +27,8 @@ impl ConstantTexture<Spectrum> {
     pub fn create_spectrum(_tex2world: &Transform,
                            tp: &mut TextureParams)
                            -> ConstantTexture<Spectrum> {
-        ConstantTexture::new(tp.find_spectrum("value", Spectrum::white()))
+        let value = tp.find_spectrum("value", Spectrum::white());
+        ConstantTexture::new(tp.decode_colour(value))
     }
 }
 
@@ -36,3 +37,59 @@ impl<T: Copy + Debug + Send + Sync> Texture<T> for ConstantTexture<T> {
         self.value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use {Point3f, Vector3f};
+    use api::{Array, ParamListEntry, ParamType};
+    use ray::Ray;
+    use shapes::{Shape, Sphere};
+    use test_utils::with_texture_params;
+
+    fn evaluate(params: Vec<ParamListEntry>, colour: bool) -> Spectrum {
+        let tex: Arc<Texture<Spectrum>> = with_texture_params(params, |tp| if colour {
+            tp.get_colour_texture("Kd", &Spectrum::grey(0.5))
+        } else {
+            tp.get_spectrum_texture("Kd", &Spectrum::grey(0.5))
+        });
+
+        let sphere = Sphere::new(Transform::default(), 1.0, -1.0, 1.0, 360.0, false);
+        let ray = Ray::new(Point3f::new(0.0, 0.0, -2.0), Vector3f::new(0.0, 0.0, 1.0));
+        let (si, _) = sphere.intersect(&ray).unwrap();
+        tex.evaluate(&si)
+    }
+
+    fn grey() -> ParamListEntry {
+        ParamListEntry::new(ParamType::Rgb,
+                            "Kd".to_owned(),
+                            Array::NumArray(vec![0.5, 0.5, 0.5]))
+    }
+
+    fn srgb() -> ParamListEntry {
+        ParamListEntry::new(ParamType::Bool,
+                            "srgb".to_owned(),
+                            Array::StrArray(vec!["true".to_owned()]))
+    }
+
+    #[test]
+    fn test_srgb_colours_are_linearized() {
+        ::init_stats();
+        assert_eq!(evaluate(vec![grey()], true), Spectrum::grey(0.5));
+
+        let linear = evaluate(vec![grey(), srgb()], true);
+        assert!((linear.r - 0.214).abs() < 1e-3, "{}", linear);
+        assert_eq!(linear.r, linear.g);
+        assert_eq!(linear.r, linear.b);
+    }
+
+    #[test]
+    fn test_srgb_only_applies_to_given_colours() {
+        ::init_stats();
+        // Non-colour spectra (e.g. metal eta / k) and defaults are always linear
+        assert_eq!(evaluate(vec![grey(), srgb()], false), Spectrum::grey(0.5));
+        assert_eq!(evaluate(vec![srgb()], true), Spectrum::grey(0.5));
+    }
+}
@@ -8,7 +8,7 @@ use {Clampable, Point2i};
 use fileutil;
 use interaction::SurfaceInteraction;
 use imageio::read_image;
-use mipmap::{MIPMap, WrapMode};
+use mipmap::{FilterMode, MIPMap, WrapMode};
 use spectrum::Spectrum;
 use texture::{Texture, TextureMapping2D, UVMapping2D};
 use paramset::TextureParams;
@@ -33,7 +33,7 @@ impl<T> ImageTexture<T>
 {
     pub fn new<F: Fn(&Spectrum) -> T>(path: &Path,
                                       wrap_mode: WrapMode,
-                                      trilerp: bool,
+                                      filter: FilterMode,
                                       max_aniso: f32,
                                       scale: f32,
                                       gamma: bool,
@@ -56,9 +56,11 @@ impl<T> ImageTexture<T>
                 (res, pixels)
             }
             Err(e) => {
-                warn!("Could not open texture file. Using grey texture instead: {}",
-                      e);
-                (Point2i::new(1, 1), vec![Spectrum::grey(0.18)])
+                error!("Could not load texture file \"{}\" ({}). Using a magenta placeholder \
+                        instead.",
+                       path.display(),
+                       e);
+                (Point2i::new(1, 1), vec![missing_texture_placeholder()])
             }
         };
 
@@ -78,7 +80,7 @@ impl<T> ImageTexture<T>
             mapping: map,
             mipmap: Arc::new(MIPMap::new(&res,
                                          &converted_texels[..],
-                                         trilerp,
+                                         filter,
                                          max_aniso,
                                          wrap_mode)),
         }
@@ -99,15 +101,8 @@ impl ImageTexture<Spectrum> {
             unimplemented!()
         };
         let max_aniso = tp.find_float("maxanisotropy", 8.0);
-        let trilerp = tp.find_bool("trilinear", false);
-        let wrap = tp.find_string("wrap", "repeat");
-        let wrap_mode = if wrap == "black" {
-            WrapMode::Black
-        } else if wrap == "clamp" {
-            WrapMode::Clamp
-        } else {
-            WrapMode::Repeat
-        };
+        let filter = find_filter_mode(tp);
+        let wrap_mode = find_wrap_mode(tp);
         let scale = tp.find_float("scale", 1.0);
         let filename = tp.find_filename("filename", "");
         let gamma = tp.find_bool("gamma",
@@ -116,7 +111,7 @@ impl ImageTexture<Spectrum> {
 
         Self::new(Path::new(&filename),
                   wrap_mode,
-                  trilerp,
+                  filter,
                   max_aniso,
                   scale,
                   gamma,
@@ -139,15 +134,8 @@ impl ImageTexture<f32> {
             unimplemented!()
         };
         let max_aniso = tp.find_float("maxanisotropy", 8.0);
-        let trilerp = tp.find_bool("trilinear", false);
-        let wrap = tp.find_string("wrap", "repeat");
-        let wrap_mode = if wrap == "black" {
-            WrapMode::Black
-        } else if wrap == "clamp" {
-            WrapMode::Clamp
-        } else {
-            WrapMode::Repeat
-        };
+        let filter = find_filter_mode(tp);
+        let wrap_mode = find_wrap_mode(tp);
         let scale = tp.find_float("scale", 1.0);
         let filename = tp.find_filename("filename", "");
         let gamma = tp.find_bool("gamma",
@@ -156,7 +144,7 @@ impl ImageTexture<f32> {
 
         Self::new(Path::new(&filename),
                   wrap_mode,
-                  trilerp,
+                  filter,
                   max_aniso,
                   scale,
                   gamma,
@@ -164,6 +152,43 @@ impl ImageTexture<f32> {
                   convert_to_float)
     }
 }
+
+/// Parse the `"string wrap"` parameter, defaulting to `WrapMode::Repeat`.
+fn find_wrap_mode(tp: &mut TextureParams) -> WrapMode {
+    let wrap = tp.find_string("wrap", "repeat");
+    match wrap.as_str() {
+        "repeat" => WrapMode::Repeat,
+        "black" => WrapMode::Black,
+        "clamp" => WrapMode::Clamp,
+        _ => {
+            warn!("Unknown wrap mode \"{}\", using \"repeat\"", wrap);
+            WrapMode::Repeat
+        }
+    }
+}
+
+/// Parse the `"string filter"` parameter. Without it, the `"bool trilinear"` parameter picks
+/// between trilinear and EWA filtering like in pbrt-v3.
+fn find_filter_mode(tp: &mut TextureParams) -> FilterMode {
+    let trilinear = tp.find_bool("trilinear", false);
+    let filter = tp.find_string("filter", if trilinear { "trilinear" } else { "ewa" });
+    match filter.as_str() {
+        "nearest" => FilterMode::Nearest,
+        "bilinear" => FilterMode::Bilinear,
+        "trilinear" => FilterMode::Trilinear,
+        "ewa" => FilterMode::EWA,
+        _ => {
+            warn!("Unknown texture filter \"{}\", using \"ewa\"", filter);
+            FilterMode::EWA
+        }
+    }
+}
+
+/// Colour used in place of textures that failed to load, so they stand out in the render.
+fn missing_texture_placeholder() -> Spectrum {
+    Spectrum::rgb(1.0, 0.0, 1.0)
+}
+
 fn convert_to_spectrum(from: &Spectrum) -> Spectrum {
     *from
 }
@@ -190,3 +215,26 @@ impl<T> Texture<T> for ImageTexture<T>
         self.mipmap.lookup_diff(&st, &dstdx, &dstdy)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Point2f;
+
+    #[test]
+    fn test_missing_texture_is_magenta() {
+        let tex = ImageTexture::new(Path::new("/does/not/exist.png"),
+                                    WrapMode::Repeat,
+                                    FilterMode::EWA,
+                                    8.0,
+                                    1.0,
+                                    true,
+                                    Box::new(UVMapping2D::new(1.0, 1.0, 0.0, 0.0)),
+                                    convert_to_spectrum);
+        let colour = tex.mipmap.lookup(&Point2f::new(0.5, 0.5), 0.0);
+        let expected = missing_texture_placeholder();
+        assert!((colour.r - expected.r).abs() < 1e-5);
+        assert!((colour.g - expected.g).abs() < 1e-5);
+        assert!((colour.b - expected.b).abs() < 1e-5);
+    }
+}
@@ -1,7 +1,5 @@
 use std::fmt::Debug;
 
-use num::Zero;
-
 use {Point2f, Point3f, Transform, Vector2f, Vector3f};
 use interaction::SurfaceInteraction;
 use paramset::TextureParams;
@@ -12,12 +10,14 @@ mod checkerboard;
 mod imagemap;
 mod fbm;
 mod scale;
+mod vertexcolor;
 
 pub use self::constant::ConstantTexture;
-pub use self::checkerboard::CheckerboardTexture;
+pub use self::checkerboard::{AAMethod, Checkerboard3DTexture, CheckerboardTexture};
 pub use self::imagemap::ImageTexture;
 pub use self::fbm::FbmTexture;
 pub use self::scale::ScaleTexture;
+pub use self::vertexcolor::VertexColorTexture;
 
 pub trait Texture<T>: Debug + Send + Sync {
     fn evaluate(&self, si: &SurfaceInteraction) -> T;
@@ -94,9 +94,12 @@ impl UVMapping2D {
 
 impl TextureMapping2D for UVMapping2D {
     fn map(&self, si: &SurfaceInteraction) -> (Point2f, Vector2f, Vector2f) {
+        // Compute texture differentials for 2D (u, v) mapping
+        let dstdx = Vector2f::new(self.su * si.dudx, self.sv * si.dvdx);
+        let dstdy = Vector2f::new(self.su * si.dudy, self.sv * si.dvdy);
         (Point2f::new(self.su * si.uv.x + self.du, self.sv * si.uv.y + self.dv),
-         Vector2f::zero(),
-         Vector2f::zero())
+         dstdx,
+         dstdy)
     }
 }
 
@@ -124,3 +127,33 @@ impl TextureMapping3D for IdentityMapping3D {
         (p, dpdx, dpdy)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::Zero;
+
+    use test_utils::hit_disk;
+
+    #[test]
+    fn test_uv_mapping_scales_differentials() {
+        ::init_stats();
+        let mut si = hit_disk();
+        si.dudx = 0.25;
+        si.dvdx = -0.5;
+        si.dudy = 0.125;
+        si.dvdy = 1.0;
+        // The offsets move the texture but don't change the footprint: image textures are
+        // filtered over (su * du/dx, sv * dv/dx) and (su * du/dy, sv * dv/dy).
+        let mapping = UVMapping2D::new(2.0, 3.0, 0.5, -1.0);
+        let (st, dstdx, dstdy) = mapping.map(&si);
+        assert_eq!(st, Point2f::new(2.0 * si.uv.x + 0.5, 3.0 * si.uv.y - 1.0));
+        assert_eq!(dstdx, Vector2f::new(0.5, -1.5));
+        assert_eq!(dstdy, Vector2f::new(0.25, 3.0));
+
+        // Without differentials (e.g. for rays that aren't camera rays), lookups are unfiltered
+        let (_, dstdx, dstdy) = mapping.map(&hit_disk());
+        assert_eq!(dstdx, Vector2f::zero());
+        assert_eq!(dstdy, Vector2f::zero());
+    }
+}
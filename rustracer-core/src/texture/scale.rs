@@ -7,17 +7,28 @@ use paramset::TextureParams;
 use spectrum::Spectrum;
 use texture::Texture;
 
+/// Product of two textures. The second one can be a float texture scaling a spectrum texture,
+/// e.g. to mask it.
 #[derive(Debug)]
-pub struct ScaleTexture<T> {
+pub struct ScaleTexture<T, S = T> {
     tex1: Arc<Texture<T>>,
-    tex2: Arc<Texture<T>>,
+    tex2: Arc<Texture<S>>,
 }
 
-impl<T> Texture<T> for ScaleTexture<T>
+impl<T, S> ScaleTexture<T, S> {
+    pub fn new(tex1: Arc<Texture<T>>, tex2: Arc<Texture<S>>) -> ScaleTexture<T, S> {
+        ScaleTexture { tex1, tex2 }
+    }
+}
+
+impl<T, S> Texture<T> for ScaleTexture<T, S>
     where T: Debug,
           T: Send,
           T: Sync,
-          T: Mul<Output = T>
+          T: Mul<S, Output = T>,
+          S: Debug,
+          S: Send,
+          S: Sync
 {
     fn evaluate(&self, si: &SurfaceInteraction) -> T {
         self.tex1.evaluate(si) * self.tex2.evaluate(si)
@@ -25,19 +36,85 @@ impl<T> Texture<T> for ScaleTexture<T>
 }
 
 impl ScaleTexture<Spectrum> {
-    pub fn create(tp: &mut TextureParams) -> ScaleTexture<Spectrum> {
+    /// Create a spectrum texture scaled by `"tex2"`, which is a float texture if it is given as a
+    /// float value or refers to a float texture, and a spectrum texture otherwise.
+    pub fn create_spectrum(tp: &mut TextureParams) -> Arc<Texture<Spectrum>> {
         let tex1 = tp.get_spectrum_texture("tex1", &Spectrum::white());
-        let tex2 = tp.get_spectrum_texture("tex2", &Spectrum::white());
-
-        ScaleTexture { tex1, tex2 }
+        if tp.is_float("tex2") {
+            let tex2 = tp.get_float_texture("tex2", 1.0);
+            Arc::new(ScaleTexture::new(tex1, tex2))
+        } else {
+            let tex2 = tp.get_spectrum_texture("tex2", &Spectrum::white());
+            Arc::new(ScaleTexture::new(tex1, tex2))
+        }
     }
 }
 
 impl ScaleTexture<f32> {
-    pub fn create(tp: &mut TextureParams) -> ScaleTexture<f32> {
+    pub fn create_float(tp: &mut TextureParams) -> ScaleTexture<f32> {
         let tex1 = tp.get_float_texture("tex1", 1.0);
         let tex2 = tp.get_float_texture("tex2", 1.0);
 
-        ScaleTexture { tex1, tex2 }
+        ScaleTexture::new(tex1, tex2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use {Point3f, Transform, Vector3f};
+    use api::{Array, ParamListEntry, ParamType};
+    use ray::Ray;
+    use shapes::{Shape, Sphere};
+    use test_utils::with_textures;
+    use texture::ConstantTexture;
+
+    fn scaled_colour(params: Vec<ParamListEntry>) -> Spectrum {
+        let mut spectrum_textures: HashMap<String, Arc<Texture<Spectrum>>> = HashMap::new();
+        spectrum_textures.insert("colour".to_owned(),
+                                 Arc::new(ConstantTexture::new(Spectrum::rgb(0.2, 0.4, 0.8))));
+        let mut float_textures: HashMap<String, Arc<Texture<f32>>> = HashMap::new();
+        float_textures.insert("mask".to_owned(), Arc::new(ConstantTexture::new(0.25)));
+        let tex = with_textures(params,
+                                &float_textures,
+                                &spectrum_textures,
+                                ScaleTexture::create_spectrum);
+
+        let sphere = Sphere::new(Transform::default(), 1.0, -1.0, 1.0, 360.0, false);
+        let ray = Ray::new(Point3f::new(0.0, 0.0, -2.0), Vector3f::new(0.0, 0.0, 1.0));
+        let (si, _) = sphere.intersect(&ray).unwrap();
+        tex.evaluate(&si)
+    }
+
+    fn texture_param(name: &str, texture: &str) -> ParamListEntry {
+        ParamListEntry::new(ParamType::Texture,
+                            name.to_owned(),
+                            Array::StrArray(vec![texture.to_owned()]))
+    }
+
+    #[test]
+    fn test_float_scales_spectrum() {
+        ::init_stats();
+        let half = scaled_colour(vec![texture_param("tex1", "colour"),
+                                      ParamListEntry::new(ParamType::Float,
+                                                          "tex2".to_owned(),
+                                                          Array::NumArray(vec![0.5]))]);
+        assert_eq!(half, Spectrum::rgb(0.1, 0.2, 0.4));
+
+        let masked = scaled_colour(vec![texture_param("tex1", "colour"),
+                                        texture_param("tex2", "mask")]);
+        assert_eq!(masked, Spectrum::rgb(0.05, 0.1, 0.2));
+    }
+
+    #[test]
+    fn test_spectrum_scales_spectrum() {
+        ::init_stats();
+        let scaled = scaled_colour(vec![texture_param("tex1", "colour"),
+                                        texture_param("tex2", "colour")]);
+        assert!((scaled.r - 0.04).abs() < 1e-6);
+        assert!((scaled.g - 0.16).abs() < 1e-6);
+        assert!((scaled.b - 0.64).abs() < 1e-6);
     }
 }
@@ -0,0 +1,77 @@
+use interaction::SurfaceInteraction;
+use paramset::TextureParams;
+use spectrum::Spectrum;
+use texture::Texture;
+
+/// Texture returning the colour interpolated from the vertices of a triangle mesh (its `Cs`
+/// parameter), for vertex-painted meshes. Surfaces without vertex colours get `default`.
+#[derive(Debug)]
+pub struct VertexColorTexture {
+    default: Spectrum,
+}
+
+impl VertexColorTexture {
+    pub fn new(default: Spectrum) -> VertexColorTexture {
+        VertexColorTexture { default }
+    }
+
+    pub fn create_spectrum(tp: &mut TextureParams) -> VertexColorTexture {
+        VertexColorTexture::new(tp.find_spectrum("default", Spectrum::white()))
+    }
+}
+
+impl Texture<Spectrum> for VertexColorTexture {
+    fn evaluate(&self, si: &SurfaceInteraction) -> Spectrum {
+        si.color.unwrap_or(self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use {Point3f, Transform, Vector3f};
+    use api::{Array, ParamListEntry, ParamType};
+    use paramset::ParamSet;
+    use ray::Ray;
+    use shapes::TriangleMesh;
+
+    #[test]
+    fn test_vertex_colors_blend_smoothly() {
+        ::init_stats();
+        let mut params = ParamSet::default();
+        params.init(vec![ParamListEntry::new(ParamType::Int,
+                                             "indices".to_owned(),
+                                             Array::NumArray(vec![0.0, 1.0, 2.0])),
+                         ParamListEntry::new(ParamType::Point3,
+                                             "P".to_owned(),
+                                             Array::NumArray(vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0,
+                                                                  0.0, 1.0, 0.0])),
+                         ParamListEntry::new(ParamType::Rgb,
+                                             "Cs".to_owned(),
+                                             Array::NumArray(vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+                                                                  0.0, 0.0, 1.0]))]);
+        let tris = TriangleMesh::create(&Transform::default(),
+                                        &Transform::default(),
+                                        false,
+                                        &mut params,
+                                        &HashMap::new());
+        let texture = VertexColorTexture::new(Spectrum::black());
+
+        // Barycentric weights of red, green and blue give the colour of the hit point
+        for &(x, y) in &[(0.1, 0.1), (0.6, 0.2), (0.2, 0.7), (1.0 / 3.0, 1.0 / 3.0)] {
+            let ray = Ray::new(Point3f::new(x, y, 1.0), Vector3f::new(0.0, 0.0, -1.0));
+            let (si, _) = tris[0].intersect(&ray).unwrap();
+            let c = texture.evaluate(&si);
+            let expected = [1.0 - x - y, x, y];
+            for i in 0..3 {
+                assert!((c[i] - expected[i]).abs() < 1e-4,
+                        "{:?} at ({}, {})",
+                        c,
+                        x,
+                        y);
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::ops::Mul;
 
-use {gamma, Normal3f, Point3f, Vector2f, Vector3f};
+use {coordinate_system, gamma, Normal3f, Point3f, Vector2f, Vector3f};
 use bounds::Bounds3f;
 use geometry::Matrix4x4;
 
@@ -149,6 +149,12 @@ impl Transform {
         Transform { m: m, m_inv: minv }
     }
 
+    /// World-to-camera transformation for a camera at `pos` looking at `look`.
+    ///
+    /// As in pbrt, camera space is left-handed: the camera looks down +z, with +y up, so looking
+    /// down +z with a +y `up` vector gives the identity. `up` only needs to be roughly up; it is
+    /// orthonormalized against the viewing direction. If it is parallel to the viewing direction,
+    /// an arbitrary perpendicular vector is used instead.
     pub fn look_at(pos: &Point3f, look: &Point3f, up: &Vector3f) -> Transform {
         let mut camera_to_world = Matrix4x4::new();
         // Initialize fourth column of viewing matrix
@@ -159,13 +165,16 @@ impl Transform {
 
         // Initialize first three columns of viewing matrix
         let dir = (*look - *pos).normalize();
-        if up.normalize().cross(&dir).length() == 0.0 {
-            error!("\"up\" vector {} and viewing direction {} passed to LookAt are pointing in the same direction.  Using the identity transformation.",
-                   up,
-                   dir);
-            return Transform::new();
+        let mut up = up.normalize();
+        if up.cross(&dir).length() < 1e-6 {
+            let (perpendicular, _) = coordinate_system(&dir);
+            warn!("\"up\" vector {} and viewing direction {} passed to LookAt are pointing in the same direction.  Using {} as \"up\" instead.",
+                  up,
+                  dir,
+                  perpendicular);
+            up = perpendicular;
         }
-        let left = up.normalize().cross(&dir).normalize();
+        let left = up.cross(&dir).normalize();
         let new_up = dir.cross(&left);
         camera_to_world.m[0][0] = left.x;
         camera_to_world.m[1][0] = left.y;
@@ -276,6 +285,8 @@ impl Transform {
                       m[0][2] * x + m[1][2] * y + m[2][2] * z)
     }
 
+    /// Return true if the transform turns a right-handed coordinate system into a left-handed
+    /// one, i.e. if the determinant of its upper-left 3x3 submatrix is negative.
     pub fn swaps_handedness(&self) -> bool {
         let m = self.m.m;
         let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) -
@@ -409,7 +420,103 @@ fn test_normal_transform() {
     assert_eq!(v.dot(&n), 0.0);
 
     let v2 = &t * &v;
-    let n2 = t_inv.transform_normal(&Normal3f::from(n));
+    let n2 = t.transform_normal(&Normal3f::from(n));
     println!("v = {}, n = {}", v2, n2);
-    relative_eq!(v2.dotn(&n2), 0.0);
+    assert!(v2.dotn(&n2).abs() < 1e-6);
+    // Transforming back gives the original normal
+    let n3 = t_inv.transform_normal(&n2);
+    assert!((Vector3f::from(n3) - n).length() < 1e-6);
+}
+
+#[test]
+fn test_normal_transform_anisotropic_scale() {
+    let t = &Transform::rotate(20.0, Vector3f::new(1.0, 1.0, 0.0)) *
+            &Transform::scale(4.0, 0.5, 1.0);
+
+    // Tangent and normal of the plane x + y = 0
+    let v = Vector3f::new(1.0, -1.0, 0.0);
+    let n = Normal3f::new(1.0, 1.0, 0.0);
+    let v2 = &t * &v;
+    let n2 = &t * &n;
+    assert!(v2.dotn(&n2).abs() < 1e-5 * v2.length() * n2.length());
+    assert_eq!(n2, t.transform_normal(&n));
+    // Transforming the normal like a vector would not keep it perpendicular
+    let n_as_vector = &t * &Vector3f::from(n);
+    assert!(v2.dot(&n_as_vector).abs() > 1.0);
+}
+
+#[test]
+fn test_swaps_handedness() {
+    assert!(Transform::scale(-1.0, 1.0, 1.0).swaps_handedness());
+    assert!(Transform::scale(1.0, 2.0, -0.5).swaps_handedness());
+    assert!(!Transform::scale(-1.0, -1.0, 1.0).swaps_handedness());
+    assert!(!Transform::default().swaps_handedness());
+    assert!(!Transform::rotate(36.0, Vector3f::new(4.0, 5.0, 6.0)).swaps_handedness());
+    assert!(!Transform::translate(&Vector3f::new(-1.0, -2.0, -3.0)).swaps_handedness());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rotation part of the camera-to-world transformation, as its three columns.
+    fn camera_axes(t: &Transform) -> (Vector3f, Vector3f, Vector3f) {
+        let m = t.m_inv.m;
+        (Vector3f::new(m[0][0], m[1][0], m[2][0]),
+         Vector3f::new(m[0][1], m[1][1], m[2][1]),
+         Vector3f::new(m[0][2], m[1][2], m[2][2]))
+    }
+
+    fn assert_rotation(t: &Transform) {
+        let (x, y, z) = camera_axes(t);
+        for v in &[x, y, z] {
+            assert!((v.length() - 1.0).abs() < 1e-5);
+        }
+        assert!(x.dot(&y).abs() < 1e-5 && y.dot(&z).abs() < 1e-5 && z.dot(&x).abs() < 1e-5);
+        // A determinant of -1 would mirror the scene
+        assert!((x.cross(&y).dot(&z) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_look_at_down_positive_z_is_identity() {
+        let t = Transform::look_at(&Point3f::new(0.0, 0.0, 0.0),
+                                   &Point3f::new(0.0, 0.0, 1.0),
+                                   &Vector3f::new(0.0, 1.0, 0.0));
+        let (x, y, z) = camera_axes(&t);
+        assert_eq!(x, Vector3f::new(1.0, 0.0, 0.0));
+        assert_eq!(y, Vector3f::new(0.0, 1.0, 0.0));
+        assert_eq!(z, Vector3f::new(0.0, 0.0, 1.0));
+
+        // Looking the other way is a half turn around +y, not a mirror image
+        let t = Transform::look_at(&Point3f::new(0.0, 0.0, 0.0),
+                                   &Point3f::new(0.0, 0.0, -1.0),
+                                   &Vector3f::new(0.0, 1.0, 0.0));
+        assert_rotation(&t);
+        let (x, y, _) = camera_axes(&t);
+        assert_eq!(x, Vector3f::new(-1.0, 0.0, 0.0));
+        assert_eq!(y, Vector3f::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_look_at_is_orthonormal() {
+        let pos = Point3f::new(1.0, 2.0, -5.0);
+        let look = Point3f::new(0.3, -0.2, 0.5);
+        let t = Transform::look_at(&pos, &look, &Vector3f::new(0.2, 1.0, 0.1));
+        assert_rotation(&t);
+        // The camera sits at `pos` and looks at `look`
+        let p = &t * &pos;
+        assert!(p.x.abs() < 1e-5 && p.y.abs() < 1e-5 && p.z.abs() < 1e-5);
+        let l = &t * &look;
+        assert!(l.x.abs() < 1e-4 && l.y.abs() < 1e-4 && l.z > 0.0);
+    }
+
+    #[test]
+    fn test_look_at_with_degenerate_up() {
+        let t = Transform::look_at(&Point3f::new(0.0, 0.0, 0.0),
+                                   &Point3f::new(0.0, 3.0, 0.0),
+                                   &Vector3f::new(0.0, 2.0, 0.0));
+        assert_rotation(&t);
+        let (_, _, z) = camera_axes(&t);
+        assert_eq!(z, Vector3f::new(0.0, 1.0, 0.0));
+    }
 }
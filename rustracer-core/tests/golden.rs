@@ -0,0 +1,112 @@
+//! Golden image regression tests: render small canonical scenes from `tests/scenes` and compare
+//! them with the reference images in `tests/golden`.
+//!
+//! Renders are deterministic for a given seed, so the references only need updating when the
+//! renderer's output changes on purpose. To regenerate them, run:
+//!
+//! ```sh
+//! RUSTRACER_UPDATE_GOLDEN=1 cargo test --test golden
+//! ```
+//!
+//! and commit the new images. A missing reference fails the test unless it's being regenerated.
+//!
+//! The references haven't been generated yet, so the tests are ignored until they are: create
+//! them with `RUSTRACER_UPDATE_GOLDEN=1 cargo test --test golden -- --ignored`, commit them and
+//! remove the `#[ignore]` attributes.
+//!
+//! The images are PFM files, so the tests don't depend on the optional EXR support.
+
+extern crate rustracer_core as rt;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rt::{pbrt, Options};
+use rt::imageio::read_image;
+use rt::spectrum::Spectrum;
+
+/// Pixels whose relative difference with the reference is above this threshold are counted as
+/// different.
+const MAX_RELATIVE_ERROR: f32 = 0.05;
+/// Differences below this absolute value are ignored, to avoid flagging noise in dark pixels.
+const MIN_ABSOLUTE_ERROR: f32 = 1e-3;
+/// Fraction of the pixels that can differ from the reference before the test fails.
+const MAX_DIFFERENT_PIXELS: f32 = 0.01;
+
+fn test_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn update_golden() -> bool {
+    env::var("RUSTRACER_UPDATE_GOLDEN")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+fn pixel_differs(p: &Spectrum, reference: &Spectrum) -> bool {
+    (0..3).any(|i| {
+                   let err = (p[i] - reference[i]).abs();
+                   err > MIN_ABSOLUTE_ERROR && err > MAX_RELATIVE_ERROR * reference[i].abs()
+               })
+}
+
+/// Render `tests/scenes/<name>.pbrt` and compare it with `tests/golden/<name>.pfm`.
+fn check_golden(name: &str) {
+    rt::init_stats();
+    let scene = test_dir().join("scenes").join(format!("{}.pbrt", name));
+    let reference = test_dir().join("golden").join(format!("{}.pfm", name));
+    let output = env::temp_dir().join(format!("rustracer-golden-{}.pfm", name));
+
+    let mut options = Options::default();
+    options.image_file = Some(output.to_str().unwrap().to_owned());
+    options.quiet = true;
+    pbrt::parse_scene(&scene, &options).unwrap();
+
+    if update_golden() {
+        fs::copy(&output, &reference).unwrap();
+        println!("Updated reference image {}", reference.display());
+        return;
+    }
+    assert!(reference.exists(),
+            "{}: reference image {} is missing. Run the test with RUSTRACER_UPDATE_GOLDEN=1 to \
+             create it from the current render ({}), and commit it.",
+            name,
+            reference.display(),
+            output.display());
+
+    let (pixels, res) = read_image(&output).unwrap();
+    let (ref_pixels, ref_res) = read_image(&reference).unwrap();
+    assert_eq!(res, ref_res, "{}: resolution differs from the reference", name);
+
+    let n_different = pixels
+        .iter()
+        .zip(ref_pixels.iter())
+        .filter(|&(p, r)| pixel_differs(p, r))
+        .count();
+    let max_different = (MAX_DIFFERENT_PIXELS * pixels.len() as f32) as usize;
+    assert!(n_different <= max_different,
+            "{}: {} pixels out of {} differ from the reference (see {})",
+            name,
+            n_different,
+            pixels.len(),
+            output.display());
+}
+
+#[test]
+#[ignore]
+fn golden_sphere_on_plane() {
+    check_golden("sphere_on_plane");
+}
+
+#[test]
+#[ignore]
+fn golden_area_light() {
+    check_golden("area_light");
+}
+
+#[test]
+#[ignore]
+fn golden_glass_ball() {
+    check_golden("glass_ball");
+}
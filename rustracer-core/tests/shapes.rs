@@ -5,10 +5,10 @@ use std::f32;
 use rand::{Rng, SeedableRng, StdRng};
 use rand::distributions::{IndependentSample, Range};
 
-use rt::{Point2f, Point3f, Transform};
+use rt::{Point2f, Point3f, Transform, Vector3f};
 use rt::ray::Ray;
 use rt::sampling;
-use rt::shapes::{Shape, Sphere};
+use rt::shapes::{Disk, Shape, Sphere};
 
 fn pexp<T: Rng>(rng: &mut T, exp: f32) -> f32 {
     let range = Range::new(-exp, exp);
@@ -28,6 +28,50 @@ fn full_sphere_reintersect() {
     }
 }
 
+#[test]
+fn full_sphere_shadow_ray_reintersect() {
+    let mut rng = StdRng::from_seed(&[0]);
+    for i in 0..1000 {
+        rng.reseed(&[i]);
+        let radius = pexp(&mut rng, 4.0);
+        let sphere = Sphere::new(Transform::default(), radius, -radius, radius, 360.0, false);
+        test_shadow_ray_reintersection(&sphere, &mut rng);
+    }
+}
+
+#[test]
+fn disk_shadow_ray_reintersect() {
+    let mut rng = StdRng::from_seed(&[0]);
+    for i in 0..1000 {
+        rng.reseed(&[i]);
+        let radius = pexp(&mut rng, 4.0);
+        let height = pexp(&mut rng, 4.0);
+        let disk = Disk::new(height, radius, 0.0, 360.0, Transform::default(), false);
+        test_shadow_ray_reintersection(&disk, &mut rng);
+    }
+}
+
+#[test]
+fn ellipsoid_normal_is_perpendicular_to_surface() {
+    let o2w = &Transform::rotate(30.0, Vector3f::new(0.0, 1.0, 1.0)) *
+              &Transform::scale(3.0, 0.5, 1.0);
+    let sphere = Sphere::new(o2w, 1.0, -1.0, 1.0, 360.0, false);
+    let mut rng = StdRng::from_seed(&[0]);
+    for _ in 0..100 {
+        // Ray origin outside of the ellipsoid, aimed at its center
+        let d = Vector3f::new(rng.next_f32() - 0.5, rng.next_f32() - 0.5, rng.next_f32() - 0.5);
+        let o = Point3f::from(10.0 * d.normalize());
+        let ray = Ray::new(o, Point3f::new(0.0, 0.0, 0.0) - o);
+        if let Some((si, _)) = sphere.intersect(&ray) {
+            let n = Vector3f::from(si.hit.n);
+            assert!(n.dot(&si.dpdu.normalize()).abs() < 1e-4);
+            assert!(n.dot(&si.dpdv.normalize()).abs() < 1e-4);
+            // The geometric normal points outwards
+            assert!(n.dot(&ray.d) < 0.0);
+        }
+    }
+}
+
 fn test_reintersection_convex<T: Shape>(shape: &T, rng: &mut StdRng) {
     // Ray origin
     let o = Point3f::new(pexp(rng, 8.0), pexp(rng, 8.0), pexp(rng, 8.0));
@@ -57,3 +101,30 @@ fn test_reintersection_convex<T: Shape>(shape: &T, rng: &mut StdRng) {
         }
     }
 }
+
+fn test_shadow_ray_reintersection<T: Shape>(shape: &T, rng: &mut StdRng) {
+    // Ray origin
+    let o = Point3f::new(pexp(rng, 8.0), pexp(rng, 8.0), pexp(rng, 8.0));
+
+    // Destination
+    let bounds = shape.world_bounds();
+    let t = Point3f::new(rng.next_f32(), rng.next_f32(), rng.next_f32());
+    let p = bounds.lerp(&t);
+    let ray = Ray::new(o, p - o);
+
+    if let Some((isect, _t_hit)) = shape.intersect(&ray) {
+        // Shadow rays towards lights on the side of the surface the ray came from must not hit
+        // the surface again
+        for _ in 0..100 {
+            let u = Point2f::new(rng.next_f32(), rng.next_f32());
+            let mut w = sampling::uniform_sample_sphere(&u);
+            if w.dotn(&isect.hit.n) * ray.d.dotn(&isect.hit.n) > 0.0 {
+                w = -w;
+            }
+            let light = isect.hit.p + w * pexp(rng, 4.0);
+            let shadow_ray = isect.spawn_ray_to(&light);
+            assert!(!shape.intersect_p(&shadow_ray));
+            assert!(shape.intersect(&shadow_ray).is_none());
+        }
+    }
+}